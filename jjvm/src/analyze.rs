@@ -0,0 +1,134 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{
+    classpath,
+    hierarchy::{self, CallGraph, Hierarchy, MemberRef, Unresolved},
+};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("analyze")
+        .about("Reports the class hierarchy, reachable methods and unresolved references")
+        .arg(
+            Arg::with_name("PATH")
+                .help("Class files, jars, jmods or directories to analyze")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("classpath")
+                .long("classpath")
+                .short("c")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Libraries used to resolve references but not reported on"),
+        )
+        .arg(
+            Arg::with_name("entry")
+                .long("entry")
+                .short("e")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Method to start from as Class.name(descriptor), defaults to every main"),
+        )
+        .arg(
+            Arg::with_name("calls")
+                .long("calls")
+                .help("Lists the callees of every reachable method"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let paths: Vec<&str> = matches.values_of("PATH").unwrap().collect();
+    let libraries: Vec<&str> = matches
+        .values_of("classpath")
+        .map(|v| v.collect())
+        .unwrap_or_default();
+
+    let application = classpath::load_all(&paths)?;
+    let mut classes = classpath::load_all(&libraries)?;
+    classes.extend(application.clone());
+    let hierarchy = Hierarchy::new(&classes);
+
+    let mut names: Vec<&String> = application.keys().collect();
+    names.sort();
+
+    let entries = match matches.values_of("entry") {
+        Some(values) => values.map(parse_entry).collect::<Result<Vec<_>, _>>()?,
+        None => {
+            hierarchy::main_methods(&names.iter().map(|n| &application[*n]).collect::<Vec<_>>())
+        }
+    };
+    let graph = CallGraph::build(&hierarchy, &entries);
+
+    println!("Classes: {}", application.len());
+    println!();
+    println!("Hierarchy:");
+    for name in &names {
+        let class = &application[*name];
+        let mut supertypes = vec![];
+        if !class.superclass.is_empty() {
+            supertypes.push(format!("extends {}", class.superclass));
+        }
+        if !class.interfaces.is_empty() {
+            supertypes.push(format!("implements {}", class.interfaces.join(", ")));
+        }
+        println!("  {} {}", name, supertypes.join(" "));
+        for (method, overridden) in hierarchy.overrides(name) {
+            println!(
+                "    {}{} overrides {}",
+                method.name, method.descriptor, overridden
+            );
+        }
+    }
+
+    println!();
+    println!("Entry points:");
+    for entry in &entries {
+        println!("  {}", entry);
+    }
+
+    println!();
+    println!("Reachable methods: {}", graph.reachable.len());
+    for method in &graph.reachable {
+        println!("  {}", method);
+        if matches.is_present("calls") {
+            for callee in &graph.calls[method] {
+                println!("    -> {}", callee);
+            }
+        }
+    }
+
+    let mut unresolved = graph.unresolved.clone();
+    unresolved.extend(
+        hierarchy
+            .missing_supertypes()
+            .into_iter()
+            .map(Unresolved::Class),
+    );
+    println!();
+    println!("Unresolved: {}", unresolved.len());
+    for reference in &unresolved {
+        println!("  {}", reference);
+    }
+
+    Ok(())
+}
+
+/// Parses an entry point such as `Main.main([Ljava/lang/String;)V`
+fn parse_entry(entry: &str) -> Result<MemberRef, String> {
+    let invalid = || {
+        format!(
+            "invalid entry point {}, expected Class.name(descriptor)",
+            entry
+        )
+    };
+    let paren = entry.find('(').ok_or_else(invalid)?;
+    let dot = entry[..paren].rfind('.').ok_or_else(invalid)?;
+
+    Ok(MemberRef::new(
+        &entry[..dot],
+        &entry[dot + 1..paren],
+        &entry[paren..],
+    ))
+}
@@ -0,0 +1,44 @@
+use std::fs;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::archive::Archive;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("archive")
+        .about(
+            "Saves parsed classes into one archive that loads faster than the classes themselves",
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .help("Class files, jars, jmods or directories to archive")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .required(true)
+                .help("The archive to write"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let paths: Vec<&str> = matches.values_of("PATH").unwrap().collect();
+    let output = matches.value_of("output").unwrap();
+
+    let archive = Archive::create(&paths)?;
+    archive.save(output)?;
+
+    let size = fs::metadata(output).map(|m| m.len()).unwrap_or(0);
+    println!(
+        "Archived {} classes from {} files into {} ({} bytes)",
+        archive.classes.len(),
+        archive.sources.len(),
+        output,
+        size
+    );
+
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{
+    class::{Class, Field},
+    classpath,
+    flags::MethodFlag,
+};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("builtin")
+        .about("Generates a stdlib BuiltinClass skeleton from a JDK class")
+        .arg(
+            Arg::with_name("PATH")
+                .help("The class file, or a jar, jmod or directory holding the class")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("CLASS")
+                .help("Internal name of the class to use when PATH holds several, such as java/util/ArrayList"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Writes the module into this directory instead of stdout"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let path = matches.value_of("PATH").unwrap();
+    let mut classes = classpath::load(path)?;
+    let class = match matches.value_of("CLASS") {
+        Some(name) => classes
+            .into_iter()
+            .find(|class| class.name == name)
+            .ok_or_else(|| format!("{} has no class {}", path, name))?,
+        None if classes.len() == 1 => classes.remove(0),
+        None => {
+            return Err(format!(
+                "{} holds {} classes, name one of them",
+                path,
+                classes.len()
+            ))
+        }
+    };
+
+    let (module, source) = generate(&class);
+    match matches.value_of("output") {
+        Some(dir) => {
+            let file = Path::new(dir).join(format!("{}.rs", module));
+            if file.exists() {
+                return Err(format!("{} already exists", file.display()));
+            }
+            fs::write(&file, source).map_err(|e| format!("{}: {}", file.display(), e))?;
+            eprintln!(
+                "Wrote {}. Add `pub mod {};` and a case for {} in stdlib::find_builtin to use it.",
+                file.display(),
+                module,
+                class.name
+            );
+        }
+        None => print!("{}", source),
+    }
+
+    Ok(())
+}
+
+/// Module name and source of a builtin with a stub for each public method
+/// and every public field of the class
+fn generate(class: &Class) -> (String, String) {
+    let simple = class
+        .name
+        .rsplit('/')
+        .next()
+        .unwrap_or(&class.name)
+        .replace('$', "");
+    let module = snake_case(&simple);
+    let builtin = format!("{}Class", simple);
+
+    // Overloads share a stub, since builtins are looked up by name
+    let mut methods: BTreeMap<&str, Vec<&Field>> = BTreeMap::new();
+    for method in &class.methods {
+        if MethodFlag::Public.is_set(method.flags) && method.name != "<clinit>" {
+            methods.entry(&method.name).or_default().push(method);
+        }
+    }
+    let fields: Vec<&Field> = class
+        .fields
+        .iter()
+        .filter(|f| f.flags & 0x0001 != 0)
+        .collect();
+
+    let mut out = String::new();
+    out.push_str("use jjvm_loader::class::Field;\n\n");
+    out.push_str("use crate::{jvm_val::JvmVal, vm::VM};\n\n");
+    out.push_str("use super::class::BuiltinClass;\n\n");
+    writeln!(out, "pub struct {} {{}}\n", builtin).unwrap();
+
+    writeln!(out, "impl BuiltinClass for {} {{", builtin).unwrap();
+    out.push_str("    fn get_class_name(self) -> String {\n");
+    writeln!(out, "        \"{}\".to_string()", class.name).unwrap();
+    out.push_str("    }\n\n");
+
+    out.push_str("    fn get_fields(&self) -> Vec<Field> {\n");
+    if fields.is_empty() {
+        out.push_str("        vec![]\n");
+    } else {
+        out.push_str("        vec![\n");
+        for field in &fields {
+            out.push_str("            Field {\n");
+            writeln!(out, "                flags: {:#06x},", field.flags).unwrap();
+            writeln!(out, "                name: \"{}\".to_string(),", field.name).unwrap();
+            writeln!(
+                out,
+                "                descriptor: \"{}\".to_string(),",
+                field.descriptor
+            )
+            .unwrap();
+            out.push_str("                attributes: vec![],\n");
+            out.push_str("            },\n");
+        }
+        out.push_str("        ]\n");
+    }
+    out.push_str("    }\n\n");
+
+    out.push_str(
+        "    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {\n",
+    );
+    out.push_str("        match method {\n");
+    for name in methods.keys() {
+        writeln!(
+            out,
+            "            \"{}\" => Some({}::{}),",
+            name,
+            builtin,
+            stub_name(name)
+        )
+        .unwrap();
+    }
+    out.push_str("            _ => None,\n");
+    out.push_str("        }\n");
+    out.push_str("    }\n\n");
+
+    // No overload is claimed until it is implemented, so that `check` does
+    // not report the stubs as supported
+    out.push_str("    /// Opt overloads in as they are implemented, such as with\n");
+    out.push_str("    /// `matches!((method, descriptor), (\"name\", \"()V\"))`\n");
+    out.push_str("    fn has_overload(&self, _method: &str, _descriptor: &str) -> bool {\n");
+    out.push_str("        false\n");
+    out.push_str("    }\n");
+    out.push_str("}\n");
+
+    if !methods.is_empty() {
+        writeln!(out, "\nimpl {} {{", builtin).unwrap();
+        for (i, (name, overloads)) in methods.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            for method in overloads {
+                let kind = if MethodFlag::Static.is_set(method.flags) {
+                    "static "
+                } else {
+                    ""
+                };
+                writeln!(out, "    /// {}{}{}", kind, name, method.descriptor).unwrap();
+            }
+            writeln!(
+                out,
+                "    fn {}(_: &mut VM, _: Vec<JvmVal>) -> JvmVal {{",
+                stub_name(name)
+            )
+            .unwrap();
+            writeln!(out, "        unimplemented!(\"{}.{}\")", class.name, name).unwrap();
+            out.push_str("    }\n");
+        }
+        out.push_str("}\n");
+    }
+
+    (module, out)
+}
+
+fn stub_name(method: &str) -> String {
+    let name = match method {
+        "<init>" => "init".to_string(),
+        _ => snake_case(method),
+    };
+    // Java method names that are Rust keywords
+    match name.as_str() {
+        "as" | "box" | "break" | "const" | "continue" | "crate" | "else" | "enum" | "extern"
+        | "fn" | "for" | "if" | "impl" | "in" | "let" | "loop" | "match" | "mod" | "move"
+        | "mut" | "pub" | "ref" | "return" | "self" | "static" | "struct" | "super" | "trait"
+        | "type" | "unsafe" | "use" | "where" | "while" | "yield" => format!("{}_", name),
+        _ => name,
+    }
+}
+
+/// `ArrayList` as `array_list` and `URLDecoder` as `url_decoder`
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let after_lower = !chars[i - 1].is_uppercase() && chars[i - 1] != '_';
+            let ends_acronym = chars[i - 1].is_uppercase()
+                && matches!(chars.get(i + 1), Some(next) if next.is_lowercase());
+            if after_lower || ends_acronym {
+                out.push('_');
+            }
+        }
+        out.extend(c.to_lowercase());
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use jjvm_loader::const_pool::ConstPool;
+
+    use super::*;
+
+    fn member(flags: u16, name: &str, descriptor: &str) -> Field {
+        Field {
+            flags,
+            name: name.to_string(),
+            descriptor: descriptor.to_string(),
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let class = Class {
+            major: 52,
+            minor: 0,
+            const_pool: ConstPool { consts: vec![] },
+            name: "java/net/URLDecoder".to_string(),
+            superclass: "java/lang/Object".to_string(),
+            flags: 0x0021,
+            interfaces: vec![],
+            fields: vec![member(0x0019, "MAX", "I"), member(0x0002, "hidden", "I")],
+            methods: vec![
+                member(0x0001, "<init>", "()V"),
+                member(0x0009, "decode", "(Ljava/lang/String;)Ljava/lang/String;"),
+                member(0x0009, "decode", "(Ljava/lang/String;I)Ljava/lang/String;"),
+                member(0x0001, "match", "()Z"),
+                member(0x0002, "helper", "()V"),
+                member(0x0009, "<clinit>", "()V"),
+            ],
+            attributes: vec![],
+        };
+
+        let (module, source) = generate(&class);
+        assert_eq!(module, "url_decoder");
+        assert!(source.contains("pub struct URLDecoderClass {}"));
+        assert!(source.contains("\"java/net/URLDecoder\".to_string()"));
+        assert!(source.contains("name: \"MAX\".to_string(),"));
+        assert!(!source.contains("hidden"));
+
+        // Overloads share a stub, and only public methods other than
+        // <clinit> get one
+        assert!(source.contains("\"<init>\" => Some(URLDecoderClass::init),"));
+        assert!(source.contains("\"decode\" => Some(URLDecoderClass::decode),"));
+        assert!(source.contains("\"match\" => Some(URLDecoderClass::match_),"));
+        assert_eq!(source.matches("fn decode(").count(), 1);
+        assert!(source.contains("    /// static decode(Ljava/lang/String;I)Ljava/lang/String;\n"));
+        assert!(!source.contains("helper"));
+        assert!(!source.contains("clinit"));
+
+        // No stub is claimed as implemented
+        assert!(source.contains(
+            "fn has_overload(&self, _method: &str, _descriptor: &str) -> bool {\n        false\n"
+        ));
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(snake_case("ArrayList"), "array_list");
+        assert_eq!(snake_case("URLDecoder"), "url_decoder");
+        assert_eq!(snake_case("hashCode"), "hash_code");
+        assert_eq!(snake_case("getURL"), "get_url");
+        assert_eq!(stub_name("<init>"), "init");
+        assert_eq!(stub_name("toString"), "to_string");
+        assert_eq!(stub_name("match"), "match_");
+        assert_eq!(stub_name("type"), "type_");
+    }
+}
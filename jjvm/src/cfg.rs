@@ -0,0 +1,75 @@
+use std::{fs, io::Cursor};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{attribute::Code, cfg::ControlFlowGraph, class_loader::ClassLoader};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("cfg")
+        .about("Exports the control-flow graph of a method in Graphviz DOT format")
+        .arg(
+            Arg::with_name("CLASS")
+                .help("The class file containing the method")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("METHOD")
+                .help("The method name, optionally followed by its descriptor")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Writes the graph to a file instead of stdout"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let file = matches.value_of("CLASS").unwrap();
+    let method_name = matches.value_of("METHOD").unwrap();
+
+    let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+    let class = ClassLoader::new(Cursor::new(bytes))
+        .load()
+        .map_err(|e| format!("{}: {}", file, e))?;
+
+    let methods: Vec<_> = class
+        .methods
+        .iter()
+        .filter(|m| m.name == method_name || format!("{}{}", m.name, m.descriptor) == method_name)
+        .collect();
+    let method = match methods.as_slice() {
+        [] => return Err(format!("{} has no method {}", class.name, method_name)),
+        [method] => method,
+        _ => {
+            let overloads: Vec<String> = methods
+                .iter()
+                .map(|m| format!("{}{}", m.name, m.descriptor))
+                .collect();
+            return Err(format!(
+                "{} is overloaded, pick one of: {}",
+                method_name,
+                overloads.join(", ")
+            ));
+        }
+    };
+
+    let code = match Code::from_method(method, &class.const_pool)? {
+        Some(code) => code,
+        None => return Err(format!("{}.{} has no code", class.name, method.name)),
+    };
+    let cfg = ControlFlowGraph::build(&code)?;
+    let dot = cfg.to_dot(&format!(
+        "{}.{}{}",
+        class.name, method.name, method.descriptor
+    ));
+
+    match matches.value_of("output") {
+        Some(output) => fs::write(output, dot).map_err(|e| format!("{}: {}", output, e)),
+        None => {
+            print!("{}", dot);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{
+    attribute::Code,
+    class::Class,
+    classpath,
+    instruction::{self, Operand},
+    opcode::Opcode,
+};
+use jjvm_vm::{stdlib, vm};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("check")
+        .about("Lists the opcodes, classes and methods used that jjvm cannot run")
+        .arg(
+            Arg::with_name("PATH")
+                .help("Class files, jars, jmods or directories to check")
+                .required(true)
+                .multiple(true),
+        )
+}
+
+/// What the scanned classes use that the VM does not support, each with the
+/// methods using it
+#[derive(Default)]
+struct Report {
+    opcodes: BTreeMap<String, BTreeSet<String>>,
+    classes: BTreeMap<String, BTreeSet<String>>,
+    methods: BTreeMap<String, BTreeSet<String>>,
+    errors: Vec<String>,
+}
+
+impl Report {
+    fn is_empty(self: &Report) -> bool {
+        self.opcodes.is_empty() && self.classes.is_empty() && self.methods.is_empty()
+    }
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let paths: Vec<&str> = matches.values_of("PATH").unwrap().collect();
+    let mut classes = classpath::load_paths(&paths)?;
+    classes.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let given: BTreeMap<&str, &Class> = classes
+        .iter()
+        .map(|class| (class.name.as_str(), class))
+        .collect();
+    let mut report = Report::default();
+    for class in &classes {
+        check_class(class, &given, &mut report);
+    }
+
+    print_section("Unsupported opcodes", &report.opcodes);
+    print_section("Missing classes", &report.classes);
+    print_section("Missing methods", &report.methods);
+    for error in &report.errors {
+        println!("could not check {}", error);
+    }
+
+    if report.is_empty() {
+        println!("{} classes checked, everything is supported", classes.len());
+        Ok(())
+    } else {
+        Err(format!(
+            "{} unsupported opcodes, {} missing classes and {} missing methods",
+            report.opcodes.len(),
+            report.classes.len(),
+            report.methods.len()
+        ))
+    }
+}
+
+fn check_class(class: &Class, given: &BTreeMap<&str, &Class>, report: &mut Report) {
+    let pool = &class.const_pool;
+    // A class the VM cannot find is reported once, not for each member
+    let missing_class = |name: &str| !given.contains_key(name) && !stdlib::is_builtin(name);
+
+    for method in &class.methods {
+        let user = format!("{}.{}{}", class.name, method.name, method.descriptor);
+        let instructions = match Code::from_method(method, pool)
+            .and_then(|code| code.map_or(Ok(vec![]), |code| instruction::decode(&code.code)))
+        {
+            Ok(instructions) => instructions,
+            Err(err) => {
+                report.errors.push(format!("{}: {}", user, err));
+                continue;
+            }
+        };
+
+        for ins in instructions {
+            if !vm::supports_opcode(ins.opcode) {
+                let name = format!("{:?}", ins.opcode).to_lowercase();
+                add(&mut report.opcodes, &name, &user);
+            }
+            if ins.wide && !vm::supports_opcode(Opcode::Wide) {
+                add(&mut report.opcodes, "wide", &user);
+            }
+
+            let index = match ins.operand {
+                Operand::Constant(index)
+                | Operand::InvokeInterface(index, _)
+                | Operand::MultiArray(index, _) => index,
+                _ => continue,
+            };
+            match ins.opcode {
+                Opcode::New
+                | Opcode::ANewArray
+                | Opcode::MultiANewArray
+                | Opcode::CheckCast
+                | Opcode::InstanceOf => {
+                    // Array classes are described by their element type
+                    let name = match pool.class_name(index) {
+                        Some(name) => name.trim_start_matches('['),
+                        None => continue,
+                    };
+                    let name = name
+                        .strip_prefix('L')
+                        .and_then(|n| n.strip_suffix(';'))
+                        .unwrap_or(name);
+                    if name.len() > 1 && missing_class(name) {
+                        add(&mut report.classes, name, &user);
+                    }
+                }
+                Opcode::GetStatic | Opcode::PutStatic | Opcode::GetField | Opcode::PutField => {
+                    if let Some((owner, _, _)) = pool.member_ref(index) {
+                        if missing_class(owner) {
+                            add(&mut report.classes, owner, &user);
+                        }
+                    }
+                }
+                Opcode::InvokeVirtual
+                | Opcode::InvokeSpecial
+                | Opcode::InvokeStatic
+                | Opcode::InvokeInterface => {
+                    let (owner, name, descriptor) = match pool.member_ref(index) {
+                        Some(member) => member,
+                        None => continue,
+                    };
+                    // Methods of the given classes may be inherited from a builtin
+                    let owner = match inherited_from(given, owner, name, descriptor) {
+                        Some(owner) => owner,
+                        None => continue,
+                    };
+                    if missing_class(owner) {
+                        add(&mut report.classes, owner, &user);
+                    } else if !stdlib::has_method(owner, name, descriptor) {
+                        let method = format!("{}.{}{}", owner, name, descriptor);
+                        add(&mut report.methods, &method, &user);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The first class outside the given ones that `owner` and its superclasses
+/// reach without declaring the method, or `None` when one of them or their
+/// interfaces declares it
+fn inherited_from<'a>(
+    given: &BTreeMap<&str, &'a Class>,
+    owner: &'a str,
+    name: &str,
+    descriptor: &str,
+) -> Option<&'a str> {
+    let mut current = owner;
+    while let Some(class) = given.get(current) {
+        if declares(given, class, name, descriptor) {
+            return None;
+        }
+        current = &class.superclass;
+    }
+
+    Some(current)
+}
+
+fn declares(given: &BTreeMap<&str, &Class>, class: &Class, name: &str, descriptor: &str) -> bool {
+    class
+        .methods
+        .iter()
+        .any(|method| method.name == name && method.descriptor == descriptor)
+        || class.interfaces.iter().any(|interface| {
+            matches!(given.get(interface.as_str()),
+                Some(interface) if declares(given, interface, name, descriptor))
+        })
+}
+
+fn add(items: &mut BTreeMap<String, BTreeSet<String>>, item: &str, user: &str) {
+    items
+        .entry(item.to_string())
+        .or_default()
+        .insert(user.to_string());
+}
+
+fn print_section(title: &str, items: &BTreeMap<String, BTreeSet<String>>) {
+    if items.is_empty() {
+        return;
+    }
+
+    println!("{}: {}", title, items.len());
+    for (item, users) in items {
+        println!("  {}", item);
+        for user in users {
+            println!("    used by {}", user);
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod test {
+    use jjvm_loader::{class::Field, const_pool::ConstPool, jvm_const::JvmConst};
+
+    use super::*;
+
+    #[test]
+    fn test_check_class() {
+        let utf8 = |val: &str| JvmConst::UTF8(val.to_string());
+        let mut const_pool = ConstPool {
+            consts: vec![
+                utf8("java/util/ArrayList"),
+                JvmConst::Class(1),
+                utf8("<init>"),
+                utf8("()V"),
+                JvmConst::NameAndType(3, 4),
+                JvmConst::MethodRef(2, 5),
+                utf8("java/lang/Integer"),
+                JvmConst::Class(7),
+                utf8("valueOf"),
+                utf8("(I)Ljava/lang/Integer;"),
+                JvmConst::NameAndType(9, 10),
+                JvmConst::MethodRef(8, 11),
+                utf8("Test"),
+                JvmConst::Class(13),
+                utf8("hashCode"),
+                utf8("()I"),
+                JvmConst::NameAndType(15, 16),
+                JvmConst::MethodRef(14, 17),
+            ],
+        };
+        // new ArrayList; dup; invokespecial ArrayList.<init>; monitorenter;
+        // iconst_1; invokestatic Integer.valueOf(I); pop; aconst_null;
+        // invokevirtual Test.hashCode; pop; return
+        let code = Code {
+            max_stack: 2,
+            max_locals: 0,
+            code: vec![
+                0xbb, 0x00, 0x02, 0x59, 0xb7, 0x00, 0x06, 0xc2, 0x04, 0xb8, 0x00, 0x0c, 0x57, 0x01,
+                0xb6, 0x00, 0x12, 0x57, 0xb1,
+            ],
+            exception_table: vec![],
+            attributes: vec![],
+        };
+        let attribute = code.to_attribute(&mut const_pool);
+        let class = Class {
+            major: 52,
+            minor: 0,
+            const_pool,
+            name: "Test".to_string(),
+            superclass: "java/lang/Object".to_string(),
+            flags: 0x0021,
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![Field {
+                flags: 0x0009,
+                name: "test".to_string(),
+                descriptor: "()V".to_string(),
+                attributes: vec![attribute],
+            }],
+            attributes: vec![],
+        };
+
+        let mut given = BTreeMap::new();
+        given.insert("Test", &class);
+        let mut report = Report::default();
+        check_class(&class, &given, &mut report);
+
+        let names = |items: &BTreeMap<String, BTreeSet<String>>| -> Vec<String> {
+            items.keys().cloned().collect()
+        };
+        assert_eq!(names(&report.opcodes), ["monitorenter"]);
+        assert_eq!(names(&report.classes), ["java/util/ArrayList"]);
+        // hashCode is inherited by Test from Object, which does not have it
+        assert_eq!(
+            names(&report.methods),
+            [
+                "java/lang/Integer.valueOf(I)Ljava/lang/Integer;",
+                "java/lang/Object.hashCode()I"
+            ]
+        );
+        assert!(report.methods["java/lang/Object.hashCode()I"].contains("Test.test()V"));
+    }
+}
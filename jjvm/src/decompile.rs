@@ -0,0 +1,62 @@
+use std::{fs, io::Cursor};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{class_loader::ClassLoader, decompiler};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("decompile")
+        .about("Prints a class file as Java-like source")
+        .arg(
+            Arg::with_name("CLASS")
+                .help("The class file to decompile")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("method")
+                .short("m")
+                .long("method")
+                .takes_value(true)
+                .help("Only prints methods with this name, optionally followed by a descriptor"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Writes the source to a file instead of stdout"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let file = matches.value_of("CLASS").unwrap();
+    let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+    let class = ClassLoader::new(Cursor::new(bytes))
+        .load()
+        .map_err(|e| format!("{}: {}", file, e))?;
+
+    let source = match matches.value_of("method") {
+        Some(method_name) => {
+            let methods: Vec<String> = class
+                .methods
+                .iter()
+                .filter(|m| {
+                    m.name == method_name || format!("{}{}", m.name, m.descriptor) == method_name
+                })
+                .map(|m| decompiler::method_source(&class, m))
+                .collect();
+            if methods.is_empty() {
+                return Err(format!("{} has no method {}", class.name, method_name));
+            }
+            methods.join("\n")
+        }
+        None => decompiler::decompile_class(&class),
+    };
+
+    match matches.value_of("output") {
+        Some(output) => fs::write(output, source).map_err(|e| format!("{}: {}", output, e)),
+        None => {
+            print!("{}", source);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{class::Class, classpath, deps};
+use jjvm_vm::stdlib;
+use serde_json::{json, Value};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("deps")
+        .about("Lists the packages and classes each class depends on")
+        .arg(
+            Arg::with_name("PATH")
+                .help("Class files, jars, jmods or directories to inspect")
+                .required(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .short("f")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("packages")
+                .long("packages")
+                .short("p")
+                .help("Only lists the packages each class depends on"),
+        )
+}
+
+/// Classes loaded from one path, with the dependencies of each
+struct Source {
+    path: String,
+    classes: Vec<(Class, BTreeSet<String>)>,
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let mut sources = vec![];
+    for path in matches.values_of("PATH").unwrap() {
+        let mut classes: Vec<(Class, BTreeSet<String>)> = classpath::load(path)?
+            .into_iter()
+            .map(|class| {
+                let deps = deps::dependencies(&class);
+                (class, deps)
+            })
+            .collect();
+        classes.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        sources.push(Source {
+            path: path.to_string(),
+            classes,
+        });
+    }
+
+    // Dependencies on classes that were not given and that the VM does not
+    // provide itself
+    let given: BTreeSet<&str> = sources
+        .iter()
+        .flat_map(|s| s.classes.iter().map(|(c, _)| c.name.as_str()))
+        .collect();
+    let unsupported: BTreeSet<String> = sources
+        .iter()
+        .flat_map(|s| s.classes.iter().flat_map(|(_, deps)| deps.iter()))
+        .filter(|name| !given.contains(name.as_str()) && !stdlib::is_builtin(name))
+        .cloned()
+        .collect();
+
+    let packages_only = matches.is_present("packages");
+    match matches.value_of("format") {
+        Some("json") => println!(
+            "{}",
+            serde_json::to_string_pretty(&to_json(&sources, &unsupported, packages_only)).unwrap()
+        ),
+        _ => print_text(&sources, &unsupported, packages_only),
+    }
+
+    Ok(())
+}
+
+fn print_text(sources: &[Source], unsupported: &BTreeSet<String>, packages_only: bool) {
+    for source in sources {
+        println!("{}", source.path);
+        let mut used = BTreeSet::new();
+        for (class, deps) in &source.classes {
+            let packages = deps::by_package(deps);
+            used.extend(packages.keys().cloned());
+
+            if packages_only {
+                let names: Vec<&str> = packages.keys().map(|p| display_package(p)).collect();
+                println!("  {} -> {}", class.name, names.join(", "));
+                continue;
+            }
+
+            println!("  {}", class.name);
+            for (package, classes) in &packages {
+                let names: Vec<&str> = classes
+                    .iter()
+                    .map(|c| &c[package.len()..])
+                    .map(|c| c.trim_start_matches('/'))
+                    .collect();
+                println!("    {}: {}", display_package(package), names.join(", "));
+            }
+        }
+
+        let used: Vec<&str> = used.iter().map(|p| display_package(p)).collect();
+        println!("  packages: {}", used.join(", "));
+        println!();
+    }
+
+    println!("Not provided by jjvm: {}", unsupported.len());
+    for name in unsupported {
+        println!("  {}", name);
+    }
+}
+
+fn to_json(sources: &[Source], unsupported: &BTreeSet<String>, packages_only: bool) -> Value {
+    let sources: Vec<Value> = sources
+        .iter()
+        .map(|source| {
+            let mut used = BTreeSet::new();
+            let classes: Vec<Value> = source
+                .classes
+                .iter()
+                .map(|(class, deps)| {
+                    let packages = deps::by_package(deps);
+                    used.extend(packages.keys().cloned());
+                    if packages_only {
+                        json!({ "name": class.name, "packages": packages.keys().collect::<Vec<_>>() })
+                    } else {
+                        let packages: BTreeMap<&String, &BTreeSet<String>> = packages.iter().collect();
+                        json!({ "name": class.name, "dependencies": packages })
+                    }
+                })
+                .collect();
+            json!({ "path": source.path, "classes": classes, "packages": used })
+        })
+        .collect();
+
+    json!({ "sources": sources, "unsupported": unsupported })
+}
+
+fn display_package(package: &str) -> &str {
+    if package.is_empty() {
+        "(unnamed)"
+    } else {
+        package
+    }
+}
@@ -0,0 +1,42 @@
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{classpath, diff};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("diff")
+        .about("Compares the API of two versions of a class, jar or directory")
+        .arg(Arg::with_name("OLD").help("The old version").required(true))
+        .arg(Arg::with_name("NEW").help("The new version").required(true))
+        .arg(
+            Arg::with_name("incompatible")
+                .long("incompatible")
+                .short("i")
+                .help("Only lists changes that break binary compatibility"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let old = classpath::load_all(&[matches.value_of("OLD").unwrap()])?;
+    let new = classpath::load_all(&[matches.value_of("NEW").unwrap()])?;
+
+    let changes = diff::diff_classes(&old, &new);
+    let incompatible = changes.iter().filter(|c| !c.is_compatible()).count();
+
+    for change in &changes {
+        if change.is_compatible() {
+            if !matches.is_present("incompatible") {
+                println!("  {}", change);
+            }
+        } else {
+            println!("! {}", change);
+        }
+    }
+
+    println!();
+    println!(
+        "{} changes, {} binary incompatible",
+        changes.len(),
+        incompatible
+    );
+
+    Ok(())
+}
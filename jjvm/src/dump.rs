@@ -0,0 +1,51 @@
+use std::{fs, io::Cursor};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::class_loader::ClassLoader;
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("dump")
+        .about("Dumps the parsed form of a class file as JSON or YAML")
+        .arg(
+            Arg::with_name("CLASS")
+                .help("The class file to dump")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .short("f")
+                .takes_value(true)
+                .possible_values(&["json", "yaml"])
+                .default_value("json")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Writes the dump to a file instead of stdout"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let file = matches.value_of("CLASS").unwrap();
+    let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+    let class = ClassLoader::new(Cursor::new(bytes))
+        .load()
+        .map_err(|e| format!("{}: {}", file, e))?;
+
+    let dump = match matches.value_of("format") {
+        Some("yaml") => serde_yaml::to_string(&class).map_err(|e| e.to_string())?,
+        _ => serde_json::to_string_pretty(&class).map_err(|e| e.to_string())? + "\n",
+    };
+
+    match matches.value_of("output") {
+        Some(output) => fs::write(output, dump).map_err(|e| format!("{}: {}", output, e)),
+        None => {
+            print!("{}", dump);
+            Ok(())
+        }
+    }
+}
@@ -1,21 +1,29 @@
-use std::{
-    collections::HashMap,
-    fs,
-    io::{self, Cursor},
-};
+use std::{collections::HashMap, io, process};
 
-use clap::{crate_authors, crate_version, App, Arg};
-use jjvm_loader::class_loader::ClassLoader;
-use jjvm_vm::{frame::Frame, heap::Heap, vm::VM};
+use clap::{crate_authors, crate_version, App, AppSettings, Arg};
+use jjvm_loader::classpath;
+use jjvm_vm::{heap::Heap, stdlib, vm::VM};
+
+mod analyze;
+mod archive;
+mod builtin;
+mod cfg;
+mod check;
+mod decompile;
+mod deps;
+mod diff;
+mod dump;
+mod shrink;
 
 fn main() {
     let matches = App::new("jjvm")
         .version(crate_version!())
         .author(crate_authors!())
         .about("Rust-based JVM")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("INPUT")
-                .help("The input file to use")
+                .help("The class files, jars or directories to run")
                 .required(true)
                 .multiple(true),
         )
@@ -25,18 +33,88 @@ fn main() {
                 .long("debug")
                 .help("VM prints out each step"),
         )
+        .arg(
+            Arg::with_name("archive")
+                .long("archive")
+                .takes_value(true)
+                .help(
+                "Loads the classes from this archive, rebuilding it when the inputs have changed",
+            ),
+        )
+        .arg(
+            Arg::with_name("verify")
+                .long("verify")
+                .help("Verifies the bytecode of every class before running"),
+        )
+        .subcommand(analyze::subcommand())
+        .subcommand(archive::subcommand())
+        .subcommand(builtin::subcommand())
+        .subcommand(cfg::subcommand())
+        .subcommand(check::subcommand())
+        .subcommand(decompile::subcommand())
+        .subcommand(deps::subcommand())
+        .subcommand(diff::subcommand())
+        .subcommand(dump::subcommand())
+        .subcommand(shrink::subcommand())
         .get_matches();
 
+    let result = match matches.subcommand() {
+        ("analyze", Some(matches)) => Some(analyze::run(matches)),
+        ("archive", Some(matches)) => Some(archive::run(matches)),
+        ("builtin", Some(matches)) => Some(builtin::run(matches)),
+        ("cfg", Some(matches)) => Some(cfg::run(matches)),
+        ("check", Some(matches)) => Some(check::run(matches)),
+        ("decompile", Some(matches)) => Some(decompile::run(matches)),
+        ("deps", Some(matches)) => Some(deps::run(matches)),
+        ("diff", Some(matches)) => Some(diff::run(matches)),
+        ("dump", Some(matches)) => Some(dump::run(matches)),
+        ("shrink", Some(matches)) => Some(shrink::run(matches)),
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
     let input_files = matches.values_of("INPUT").unwrap().collect::<Vec<_>>();
 
-    run_files(input_files, matches.is_present("debug")).unwrap();
+    run_files(
+        input_files,
+        matches.value_of("archive"),
+        matches.is_present("debug"),
+        matches.is_present("verify"),
+    )
+    .unwrap();
 }
 
-fn run_files(input_files: Vec<&str>, debug: bool) -> Result<(), io::Error> {
+fn run_files(
+    input_files: Vec<&str>,
+    archive: Option<&str>,
+    debug: bool,
+    verify: bool,
+) -> Result<(), io::Error> {
+    let error = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut code = HashMap::new();
+    let mut constants = HashMap::new();
+    let loaded = match archive {
+        Some(archive) => jjvm_loader::archive::load_cached(&input_files, archive)
+            .map_err(error)?
+            .into_iter()
+            .map(|archived| {
+                code.insert(archived.class.name.clone(), archived.code);
+                constants.insert(archived.class.name.clone(), archived.constants);
+                archived.class
+            })
+            .collect(),
+        None => classpath::load_paths(&input_files).map_err(error)?,
+    };
+
     let mut classes = HashMap::new();
     let mut main_class = None;
-    for file in input_files {
-        let class = ClassLoader::new(Cursor::new(fs::read(file)?)).load();
+    for class in loaded {
         classes.insert(class.name.clone(), class.clone());
         if class.methods.iter().any(|f| f.name == "main") {
             main_class = Some(class.clone());
@@ -50,8 +128,20 @@ fn run_files(input_files: Vec<&str>, debug: bool) -> Result<(), io::Error> {
         heap_last_gc_size: 4,
         should_gc: false,
         debug,
+        exception: None,
+        code,
+        constants,
     };
 
+    if verify {
+        for class in vm.classes.values() {
+            if let Err(e) = vm.link(class) {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
     // let main_class = vm
     //     .classes
     //     .values()
@@ -59,14 +149,22 @@ fn run_files(input_files: Vec<&str>, debug: bool) -> Result<(), io::Error> {
     //     .clone()
     //     .expect("Could not find main class");
 
-    let mut main_frame = Frame::from_method(
-        &main_class.clone().expect("No main class"),
-        "main".to_string(),
-        vec![],
-    )
-    .unwrap();
+    let mut main_frame = vm
+        .frame(
+            &main_class.clone().expect("No main class"),
+            "main".to_string(),
+            vec![],
+        )
+        .unwrap();
 
     vm.exec(&main_class.unwrap(), &mut main_frame);
+    if let Some(exception) = vm.exception {
+        eprintln!(
+            "Exception in thread \"main\" {}",
+            stdlib::throwable::describe(&mut vm, exception)
+        );
+        process::exit(1);
+    }
 
     Ok(())
 }
@@ -0,0 +1,134 @@
+use std::{collections::HashMap, fs};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use jjvm_loader::{
+    class::Class,
+    classpath,
+    hierarchy::{self, Hierarchy, MemberRef},
+    shrink::{self, KeepRules, Reachable},
+};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("shrink")
+        .about("Writes a jar holding only the classes, methods and fields reachable from the entry points")
+        .arg(
+            Arg::with_name("INPUT")
+                .help("The jar, directory or class file to shrink")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .required(true)
+                .help("The jar to write"),
+        )
+        .arg(
+            Arg::with_name("main")
+                .long("main")
+                .takes_value(true)
+                .help("Class whose main method is the entry point, instead of the manifest's Main-Class"),
+        )
+        .arg(
+            Arg::with_name("keep")
+                .long("keep")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("File of keep rules, one class or package pattern such as com/example/** per line"),
+        )
+        .arg(
+            Arg::with_name("keep-class")
+                .long("keep-class")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("A keep rule given directly"),
+        )
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let input = matches.value_of("INPUT").unwrap();
+    let classes: HashMap<String, Class> = classpath::load(input)?
+        .into_iter()
+        .map(|class| (class.name.clone(), class))
+        .collect();
+    // Signatures no longer match once classes are removed
+    let resources: Vec<(String, Vec<u8>)> = classpath::resources(input)?
+        .into_iter()
+        .filter(|(name, _)| !is_signature(name))
+        .collect();
+
+    let mut text = String::new();
+    for file in matches.values_of("keep").into_iter().flatten() {
+        text.push_str(&fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?);
+        text.push('\n');
+    }
+    for pattern in matches.values_of("keep-class").into_iter().flatten() {
+        text.push_str(pattern);
+        text.push('\n');
+    }
+    let keep = KeepRules::parse(&text);
+
+    let main = matches
+        .value_of("main")
+        .map(|name| name.to_string())
+        .or_else(|| main_class(&resources));
+    let entries = match main {
+        Some(main) => {
+            let main = main.replace('.', "/");
+            if !classes.contains_key(&main) {
+                return Err(format!("{} has no class {}", input, main));
+            }
+            vec![MemberRef::new(&main, "main", "([Ljava/lang/String;)V")]
+        }
+        None => hierarchy::main_methods(&classes.values().collect::<Vec<_>>()),
+    };
+    if entries.is_empty() && keep.patterns.is_empty() {
+        return Err("no entry points: give --main or keep rules".to_string());
+    }
+
+    let hierarchy = Hierarchy::new(&classes);
+    let reachable = Reachable::find(&hierarchy, &entries, &keep);
+    let shrunk = shrink::shrink(&classes, &reachable);
+
+    let output = matches.value_of("output").unwrap();
+    let file = fs::File::create(output).map_err(|e| format!("{}: {}", output, e))?;
+    shrink::write_jar(file, &shrunk, &resources).map_err(|e| format!("{}: {}", output, e))?;
+
+    let count = |f: fn(&Class) -> usize| classes.values().map(f).sum::<usize>();
+    println!(
+        "Kept {} of {} classes, {} of {} methods and {} of {} fields",
+        reachable.classes.len(),
+        classes.len(),
+        reachable.methods.len(),
+        count(|c| c.methods.len()),
+        reachable.fields.len(),
+        count(|c| c.fields.len()),
+    );
+
+    Ok(())
+}
+
+/// The Main-Class named by a jar's manifest
+fn main_class(resources: &[(String, Vec<u8>)]) -> Option<String> {
+    let (_, manifest) = resources
+        .iter()
+        .find(|(name, _)| name == "META-INF/MANIFEST.MF")?;
+    // Long values continue on lines starting with a space
+    let text = String::from_utf8_lossy(manifest)
+        .replace("\r\n", "\n")
+        .replace("\n ", "");
+    text.lines()
+        .find_map(|line| line.strip_prefix("Main-Class:"))
+        .map(|name| name.trim().to_string())
+}
+
+fn is_signature(name: &str) -> bool {
+    name.starts_with("META-INF/")
+        && (name.ends_with(".SF")
+            || name.ends_with(".RSA")
+            || name.ends_with(".DSA")
+            || name.ends_with(".EC"))
+}
@@ -0,0 +1,246 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::attribute::Code;
+use crate::class::Class;
+use crate::classpath;
+use crate::const_pool::Const;
+use crate::jvm_const::JvmConst;
+
+const MAGIC: &[u8; 4] = b"JJVA";
+/// Bumped whenever the archived types change, so older archives are rebuilt
+/// instead of misread
+const VERSION: u32 = 1;
+
+/// A file the archived classes were loaded from, as it was when archived
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Source {
+    pub fn of<P: AsRef<Path>>(path: P) -> Result<Source, String> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Source {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// A class along with the parts of it that are otherwise decoded on use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedClass {
+    pub class: Class,
+    /// The Code attribute of each method, in the order of `class.methods`
+    pub code: Vec<Option<Code>>,
+    /// The value of each constant `ldc` and `ldc2_w` can load, indexed like
+    /// the constant pool from 1. References are left to the pool, since resolving them
+    /// copies their names into every use and makes the archive slower to
+    /// read than the class files.
+    pub constants: Vec<Option<Const>>,
+}
+
+impl ArchivedClass {
+    pub fn new(class: Class) -> Result<ArchivedClass, String> {
+        let code = class
+            .methods
+            .iter()
+            .map(|method| Code::from_method(method, &class.const_pool))
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("{}: {}", class.name, e))?;
+        let constants = (1..=class.const_pool.consts.len() as u16)
+            .map(|index| match class.const_pool.get(index) {
+                Some(JvmConst::String(_))
+                | Some(JvmConst::Integer(_))
+                | Some(JvmConst::Float(_))
+                | Some(JvmConst::Long(_, _))
+                | Some(JvmConst::Double(_, _)) => class.const_pool.resolve(index).ok(),
+                _ => None,
+            })
+            .collect();
+
+        Ok(ArchivedClass {
+            class,
+            code,
+            constants,
+        })
+    }
+}
+
+/// Parsed classes saved together so that later runs can skip parsing them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub sources: Vec<Source>,
+    pub classes: Vec<ArchivedClass>,
+}
+
+impl Archive {
+    /// Loads the classes at each path, as `classpath::load` does
+    pub fn create<P: AsRef<Path>>(paths: &[P]) -> Result<Archive, String> {
+        let classes = classpath::load_paths(paths)?
+            .into_par_iter()
+            .map(ArchivedClass::new)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+
+        Ok(Archive {
+            sources: sources(paths)?,
+            classes,
+        })
+    }
+
+    pub fn read<R: Read>(mut input: R) -> Result<Archive, String> {
+        let mut header = [0; 8];
+        input
+            .read_exact(&mut header)
+            .map_err(|_| "not a class archive".to_string())?;
+        if &header[..4] != MAGIC {
+            return Err("not a class archive".to_string());
+        }
+        let version = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        if version != VERSION {
+            return Err(format!(
+                "archive version {} is not the supported version {}",
+                version, VERSION
+            ));
+        }
+
+        bincode::deserialize_from(input).map_err(|e| e.to_string())
+    }
+
+    pub fn write<W: Write>(self: &Archive, mut out: W) -> Result<(), String> {
+        out.write_all(MAGIC).map_err(|e| e.to_string())?;
+        out.write_all(&VERSION.to_be_bytes())
+            .map_err(|e| e.to_string())?;
+        bincode::serialize_into(out, self).map_err(|e| e.to_string())
+    }
+
+    /// Reads an archive file in one go
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Archive, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Archive::read(&bytes[..]).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save<P: AsRef<Path>>(self: &Archive, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut bytes = vec![];
+        self.write(&mut bytes)?;
+        fs::write(path, bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Whether the archive was made from these paths and none of their
+    /// files have since been added, removed or changed
+    pub fn is_current<P: AsRef<Path>>(self: &Archive, paths: &[P]) -> bool {
+        matches!(sources(paths), Ok(sources) if sources == self.sources)
+    }
+}
+
+/// Loads the classes at each path from the archive when it is current, and
+/// otherwise loads them normally and rewrites the archive for next time. The
+/// archive is only a cache, so failing to write it still returns the classes.
+pub fn load_cached<P: AsRef<Path>, A: AsRef<Path>>(
+    paths: &[P],
+    archive: A,
+) -> Result<Vec<ArchivedClass>, String> {
+    if let Ok(cached) = Archive::open(&archive) {
+        if cached.is_current(paths) {
+            return Ok(cached.classes);
+        }
+    }
+
+    let fresh = Archive::create(paths)?;
+    let _ = fresh.save(&archive);
+    Ok(fresh.classes)
+}
+
+/// Every file `classpath::load` would read for the paths
+fn sources<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<Source>, String> {
+    let mut sources = vec![];
+    for path in paths {
+        add_sources(path.as_ref(), &mut sources)?;
+    }
+
+    Ok(sources)
+}
+
+fn add_sources(path: &Path, sources: &mut Vec<Source>) -> Result<(), String> {
+    if !path.is_dir() {
+        sources.push(Source::of(path)?);
+        return Ok(());
+    }
+
+    let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let mut entries: Vec<_> = fs::read_dir(path)
+        .map_err(error)?
+        .collect::<Result<_, _>>()
+        .map_err(error)?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let child = entry.path();
+        let loaded = ["class", "jar", "zip", "jmod"]
+            .iter()
+            .any(|extension| child.extension() == Some(OsStr::new(extension)));
+        if child.is_dir() || loaded {
+            add_sources(&child, sources)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archive() {
+        let dir = std::env::temp_dir().join(format!("jjvm_archive_{}", std::process::id()));
+        fs::create_dir_all(dir.join("classes")).unwrap();
+        let class_file = dir.join("classes").join("Empty.class");
+        // public class Empty {} with no methods
+        let class: &[u8] = &[
+            0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52, 0, 5, 1, 0, 5, b'E', b'm', b'p', b't', b'y', 7, 0,
+            1, 1, 0, 16, b'j', b'a', b'v', b'a', b'/', b'l', b'a', b'n', b'g', b'/', b'O', b'b',
+            b'j', b'e', b'c', b't', 7, 0, 3, 0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        fs::write(&class_file, class).unwrap();
+        let archive_file = dir.join("classes.jja");
+        let paths = [dir.join("classes")];
+
+        let classes = load_cached(&paths, &archive_file).unwrap();
+        assert_eq!(classes[0].class.name, "Empty");
+        let archive = Archive::open(&archive_file).unwrap();
+        assert!(archive.is_current(&paths));
+        assert_eq!(archive.classes[0].class.superclass, "java/lang/Object");
+        assert!(archive.classes[0].constants[0].is_none());
+
+        // A new file makes the archive stale
+        fs::write(dir.join("classes").join("Other.class"), class).unwrap();
+        assert!(!archive.is_current(&paths));
+        assert_eq!(load_cached(&paths, &archive_file).unwrap().len(), 2);
+        assert!(Archive::open(&archive_file).unwrap().is_current(&paths));
+
+        // An archive that cannot be written does not stop the classes loading
+        let unwritable = dir.join("missing").join("classes.jja");
+        assert_eq!(load_cached(&paths, &unwritable).unwrap().len(), 2);
+        assert!(!unwritable.exists());
+
+        assert!(Archive::read(&b"JJVA\0\0\0\x63"[..]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
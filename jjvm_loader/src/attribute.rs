@@ -0,0 +1,142 @@
+use std::io::Cursor;
+
+use crate::class::{Attribute, Class, Field};
+use crate::class_loader::ClassLoader;
+use crate::class_writer::ClassWriter;
+use crate::const_pool::ConstPool;
+
+/// Decoded form of a method's Code attribute
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Code {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionHandler>,
+    pub attributes: Vec<Attribute>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExceptionHandler {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    /// Constant pool index of the caught class, or 0 to catch everything
+    pub catch_type: u16,
+}
+
+impl Code {
+    pub fn from_attribute(attribute: &Attribute, const_pool: &ConstPool) -> Result<Code, String> {
+        if attribute.data.len() < 8 {
+            return Err("Code attribute is truncated".to_string());
+        }
+
+        let mut loader = ClassLoader::new(Cursor::new(attribute.data.clone()));
+        let max_stack = loader.u2()?;
+        let max_locals = loader.u2()?;
+        let code_length = loader.u4()?;
+        if code_length as usize > attribute.data.len() - 8 {
+            return Err(format!("code length {} overruns attribute", code_length));
+        }
+        let code = loader.read_bytes(code_length as usize)?;
+
+        let handler_count = loader.u2()?;
+        let mut exception_table = vec![];
+        for _ in 0..handler_count {
+            exception_table.push(ExceptionHandler {
+                start_pc: loader.u2()?,
+                end_pc: loader.u2()?,
+                handler_pc: loader.u2()?,
+                catch_type: loader.u2()?,
+            });
+        }
+
+        let attributes = loader.load_attributes(const_pool)?;
+
+        Ok(Code {
+            max_stack,
+            max_locals,
+            code,
+            exception_table,
+            attributes,
+        })
+    }
+
+    /// Decodes the Code attribute of a method, if it has one
+    pub fn from_method(method: &Field, const_pool: &ConstPool) -> Result<Option<Code>, String> {
+        match method.find_attribute("Code") {
+            Some(attribute) => Code::from_attribute(attribute, const_pool).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn find_attribute(self: &Code, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+
+    /// Encodes the code back into a Code attribute
+    pub fn to_attribute(self: &Code, const_pool: &mut ConstPool) -> Attribute {
+        let mut writer = ClassWriter::new();
+        writer.u2(self.max_stack);
+        writer.u2(self.max_locals);
+        writer.u4(self.code.len() as u32);
+        writer.write_bytes(&self.code);
+
+        writer.u2(self.exception_table.len() as u16);
+        for handler in &self.exception_table {
+            writer.u2(handler.start_pc);
+            writer.u2(handler.end_pc);
+            writer.u2(handler.handler_pc);
+            writer.u2(handler.catch_type);
+        }
+
+        writer.write_attributes(&self.attributes, const_pool);
+
+        Attribute {
+            name: "Code".to_string(),
+            data: writer.bytes,
+        }
+    }
+}
+
+/// An entry of a class's BootstrapMethods attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapMethod {
+    /// Constant pool index of the bootstrap method's MethodHandle
+    pub handle: u16,
+    /// Constant pool indexes of the static arguments
+    pub args: Vec<u16>,
+}
+
+impl BootstrapMethod {
+    /// Decodes the BootstrapMethods attribute of a class, stopping at the
+    /// first truncated entry
+    pub fn from_class(class: &Class) -> Vec<BootstrapMethod> {
+        let data = match class
+            .attributes
+            .iter()
+            .find(|attribute| attribute.name == "BootstrapMethods")
+        {
+            Some(attribute) => &attribute.data,
+            None => return vec![],
+        };
+        let u2 = |pos: usize| -> Option<u16> {
+            Some(u16::from_be_bytes([*data.get(pos)?, *data.get(pos + 1)?]))
+        };
+
+        let mut methods = vec![];
+        let mut pos = 2;
+        for _ in 0..u2(0).unwrap_or(0) {
+            let (handle, count) = match (u2(pos), u2(pos + 2)) {
+                (Some(handle), Some(count)) => (handle, count as usize),
+                _ => break,
+            };
+            let args = (0..count).filter_map(|i| u2(pos + 4 + i * 2)).collect();
+            methods.push(BootstrapMethod { handle, args });
+            pos += 4 + count * 2;
+        }
+
+        methods
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::attribute::Code;
+use crate::instruction::{self, Instruction, Operand};
+use crate::opcode::Opcode;
+
+/// A run of instructions that is only entered at its first instruction and
+/// only left after its last
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub start: u32,
+    /// Offset just past the last instruction
+    pub end: u32,
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgeKind {
+    FallThrough,
+    /// A conditional branch being taken
+    Branch,
+    Goto,
+    Case(i32),
+    Default,
+    /// A handler catching an exception thrown in the block, with the constant
+    /// pool index of the caught class or 0 for any
+    Exception(u16),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// A natural loop, made of the blocks that can reach a back edge to the
+/// header without passing through the header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub header: usize,
+    pub blocks: BTreeSet<usize>,
+}
+
+/// Control-flow graph of a method. Block 0 is the entry.
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+impl ControlFlowGraph {
+    /// Splits a method's code into basic blocks. `ret` has no edges as its
+    /// target is only known at run time.
+    pub fn build(code: &Code) -> Result<ControlFlowGraph, String> {
+        let instructions = instruction::decode(&code.code)?;
+        if instructions.is_empty() {
+            return Err("method has no code".to_string());
+        }
+
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for ins in &instructions {
+            let targets = ins.branch_targets();
+            if !targets.is_empty() || !ins.falls_through() {
+                leaders.insert(ins.offset + ins.length);
+            }
+            leaders.extend(targets);
+        }
+        for handler in &code.exception_table {
+            leaders.insert(handler.start_pc as u32);
+            leaders.insert(handler.end_pc as u32);
+            leaders.insert(handler.handler_pc as u32);
+        }
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        for ins in instructions {
+            match blocks.last_mut() {
+                Some(block) if !leaders.contains(&ins.offset) => {
+                    block.end = ins.offset + ins.length;
+                    block.instructions.push(ins);
+                }
+                _ => blocks.push(BasicBlock {
+                    start: ins.offset,
+                    end: ins.offset + ins.length,
+                    instructions: vec![ins],
+                }),
+            }
+        }
+
+        let starts: BTreeMap<u32, usize> = blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| (block.start, i))
+            .collect();
+        let block_at = |offset: u32| match starts.get(&offset) {
+            Some(i) => Ok(*i),
+            None => Err(format!(
+                "offset {} is not the start of an instruction",
+                offset
+            )),
+        };
+
+        let mut edges = vec![];
+        for (i, block) in blocks.iter().enumerate() {
+            let last = block.instructions.last().unwrap();
+            let mut edge = |to: u32, kind: EdgeKind| -> Result<(), String> {
+                edges.push(Edge {
+                    from: i,
+                    to: block_at(to)?,
+                    kind,
+                });
+                Ok(())
+            };
+
+            match &last.operand {
+                Operand::TableSwitch {
+                    default,
+                    low,
+                    targets,
+                    ..
+                } => {
+                    for (key, target) in targets.iter().enumerate() {
+                        edge(*target, EdgeKind::Case(low + key as i32))?;
+                    }
+                    edge(*default, EdgeKind::Default)?;
+                }
+                Operand::LookupSwitch { default, pairs } => {
+                    for (key, target) in pairs {
+                        edge(*target, EdgeKind::Case(*key))?;
+                    }
+                    edge(*default, EdgeKind::Default)?;
+                }
+                Operand::Branch(target) => match last.opcode {
+                    Opcode::Goto | Opcode::GotoW | Opcode::Jsr | Opcode::JsrW => {
+                        edge(*target, EdgeKind::Goto)?
+                    }
+                    _ => edge(*target, EdgeKind::Branch)?,
+                },
+                _ => {}
+            }
+
+            if last.falls_through() && !matches!(last.opcode, Opcode::Jsr | Opcode::JsrW) {
+                if block.end as usize >= code.code.len() {
+                    return Err(format!("falls off the end of the code at {}", last.offset));
+                }
+                edge(block.end, EdgeKind::FallThrough)?;
+            }
+
+            for handler in &code.exception_table {
+                if block.start >= handler.start_pc as u32 && block.start < handler.end_pc as u32 {
+                    edge(
+                        handler.handler_pc as u32,
+                        EdgeKind::Exception(handler.catch_type),
+                    )?;
+                }
+            }
+        }
+
+        Ok(ControlFlowGraph { blocks, edges })
+    }
+
+    /// Index of the block containing an offset
+    pub fn block_at(self: &ControlFlowGraph, offset: u32) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| offset >= block.start && offset < block.end)
+    }
+
+    pub fn successors(self: &ControlFlowGraph, block: usize) -> Vec<usize> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.from == block)
+            .map(|edge| edge.to)
+            .collect()
+    }
+
+    pub fn predecessors(self: &ControlFlowGraph, block: usize) -> Vec<usize> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.to == block)
+            .map(|edge| edge.from)
+            .collect()
+    }
+
+    /// Immediate dominator of each block. The entry is its own dominator and
+    /// unreachable blocks have none.
+    pub fn dominators(self: &ControlFlowGraph) -> Vec<Option<usize>> {
+        // Cooper, Harvey and Kennedy's iterative algorithm over reverse
+        // postorder
+        let order = self.reverse_postorder();
+        let mut position = vec![usize::MAX; self.blocks.len()];
+        for (i, block) in order.iter().enumerate() {
+            position[*block] = i;
+        }
+
+        let mut idom: Vec<Option<usize>> = vec![None; self.blocks.len()];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in order.iter().skip(1) {
+                let mut new_idom: Option<usize> = None;
+                for pred in self.predecessors(*block) {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(current) => {
+                            let (mut a, mut b) = (pred, current);
+                            while a != b {
+                                while position[a] > position[b] {
+                                    a = idom[a].unwrap();
+                                }
+                                while position[b] > position[a] {
+                                    b = idom[b].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new_idom.is_some() && idom[*block] != new_idom {
+                    idom[*block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        idom
+    }
+
+    /// Whether every path from the entry to `b` passes through `a`
+    pub fn dominates(self: &ControlFlowGraph, idom: &[Option<usize>], a: usize, b: usize) -> bool {
+        let mut current = b;
+        loop {
+            if current == a {
+                return true;
+            }
+            match idom[current] {
+                Some(next) if next != current => current = next,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Natural loops, found from the edges that go back to a block dominating
+    /// their source. Loops sharing a header are merged.
+    pub fn loops(self: &ControlFlowGraph) -> Vec<Loop> {
+        let idom = self.dominators();
+        let mut loops: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+
+        for edge in &self.edges {
+            if idom[edge.from].is_none() || !self.dominates(&idom, edge.to, edge.from) {
+                continue;
+            }
+
+            let blocks = loops.entry(edge.to).or_default();
+            blocks.insert(edge.to);
+            let mut worklist = vec![edge.from];
+            while let Some(block) = worklist.pop() {
+                if blocks.insert(block) {
+                    worklist.extend(self.predecessors(block));
+                }
+            }
+        }
+
+        loops
+            .into_iter()
+            .map(|(header, blocks)| Loop { header, blocks })
+            .collect()
+    }
+
+    /// Renders the graph in Graphviz's DOT language. Loop headers are drawn
+    /// with a double border and exception edges are dashed.
+    pub fn to_dot(self: &ControlFlowGraph, name: &str) -> String {
+        let headers: BTreeSet<usize> = self.loops().iter().map(|l| l.header).collect();
+        let mut dot = String::new();
+
+        writeln!(dot, "digraph \"{}\" {{", escape(name)).unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for (i, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for ins in &block.instructions {
+                write!(label, "{}\\l", escape(&describe(ins))).unwrap();
+            }
+            let peripheries = if headers.contains(&i) { 2 } else { 1 };
+            writeln!(
+                dot,
+                "    b{} [label=\"{}\", peripheries={}];",
+                i, label, peripheries
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            let attributes = match &edge.kind {
+                EdgeKind::FallThrough => String::new(),
+                EdgeKind::Branch => " [label=\"jump\"]".to_string(),
+                EdgeKind::Goto => " [label=\"goto\"]".to_string(),
+                EdgeKind::Case(key) => format!(" [label=\"case {}\"]", key),
+                EdgeKind::Default => " [label=\"default\"]".to_string(),
+                EdgeKind::Exception(_) => " [style=dashed, label=\"catch\"]".to_string(),
+            };
+            writeln!(dot, "    b{} -> b{}{};", edge.from, edge.to, attributes).unwrap();
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    fn reverse_postorder(self: &ControlFlowGraph) -> Vec<usize> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = vec![];
+        // Each entry is a block and how many of its successors have been seen
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.successors(block);
+            match successors.get(next) {
+                Some(successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[*successor] {
+                        visited[*successor] = true;
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+
+        order.reverse();
+        order
+    }
+}
+
+fn describe(ins: &Instruction) -> String {
+    let operand = match &ins.operand {
+        Operand::None => String::new(),
+        Operand::Byte(val) => format!(" {}", val),
+        Operand::Short(val) => format!(" {}", val),
+        Operand::Local(index) => format!(" {}", index),
+        Operand::Constant(index) => format!(" #{}", index),
+        Operand::Branch(target) => format!(" {}", target),
+        Operand::Increment(index, by) => format!(" {} {}", index, by),
+        Operand::TableSwitch { low, high, .. } => format!(" {} to {}", low, high),
+        Operand::LookupSwitch { pairs, .. } => format!(" {} cases", pairs.len()),
+        Operand::InvokeInterface(index, count) => format!(" #{} {}", index, count),
+        Operand::ArrayType(typ) => format!(" {}", typ),
+        Operand::MultiArray(index, dimensions) => format!(" #{} {}", index, dimensions),
+    };
+
+    format!("{}: {:?}{}", ins.offset, ins.opcode, operand)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attribute::ExceptionHandler;
+
+    fn code(bytes: &[u8], exception_table: Vec<ExceptionHandler>) -> Code {
+        Code {
+            max_stack: 2,
+            max_locals: 2,
+            code: bytes.to_vec(),
+            exception_table,
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_loop() {
+        // int i = 0; while (i < 10) i++; return i;
+        let cfg = ControlFlowGraph::build(&code(
+            &[
+                0x03, 0x3b, 0x1a, 0x10, 0x0a, 0xa2, 0x00, 0x09, 0x84, 0x00, 0x01, 0xa7, 0xff, 0xf7,
+                0x1a, 0xac,
+            ],
+            vec![],
+        ))
+        .unwrap();
+
+        let starts: Vec<u32> = cfg.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 2, 8, 14]);
+        assert_eq!(cfg.successors(1), vec![3, 2]);
+        assert_eq!(cfg.edges.len(), 4);
+        assert_eq!(cfg.dominators(), vec![Some(0), Some(0), Some(1), Some(1)]);
+        assert_eq!(
+            cfg.loops(),
+            vec![Loop {
+                header: 1,
+                blocks: [1, 2].iter().cloned().collect(),
+            }]
+        );
+        assert!(cfg.to_dot("test").contains("b2 -> b1 [label=\"goto\"];"));
+    }
+
+    #[test]
+    fn test_exception_edges() {
+        // try { nop } catch (Throwable t) { } return
+        let cfg = ControlFlowGraph::build(&code(
+            &[0x00, 0xa7, 0x00, 0x04, 0x4b, 0xb1],
+            vec![ExceptionHandler {
+                start_pc: 0,
+                end_pc: 1,
+                handler_pc: 4,
+                catch_type: 0,
+            }],
+        ))
+        .unwrap();
+
+        assert_eq!(cfg.blocks.len(), 4);
+        assert!(cfg.edges.contains(&Edge {
+            from: 0,
+            to: 2,
+            kind: EdgeKind::Exception(0),
+        }));
+        assert_eq!(cfg.predecessors(3), vec![1, 2]);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::const_pool::ConstPool;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Class {
     pub major: u16,
     pub minor: u16,
@@ -15,16 +16,30 @@ pub struct Class {
     pub attributes: Vec<Attribute>,
 }
 
+// Classes are parsed and analysed on several threads at once
+const _: fn() = || {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<Class>();
+};
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Attribute {
     pub name: String,
     pub data: Vec<u8>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Field {
     pub flags: u16,
     pub name: String,
     pub descriptor: String,
     pub attributes: Vec<Attribute>,
 }
+
+impl Field {
+    pub fn find_attribute(self: &Field, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+}
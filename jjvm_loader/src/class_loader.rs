@@ -4,40 +4,78 @@ use crate::class::{Attribute, Class, Field};
 use crate::const_pool::{Const, ConstPool};
 use crate::jvm_const::JvmConst;
 
+/// Bounds on what a class file may ask the loader to allocate, so that a
+/// malformed or hostile file fails with an error
+#[derive(Debug, Clone)]
+pub struct LoaderLimits {
+    /// Size in bytes of the whole class file
+    pub max_size: usize,
+    /// Number of constant pool entries
+    pub max_const_pool: u16,
+    /// Length in bytes of a single attribute
+    pub max_attribute_length: u32,
+    /// Longest chain of references between constants
+    pub max_depth: usize,
+}
+
+impl Default for LoaderLimits {
+    fn default() -> LoaderLimits {
+        LoaderLimits {
+            max_size: 64 * 1024 * 1024,
+            max_const_pool: u16::MAX,
+            max_attribute_length: 64 * 1024 * 1024,
+            max_depth: 16,
+        }
+    }
+}
+
 /// ClassLoader is used to load a JVM Class file
 pub struct ClassLoader {
     pub bytes: Cursor<Vec<u8>>,
+    pub limits: LoaderLimits,
 }
 
 impl ClassLoader {
     pub fn new(bytes: Cursor<Vec<u8>>) -> ClassLoader {
-        ClassLoader { bytes }
+        ClassLoader::with_limits(bytes, LoaderLimits::default())
+    }
+
+    pub fn with_limits(bytes: Cursor<Vec<u8>>, limits: LoaderLimits) -> ClassLoader {
+        ClassLoader { bytes, limits }
     }
 
     /// Loads the class file
-    pub fn load(self: &mut ClassLoader) -> Class {
-        self.u4();
+    pub fn load(self: &mut ClassLoader) -> Result<Class, String> {
+        let size = self.bytes.get_ref().len();
+        if size > self.limits.max_size {
+            return Err(format!(
+                "class file is {} bytes, over the limit of {}",
+                size, self.limits.max_size
+            ));
+        }
+        if self.u4()? != 0xcafebabe {
+            return Err("not a class file".to_string());
+        }
 
-        let minor = self.u2();
-        let major = self.u2();
+        let minor = self.u2()?;
+        let major = self.u2()?;
 
-        let const_pool = self.cp_info();
+        let const_pool = self.cp_info()?;
+        const_pool.check_references(self.limits.max_depth)?;
 
-        let flags = self.u2();
-        let name = match const_pool.resolve(self.u2()).unwrap() {
-            Const::String(val) => val,
-            _ => panic!("non-string const"),
-        };
-        let superclass = match const_pool.resolve(self.u2()).unwrap() {
-            Const::String(val) => val,
-            _ => panic!("non-string const"),
+        let flags = self.u2()?;
+        let name = self.string(&const_pool)?;
+        // java/lang/Object is the only class without a superclass
+        let superclass = match self.u2()? {
+            0 => "".to_string(),
+            index => string_const(&const_pool, index)?,
         };
-        let interfaces = self.load_interfaces(&const_pool);
-        let fields = self.load_fields(&const_pool);
-        let methods = self.load_fields(&const_pool);
-        let attributes = self.load_attributes(&const_pool);
+        let interfaces = self.load_interfaces(&const_pool)?;
+        let fields = self.load_fields(&const_pool)?;
+        let methods = self.load_fields(&const_pool)?;
+        let attributes = self.load_attributes(&const_pool)?;
 
-        Class {
+        Ok(Class {
             major,
             minor,
             const_pool,
@@ -48,116 +86,247 @@ impl ClassLoader {
             fields,
             methods,
             attributes,
-        }
+        })
     }
 
-    fn load_interfaces(self: &mut ClassLoader, const_pool: &ConstPool) -> Vec<String> {
-        let interface_count = self.u2();
+    fn load_interfaces(
+        self: &mut ClassLoader,
+        const_pool: &ConstPool,
+    ) -> Result<Vec<String>, String> {
+        let interface_count = self.u2()?;
         let mut interfaces = vec![];
         for _ in 0..interface_count {
-            interfaces.push(match const_pool.resolve(self.u2()).unwrap() {
-                Const::String(val) => val,
-                _ => panic!("non-string const"),
-            });
+            interfaces.push(self.string(const_pool)?);
         }
 
-        interfaces
+        Ok(interfaces)
     }
 
-    fn load_fields(self: &mut ClassLoader, const_pool: &ConstPool) -> Vec<Field> {
-        let field_count = self.u2();
+    fn load_fields(self: &mut ClassLoader, const_pool: &ConstPool) -> Result<Vec<Field>, String> {
+        let field_count = self.u2()?;
         let mut fields = vec![];
         for _ in 0..field_count {
             fields.push(Field {
-                flags: self.u2(),
-                name: match const_pool.resolve(self.u2()).unwrap() {
-                    Const::String(val) => val,
-                    _ => panic!("non-string const"),
-                },
-                descriptor: match const_pool.resolve(self.u2()).unwrap() {
-                    Const::String(val) => val,
-                    _ => panic!("non-string const"),
-                },
-                attributes: self.load_attributes(const_pool),
+                flags: self.u2()?,
+                name: self.string(const_pool)?,
+                descriptor: self.string(const_pool)?,
+                attributes: self.load_attributes(const_pool)?,
             })
         }
 
-        fields
+        Ok(fields)
     }
 
-    fn load_attributes(self: &mut ClassLoader, const_pool: &ConstPool) -> Vec<Attribute> {
-        let attribute_count = self.u2();
+    pub(crate) fn load_attributes(
+        self: &mut ClassLoader,
+        const_pool: &ConstPool,
+    ) -> Result<Vec<Attribute>, String> {
+        let attribute_count = self.u2()?;
         let mut attributes = vec![];
 
         for _ in 0..attribute_count {
-            let name = self.u2();
-            let data_count = self.u4();
+            let name = self.string(const_pool)?;
+            let data_count = self.u4()?;
+            if data_count > self.limits.max_attribute_length {
+                return Err(format!(
+                    "attribute {} is {} bytes, over the limit of {}",
+                    name, data_count, self.limits.max_attribute_length
+                ));
+            }
             attributes.push(Attribute {
-                name: match const_pool.resolve(name).unwrap() {
-                    Const::String(val) => val,
-                    _ => panic!("non-string const"),
-                },
-                data: self.read_bytes(data_count as i32),
+                name,
+                data: self.read_bytes(data_count as usize)?,
             })
         }
 
-        attributes
+        Ok(attributes)
+    }
+
+    /// Reads a constant pool index that must name a string
+    fn string(self: &mut ClassLoader, const_pool: &ConstPool) -> Result<String, String> {
+        let index = self.u2()?;
+        string_const(const_pool, index)
     }
 
     /// Read a single byte from the byte stream
-    pub fn u1(self: &mut ClassLoader) -> u8 {
+    pub fn u1(self: &mut ClassLoader) -> Result<u8, String> {
         let mut val = [0u8; 1];
-        self.bytes.read_exact(&mut val).unwrap();
+        self.read_exact(&mut val)?;
 
-        u8::from_be_bytes(val)
+        Ok(u8::from_be_bytes(val))
     }
 
     /// Read two bytes from the byte stream
-    pub fn u2(self: &mut ClassLoader) -> u16 {
+    pub fn u2(self: &mut ClassLoader) -> Result<u16, String> {
         let mut val = [0u8; 2];
-        self.bytes.read_exact(&mut val).unwrap();
+        self.read_exact(&mut val)?;
 
-        u16::from_be_bytes(val)
+        Ok(u16::from_be_bytes(val))
     }
 
     /// Read four bytes from the byte stream
-    pub fn u4(self: &mut ClassLoader) -> u32 {
+    pub fn u4(self: &mut ClassLoader) -> Result<u32, String> {
         let mut val = [0u8; 4];
-        self.bytes.read_exact(&mut val).unwrap();
+        self.read_exact(&mut val)?;
 
-        u32::from_be_bytes(val)
+        Ok(u32::from_be_bytes(val))
     }
 
-    /// Read four bytes from the byte stream
-    pub fn u8(self: &mut ClassLoader) -> u64 {
+    /// Read eight bytes from the byte stream
+    pub fn u8(self: &mut ClassLoader) -> Result<u64, String> {
         let mut val = [0u8; 8];
-        self.bytes.read_exact(&mut val).unwrap();
+        self.read_exact(&mut val)?;
 
-        u64::from_be_bytes(val)
+        Ok(u64::from_be_bytes(val))
     }
 
-    pub fn read_bytes(self: &mut ClassLoader, count: i32) -> Vec<u8> {
-        let mut bytes = vec![];
-
-        for _ in 0..count {
-            bytes.push(self.u1());
+    /// Reads `count` bytes, failing before allocating anything if the
+    /// stream does not have that many left
+    pub fn read_bytes(self: &mut ClassLoader, count: usize) -> Result<Vec<u8>, String> {
+        let remaining = (self.bytes.get_ref().len() as u64).saturating_sub(self.bytes.position());
+        if count as u64 > remaining {
+            return Err(format!(
+                "unexpected end of class file: {} bytes wanted, {} left",
+                count, remaining
+            ));
         }
+        let mut bytes = vec![0u8; count];
+        self.read_exact(&mut bytes)?;
 
-        bytes
+        Ok(bytes)
     }
 
-    pub fn cp_info(self: &mut ClassLoader) -> ConstPool {
-        let const_pool_count = self.u2();
+    fn read_exact(self: &mut ClassLoader, buf: &mut [u8]) -> Result<(), String> {
+        self.bytes
+            .read_exact(buf)
+            .map_err(|_| "unexpected end of class file".to_string())
+    }
+
+    pub fn cp_info(self: &mut ClassLoader) -> Result<ConstPool, String> {
+        let const_pool_count = self.u2()?;
+        if const_pool_count > self.limits.max_const_pool {
+            return Err(format!(
+                "constant pool has {} entries, over the limit of {}",
+                const_pool_count, self.limits.max_const_pool
+            ));
+        }
         let mut consts = vec![];
 
-        for _ in 1..const_pool_count {
-            let tag = self.u1();
-            // let pos = self.bytes.position() - 1;
-            let result = JvmConst::from_tag(tag, self).unwrap();
-            // println!("{:#x}, {:?}, {:?}", pos, result, tag);
+        while consts.len() + 1 < const_pool_count as usize {
+            let tag = self.u1()?;
+            let result = JvmConst::from_tag(tag, self)?;
+            let wide = matches!(result, JvmConst::Long(_, _) | JvmConst::Double(_, _));
             consts.push(result);
+            if wide {
+                consts.push(JvmConst::Unusable);
+            }
         }
 
-        ConstPool { consts }
+        Ok(ConstPool { consts })
+    }
+}
+
+fn string_const(const_pool: &ConstPool, index: u16) -> Result<String, String> {
+    match const_pool.resolve(index)? {
+        Const::String(val) => Ok(val),
+        _ => Err(format!("constant #{} is not a string", index)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A class named `A` with the given constant pool after its name, and
+    /// the given bytes after its header
+    fn class_bytes(consts: &[u8], count: u16, rest: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52];
+        bytes.extend_from_slice(&(count + 3).to_be_bytes());
+        bytes.extend_from_slice(&[0x01, 0, 1, b'A', 0x07, 0, 1]);
+        bytes.extend_from_slice(consts);
+        bytes.extend_from_slice(&[0, 0x21, 0, 2, 0, 0]);
+        bytes.extend_from_slice(rest);
+        bytes
+    }
+
+    fn load(bytes: Vec<u8>) -> Result<Class, String> {
+        ClassLoader::new(Cursor::new(bytes)).load()
+    }
+
+    #[test]
+    fn test_load() {
+        let class = load(class_bytes(&[], 0, &[0, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(class.name, "A");
+        assert_eq!(class.superclass, "");
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = class_bytes(&[], 0, &[0, 0, 0, 0, 0, 0]);
+        assert_eq!(load(bytes).unwrap_err(), "unexpected end of class file");
+    }
+
+    #[test]
+    fn test_attribute_length() {
+        // One class attribute named `A` claiming four gigabytes
+        let bytes = class_bytes(
+            &[],
+            0,
+            &[0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0xff, 0xff, 0xff, 0xff],
+        );
+        assert!(load(bytes).unwrap_err().contains("over the limit"));
+
+        let limits = LoaderLimits {
+            max_attribute_length: u32::MAX,
+            ..LoaderLimits::default()
+        };
+        let bytes = class_bytes(
+            &[],
+            0,
+            &[0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0xff, 0xff, 0xff, 0xff],
+        );
+        let err = ClassLoader::with_limits(Cursor::new(bytes), limits)
+            .load()
+            .unwrap_err();
+        assert!(err.starts_with("unexpected end of class file"));
+    }
+
+    #[test]
+    fn test_const_pool_limit() {
+        let limits = LoaderLimits {
+            max_const_pool: 2,
+            ..LoaderLimits::default()
+        };
+        let bytes = class_bytes(&[], 0, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        let err = ClassLoader::with_limits(Cursor::new(bytes), limits)
+            .load()
+            .unwrap_err();
+        assert!(err.contains("over the limit of 2"));
+    }
+
+    #[test]
+    fn test_bad_references() {
+        // A String constant referring to itself
+        let bytes = class_bytes(&[0x08, 0, 3], 1, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(load(bytes).unwrap_err(), "cyclic constant reference at #3");
+
+        // A String constant referring past the end of the pool
+        let bytes = class_bytes(&[0x08, 0, 9], 1, &[0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            load(bytes).unwrap_err(),
+            "constant #3 refers to invalid index 9"
+        );
+
+        let pool = ConstPool {
+            consts: vec![JvmConst::String(2), JvmConst::String(1)],
+        };
+        assert_eq!(
+            pool.resolve(1).unwrap_err(),
+            "cyclic constant reference at #1"
+        );
+        assert_eq!(
+            pool.resolve(7).unwrap_err(),
+            "constant index 7 out of range"
+        );
     }
 }
@@ -0,0 +1,224 @@
+use crate::class::{Attribute, Class, Field};
+use crate::const_pool::ConstPool;
+use crate::jvm_const::JvmConst;
+
+const MAGIC: u32 = 0xCAFE_BABE;
+
+/// ClassWriter turns a Class back into the bytes of a class file
+pub struct ClassWriter {
+    pub bytes: Vec<u8>,
+}
+
+impl ClassWriter {
+    pub fn new() -> ClassWriter {
+        ClassWriter { bytes: vec![] }
+    }
+
+    /// Writes the class file. Names that are missing from the constant pool,
+    /// such as those of added attributes, are appended to it.
+    pub fn write(self: &mut ClassWriter, class: &Class) -> Vec<u8> {
+        let mut const_pool = class.const_pool.clone();
+
+        // The body is written first as it may add to the constant pool
+        let mut body = ClassWriter::new();
+        body.u2(class.flags);
+        body.u2(const_pool.add_class(&class.name));
+        body.u2(if class.superclass.is_empty() {
+            0
+        } else {
+            const_pool.add_class(&class.superclass)
+        });
+        body.u2(class.interfaces.len() as u16);
+        for interface in &class.interfaces {
+            body.u2(const_pool.add_class(interface));
+        }
+        body.write_fields(&class.fields, &mut const_pool);
+        body.write_fields(&class.methods, &mut const_pool);
+        body.write_attributes(&class.attributes, &mut const_pool);
+
+        self.u4(MAGIC);
+        self.u2(class.minor);
+        self.u2(class.major);
+        self.cp_info(&const_pool);
+        self.write_bytes(&body.bytes);
+
+        self.bytes.clone()
+    }
+
+    fn write_fields(self: &mut ClassWriter, fields: &[Field], const_pool: &mut ConstPool) {
+        self.u2(fields.len() as u16);
+        for field in fields {
+            self.u2(field.flags);
+            self.u2(const_pool.add_utf8(&field.name));
+            self.u2(const_pool.add_utf8(&field.descriptor));
+            self.write_attributes(&field.attributes, const_pool);
+        }
+    }
+
+    pub fn write_attributes(
+        self: &mut ClassWriter,
+        attributes: &[Attribute],
+        const_pool: &mut ConstPool,
+    ) {
+        self.u2(attributes.len() as u16);
+        for attribute in attributes {
+            self.u2(const_pool.add_utf8(&attribute.name));
+            self.u4(attribute.data.len() as u32);
+            self.write_bytes(&attribute.data);
+        }
+    }
+
+    pub fn cp_info(self: &mut ClassWriter, const_pool: &ConstPool) {
+        self.u2(const_pool.consts.len() as u16 + 1);
+
+        for c in &const_pool.consts {
+            match c {
+                JvmConst::UTF8(val) => {
+                    let bytes = cesu8::to_java_cesu8(val);
+                    self.u1(0x01);
+                    self.u2(bytes.len() as u16);
+                    self.write_bytes(&bytes);
+                }
+                JvmConst::Integer(val) => {
+                    self.u1(0x03);
+                    self.u4(*val);
+                }
+                JvmConst::Float(val) => {
+                    self.u1(0x04);
+                    self.u4(*val);
+                }
+                JvmConst::Long(high, low) => {
+                    self.u1(0x05);
+                    self.u4(*high);
+                    self.u4(*low);
+                }
+                JvmConst::Double(high, low) => {
+                    self.u1(0x06);
+                    self.u4(*high);
+                    self.u4(*low);
+                }
+                JvmConst::Class(i) => {
+                    self.u1(0x07);
+                    self.u2(*i);
+                }
+                JvmConst::String(i) => {
+                    self.u1(0x08);
+                    self.u2(*i);
+                }
+                JvmConst::FieldRef(i, j) => self.two_indexes(0x09, *i, *j),
+                JvmConst::MethodRef(i, j) => self.two_indexes(0x0a, *i, *j),
+                JvmConst::InterfaceMethodRef(i, j) => self.two_indexes(0x0b, *i, *j),
+                JvmConst::NameAndType(i, j) => self.two_indexes(0x0c, *i, *j),
+                JvmConst::MethodHandle(kind, i) => {
+                    self.u1(0x0f);
+                    self.u1(*kind);
+                    self.u2(*i);
+                }
+                JvmConst::MethodType(i) => {
+                    self.u1(0x10);
+                    self.u2(*i);
+                }
+                JvmConst::Dynamic(i, j) => self.two_indexes(0x11, *i, *j),
+                JvmConst::InvokeDynamic(i, j) => self.two_indexes(0x12, *i, *j),
+                JvmConst::Module(i) => {
+                    self.u1(0x13);
+                    self.u2(*i);
+                }
+                JvmConst::Package(i) => {
+                    self.u1(0x14);
+                    self.u2(*i);
+                }
+                JvmConst::Unusable => {}
+            }
+        }
+    }
+
+    fn two_indexes(self: &mut ClassWriter, tag: u8, i: u16, j: u16) {
+        self.u1(tag);
+        self.u2(i);
+        self.u2(j);
+    }
+
+    /// Write a single byte to the byte stream
+    pub fn u1(self: &mut ClassWriter, val: u8) {
+        self.bytes.push(val);
+    }
+
+    /// Write two bytes to the byte stream
+    pub fn u2(self: &mut ClassWriter, val: u16) {
+        self.bytes.extend_from_slice(&val.to_be_bytes());
+    }
+
+    /// Write four bytes to the byte stream
+    pub fn u4(self: &mut ClassWriter, val: u32) {
+        self.bytes.extend_from_slice(&val.to_be_bytes());
+    }
+
+    pub fn write_bytes(self: &mut ClassWriter, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+impl Default for ClassWriter {
+    fn default() -> Self {
+        ClassWriter::new()
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+    use crate::attribute::Code;
+    use crate::class::Field;
+    use crate::jvm_const::JvmConst;
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut const_pool = ConstPool {
+            consts: vec![
+                JvmConst::Long(0x1, 0xffff_ffff),
+                JvmConst::Unusable,
+                JvmConst::Float(f32::NAN.to_bits()),
+                JvmConst::UTF8("caf\u{e9} \u{0}".to_string()),
+            ],
+        };
+        let code = Code {
+            max_stack: 2,
+            max_locals: 0,
+            code: vec![0x14, 0x00, 0x01, 0xad],
+            exception_table: vec![],
+            attributes: vec![],
+        };
+        let attribute = code.to_attribute(&mut const_pool);
+        let class = Class {
+            major: 52,
+            minor: 0,
+            const_pool,
+            name: "Test".to_string(),
+            superclass: "java/lang/Object".to_string(),
+            flags: 0x0021,
+            interfaces: vec!["java/io/Serializable".to_string()],
+            fields: vec![Field {
+                flags: 0x0002,
+                name: "value".to_string(),
+                descriptor: "J".to_string(),
+                attributes: vec![],
+            }],
+            methods: vec![Field {
+                flags: 0x0009,
+                name: "test".to_string(),
+                descriptor: "()J".to_string(),
+                attributes: vec![attribute],
+            }],
+            attributes: vec![],
+        };
+
+        let json = serde_json::to_string(&class).unwrap();
+        let decoded: Class = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            ClassWriter::new().write(&decoded),
+            ClassWriter::new().write(&class)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use rayon::prelude::*;
+use zip::ZipArchive;
+
+use crate::class::Class;
+use crate::class_loader::ClassLoader;
+use crate::jmod::JmodFile;
+
+/// Loads every class found at a path, which may be a class file, a jar, a
+/// jmod or a directory that is searched recursively
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Class>, String> {
+    load_paths(&[path])
+}
+
+/// Loads every class found at several paths, in the order they are given.
+/// Files are read one at a time but the classes in them are parsed in
+/// parallel.
+pub fn load_paths<P: AsRef<Path>>(paths: &[P]) -> Result<Vec<Class>, String> {
+    let mut entries = vec![];
+    for path in paths {
+        read_entries(path.as_ref(), &mut entries)?;
+    }
+
+    parse_all(entries)
+}
+
+/// Loads the classes from several paths, keyed by name. Where two paths have
+/// a class with the same name the first one wins, as on a Java classpath.
+pub fn load_all<P: AsRef<Path>>(paths: &[P]) -> Result<HashMap<String, Class>, String> {
+    let mut classes = HashMap::new();
+    for class in load_paths(paths)? {
+        classes.entry(class.name.clone()).or_insert(class);
+    }
+
+    Ok(classes)
+}
+
+/// Parses class files on the rayon thread pool. The classes, and the first
+/// error if any, come out in the order of `entries` however the work is
+/// split between threads.
+pub fn parse_all(entries: Vec<(String, Vec<u8>)>) -> Result<Vec<Class>, String> {
+    let results: Vec<Result<Class, String>> = entries
+        .into_par_iter()
+        .map(|(name, bytes)| {
+            ClassLoader::new(Cursor::new(bytes))
+                .load()
+                .map_err(|e| format!("{}: {}", name, e))
+        })
+        .collect();
+
+    results.into_iter().collect()
+}
+
+/// Reads the bytes of every class at a path, each named for error messages
+fn read_entries(path: &Path, entries: &mut Vec<(String, Vec<u8>)>) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+
+    if path.is_dir() {
+        let mut children: Vec<_> = fs::read_dir(path)
+            .map_err(error)?
+            .collect::<Result<_, _>>()
+            .map_err(error)?;
+        children.sort_by_key(|entry| entry.path());
+        for entry in children {
+            let child = entry.path();
+            if child.is_dir() || is_archive(&child) || has_extension(&child, "class") {
+                read_entries(&child, entries)?;
+            }
+        }
+        return Ok(());
+    }
+
+    if has_extension(path, "jmod") {
+        let mut jmod = JmodFile::open(path)?;
+        for name in jmod.class_names() {
+            let bytes = jmod.read_class_bytes(&name)?;
+            entries.push((name, bytes));
+        }
+        return Ok(());
+    }
+
+    let bytes = fs::read(path).map_err(error)?;
+    if has_extension(path, "jar") || has_extension(path, "zip") {
+        return read_jar(path, bytes, entries).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+
+    entries.push((path.display().to_string(), bytes));
+    Ok(())
+}
+
+fn read_jar(
+    path: &Path,
+    bytes: Vec<u8>,
+    entries: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
+        let name = file.name().to_string();
+        // Versioned and module descriptors are not ordinary classes
+        if !name.ends_with(".class")
+            || name.ends_with("module-info.class")
+            || name.starts_with("META-INF/")
+        {
+            continue;
+        }
+
+        let mut class_bytes = vec![];
+        file.read_to_end(&mut class_bytes)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        entries.push((format!("{}: {}", path.display(), name), class_bytes));
+    }
+
+    Ok(())
+}
+
+/// Files other than classes in a jar, such as its manifest, in the order
+/// they are stored. Other kinds of path have none.
+pub fn resources<P: AsRef<Path>>(path: P) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let path = path.as_ref();
+    if !(has_extension(path, "jar") || has_extension(path, "zip")) {
+        return Ok(vec![]);
+    }
+
+    let error = |e: String| format!("{}: {}", path.display(), e);
+    let bytes = fs::read(path).map_err(|e| error(e.to_string()))?;
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| error(e.to_string()))?;
+    let mut resources = vec![];
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| error(e.to_string()))?;
+        let name = file.name().to_string();
+        // The classes read_jar reads
+        let is_class = name.ends_with(".class")
+            && !name.ends_with("module-info.class")
+            && !name.starts_with("META-INF/");
+        if file.is_dir() || is_class {
+            continue;
+        }
+
+        let mut data = vec![];
+        file.read_to_end(&mut data)
+            .map_err(|e| error(format!("{}: {}", name, e)))?;
+        resources.push((name, data));
+    }
+
+    Ok(resources)
+}
+
+fn is_archive(path: &Path) -> bool {
+    has_extension(path, "jar") || has_extension(path, "zip") || has_extension(path, "jmod")
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension() == Some(OsStr::new(extension))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_order() {
+        // public class A {} and public class B {}, differing only in name
+        let class = |name: u8| {
+            vec![
+                0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52, 0, 5, 1, 0, 1, name, 7, 0, 1, 1, 0, 16, b'j',
+                b'a', b'v', b'a', b'/', b'l', b'a', b'n', b'g', b'/', b'O', b'b', b'j', b'e', b'c',
+                b't', 7, 0, 3, 0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0,
+            ]
+        };
+        let entries: Vec<(String, Vec<u8>)> = (0..64)
+            .map(|i| (i.to_string(), class(if i % 2 == 0 { b'A' } else { b'B' })))
+            .collect();
+        let classes = parse_all(entries.clone()).unwrap();
+        assert!(classes
+            .iter()
+            .enumerate()
+            .all(|(i, c)| c.name == if i % 2 == 0 { "A" } else { "B" }));
+
+        let mut broken = entries;
+        broken[40].1.truncate(10);
+        broken[20].1[0] = 0;
+        assert!(parse_all(broken).unwrap_err().starts_with("20: "));
+    }
+}
@@ -1,11 +1,13 @@
 use crate::jvm_const::JvmConst;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConstPool {
     pub consts: Vec<JvmConst>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Const {
     String(String),
     FieldRef(Box<Const>, Box<Const>),
@@ -13,33 +15,203 @@ pub enum Const {
     MethodRef(Box<Const>, Box<Const>),
     Integer(i32),
     Float(f32),
+    Long(i64),
+    Double(f64),
 }
 
 impl ConstPool {
     pub fn resolve(self: &ConstPool, index: u16) -> Result<Const, String> {
-        match &self.consts[index as usize - 1] {
+        self.resolve_from(index, &mut vec![])
+    }
+
+    /// Resolves a constant, with `seen` holding the constants that refer to
+    /// it so that a reference back to one of them is reported instead of
+    /// recursing forever
+    fn resolve_from(self: &ConstPool, index: u16, seen: &mut Vec<u16>) -> Result<Const, String> {
+        if seen.contains(&index) {
+            return Err(format!("cyclic constant reference at #{}", index));
+        }
+        let constant = self
+            .get(index)
+            .ok_or_else(|| format!("constant index {} out of range", index))?;
+
+        seen.push(index);
+        let result = match constant {
             JvmConst::UTF8(val) => Ok(Const::String(val.clone())),
-            JvmConst::String(i) => self.resolve(*i),
+            JvmConst::String(i) => self.resolve_from(*i, seen),
             JvmConst::Integer(i) => Ok(Const::Integer(*i as i32)),
             JvmConst::Float(f) => Ok(Const::Float(f32::from_be_bytes(f.to_be_bytes()))),
-            JvmConst::Class(c) => self.resolve(*c),
+            JvmConst::Long(high, low) => {
+                Ok(Const::Long(((*high as u64) << 32 | *low as u64) as i64))
+            }
+            JvmConst::Double(high, low) => Ok(Const::Double(f64::from_bits(
+                (*high as u64) << 32 | *low as u64,
+            ))),
+            JvmConst::Class(c) => self.resolve_from(*c, seen),
             JvmConst::FieldRef(i, j) => Ok(Const::FieldRef(
-                Box::new(self.resolve(*i).unwrap()),
-                Box::new(self.resolve(*j).unwrap()),
+                Box::new(self.resolve_from(*i, seen)?),
+                Box::new(self.resolve_from(*j, seen)?),
             )),
             JvmConst::NameAndType(i, j) => Ok(Const::NameAndType(
-                Box::new(self.resolve(*i).unwrap()),
-                Box::new(self.resolve(*j).unwrap()),
+                Box::new(self.resolve_from(*i, seen)?),
+                Box::new(self.resolve_from(*j, seen)?),
             )),
-            JvmConst::MethodRef(i, j) => Ok(Const::MethodRef(
-                Box::new(self.resolve(*i).unwrap()),
-                Box::new(self.resolve(*j).unwrap()),
+            JvmConst::MethodRef(i, j) | JvmConst::InterfaceMethodRef(i, j) => Ok(Const::MethodRef(
+                Box::new(self.resolve_from(*i, seen)?),
+                Box::new(self.resolve_from(*j, seen)?),
             )),
             JvmConst::InvokeDynamic(i, j) => Ok(Const::MethodRef(
-                Box::new(self.resolve(*i).unwrap()),
-                Box::new(self.resolve(*j).unwrap()),
+                Box::new(self.resolve_from(*i, seen)?),
+                Box::new(self.resolve_from(*j, seen)?),
             )),
             _ => Err("const not found".to_string()),
+        };
+        seen.pop();
+
+        result
+    }
+
+    /// Checks that every reference between constants points into the pool
+    /// and that no chain of references is longer than `max_depth`
+    pub fn check_references(self: &ConstPool, max_depth: usize) -> Result<(), String> {
+        let mut depths = vec![None; self.consts.len()];
+        for index in 1..=self.consts.len() as u16 {
+            self.reference_depth(index, &mut depths, &mut vec![], max_depth)?;
+        }
+
+        Ok(())
+    }
+
+    fn reference_depth(
+        self: &ConstPool,
+        index: u16,
+        depths: &mut Vec<Option<usize>>,
+        seen: &mut Vec<u16>,
+        max_depth: usize,
+    ) -> Result<usize, String> {
+        if let Some(Some(depth)) = depths.get(index as usize - 1) {
+            return Ok(*depth);
+        }
+        if seen.contains(&index) {
+            return Err(format!("cyclic constant reference at #{}", index));
+        }
+        if seen.len() >= max_depth {
+            return Err(format!(
+                "constant #{} nests deeper than {}",
+                index, max_depth
+            ));
+        }
+
+        let references = match &self.consts[index as usize - 1] {
+            JvmConst::Class(i)
+            | JvmConst::String(i)
+            | JvmConst::MethodType(i)
+            | JvmConst::Module(i)
+            | JvmConst::Package(i)
+            | JvmConst::MethodHandle(_, i)
+            | JvmConst::Dynamic(_, i)
+            | JvmConst::InvokeDynamic(_, i) => vec![*i],
+            JvmConst::FieldRef(i, j)
+            | JvmConst::MethodRef(i, j)
+            | JvmConst::InterfaceMethodRef(i, j)
+            | JvmConst::NameAndType(i, j) => vec![*i, *j],
+            _ => vec![],
+        };
+
+        seen.push(index);
+        let mut depth = 0;
+        for reference in references {
+            match self.get(reference) {
+                None | Some(JvmConst::Unusable) => {
+                    return Err(format!(
+                        "constant #{} refers to invalid index {}",
+                        index, reference
+                    ))
+                }
+                Some(_) => {
+                    depth = depth.max(self.reference_depth(reference, depths, seen, max_depth)? + 1)
+                }
+            }
+        }
+        seen.pop();
+        depths[index as usize - 1] = Some(depth);
+
+        Ok(depth)
+    }
+
+    pub fn get(self: &ConstPool, index: u16) -> Option<&JvmConst> {
+        if index == 0 {
+            return None;
+        }
+
+        self.consts.get(index as usize - 1)
+    }
+
+    pub fn utf8(self: &ConstPool, index: u16) -> Option<&str> {
+        match self.get(index) {
+            Some(JvmConst::UTF8(val)) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// Name of the class a Class constant refers to
+    pub fn class_name(self: &ConstPool, index: u16) -> Option<&str> {
+        match self.get(index) {
+            Some(JvmConst::Class(name)) => self.utf8(*name),
+            _ => None,
+        }
+    }
+
+    /// Name and descriptor of a NameAndType constant
+    pub fn name_and_type(self: &ConstPool, index: u16) -> Option<(&str, &str)> {
+        match self.get(index) {
+            Some(JvmConst::NameAndType(name, descriptor)) => {
+                Some((self.utf8(*name)?, self.utf8(*descriptor)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Index of a UTF8 constant, adding it to the pool if it is missing
+    pub fn add_utf8(self: &mut ConstPool, val: &str) -> u16 {
+        match self
+            .consts
+            .iter()
+            .position(|c| matches!(c, JvmConst::UTF8(v) if v == val))
+        {
+            Some(i) => i as u16 + 1,
+            None => self.push(JvmConst::UTF8(val.to_string())),
+        }
+    }
+
+    /// Index of a Class constant, adding it to the pool if it is missing
+    pub fn add_class(self: &mut ConstPool, name: &str) -> u16 {
+        let found = (1..=self.consts.len() as u16).find(|i| self.class_name(*i) == Some(name));
+        match found {
+            Some(i) => i,
+            None => {
+                let name = self.add_utf8(name);
+                self.push(JvmConst::Class(name))
+            }
+        }
+    }
+
+    fn push(self: &mut ConstPool, val: JvmConst) -> u16 {
+        self.consts.push(val);
+        self.consts.len() as u16
+    }
+
+    /// Class, name and descriptor of a field, method or interface method
+    /// reference
+    pub fn member_ref(self: &ConstPool, index: u16) -> Option<(&str, &str, &str)> {
+        match self.get(index) {
+            Some(JvmConst::FieldRef(class, nat))
+            | Some(JvmConst::MethodRef(class, nat))
+            | Some(JvmConst::InterfaceMethodRef(class, nat)) => {
+                let (name, descriptor) = self.name_and_type(*nat)?;
+                Some((self.class_name(*class)?, name, descriptor))
+            }
+            _ => None,
         }
     }
 }
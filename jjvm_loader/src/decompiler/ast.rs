@@ -0,0 +1,496 @@
+/// A local variable, named from the LocalVariableTable where there is one.
+/// Two locals are the same variable when they have the same name.
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub name: String,
+    /// Java type, when it is known
+    pub typ: Option<String>,
+}
+
+impl PartialEq for Local {
+    fn eq(&self, other: &Local) -> bool {
+        self.name == other.name
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallTarget {
+    Static(String),
+    Instance(Box<Expr>),
+    /// A superclass method called with invokespecial
+    Super,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A literal already in source form, such as `"text"` or `1.5f`
+    Literal(String),
+    /// An int constant, kept apart from other literals as it may turn out to
+    /// be a boolean or a char
+    Int(i32),
+    Local(Local),
+    /// The exception on the stack at the start of a handler
+    Caught(String),
+    /// The result of `new` before its constructor has been called
+    Uninit {
+        offset: u32,
+        class: String,
+    },
+    New {
+        class: String,
+        args: Vec<Expr>,
+    },
+    /// A new array with the lengths of its first dimensions and a count of
+    /// the dimensions left unallocated
+    NewArray {
+        element: String,
+        dims: Vec<Expr>,
+        extra: usize,
+    },
+    ArrayInit {
+        element: String,
+        values: Vec<Expr>,
+    },
+    Field {
+        /// The object, or None for a static field
+        target: Option<Box<Expr>>,
+        class: String,
+        name: String,
+        descriptor: String,
+    },
+    Index {
+        array: Box<Expr>,
+        index: Box<Expr>,
+    },
+    Length(Box<Expr>),
+    Call {
+        target: CallTarget,
+        class: String,
+        name: String,
+        descriptor: String,
+        args: Vec<Expr>,
+    },
+    /// An invokedynamic call site that is neither a lambda nor a string
+    /// concatenation
+    Dynamic {
+        name: String,
+        args: Vec<Expr>,
+    },
+    MethodRef {
+        target: CallTarget,
+        name: String,
+    },
+    Lambda {
+        params: Vec<String>,
+        body: Box<Expr>,
+    },
+    Binary {
+        op: &'static str,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Unary {
+        op: &'static str,
+        expr: Box<Expr>,
+    },
+    Cast {
+        typ: String,
+        expr: Box<Expr>,
+    },
+    InstanceOf {
+        expr: Box<Expr>,
+        typ: String,
+    },
+    /// lcmp, fcmpl and the like, named by the boxed type whose `compare`
+    /// they behave as. These are folded into the branch that follows them.
+    Compare {
+        typ: &'static str,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Ternary {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        els: Box<Expr>,
+    },
+    /// `x++` or `x--` used as a value
+    PostIncrement {
+        local: Local,
+        delta: i16,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub keys: Vec<i32>,
+    pub default: bool,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catch {
+    pub typ: String,
+    pub name: String,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Expr(Expr),
+    Assign {
+        target: Expr,
+        value: Expr,
+    },
+    Return(Option<Expr>),
+    Throw(Expr),
+    If {
+        cond: Expr,
+        then: Vec<Stmt>,
+        els: Vec<Stmt>,
+    },
+    While {
+        label: Option<String>,
+        cond: Expr,
+        body: Vec<Stmt>,
+    },
+    DoWhile {
+        label: Option<String>,
+        cond: Expr,
+        body: Vec<Stmt>,
+    },
+    Switch {
+        label: Option<String>,
+        expr: Expr,
+        cases: Vec<Case>,
+    },
+    Try {
+        body: Vec<Stmt>,
+        catches: Vec<Catch>,
+    },
+    Break(Option<String>),
+    Continue(Option<String>),
+    Monitor {
+        enter: bool,
+        expr: Expr,
+    },
+    /// A jump that could not be expressed with structured control flow
+    Goto(u32),
+    Comment(String),
+}
+
+impl Expr {
+    pub fn binary(op: &'static str, left: Expr, right: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn logical_not(expr: Expr) -> Expr {
+        Expr::Unary {
+            op: "!",
+            expr: Box::new(expr),
+        }
+    }
+
+    /// The condition that holds exactly when this one does not
+    pub fn negate(self: Expr) -> Expr {
+        match self {
+            Expr::Binary { op, left, right } => match op {
+                "==" => Expr::Binary {
+                    op: "!=",
+                    left,
+                    right,
+                },
+                "!=" => Expr::Binary {
+                    op: "==",
+                    left,
+                    right,
+                },
+                "<" => Expr::Binary {
+                    op: ">=",
+                    left,
+                    right,
+                },
+                ">=" => Expr::Binary {
+                    op: "<",
+                    left,
+                    right,
+                },
+                ">" => Expr::Binary {
+                    op: "<=",
+                    left,
+                    right,
+                },
+                "<=" => Expr::Binary {
+                    op: ">",
+                    left,
+                    right,
+                },
+                "&&" => Expr::binary("||", left.negate(), right.negate()),
+                "||" => Expr::binary("&&", left.negate(), right.negate()),
+                _ => Expr::logical_not(Expr::Binary { op, left, right }),
+            },
+            Expr::Unary { op: "!", expr } => *expr,
+            Expr::Literal(ref text) if text == "true" => Expr::Literal("false".to_string()),
+            Expr::Literal(ref text) if text == "false" => Expr::Literal("true".to_string()),
+            expr => Expr::logical_not(expr),
+        }
+    }
+
+    /// Whether the expression is known to be a boolean
+    pub fn is_boolean(self: &Expr) -> bool {
+        match self {
+            Expr::Literal(text) => text == "true" || text == "false",
+            Expr::Local(local) => local.typ.as_deref() == Some("boolean"),
+            Expr::Field { descriptor, .. } => descriptor == "Z",
+            Expr::Call { descriptor, .. } => descriptor.ends_with(")Z"),
+            Expr::Binary { op, left, right } => match *op {
+                "==" | "!=" | "<" | ">" | "<=" | ">=" | "&&" | "||" => true,
+                "&" | "|" | "^" => left.is_boolean() && right.is_boolean(),
+                _ => false,
+            },
+            Expr::Unary { op, .. } => *op == "!",
+            Expr::InstanceOf { .. } => true,
+            Expr::Ternary { then, els, .. } => then.is_boolean() && els.is_boolean(),
+            _ => false,
+        }
+    }
+
+    /// Whether the expression is known to be a char
+    pub fn is_char(self: &Expr) -> bool {
+        match self {
+            Expr::Local(local) => local.typ.as_deref() == Some("char"),
+            Expr::Field { descriptor, .. } => descriptor == "C",
+            Expr::Call { descriptor, .. } => descriptor.ends_with(")C"),
+            Expr::Cast { typ, .. } => typ == "char",
+            _ => false,
+        }
+    }
+
+    /// How tightly the expression binds, from 1 for assignments up to 15 for
+    /// primaries
+    pub fn precedence(self: &Expr) -> u8 {
+        match self {
+            Expr::Lambda { .. } => 1,
+            Expr::Ternary { .. } => 2,
+            Expr::Binary { op, .. } => binary_precedence(op),
+            Expr::InstanceOf { .. } => 9,
+            Expr::Unary { .. } | Expr::Cast { .. } => 13,
+            Expr::Int(val) if *val < 0 => 13,
+            Expr::Literal(text) if text.starts_with('-') => 13,
+            Expr::PostIncrement { .. } => 14,
+            _ => 15,
+        }
+    }
+
+    /// Whether evaluating the expression can neither have side effects nor
+    /// observe them
+    pub fn is_pure(self: &Expr) -> bool {
+        match self {
+            Expr::Literal(_)
+            | Expr::Int(_)
+            | Expr::Local(_)
+            | Expr::Caught(_)
+            | Expr::Uninit { .. }
+            | Expr::MethodRef { .. }
+            | Expr::Lambda { .. }
+            | Expr::Binary { .. }
+            | Expr::Unary { .. }
+            | Expr::Cast { .. }
+            | Expr::InstanceOf { .. }
+            | Expr::Compare { .. }
+            | Expr::Ternary { .. } => self.children().iter().all(|child| child.is_pure()),
+            _ => false,
+        }
+    }
+
+    /// Whether the expression reads the local variable with this name
+    pub fn uses_local(self: &Expr, name: &str) -> bool {
+        match self {
+            Expr::Local(local) | Expr::PostIncrement { local, .. } if local.name == name => true,
+            _ => self.children().iter().any(|child| child.uses_local(name)),
+        }
+    }
+
+    /// Direct subexpressions in the order they are evaluated
+    pub fn children(self: &Expr) -> Vec<&Expr> {
+        match self {
+            Expr::New { args, .. } | Expr::Dynamic { args, .. } => args.iter().collect(),
+            Expr::NewArray { dims, .. } => dims.iter().collect(),
+            Expr::ArrayInit { values, .. } => values.iter().collect(),
+            Expr::Field { target, .. } => target.iter().map(|t| t.as_ref()).collect(),
+            Expr::Index { array, index } => vec![array, index],
+            Expr::Length(expr)
+            | Expr::Unary { expr, .. }
+            | Expr::Cast { expr, .. }
+            | Expr::InstanceOf { expr, .. } => vec![expr],
+            Expr::Call { target, args, .. } => {
+                let mut children = vec![];
+                if let CallTarget::Instance(target) = target {
+                    children.push(target.as_ref());
+                }
+                children.extend(args);
+                children
+            }
+            Expr::MethodRef {
+                target: CallTarget::Instance(target),
+                ..
+            } => vec![target],
+            Expr::Binary { left, right, .. } | Expr::Compare { left, right, .. } => {
+                vec![left, right]
+            }
+            Expr::Ternary { cond, then, els } => vec![cond, then, els],
+            _ => vec![],
+        }
+    }
+
+    pub fn children_mut(self: &mut Expr) -> Vec<&mut Expr> {
+        match self {
+            Expr::New { args, .. } | Expr::Dynamic { args, .. } => args.iter_mut().collect(),
+            Expr::NewArray { dims, .. } => dims.iter_mut().collect(),
+            Expr::ArrayInit { values, .. } => values.iter_mut().collect(),
+            Expr::Field { target, .. } => target.iter_mut().map(|t| t.as_mut()).collect(),
+            Expr::Index { array, index } => vec![array, index],
+            Expr::Length(expr)
+            | Expr::Unary { expr, .. }
+            | Expr::Cast { expr, .. }
+            | Expr::InstanceOf { expr, .. } => vec![expr],
+            Expr::Call { target, args, .. } => {
+                let mut children = vec![];
+                if let CallTarget::Instance(target) = target {
+                    children.push(target.as_mut());
+                }
+                children.extend(args);
+                children
+            }
+            Expr::MethodRef {
+                target: CallTarget::Instance(target),
+                ..
+            } => vec![target],
+            Expr::Binary { left, right, .. } | Expr::Compare { left, right, .. } => {
+                vec![left, right]
+            }
+            Expr::Ternary { cond, then, els } => vec![cond, then, els],
+            _ => vec![],
+        }
+    }
+
+    /// Calls `f` on this expression and every subexpression, innermost first
+    pub fn walk_mut<F: FnMut(&mut Expr)>(self: &mut Expr, f: &mut F) {
+        for child in self.children_mut() {
+            child.walk_mut(f);
+        }
+        f(self);
+    }
+}
+
+impl Stmt {
+    /// Expressions evaluated by the statement itself, leaving out those in
+    /// nested statements
+    pub fn exprs(self: &Stmt) -> Vec<&Expr> {
+        match self {
+            Stmt::Expr(expr)
+            | Stmt::Throw(expr)
+            | Stmt::Return(Some(expr))
+            | Stmt::If { cond: expr, .. }
+            | Stmt::While { cond: expr, .. }
+            | Stmt::DoWhile { cond: expr, .. }
+            | Stmt::Switch { expr, .. }
+            | Stmt::Monitor { expr, .. } => vec![expr],
+            Stmt::Assign { target, value } => vec![target, value],
+            _ => vec![],
+        }
+    }
+
+    pub fn exprs_mut(self: &mut Stmt) -> Vec<&mut Expr> {
+        match self {
+            Stmt::Expr(expr)
+            | Stmt::Throw(expr)
+            | Stmt::Return(Some(expr))
+            | Stmt::If { cond: expr, .. }
+            | Stmt::While { cond: expr, .. }
+            | Stmt::DoWhile { cond: expr, .. }
+            | Stmt::Switch { expr, .. }
+            | Stmt::Monitor { expr, .. } => vec![expr],
+            Stmt::Assign { target, value } => vec![target, value],
+            _ => vec![],
+        }
+    }
+
+    /// Statement lists nested inside this statement
+    pub fn bodies(self: &Stmt) -> Vec<&Vec<Stmt>> {
+        match self {
+            Stmt::If { then, els, .. } => vec![then, els],
+            Stmt::While { body, .. } | Stmt::DoWhile { body, .. } => vec![body],
+            Stmt::Switch { cases, .. } => cases.iter().map(|case| &case.body).collect(),
+            Stmt::Try { body, catches } => {
+                let mut bodies = vec![body];
+                bodies.extend(catches.iter().map(|catch| &catch.body));
+                bodies
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn bodies_mut(self: &mut Stmt) -> Vec<&mut Vec<Stmt>> {
+        match self {
+            Stmt::If { then, els, .. } => vec![then, els],
+            Stmt::While { body, .. } | Stmt::DoWhile { body, .. } => vec![body],
+            Stmt::Switch { cases, .. } => cases.iter_mut().map(|case| &mut case.body).collect(),
+            Stmt::Try { body, catches } => {
+                let mut bodies = vec![body];
+                bodies.extend(catches.iter_mut().map(|catch| &mut catch.body));
+                bodies
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Whether control never continues on to the next statement
+    pub fn is_jump(self: &Stmt) -> bool {
+        match self {
+            Stmt::Return(_)
+            | Stmt::Throw(_)
+            | Stmt::Break(_)
+            | Stmt::Continue(_)
+            | Stmt::Goto(_) => true,
+            Stmt::If { then, els, .. } => ends_in_jump(then) && ends_in_jump(els),
+            _ => false,
+        }
+    }
+}
+
+/// Whether a statement list never completes normally
+pub fn ends_in_jump(stmts: &[Stmt]) -> bool {
+    stmts.last().map(Stmt::is_jump).unwrap_or(false)
+}
+
+/// Calls `f` on every expression in a statement list, including those in
+/// nested statements
+pub fn walk_stmts_mut<F: FnMut(&mut Expr)>(stmts: &mut [Stmt], f: &mut F) {
+    for stmt in stmts {
+        for expr in stmt.exprs_mut() {
+            expr.walk_mut(f);
+        }
+        for body in stmt.bodies_mut() {
+            walk_stmts_mut(body, f);
+        }
+    }
+}
+
+pub fn binary_precedence(op: &str) -> u8 {
+    match op {
+        "||" => 3,
+        "&&" => 4,
+        "|" => 5,
+        "^" => 6,
+        "&" => 7,
+        "==" | "!=" => 8,
+        "<" | ">" | "<=" | ">=" => 9,
+        "<<" | ">>" | ">>>" => 10,
+        "+" | "-" => 11,
+        _ => 12,
+    }
+}
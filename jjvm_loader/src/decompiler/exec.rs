@@ -0,0 +1,1357 @@
+use std::collections::BTreeMap;
+
+use super::ast::{CallTarget, Expr, Local, Stmt};
+use super::{class_name, java_type, string_literal, type_name};
+use crate::attribute::{BootstrapMethod, Code};
+use crate::cfg::{BasicBlock, ControlFlowGraph};
+use crate::class::{Class, Field};
+use crate::const_pool::ConstPool;
+use crate::instruction::{Instruction, Operand};
+use crate::jvm_const::JvmConst;
+use crate::opcode::Opcode;
+use crate::signature::{JavaType, Primitive, TypeSignature};
+
+/// An entry of a LocalVariableTable attribute
+struct LocalVariable {
+    start: u32,
+    end: u32,
+    slot: u16,
+    name: String,
+    descriptor: String,
+}
+
+/// What the decompiler knows about the method being decompiled
+pub(crate) struct Method<'a> {
+    pub class: &'a Class,
+    pub pool: &'a ConstPool,
+    pub signature: Option<TypeSignature>,
+    /// `this` and the parameters, by slot
+    pub params: Vec<(u16, Local)>,
+    variables: Vec<LocalVariable>,
+    bootstrap_methods: Vec<BootstrapMethod>,
+    temporaries: usize,
+}
+
+impl<'a> Method<'a> {
+    pub fn new(class: &'a Class, method: &Field, code: Option<&Code>) -> Method<'a> {
+        let pool = &class.const_pool;
+        let variables = code
+            .and_then(|code| code.find_attribute("LocalVariableTable"))
+            .map(|attribute| local_variables(&attribute.data, pool))
+            .unwrap_or_default();
+        let is_static = method.flags & 0x0008 != 0;
+        let signature = TypeSignature::from_str(&method.descriptor).ok();
+
+        let mut context = Method {
+            class,
+            pool,
+            signature: signature.clone(),
+            params: vec![],
+            variables,
+            bootstrap_methods: BootstrapMethod::from_class(class),
+            temporaries: 0,
+        };
+
+        let mut slot = 0;
+        if !is_static {
+            let this = Local {
+                name: "this".to_string(),
+                typ: Some(class_name(&class.name)),
+            };
+            context.params.push((0, this));
+            slot = 1;
+        }
+        let args = signature
+            .map(|signature| signature.args)
+            .unwrap_or_default();
+        for (i, arg) in args.iter().enumerate() {
+            let local = match context.variable(slot, 0) {
+                Some(local) => local,
+                None => Local {
+                    name: format!("arg{}", i),
+                    typ: Some(java_type(arg)),
+                },
+            };
+            context.params.push((slot, local));
+            slot += if is_wide(arg) { 2 } else { 1 };
+        }
+
+        context
+    }
+
+    /// The local variable in a slot at an offset. `typ` is used for locals
+    /// that the LocalVariableTable does not cover.
+    fn local(self: &Method<'a>, slot: u16, offset: u32, typ: Option<&str>) -> Local {
+        if let Some(local) = self.variable(slot, offset) {
+            return local;
+        }
+        if let Some((_, local)) = self.params.iter().find(|(s, _)| *s == slot) {
+            return local.clone();
+        }
+
+        Local {
+            name: format!("local{}", slot),
+            typ: typ.map(|typ| typ.to_string()),
+        }
+    }
+
+    fn variable(self: &Method<'a>, slot: u16, offset: u32) -> Option<Local> {
+        let variable = self
+            .variables
+            .iter()
+            .find(|v| v.slot == slot && offset >= v.start && offset < v.end)?;
+        Some(Local {
+            name: variable.name.clone(),
+            typ: Some(type_name(&variable.descriptor)),
+        })
+    }
+
+    fn temporary(self: &mut Method<'a>) -> Local {
+        self.temporaries += 1;
+        Local {
+            name: format!("tmp{}", self.temporaries - 1),
+            typ: None,
+        }
+    }
+
+    fn constant(self: &Method<'a>, index: u16) -> (Expr, bool) {
+        constant(self.pool, index)
+    }
+}
+
+/// Source form of a loadable constant, and whether it takes two stack slots
+pub(crate) fn constant(pool: &ConstPool, index: u16) -> (Expr, bool) {
+    match pool.get(index) {
+        Some(JvmConst::Integer(val)) => (Expr::Int(*val as i32), false),
+        Some(JvmConst::Float(bits)) => (Expr::Literal(float_literal(f32::from_bits(*bits))), false),
+        Some(JvmConst::Long(high, low)) => {
+            let val = ((*high as u64) << 32 | *low as u64) as i64;
+            (Expr::Literal(format!("{}L", val)), true)
+        }
+        Some(JvmConst::Double(high, low)) => {
+            let val = f64::from_bits((*high as u64) << 32 | *low as u64);
+            (Expr::Literal(double_literal(val)), true)
+        }
+        Some(JvmConst::String(val)) => (
+            Expr::Literal(string_literal(pool.utf8(*val).unwrap_or(""))),
+            false,
+        ),
+        Some(JvmConst::Class(_)) => {
+            let name = pool.class_name(index).unwrap_or("?");
+            (
+                Expr::Literal(format!("{}.class", reference_type(name))),
+                false,
+            )
+        }
+        Some(JvmConst::MethodType(descriptor)) => (
+            Expr::Dynamic {
+                name: "MethodType".to_string(),
+                args: vec![Expr::Literal(string_literal(
+                    pool.utf8(*descriptor).unwrap_or("?"),
+                ))],
+            },
+            false,
+        ),
+        Some(JvmConst::MethodHandle(_, reference)) => {
+            let (class, name, _) = pool.member_ref(*reference).unwrap_or(("?", "?", "?"));
+            (
+                Expr::MethodRef {
+                    target: CallTarget::Static(class.to_string()),
+                    name: name.to_string(),
+                },
+                false,
+            )
+        }
+        Some(JvmConst::Dynamic(_, nat)) => {
+            let (name, descriptor) = pool.name_and_type(*nat).unwrap_or(("?", "?"));
+            let wide = matches!(descriptor, "J" | "D");
+            (
+                Expr::Dynamic {
+                    name: name.to_string(),
+                    args: vec![],
+                },
+                wide,
+            )
+        }
+        _ => (
+            Expr::Literal(format!("/* bad constant #{} */", index)),
+            false,
+        ),
+    }
+}
+
+/// A value on the simulated operand stack
+#[derive(Debug, Clone)]
+pub(crate) struct Value {
+    pub expr: Expr,
+    /// Whether the value is a long or double, taking two slots
+    pub wide: bool,
+}
+
+/// How control leaves a block, naming other blocks by index
+#[derive(Debug, Clone)]
+pub(crate) enum Exit {
+    /// Returns, throws or leaves through `ret`
+    End,
+    Goto(usize),
+    If {
+        cond: Expr,
+        taken: usize,
+        fall: usize,
+    },
+    Switch {
+        expr: Expr,
+        cases: Vec<(i32, usize)>,
+        default: usize,
+    },
+}
+
+impl Exit {
+    pub fn successors(self: &Exit) -> Vec<usize> {
+        match self {
+            Exit::End => vec![],
+            Exit::Goto(target) => vec![*target],
+            Exit::If { taken, fall, .. } => vec![*fall, *taken],
+            Exit::Switch { cases, default, .. } => {
+                let mut all: Vec<usize> = cases.iter().map(|(_, target)| *target).collect();
+                all.push(*default);
+                all
+            }
+        }
+    }
+}
+
+/// A basic block turned into statements
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub offset: u32,
+    pub stmts: Vec<Stmt>,
+    pub exit: Exit,
+}
+
+/// Turns every block of a method into statements. Values left on the stack
+/// at the end of a block, as in a `?:`, are passed on through `stackN`
+/// variables.
+pub(crate) fn execute(method: &mut Method, cfg: &ControlFlowGraph, code: &Code) -> Vec<Node> {
+    let starts: BTreeMap<u32, usize> = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| (block.start, i))
+        .collect();
+
+    let count = cfg.blocks.len();
+    let mut entries: Vec<Option<Vec<Value>>> = vec![None; count];
+    entries[0] = Some(vec![]);
+    for handler in &code.exception_table {
+        if let Some(i) = starts.get(&(handler.handler_pc as u32)) {
+            let caught = match method.pool.class_name(handler.catch_type) {
+                Some(name) => class_name(name),
+                None => "Throwable".to_string(),
+            };
+            entries[*i] = Some(vec![Value {
+                expr: Expr::Caught(caught),
+                wide: false,
+            }]);
+        }
+    }
+
+    let mut nodes: Vec<Option<Node>> = vec![None; count];
+    let mut worklist: Vec<usize> = (0..count).rev().filter(|i| entries[*i].is_some()).collect();
+    loop {
+        while let Some(i) = worklist.pop() {
+            if nodes[i].is_some() {
+                continue;
+            }
+
+            let state = State {
+                method: &mut *method,
+                stack: entries[i].take().unwrap_or_default(),
+                stmts: vec![],
+            };
+            let (node, stack) = state.run(&cfg.blocks[i], &starts);
+            for successor in node.exit.successors() {
+                if nodes[successor].is_none() && entries[successor].is_none() {
+                    entries[successor] = Some(stack.clone());
+                    worklist.push(successor);
+                }
+            }
+            nodes[i] = Some(node);
+        }
+
+        // Blocks that are never reached start with an empty stack
+        match nodes.iter().position(|node| node.is_none()) {
+            Some(i) => {
+                entries[i] = Some(vec![]);
+                worklist.push(i);
+            }
+            None => break,
+        }
+    }
+
+    nodes.into_iter().map(|node| node.unwrap()).collect()
+}
+
+/// What a statement changes, which decides the stack values that must be
+/// evaluated before it
+enum Effect {
+    Local(String),
+    Memory,
+}
+
+struct State<'m, 'a> {
+    method: &'m mut Method<'a>,
+    stack: Vec<Value>,
+    stmts: Vec<Stmt>,
+}
+
+impl<'m, 'a> State<'m, 'a> {
+    fn run(mut self, block: &BasicBlock, starts: &BTreeMap<u32, usize>) -> (Node, Vec<Value>) {
+        let mut exit = None;
+        for ins in &block.instructions {
+            exit = self.instruction(ins, starts);
+        }
+        let exit = match exit {
+            Some(exit) => exit,
+            None => match starts.get(&block.end) {
+                Some(next) => Exit::Goto(*next),
+                None => Exit::End,
+            },
+        };
+
+        let mut stack = vec![];
+        for (i, value) in self.stack.into_iter().enumerate() {
+            // An object waiting for its constructor is the same on every path
+            if let Expr::Uninit { .. } = value.expr {
+                stack.push(value);
+                continue;
+            }
+            let var = Expr::Local(Local {
+                name: format!("stack{}", i),
+                typ: None,
+            });
+            if value.expr != var {
+                self.stmts.push(Stmt::Assign {
+                    target: var.clone(),
+                    value: value.expr,
+                });
+            }
+            stack.push(Value {
+                expr: var,
+                wide: value.wide,
+            });
+        }
+
+        let node = Node {
+            offset: block.start,
+            stmts: self.stmts,
+            exit,
+        };
+        (node, stack)
+    }
+
+    fn push(self: &mut State<'m, 'a>, expr: Expr, wide: bool) {
+        self.stack.push(Value { expr, wide });
+    }
+
+    fn pop_value(self: &mut State<'m, 'a>) -> Value {
+        self.stack.pop().unwrap_or(Value {
+            expr: Expr::Literal("/* empty stack */".to_string()),
+            wide: false,
+        })
+    }
+
+    fn pop(self: &mut State<'m, 'a>) -> Expr {
+        self.pop_value().expr
+    }
+
+    /// Pops a value that is about to be duplicated, first moving it into a
+    /// temporary if evaluating it twice would repeat a side effect
+    fn pop_shared(self: &mut State<'m, 'a>) -> Value {
+        let impure = match self.stack.last() {
+            Some(value) => !value.expr.is_pure(),
+            None => false,
+        };
+        if impure {
+            let top = self.stack.len() - 1;
+            if has_side_effects(&self.stack[top].expr) {
+                self.spill_where(|expr| !expr.is_pure());
+            } else {
+                // A read can stay behind other reads, but not side effects
+                self.spill_where(has_side_effects);
+                self.spill(top);
+            }
+        }
+        self.pop_value()
+    }
+
+    /// Moves the stack values matching `f` into temporaries, so that they
+    /// keep their place in the evaluation order
+    fn spill_where<F: Fn(&Expr) -> bool>(self: &mut State<'m, 'a>, f: F) {
+        for i in 0..self.stack.len() {
+            if f(&self.stack[i].expr) {
+                self.spill(i);
+            }
+        }
+    }
+
+    fn spill(self: &mut State<'m, 'a>, i: usize) {
+        let tmp = Expr::Local(self.method.temporary());
+        let value = std::mem::replace(&mut self.stack[i].expr, tmp.clone());
+        self.stmts.push(Stmt::Assign { target: tmp, value });
+    }
+
+    fn statement(self: &mut State<'m, 'a>, stmt: Stmt, effect: Effect) {
+        match effect {
+            Effect::Local(name) => self.spill_where(|expr| {
+                expr.uses_local(&name) || matches!(expr, Expr::PostIncrement { .. })
+            }),
+            Effect::Memory => self.spill_where(|expr| !expr.is_pure()),
+        }
+        self.stmts.push(stmt);
+    }
+
+    /// Drops a popped value, keeping it as a statement if it has side effects
+    fn discard(self: &mut State<'m, 'a>, expr: Expr) {
+        if matches!(
+            expr,
+            Expr::Call { .. }
+                | Expr::New { .. }
+                | Expr::Dynamic { .. }
+                | Expr::PostIncrement { .. }
+        ) {
+            self.statement(Stmt::Expr(expr), Effect::Memory);
+        }
+    }
+
+    fn store(self: &mut State<'m, 'a>, target: Expr, value: Expr) {
+        let effect = match &target {
+            Expr::Local(local) => Effect::Local(local.name.clone()),
+            _ => Effect::Memory,
+        };
+        self.statement(Stmt::Assign { target, value }, effect);
+    }
+
+    fn increment(self: &mut State<'m, 'a>, local: Local, delta: i16) {
+        let users: Vec<usize> = (0..self.stack.len())
+            .filter(|i| self.stack[*i].expr.uses_local(&local.name))
+            .collect();
+        let target = Expr::Local(local.clone());
+        if let [user] = users.as_slice() {
+            if self.stack[*user].expr == target && (delta == 1 || delta == -1) {
+                self.stack[*user].expr = Expr::PostIncrement { local, delta };
+                return;
+            }
+        }
+
+        let value = if delta < 0 {
+            Expr::binary("-", target.clone(), Expr::Int(-(delta as i32)))
+        } else {
+            Expr::binary("+", target.clone(), Expr::Int(delta as i32))
+        };
+        self.store(target, value);
+    }
+
+    fn instruction(
+        self: &mut State<'m, 'a>,
+        ins: &Instruction,
+        starts: &BTreeMap<u32, usize>,
+    ) -> Option<Exit> {
+        let op = ins.opcode as u8;
+        let next = ins.offset + ins.length;
+        let block = |offset: u32| starts.get(&offset).copied().unwrap_or(0);
+        let constant = match ins.operand {
+            Operand::Constant(index)
+            | Operand::InvokeInterface(index, _)
+            | Operand::MultiArray(index, _) => index,
+            _ => 0,
+        };
+        let branch = match ins.operand {
+            Operand::Branch(target) => block(target),
+            _ => 0,
+        };
+
+        match ins.opcode {
+            Opcode::Nop | Opcode::Wide | Opcode::BreakPoint => {}
+            Opcode::AConstNull => self.push(Expr::Literal("null".to_string()), false),
+            Opcode::IConstM1
+            | Opcode::IConst0
+            | Opcode::IConst1
+            | Opcode::IConst2
+            | Opcode::IConst3
+            | Opcode::IConst4
+            | Opcode::IConst5 => self.push(Expr::Int(op as i32 - Opcode::IConst0 as i32), false),
+            Opcode::LConst0 | Opcode::LConst1 => self.push(
+                Expr::Literal(format!("{}L", op - Opcode::LConst0 as u8)),
+                true,
+            ),
+            Opcode::FConst0 | Opcode::FConst1 | Opcode::FConst2 => self.push(
+                Expr::Literal(format!("{}.0f", op - Opcode::FConst0 as u8)),
+                false,
+            ),
+            Opcode::DConst0 | Opcode::DConst1 => self.push(
+                Expr::Literal(format!("{}.0", op - Opcode::DConst0 as u8)),
+                true,
+            ),
+            Opcode::BiPush | Opcode::SiPush => {
+                let val = match ins.operand {
+                    Operand::Byte(val) => val as i32,
+                    Operand::Short(val) => val as i32,
+                    _ => 0,
+                };
+                self.push(Expr::Int(val), false);
+            }
+            Opcode::Ldc | Opcode::LdcW | Opcode::Ldc2W => {
+                let (expr, wide) = self.method.constant(constant);
+                self.push(expr, wide);
+            }
+            Opcode::ILoad
+            | Opcode::LLoad
+            | Opcode::FLoad
+            | Opcode::DLoad
+            | Opcode::ALoad
+            | Opcode::ILoad0
+            | Opcode::ILoad1
+            | Opcode::ILoad2
+            | Opcode::ILoad3
+            | Opcode::LLoad0
+            | Opcode::LLoad1
+            | Opcode::LLoad2
+            | Opcode::LLoad3
+            | Opcode::FLoad0
+            | Opcode::FLoad1
+            | Opcode::FLoad2
+            | Opcode::FLoad3
+            | Opcode::DLoad0
+            | Opcode::DLoad1
+            | Opcode::DLoad2
+            | Opcode::DLoad3
+            | Opcode::ALoad0
+            | Opcode::ALoad1
+            | Opcode::ALoad2
+            | Opcode::ALoad3 => {
+                let kind = if op <= Opcode::ALoad as u8 {
+                    op - Opcode::ILoad as u8
+                } else {
+                    (op - Opcode::ILoad0 as u8) / 4
+                };
+                let local = self.method.local(ins.local().unwrap(), ins.offset, None);
+                self.push(Expr::Local(local), kind == 1 || kind == 3);
+            }
+            Opcode::IaLoad
+            | Opcode::LaLoad
+            | Opcode::FaLoad
+            | Opcode::DaLoad
+            | Opcode::AaLoad
+            | Opcode::BaLoad
+            | Opcode::CaLoad
+            | Opcode::SaLoad => {
+                let index = self.pop();
+                let array = self.pop();
+                let wide = matches!(ins.opcode, Opcode::LaLoad | Opcode::DaLoad);
+                self.push(
+                    Expr::Index {
+                        array: Box::new(array),
+                        index: Box::new(index),
+                    },
+                    wide,
+                );
+            }
+            Opcode::IStore
+            | Opcode::LStore
+            | Opcode::FStore
+            | Opcode::DStore
+            | Opcode::AStore
+            | Opcode::IStore0
+            | Opcode::IStore1
+            | Opcode::IStore2
+            | Opcode::IStore3
+            | Opcode::LStore0
+            | Opcode::LStore1
+            | Opcode::LStore2
+            | Opcode::LStore3
+            | Opcode::FStore0
+            | Opcode::FStore1
+            | Opcode::FStore2
+            | Opcode::FStore3
+            | Opcode::DStore0
+            | Opcode::DStore1
+            | Opcode::DStore2
+            | Opcode::DStore3
+            | Opcode::AStore0
+            | Opcode::AStore1
+            | Opcode::AStore2
+            | Opcode::AStore3 => {
+                let kind = if op <= Opcode::AStore as u8 {
+                    op - Opcode::IStore as u8
+                } else {
+                    (op - Opcode::IStore0 as u8) / 4
+                };
+                let typ = ["int", "long", "float", "double"]
+                    .get(kind as usize)
+                    .copied();
+                // A variable's scope starts after the store that sets it
+                let slot = ins.local().unwrap();
+                let local = match self.method.variable(slot, next) {
+                    Some(local) => local,
+                    None => self.method.local(slot, ins.offset, typ),
+                };
+                let value = self.pop();
+                self.store(Expr::Local(local), value);
+            }
+            Opcode::IaStore
+            | Opcode::LaStore
+            | Opcode::FaStore
+            | Opcode::DaStore
+            | Opcode::AaStore
+            | Opcode::BaStore
+            | Opcode::CaStore
+            | Opcode::SaStore => {
+                let value = self.pop();
+                let index = self.pop();
+                let array = self.pop();
+                let target = Expr::Index {
+                    array: Box::new(array),
+                    index: Box::new(index),
+                };
+                self.store(target, value);
+            }
+            Opcode::Pop => {
+                let expr = self.pop();
+                self.discard(expr);
+            }
+            Opcode::Pop2 => {
+                let value = self.pop_value();
+                self.discard(value.expr);
+                if !value.wide {
+                    let expr = self.pop();
+                    self.discard(expr);
+                }
+            }
+            Opcode::Dup => {
+                let v1 = self.pop_shared();
+                self.stack.extend(vec![v1.clone(), v1]);
+            }
+            Opcode::DupX1 => {
+                let v1 = self.pop_shared();
+                let v2 = self.pop_value();
+                self.stack.extend(vec![v1.clone(), v2, v1]);
+            }
+            Opcode::DupX2 => {
+                let v1 = self.pop_shared();
+                let v2 = self.pop_value();
+                if v2.wide {
+                    self.stack.extend(vec![v1.clone(), v2, v1]);
+                } else {
+                    let v3 = self.pop_value();
+                    self.stack.extend(vec![v1.clone(), v3, v2, v1]);
+                }
+            }
+            Opcode::Dup2 => {
+                let v1 = self.pop_shared();
+                if v1.wide {
+                    self.stack.extend(vec![v1.clone(), v1]);
+                } else {
+                    let v2 = self.pop_shared();
+                    self.stack.extend(vec![v2.clone(), v1.clone(), v2, v1]);
+                }
+            }
+            Opcode::Dup2X1 => {
+                let v1 = self.pop_shared();
+                if v1.wide {
+                    let v2 = self.pop_value();
+                    self.stack.extend(vec![v1.clone(), v2, v1]);
+                } else {
+                    let v2 = self.pop_shared();
+                    let v3 = self.pop_value();
+                    self.stack.extend(vec![v2.clone(), v1.clone(), v3, v2, v1]);
+                }
+            }
+            Opcode::Dup2X2 => {
+                let v1 = self.pop_shared();
+                if v1.wide {
+                    let v2 = self.pop_value();
+                    if v2.wide {
+                        self.stack.extend(vec![v1.clone(), v2, v1]);
+                    } else {
+                        let v3 = self.pop_value();
+                        self.stack.extend(vec![v1.clone(), v3, v2, v1]);
+                    }
+                } else {
+                    let v2 = self.pop_shared();
+                    let v3 = self.pop_value();
+                    if v3.wide {
+                        self.stack.extend(vec![v2.clone(), v1.clone(), v3, v2, v1]);
+                    } else {
+                        let v4 = self.pop_value();
+                        self.stack
+                            .extend(vec![v2.clone(), v1.clone(), v4, v3, v2, v1]);
+                    }
+                }
+            }
+            Opcode::Swap => {
+                let v1 = self.pop_value();
+                let v2 = self.pop_value();
+                self.stack.extend(vec![v1, v2]);
+            }
+            Opcode::IAdd
+            | Opcode::LAdd
+            | Opcode::FAdd
+            | Opcode::DAdd
+            | Opcode::ISub
+            | Opcode::LSub
+            | Opcode::FSub
+            | Opcode::DSub
+            | Opcode::IMul
+            | Opcode::LMul
+            | Opcode::FMul
+            | Opcode::DMul
+            | Opcode::IDiv
+            | Opcode::LDiv
+            | Opcode::FDiv
+            | Opcode::DDiv
+            | Opcode::IRem
+            | Opcode::LRem
+            | Opcode::FRem
+            | Opcode::DRem => {
+                let index = op - Opcode::IAdd as u8;
+                let operator = ["+", "-", "*", "/", "%"][index as usize / 4];
+                let right = self.pop();
+                let left = self.pop();
+                self.push(Expr::binary(operator, left, right), index % 2 == 1);
+            }
+            Opcode::INeg | Opcode::LNeg | Opcode::FNeg | Opcode::DNeg => {
+                let expr = self.pop();
+                let wide = (op - Opcode::INeg as u8) % 2 == 1;
+                self.push(
+                    Expr::Unary {
+                        op: "-",
+                        expr: Box::new(expr),
+                    },
+                    wide,
+                );
+            }
+            Opcode::IShl
+            | Opcode::LShl
+            | Opcode::IShr
+            | Opcode::LShr
+            | Opcode::IuShr
+            | Opcode::LuShr
+            | Opcode::IAnd
+            | Opcode::LAnd
+            | Opcode::IOr
+            | Opcode::LOr
+            | Opcode::IXOr
+            | Opcode::LXOr => {
+                let index = op - Opcode::IShl as u8;
+                let operator = ["<<", ">>", ">>>", "&", "|", "^"][index as usize / 2];
+                let right = self.pop();
+                let left = self.pop();
+                // javac negates a boolean by xor-ing it with 1
+                if ins.opcode == Opcode::IXOr && right == Expr::Int(1) && left.is_boolean() {
+                    self.push(left.negate(), false);
+                } else {
+                    self.push(Expr::binary(operator, left, right), index % 2 == 1);
+                }
+            }
+            Opcode::IInc => {
+                if let Operand::Increment(slot, delta) = ins.operand {
+                    let local = self.method.local(slot, ins.offset, Some("int"));
+                    self.increment(local, delta);
+                }
+            }
+            Opcode::I2L
+            | Opcode::I2F
+            | Opcode::I2D
+            | Opcode::L2I
+            | Opcode::L2F
+            | Opcode::L2D
+            | Opcode::F2I
+            | Opcode::F2L
+            | Opcode::F2D
+            | Opcode::D2I
+            | Opcode::D2L
+            | Opcode::D2F
+            | Opcode::I2B
+            | Opcode::I2C
+            | Opcode::I2S => {
+                let typ = match ins.opcode {
+                    Opcode::I2L | Opcode::F2L | Opcode::D2L => "long",
+                    Opcode::I2F | Opcode::L2F | Opcode::D2F => "float",
+                    Opcode::I2D | Opcode::L2D | Opcode::F2D => "double",
+                    Opcode::I2B => "byte",
+                    Opcode::I2C => "char",
+                    Opcode::I2S => "short",
+                    _ => "int",
+                };
+                let expr = self.pop();
+                self.push(
+                    Expr::Cast {
+                        typ: typ.to_string(),
+                        expr: Box::new(expr),
+                    },
+                    typ == "long" || typ == "double",
+                );
+            }
+            Opcode::LCmp | Opcode::FCmpl | Opcode::FCmpg | Opcode::DCmpl | Opcode::DCmpg => {
+                let typ = match ins.opcode {
+                    Opcode::LCmp => "Long",
+                    Opcode::FCmpl | Opcode::FCmpg => "Float",
+                    _ => "Double",
+                };
+                let right = self.pop();
+                let left = self.pop();
+                self.push(
+                    Expr::Compare {
+                        typ,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                    false,
+                );
+            }
+            Opcode::IfEq
+            | Opcode::IfNe
+            | Opcode::IfLt
+            | Opcode::IfGe
+            | Opcode::IfGt
+            | Opcode::IfLe => {
+                let operator = comparison(op - Opcode::IfEq as u8);
+                let value = self.pop();
+                let cond = match value {
+                    Expr::Compare { left, right, .. } => Expr::Binary {
+                        op: operator,
+                        left,
+                        right,
+                    },
+                    value if value.is_boolean() && operator == "==" => value.negate(),
+                    value if value.is_boolean() && operator == "!=" => value,
+                    value => Expr::binary(operator, value, Expr::Int(0)),
+                };
+                return Some(Exit::If {
+                    cond,
+                    taken: branch,
+                    fall: block(next),
+                });
+            }
+            Opcode::IfIcmpEq
+            | Opcode::IfIcmpNe
+            | Opcode::IfIcmpLt
+            | Opcode::IfIcmpGe
+            | Opcode::IfIcmpGt
+            | Opcode::IfIcmpLe
+            | Opcode::IfAcmpEq
+            | Opcode::IfAcmpNe => {
+                let operator = comparison((op - Opcode::IfIcmpEq as u8) % 6);
+                let right = self.pop();
+                let left = self.pop();
+                return Some(Exit::If {
+                    cond: Expr::binary(operator, left, right),
+                    taken: branch,
+                    fall: block(next),
+                });
+            }
+            Opcode::IfNull | Opcode::IfNonNull => {
+                let operator = if ins.opcode == Opcode::IfNull {
+                    "=="
+                } else {
+                    "!="
+                };
+                let value = self.pop();
+                return Some(Exit::If {
+                    cond: Expr::binary(operator, value, Expr::Literal("null".to_string())),
+                    taken: branch,
+                    fall: block(next),
+                });
+            }
+            Opcode::Goto | Opcode::GotoW => return Some(Exit::Goto(branch)),
+            Opcode::Jsr | Opcode::JsrW => {
+                // Subroutines are shown on their own, as calls cannot be
+                // expressed in Java
+                if let Operand::Branch(target) = ins.operand {
+                    self.stmts.push(Stmt::Comment(format!("jsr {}", target)));
+                }
+                return Some(match starts.get(&next) {
+                    Some(next) => Exit::Goto(*next),
+                    None => Exit::End,
+                });
+            }
+            Opcode::Ret => {
+                self.stmts.push(Stmt::Comment("ret".to_string()));
+                return Some(Exit::End);
+            }
+            Opcode::TableSwitch | Opcode::LookupSwitch => {
+                let expr = self.pop();
+                let (default, cases) = match &ins.operand {
+                    Operand::TableSwitch {
+                        default,
+                        low,
+                        targets,
+                        ..
+                    } => (
+                        *default,
+                        targets
+                            .iter()
+                            .enumerate()
+                            .map(|(i, target)| (low + i as i32, block(*target)))
+                            .collect(),
+                    ),
+                    Operand::LookupSwitch { default, pairs } => (
+                        *default,
+                        pairs
+                            .iter()
+                            .map(|(key, target)| (*key, block(*target)))
+                            .collect(),
+                    ),
+                    _ => (next, vec![]),
+                };
+                return Some(Exit::Switch {
+                    expr,
+                    cases,
+                    default: block(default),
+                });
+            }
+            Opcode::IReturn
+            | Opcode::LReturn
+            | Opcode::FReturn
+            | Opcode::DReturn
+            | Opcode::AReturn => {
+                let value = self.pop();
+                self.stmts.push(Stmt::Return(Some(value)));
+                return Some(Exit::End);
+            }
+            Opcode::Return => {
+                self.stmts.push(Stmt::Return(None));
+                return Some(Exit::End);
+            }
+            Opcode::GetStatic | Opcode::GetField | Opcode::PutStatic | Opcode::PutField => {
+                let (class, name, descriptor) = match self.method.pool.member_ref(constant) {
+                    Some((class, name, descriptor)) => {
+                        (class.to_string(), name.to_string(), descriptor.to_string())
+                    }
+                    None => ("?".to_string(), "?".to_string(), "I".to_string()),
+                };
+                let wide = descriptor == "J" || descriptor == "D";
+                let value = match ins.opcode {
+                    Opcode::PutStatic | Opcode::PutField => Some(self.pop()),
+                    _ => None,
+                };
+                let target = match ins.opcode {
+                    Opcode::GetField | Opcode::PutField => Some(Box::new(self.pop())),
+                    _ => None,
+                };
+                let field = Expr::Field {
+                    target,
+                    class,
+                    name,
+                    descriptor,
+                };
+                match value {
+                    Some(value) => self.store(field, value),
+                    None => self.push(field, wide),
+                }
+            }
+            Opcode::InvokeVirtual
+            | Opcode::InvokeSpecial
+            | Opcode::InvokeStatic
+            | Opcode::InvokeInterface => self.invoke(ins.opcode, constant),
+            Opcode::InvokeDynamic => self.invoke_dynamic(constant),
+            Opcode::New => {
+                let class = self.method.pool.class_name(constant).unwrap_or("?");
+                self.push(
+                    Expr::Uninit {
+                        offset: ins.offset,
+                        class: class.to_string(),
+                    },
+                    false,
+                );
+            }
+            Opcode::NewArray | Opcode::ANewArray | Opcode::MultiANewArray => {
+                let (element, extra, count) = match ins.operand {
+                    Operand::ArrayType(typ) => {
+                        let element = match typ {
+                            4 => "boolean",
+                            5 => "char",
+                            6 => "float",
+                            7 => "double",
+                            8 => "byte",
+                            9 => "short",
+                            11 => "long",
+                            _ => "int",
+                        };
+                        (element.to_string(), 0, 1)
+                    }
+                    Operand::MultiArray(_, count) => {
+                        let name = self.method.pool.class_name(constant).unwrap_or("[?");
+                        let dims = name.chars().take_while(|c| *c == '[').count();
+                        let element = type_name(&name[dims..]);
+                        (element, dims.saturating_sub(count as usize), count as usize)
+                    }
+                    _ => {
+                        let name = self.method.pool.class_name(constant).unwrap_or("?");
+                        let dims = name.chars().take_while(|c| *c == '[').count();
+                        let element = if dims > 0 {
+                            type_name(&name[dims..])
+                        } else {
+                            class_name(name)
+                        };
+                        (element, dims, 1)
+                    }
+                };
+                let mut dims: Vec<Expr> = (0..count).map(|_| self.pop()).collect();
+                dims.reverse();
+                self.push(
+                    Expr::NewArray {
+                        element,
+                        dims,
+                        extra,
+                    },
+                    false,
+                );
+            }
+            Opcode::ArrayLength => {
+                let array = self.pop();
+                self.push(Expr::Length(Box::new(array)), false);
+            }
+            Opcode::AThrow => {
+                let value = self.pop();
+                self.stmts.push(Stmt::Throw(value));
+                return Some(Exit::End);
+            }
+            Opcode::CheckCast | Opcode::InstanceOf => {
+                let typ = reference_type(self.method.pool.class_name(constant).unwrap_or("?"));
+                let expr = Box::new(self.pop());
+                if ins.opcode == Opcode::CheckCast {
+                    self.push(Expr::Cast { typ, expr }, false);
+                } else {
+                    self.push(Expr::InstanceOf { expr, typ }, false);
+                }
+            }
+            Opcode::MonitorEnter | Opcode::MonitorExit => {
+                let expr = self.pop();
+                let enter = ins.opcode == Opcode::MonitorEnter;
+                self.statement(Stmt::Monitor { enter, expr }, Effect::Memory);
+            }
+        }
+
+        None
+    }
+
+    fn invoke(self: &mut State<'m, 'a>, opcode: Opcode, index: u16) {
+        let (class, name, descriptor) = match self.method.pool.member_ref(index) {
+            Some((class, name, descriptor)) => {
+                (class.to_string(), name.to_string(), descriptor.to_string())
+            }
+            None => ("?".to_string(), "?".to_string(), "()V".to_string()),
+        };
+        let signature = match TypeSignature::from_str(&descriptor) {
+            Ok(signature) => signature,
+            Err(_) => return,
+        };
+        let mut args: Vec<Expr> = signature.args.iter().map(|_| self.pop()).collect();
+        args.reverse();
+
+        let target = if opcode == Opcode::InvokeStatic {
+            CallTarget::Static(class.clone())
+        } else {
+            let receiver = self.pop();
+            if name == "<init>" {
+                if let Expr::Uninit { .. } = receiver {
+                    let new = Expr::New { class, args };
+                    let mut constructed = false;
+                    for value in &mut self.stack {
+                        if value.expr == receiver {
+                            value.expr = new.clone();
+                            constructed = true;
+                        }
+                    }
+                    if !constructed {
+                        self.statement(Stmt::Expr(new), Effect::Memory);
+                    }
+                    return;
+                }
+                // A constructor calling `super(...)` or `this(...)`
+                CallTarget::Super
+            } else if opcode == Opcode::InvokeSpecial
+                && class != self.method.class.name
+                && matches!(&receiver, Expr::Local(local) if local.name == "this")
+            {
+                CallTarget::Super
+            } else {
+                CallTarget::Instance(Box::new(receiver))
+            }
+        };
+
+        let call = Expr::Call {
+            target,
+            class,
+            name,
+            descriptor,
+            args,
+        };
+        match signature.ret {
+            JavaType::Primitive(Primitive::Void) => {
+                self.statement(Stmt::Expr(call), Effect::Memory)
+            }
+            ret => self.push(call, is_wide(&ret)),
+        }
+    }
+
+    fn invoke_dynamic(self: &mut State<'m, 'a>, index: u16) {
+        let pool = self.method.pool;
+        let (bootstrap, nat) = match pool.get(index) {
+            Some(JvmConst::InvokeDynamic(bootstrap, nat)) => (*bootstrap, *nat),
+            _ => return,
+        };
+        let (name, descriptor) = pool.name_and_type(nat).unwrap_or(("?", "()V"));
+        let signature = match TypeSignature::from_str(descriptor) {
+            Ok(signature) => signature,
+            Err(_) => return,
+        };
+        let mut args: Vec<Expr> = signature.args.iter().map(|_| self.pop()).collect();
+        args.reverse();
+
+        let bootstrap = self.method.bootstrap_methods.get(bootstrap as usize);
+        let handle = bootstrap.and_then(|bootstrap| method_handle(pool, bootstrap.handle));
+        let static_args: &[u16] = bootstrap.map(|b| b.args.as_slice()).unwrap_or(&[]);
+
+        let expr = match handle {
+            Some((_, _, "makeConcatWithConstants", _)) => {
+                let recipe = match static_args.first().and_then(|arg| pool.get(*arg)) {
+                    Some(JvmConst::String(recipe)) => pool.utf8(*recipe).unwrap_or(""),
+                    _ => "",
+                };
+                let mut args = args.into_iter();
+                let mut constants = static_args.iter().skip(1);
+                let mut parts = vec![];
+                let mut text = String::new();
+                for c in recipe.chars() {
+                    let part = match c {
+                        '\u{1}' => args.next(),
+                        '\u{2}' => constants.next().map(|index| self.method.constant(*index).0),
+                        _ => {
+                            text.push(c);
+                            continue;
+                        }
+                    };
+                    if !text.is_empty() {
+                        parts.push(Expr::Literal(string_literal(&text)));
+                        text.clear();
+                    }
+                    parts.extend(part);
+                }
+                if !text.is_empty() {
+                    parts.push(Expr::Literal(string_literal(&text)));
+                }
+                concat(parts)
+            }
+            Some((_, _, "makeConcat", _)) => concat(args),
+            Some((_, _, "metafactory", _)) | Some((_, _, "altMetafactory", _)) => {
+                match static_args.get(1).and_then(|arg| method_handle(pool, *arg)) {
+                    Some((kind, class, name, descriptor)) => {
+                        let count = match static_args.get(2).and_then(|arg| pool.get(*arg)) {
+                            Some(JvmConst::MethodType(index)) => pool
+                                .utf8(*index)
+                                .and_then(|d| TypeSignature::from_str(d).ok())
+                                .map(|signature| signature.args.len())
+                                .unwrap_or(0),
+                            _ => 0,
+                        };
+                        lambda(kind, class, name, descriptor, args, count)
+                    }
+                    None => Expr::Dynamic {
+                        name: name.to_string(),
+                        args,
+                    },
+                }
+            }
+            _ => Expr::Dynamic {
+                name: name.to_string(),
+                args,
+            },
+        };
+
+        match signature.ret {
+            JavaType::Primitive(Primitive::Void) => {
+                self.statement(Stmt::Expr(expr), Effect::Memory)
+            }
+            ret => self.push(expr, is_wide(&ret)),
+        }
+    }
+}
+
+/// Joins the parts of a string concatenation with `+`, starting with `""`
+/// where the first two parts would otherwise be added as numbers
+fn concat(mut parts: Vec<Expr>) -> Expr {
+    let is_string = |expr: &Expr| matches!(expr, Expr::Literal(text) if text.starts_with('"'));
+    if parts.len() < 2 || !(is_string(&parts[0]) || is_string(&parts[1])) {
+        parts.insert(0, Expr::Literal("\"\"".to_string()));
+    }
+
+    let mut parts = parts.into_iter();
+    let first = parts.next().unwrap();
+    parts.fold(first, |left, right| Expr::binary("+", left, right))
+}
+
+/// A lambda or method reference made by LambdaMetafactory from the
+/// implementation method, the captured values and the number of parameters
+/// the functional interface method takes
+fn lambda(
+    kind: u8,
+    class: &str,
+    name: &str,
+    descriptor: &str,
+    mut captured: Vec<Expr>,
+    count: usize,
+) -> Expr {
+    let params: Vec<String> = (0..count).map(|i| format!("p{}", i)).collect();
+    let mut args = captured.clone();
+    args.extend(params.iter().map(|param| {
+        Expr::Local(Local {
+            name: param.clone(),
+            typ: None,
+        })
+    }));
+
+    let body = match kind {
+        // newInvokeSpecial
+        8 if captured.is_empty() => {
+            return Expr::MethodRef {
+                target: CallTarget::Static(class.to_string()),
+                name: "new".to_string(),
+            }
+        }
+        8 => Expr::New {
+            class: class.to_string(),
+            args,
+        },
+        // invokestatic, or an instance method whose receiver is the first
+        // parameter
+        _ if captured.is_empty() => {
+            return Expr::MethodRef {
+                target: CallTarget::Static(class.to_string()),
+                name: name.to_string(),
+            }
+        }
+        6 => Expr::Call {
+            target: CallTarget::Static(class.to_string()),
+            class: class.to_string(),
+            name: name.to_string(),
+            descriptor: descriptor.to_string(),
+            args,
+        },
+        _ => {
+            let receiver = captured.remove(0);
+            if captured.is_empty() {
+                return Expr::MethodRef {
+                    target: CallTarget::Instance(Box::new(receiver)),
+                    name: name.to_string(),
+                };
+            }
+            Expr::Call {
+                target: CallTarget::Instance(Box::new(receiver)),
+                class: class.to_string(),
+                name: name.to_string(),
+                descriptor: descriptor.to_string(),
+                args: args.split_off(1),
+            }
+        }
+    };
+
+    Expr::Lambda {
+        params,
+        body: Box::new(body),
+    }
+}
+
+/// Kind, class, name and descriptor of a MethodHandle constant
+fn method_handle(pool: &ConstPool, index: u16) -> Option<(u8, &str, &str, &str)> {
+    match pool.get(index) {
+        Some(JvmConst::MethodHandle(kind, reference)) => {
+            let (class, name, descriptor) = pool.member_ref(*reference)?;
+            Some((*kind, class, name, descriptor))
+        }
+        _ => None,
+    }
+}
+
+/// Whether evaluating an expression can change memory or throw from a call
+fn has_side_effects(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call { .. }
+        | Expr::New { .. }
+        | Expr::Dynamic { .. }
+        | Expr::PostIncrement { .. } => true,
+        _ => expr.children().into_iter().any(has_side_effects),
+    }
+}
+
+fn comparison(index: u8) -> &'static str {
+    ["==", "!=", "<", ">=", ">", "<="][index as usize]
+}
+
+fn is_wide(typ: &JavaType) -> bool {
+    matches!(
+        typ,
+        JavaType::Primitive(Primitive::Long) | JavaType::Primitive(Primitive::Double)
+    )
+}
+
+/// Java name of a class constant, which may also name an array type
+fn reference_type(name: &str) -> String {
+    if name.starts_with('[') {
+        type_name(name)
+    } else {
+        class_name(name)
+    }
+}
+
+fn float_literal(val: f32) -> String {
+    if val.is_nan() {
+        "Float.NaN".to_string()
+    } else if val.is_infinite() {
+        let sign = if val > 0.0 { "POSITIVE" } else { "NEGATIVE" };
+        format!("Float.{}_INFINITY", sign)
+    } else {
+        format!("{:?}f", val)
+    }
+}
+
+fn double_literal(val: f64) -> String {
+    if val.is_nan() {
+        "Double.NaN".to_string()
+    } else if val.is_infinite() {
+        let sign = if val > 0.0 { "POSITIVE" } else { "NEGATIVE" };
+        format!("Double.{}_INFINITY", sign)
+    } else {
+        format!("{:?}", val)
+    }
+}
+
+fn local_variables(data: &[u8], pool: &ConstPool) -> Vec<LocalVariable> {
+    let u2 = |pos: usize| u16::from_be_bytes([data[pos], data[pos + 1]]);
+    if data.len() < 2 {
+        return vec![];
+    }
+
+    let count = u2(0) as usize;
+    let mut variables = vec![];
+    for i in 0..count {
+        let pos = 2 + i * 10;
+        if pos + 10 > data.len() {
+            break;
+        }
+        let start = u2(pos) as u32;
+        if let (Some(name), Some(descriptor)) = (pool.utf8(u2(pos + 4)), pool.utf8(u2(pos + 6))) {
+            variables.push(LocalVariable {
+                start,
+                end: start + u2(pos + 2) as u32,
+                slot: u2(pos + 8),
+                name: name.to_string(),
+                descriptor: descriptor.to_string(),
+            });
+        }
+    }
+
+    variables
+}
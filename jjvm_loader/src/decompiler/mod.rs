@@ -0,0 +1,405 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::attribute::Code;
+use crate::cfg::ControlFlowGraph;
+use crate::class::{Class, Field};
+use crate::flags::{ClassFlag, MethodFlag};
+use crate::signature::{JavaType, Primitive};
+
+mod ast;
+mod exec;
+mod print;
+mod simplify;
+mod structure;
+
+pub use ast::{CallTarget, Case, Catch, Expr, Local, Stmt};
+
+/// Decompiles a class to Java-like source. A method that cannot be
+/// decompiled keeps its declaration, with the error in place of its body.
+pub fn decompile_class(class: &Class) -> String {
+    let mut out = String::new();
+    let (package, simple) = match class.name.rfind('/') {
+        Some(pos) => (Some(&class.name[..pos]), &class.name[pos + 1..]),
+        None => (None, class.name.as_str()),
+    };
+    if let Some(package) = package {
+        writeln!(out, "package {};\n", package.replace('/', ".")).unwrap();
+    }
+
+    let flags = class.flags;
+    let mut header = String::new();
+    if ClassFlag::Public.is_set(flags) {
+        header.push_str("public ");
+    }
+    let kind = if ClassFlag::Annotation.is_set(flags) {
+        "@interface"
+    } else if ClassFlag::Interface.is_set(flags) {
+        "interface"
+    } else if ClassFlag::Enum.is_set(flags) {
+        "enum"
+    } else {
+        if ClassFlag::Abstract.is_set(flags) {
+            header.push_str("abstract ");
+        }
+        if ClassFlag::Final.is_set(flags) {
+            header.push_str("final ");
+        }
+        "class"
+    };
+    write!(header, "{} {}", kind, simple).unwrap();
+
+    let interfaces: Vec<String> = class
+        .interfaces
+        .iter()
+        .map(|name| class_name(name))
+        .collect();
+    if kind == "class" && class.superclass != "java/lang/Object" && !class.superclass.is_empty() {
+        write!(header, " extends {}", class_name(&class.superclass)).unwrap();
+    }
+    if !interfaces.is_empty() {
+        let keyword = if kind == "class" || kind == "enum" {
+            "implements"
+        } else {
+            "extends"
+        };
+        write!(header, " {} {}", keyword, interfaces.join(", ")).unwrap();
+    }
+    writeln!(out, "{} {{", header).unwrap();
+
+    for field in &class.fields {
+        writeln!(out, "    {}", field_source(class, field)).unwrap();
+    }
+
+    for method in &class.methods {
+        out.push('\n');
+        for line in method_source(class, method).lines() {
+            if line.is_empty() {
+                out.push('\n');
+            } else {
+                writeln!(out, "    {}", line).unwrap();
+            }
+        }
+    }
+    out.push_str("}\n");
+
+    out
+}
+
+/// Decompiles the body of a method. Abstract and native methods have an
+/// empty body.
+pub fn decompile_method(class: &Class, method: &Field) -> Result<Vec<Stmt>, String> {
+    let code = match Code::from_method(method, &class.const_pool)? {
+        Some(code) => code,
+        None => return Ok(vec![]),
+    };
+    let mut context = exec::Method::new(class, method, Some(&code));
+    method_body(&mut context, method, &code)
+}
+
+fn method_body(
+    context: &mut exec::Method,
+    method: &Field,
+    code: &Code,
+) -> Result<Vec<Stmt>, String> {
+    let cfg = ControlFlowGraph::build(code)?;
+    let nodes = exec::execute(context, &cfg, code);
+    let stmts = structure::structure(nodes, &cfg, code, context.pool);
+    let mut stmts = simplify::simplify(stmts, context.signature.as_ref());
+
+    // The implicit call to the superclass constructor
+    if method.name == "<init>" {
+        if let Some(Stmt::Expr(Expr::Call {
+            target: CallTarget::Super,
+            class,
+            args,
+            ..
+        })) = stmts.first()
+        {
+            if *class == context.class.superclass && args.is_empty() {
+                stmts.remove(0);
+            }
+        }
+    }
+    Ok(stmts)
+}
+
+/// Java source of a method, from its declaration to the end of its body
+pub fn method_source(class: &Class, method: &Field) -> String {
+    let code = Code::from_method(method, &class.const_pool);
+    let mut context = exec::Method::new(class, method, code.as_ref().ok().and_then(|c| c.as_ref()));
+    let flags = method.flags;
+
+    let mut out = String::new();
+    let modifiers = [
+        (MethodFlag::Public, "public "),
+        (MethodFlag::Protected, "protected "),
+        (MethodFlag::Private, "private "),
+        (MethodFlag::Abstract, "abstract "),
+        (MethodFlag::Static, "static "),
+        (MethodFlag::Final, "final "),
+        (MethodFlag::Synchronized, "synchronized "),
+        (MethodFlag::Native, "native "),
+    ];
+    if method.name == "<clinit>" {
+        out.push_str("static");
+    } else {
+        for (flag, keyword) in modifiers.iter() {
+            if flag.is_set(flags) {
+                out.push_str(keyword);
+            }
+        }
+
+        let params: Vec<&Local> = context
+            .params
+            .iter()
+            .filter(|(_, local)| local.name != "this")
+            .map(|(_, local)| local)
+            .collect();
+        let mut declared: Vec<String> = params
+            .iter()
+            .map(|local| {
+                format!(
+                    "{} {}",
+                    local.typ.as_deref().unwrap_or("Object"),
+                    local.name
+                )
+            })
+            .collect();
+        if MethodFlag::VarArgs.is_set(flags) {
+            if let Some(last) = declared.last_mut() {
+                if let Some(pos) = last.rfind("[] ") {
+                    last.replace_range(pos..pos + 3, "... ");
+                }
+            }
+        }
+
+        if method.name == "<init>" {
+            let simple = class.name.rsplit(['/', '$']).next().unwrap_or("");
+            out.push_str(simple);
+        } else {
+            let ret = match &context.signature {
+                Some(signature) => java_type(&signature.ret),
+                None => "void".to_string(),
+            };
+            write!(out, "{} {}", ret, method.name).unwrap();
+        }
+        write!(out, "({})", declared.join(", ")).unwrap();
+
+        let exceptions = thrown(class, method);
+        if !exceptions.is_empty() {
+            write!(out, " throws {}", exceptions.join(", ")).unwrap();
+        }
+    }
+
+    let code = match code {
+        Ok(Some(code)) => code,
+        Ok(None) => {
+            out.push_str(";\n");
+            return out;
+        }
+        Err(err) => {
+            writeln!(out, " {{\n    // could not decompile: {}\n}}", err).unwrap();
+            return out;
+        }
+    };
+
+    out.push_str(" {\n");
+    match method_body(&mut context, method, &code) {
+        Ok(stmts) => {
+            let params: Vec<Local> = context
+                .params
+                .iter()
+                .map(|(_, local)| local.clone())
+                .collect();
+            let mut printer = print::Printer::new(&class.name, 1, &params);
+            printer.stmts(&stmts);
+            out.push_str(&printer.out);
+        }
+        Err(err) => writeln!(out, "    // could not decompile: {}", err).unwrap(),
+    }
+    out.push_str("}\n");
+
+    out
+}
+
+fn field_source(class: &Class, field: &Field) -> String {
+    let mut out = String::new();
+    let modifiers = [
+        (0x0001, "public "),
+        (0x0004, "protected "),
+        (0x0002, "private "),
+        (0x0008, "static "),
+        (0x0010, "final "),
+        (0x0040, "volatile "),
+        (0x0080, "transient "),
+    ];
+    for (flag, keyword) in modifiers.iter() {
+        if field.flags & flag != 0 {
+            out.push_str(keyword);
+        }
+    }
+    write!(out, "{} {}", type_name(&field.descriptor), field.name).unwrap();
+
+    let initial = field
+        .find_attribute("ConstantValue")
+        .filter(|attribute| attribute.data.len() == 2);
+    if let Some(attribute) = initial {
+        let index = u16::from_be_bytes([attribute.data[0], attribute.data[1]]);
+        let literal = match exec::constant(&class.const_pool, index).0 {
+            Expr::Int(val) => match field.descriptor.as_str() {
+                "Z" => (val != 0).to_string(),
+                "C" => char_literal(val).unwrap_or_else(|| val.to_string()),
+                _ => val.to_string(),
+            },
+            Expr::Literal(text) => text,
+            _ => "?".to_string(),
+        };
+        write!(out, " = {}", literal).unwrap();
+    }
+    out.push(';');
+
+    out
+}
+
+/// Classes named by a method's Exceptions attribute
+fn thrown(class: &Class, method: &Field) -> Vec<String> {
+    let data = match method.find_attribute("Exceptions") {
+        Some(attribute) => &attribute.data,
+        None => return vec![],
+    };
+
+    data.chunks_exact(2)
+        .skip(1)
+        .filter_map(|index| {
+            class
+                .const_pool
+                .class_name(u16::from_be_bytes([index[0], index[1]]))
+        })
+        .map(class_name)
+        .collect()
+}
+
+/// Java name of a class, leaving the package off classes in `java.lang`
+pub(crate) fn class_name(name: &str) -> String {
+    match name.strip_prefix("java/lang/") {
+        Some(simple) if !simple.contains('/') => simple.to_string(),
+        _ => name.replace('/', "."),
+    }
+}
+
+/// Java name of the type in a field descriptor, such as `int[]` for `[I`
+pub(crate) fn type_name(descriptor: &str) -> String {
+    match JavaType::from_str(descriptor) {
+        Ok(typ) => java_type(&typ),
+        Err(_) => descriptor.to_string(),
+    }
+}
+
+pub(crate) fn java_type(typ: &JavaType) -> String {
+    match typ {
+        JavaType::Primitive(primitive) => match primitive {
+            Primitive::Boolean => "boolean",
+            Primitive::Byte => "byte",
+            Primitive::Char => "char",
+            Primitive::Double => "double",
+            Primitive::Float => "float",
+            Primitive::Int => "int",
+            Primitive::Long => "long",
+            Primitive::Short => "short",
+            Primitive::Void => "void",
+        }
+        .to_string(),
+        JavaType::Object(name) => class_name(name),
+        JavaType::Array(element) => format!("{}[]", java_type(element)),
+        JavaType::Method(signature) => signature.to_string(),
+    }
+}
+
+pub(crate) fn string_literal(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            c => push_escaped(&mut out, c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A char literal for an int constant, if it is a valid char
+pub(crate) fn char_literal(val: i32) -> Option<String> {
+    let c = std::char::from_u32(val as u32).filter(|_| (0..=0xffff).contains(&val))?;
+    let mut out = String::from("'");
+    match c {
+        '\'' => out.push_str("\\'"),
+        c => push_escaped(&mut out, c),
+    }
+    out.push('\'');
+    Some(out)
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\0' => out.push_str("\\0"),
+        c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+        c => out.push(c),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::class_with_method;
+
+    #[test]
+    fn test_loop() {
+        // int i = 0; while (i < 10) i++; return i;
+        let class = class_with_method(
+            "()I",
+            2,
+            1,
+            &[
+                0x03, 0x3b, 0x1a, 0x10, 0x0a, 0xa2, 0x00, 0x09, 0x84, 0x00, 0x01, 0xa7, 0xff, 0xf7,
+                0x1a, 0xac,
+            ],
+        );
+        assert_eq!(
+            method_source(&class, &class.methods[0]),
+            "public static int test() {\n    int local0 = 0;\n    while (local0 < 10) {\n        local0++;\n    }\n    return local0;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_if() {
+        // if (a > 0) return 1; return -1;
+        let class = class_with_method(
+            "(I)I",
+            2,
+            1,
+            &[0x1a, 0x9e, 0x00, 0x05, 0x04, 0xac, 0x02, 0xac],
+        );
+        assert_eq!(
+            method_source(&class, &class.methods[0]),
+            "public static int test(int arg0) {\n    if (arg0 > 0) {\n        return 1;\n    }\n    return -1;\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(class_name("java/lang/String"), "String");
+        assert_eq!(
+            class_name("java/lang/invoke/MethodHandle"),
+            "java.lang.invoke.MethodHandle"
+        );
+        assert_eq!(type_name("[[I"), "int[][]");
+        assert_eq!(string_literal("a\"b\n"), "\"a\\\"b\\n\"");
+        assert_eq!(char_literal('\'' as i32).as_deref(), Some("'\\''"));
+        assert_eq!(char_literal(-1), None);
+    }
+}
@@ -0,0 +1,376 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::ast::{binary_precedence, CallTarget, Expr, Local, Stmt};
+use super::class_name;
+
+/// Writes statements out as Java source
+pub(crate) struct Printer<'a> {
+    /// Internal name of the class the code belongs to
+    this_class: &'a str,
+    pub out: String,
+    indent: usize,
+    /// Locals that already have a declaration
+    declared: HashSet<String>,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(this_class: &'a str, indent: usize, params: &[Local]) -> Printer<'a> {
+        Printer {
+            this_class,
+            out: String::new(),
+            indent,
+            declared: params.iter().map(|local| local.name.clone()).collect(),
+        }
+    }
+
+    fn line(self: &mut Printer<'a>, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn block(self: &mut Printer<'a>, stmts: &[Stmt]) {
+        self.indent += 1;
+        self.stmts(stmts);
+        self.indent -= 1;
+    }
+
+    /// Writes a block of statements. A local is declared where it is first
+    /// assigned, or ahead of the first statement using it when that
+    /// statement is not an assignment and the local is used again later.
+    pub fn stmts(self: &mut Printer<'a>, stmts: &[Stmt]) {
+        let saved = self.declared.clone();
+        let used: Vec<BTreeMap<String, Option<String>>> = stmts
+            .iter()
+            .map(|stmt| {
+                let mut locals = BTreeMap::new();
+                stmt_locals(stmt, &mut locals);
+                locals
+            })
+            .collect();
+
+        for (i, stmt) in stmts.iter().enumerate() {
+            for (name, typ) in &used[i] {
+                if self.declared.contains(name) {
+                    continue;
+                }
+                let assigned = matches!(
+                    stmt,
+                    Stmt::Assign { target: Expr::Local(local), .. } if local.name == *name
+                );
+                let later = used[i + 1..].iter().any(|locals| locals.contains_key(name));
+                if !assigned && later {
+                    self.declared.insert(name.clone());
+                    let typ = typ.as_deref().unwrap_or("Object");
+                    self.line(&format!("{} {};", typ, name));
+                }
+            }
+            self.stmt(stmt);
+        }
+        self.declared = saved;
+    }
+
+    fn stmt(self: &mut Printer<'a>, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => {
+                let text = format!("{};", self.expr(expr));
+                self.line(&text);
+            }
+            Stmt::Assign { target, value } => {
+                let text = format!("{};", self.assignment(target, value));
+                self.line(&text);
+            }
+            Stmt::Return(None) => self.line("return;"),
+            Stmt::Return(Some(expr)) => {
+                let text = format!("return {};", self.expr(expr));
+                self.line(&text);
+            }
+            Stmt::Throw(expr) => {
+                let text = format!("throw {};", self.expr(expr));
+                self.line(&text);
+            }
+            Stmt::If { .. } => self.if_chain(stmt, ""),
+            Stmt::While { label, cond, body } => {
+                let text = format!("{}while ({}) {{", label_prefix(label), self.expr(cond));
+                self.line(&text);
+                self.block(body);
+                self.line("}");
+            }
+            Stmt::DoWhile { label, cond, body } => {
+                let text = format!("{}do {{", label_prefix(label));
+                self.line(&text);
+                self.block(body);
+                let text = format!("}} while ({});", self.expr(cond));
+                self.line(&text);
+            }
+            Stmt::Switch { label, expr, cases } => {
+                let text = format!("{}switch ({}) {{", label_prefix(label), self.expr(expr));
+                self.line(&text);
+                self.indent += 1;
+                for case in cases {
+                    for key in &case.keys {
+                        self.line(&format!("case {}:", key));
+                    }
+                    if case.default {
+                        self.line("default:");
+                    }
+                    self.block(&case.body);
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            Stmt::Try { body, catches } => {
+                self.line("try {");
+                self.block(body);
+                for catch in catches {
+                    let saved = self.declared.clone();
+                    self.declared.insert(catch.name.clone());
+                    self.line(&format!("}} catch ({} {}) {{", catch.typ, catch.name));
+                    self.block(&catch.body);
+                    self.declared = saved;
+                }
+                self.line("}");
+            }
+            Stmt::Break(label) => self.line(&jump("break", label)),
+            Stmt::Continue(label) => self.line(&jump("continue", label)),
+            Stmt::Monitor { enter, expr } => {
+                let op = if *enter {
+                    "monitorenter"
+                } else {
+                    "monitorexit"
+                };
+                let text = format!("{}({});", op, self.expr(expr));
+                self.line(&text);
+            }
+            Stmt::Goto(offset) => self.line(&format!("// goto offset {}", offset)),
+            Stmt::Comment(text) => self.line(&format!("// {}", text)),
+        }
+    }
+
+    /// Writes an if statement, joining an else holding only another if into
+    /// an `else if`
+    fn if_chain(self: &mut Printer<'a>, stmt: &Stmt, prefix: &str) {
+        if let Stmt::If { cond, then, els } = stmt {
+            let text = format!("{}if ({}) {{", prefix, self.expr(cond));
+            self.line(&text);
+            self.block(then);
+            match els.as_slice() {
+                [] => self.line("}"),
+                [nested @ Stmt::If { .. }] => self.if_chain(nested, "} else "),
+                _ => {
+                    self.line("} else {");
+                    self.block(els);
+                    self.line("}");
+                }
+            }
+        }
+    }
+
+    fn assignment(self: &mut Printer<'a>, target: &Expr, value: &Expr) -> String {
+        if let Expr::Local(local) = target {
+            if self.declared.insert(local.name.clone()) {
+                let typ = local.typ.as_deref().unwrap_or("var");
+                return format!("{} {} = {}", typ, local.name, self.expr(value));
+            }
+        }
+
+        // x = x + n is x += n
+        if let Expr::Binary { op, left, right } = value {
+            let compound = matches!(
+                *op,
+                "+" | "-" | "*" | "/" | "%" | "&" | "|" | "^" | "<<" | ">>" | ">>>"
+            );
+            if compound && **left == *target && is_simple(target) {
+                let target = self.expr(target);
+                return match (*op, right.as_ref()) {
+                    ("+", Expr::Int(1)) => format!("{}++", target),
+                    ("-", Expr::Int(1)) => format!("{}--", target),
+                    _ => format!("{} {}= {}", target, op, self.expr(right)),
+                };
+            }
+        }
+
+        format!("{} = {}", self.expr(target), self.expr(value))
+    }
+
+    pub fn expr(self: &Printer<'a>, expr: &Expr) -> String {
+        match expr {
+            Expr::Literal(text) => text.clone(),
+            Expr::Int(val) => val.to_string(),
+            Expr::Local(local) => local.name.clone(),
+            Expr::Caught(_) => "e".to_string(),
+            Expr::Uninit { class, .. } => format!("new {}", class_name(class)),
+            Expr::New { class, args } => format!("new {}({})", class_name(class), self.args(args)),
+            Expr::NewArray {
+                element,
+                dims,
+                extra,
+            } => {
+                let dims: Vec<String> = dims
+                    .iter()
+                    .map(|dim| format!("[{}]", self.expr(dim)))
+                    .collect();
+                format!("new {}{}{}", element, dims.concat(), "[]".repeat(*extra))
+            }
+            Expr::ArrayInit { element, values } => {
+                format!("new {}[] {{{}}}", element, self.args(values))
+            }
+            Expr::Field {
+                target,
+                class,
+                name,
+                ..
+            } => match target {
+                Some(target) => format!("{}.{}", self.wrap(target, 15), name),
+                None if class == self.this_class => name.clone(),
+                None => format!("{}.{}", class_name(class), name),
+            },
+            Expr::Index { array, index } => {
+                format!("{}[{}]", self.wrap(array, 15), self.expr(index))
+            }
+            Expr::Length(array) => format!("{}.length", self.wrap(array, 15)),
+            Expr::Call {
+                target,
+                class,
+                name,
+                args,
+                ..
+            } => {
+                let args = self.args(args);
+                if name == "<init>" {
+                    let which = if class == self.this_class {
+                        "this"
+                    } else {
+                        "super"
+                    };
+                    return format!("{}({})", which, args);
+                }
+                match target {
+                    CallTarget::Static(owner) if owner == self.this_class => {
+                        format!("{}({})", name, args)
+                    }
+                    CallTarget::Static(owner) => {
+                        format!("{}.{}({})", class_name(owner), name, args)
+                    }
+                    CallTarget::Instance(target) => {
+                        format!("{}.{}({})", self.wrap(target, 15), name, args)
+                    }
+                    CallTarget::Super => format!("super.{}({})", name, args),
+                }
+            }
+            Expr::Dynamic { name, args } => {
+                format!("/* invokedynamic */ {}({})", name, self.args(args))
+            }
+            Expr::MethodRef { target, name } => match target {
+                CallTarget::Static(owner) => format!("{}::{}", class_name(owner), name),
+                CallTarget::Instance(target) => format!("{}::{}", self.wrap(target, 15), name),
+                CallTarget::Super => format!("super::{}", name),
+            },
+            Expr::Lambda { params, body } => {
+                let params = match params.as_slice() {
+                    [param] => param.clone(),
+                    _ => format!("({})", params.join(", ")),
+                };
+                format!("{} -> {}", params, self.expr(body))
+            }
+            Expr::Binary { op, left, right } => {
+                let precedence = binary_precedence(op);
+                format!(
+                    "{} {} {}",
+                    self.wrap(left, precedence),
+                    op,
+                    self.wrap(right, precedence + 1)
+                )
+            }
+            Expr::Unary { op, expr } => {
+                let operand = self.wrap(expr, 13);
+                // Keep - -x from reading as a decrement
+                if operand.starts_with(op) {
+                    format!("{}({})", op, operand)
+                } else {
+                    format!("{}{}", op, operand)
+                }
+            }
+            Expr::Cast { typ, expr } => format!("({}) {}", typ, self.wrap(expr, 13)),
+            Expr::InstanceOf { expr, typ } => format!("{} instanceof {}", self.wrap(expr, 9), typ),
+            Expr::Compare { typ, left, right } => {
+                format!("{}.compare({}, {})", typ, self.expr(left), self.expr(right))
+            }
+            Expr::Ternary { cond, then, els } => format!(
+                "{} ? {} : {}",
+                self.wrap(cond, 3),
+                self.wrap(then, 2),
+                self.wrap(els, 2)
+            ),
+            Expr::PostIncrement { local, delta } => {
+                let op = if *delta < 0 { "--" } else { "++" };
+                format!("{}{}", local.name, op)
+            }
+        }
+    }
+
+    /// An operand, in parentheses when it binds less tightly than `min`
+    fn wrap(self: &Printer<'a>, expr: &Expr, min: u8) -> String {
+        let text = self.expr(expr);
+        if expr.precedence() < min {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn args(self: &Printer<'a>, args: &[Expr]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| self.expr(arg)).collect();
+        args.join(", ")
+    }
+}
+
+fn label_prefix(label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("{}: ", label),
+        None => String::new(),
+    }
+}
+
+fn jump(keyword: &str, label: &Option<String>) -> String {
+    match label {
+        Some(label) => format!("{} {};", keyword, label),
+        None => format!("{};", keyword),
+    }
+}
+
+/// Every local a statement reads or writes, with its type when known
+fn stmt_locals(stmt: &Stmt, locals: &mut BTreeMap<String, Option<String>>) {
+    for expr in stmt.exprs() {
+        expr_locals(expr, locals);
+    }
+    for body in stmt.bodies() {
+        for stmt in body {
+            stmt_locals(stmt, locals);
+        }
+    }
+}
+
+fn expr_locals(expr: &Expr, locals: &mut BTreeMap<String, Option<String>>) {
+    if let Expr::Local(local) | Expr::PostIncrement { local, .. } = expr {
+        let typ = locals.entry(local.name.clone()).or_insert(None);
+        if typ.is_none() {
+            *typ = local.typ.clone();
+        }
+    }
+    for child in expr.children() {
+        expr_locals(child, locals);
+    }
+}
+
+/// Whether a compound assignment can name the target once without skipping
+/// a side effect
+fn is_simple(target: &Expr) -> bool {
+    match target {
+        Expr::Local(_) => true,
+        Expr::Field { target, .. } => target.iter().all(|target| target.is_pure()),
+        _ => false,
+    }
+}
@@ -0,0 +1,726 @@
+use std::collections::HashMap;
+
+use super::ast::{ends_in_jump, walk_stmts_mut, Catch, Expr, Local, Stmt};
+use super::char_literal;
+use crate::signature::{JavaType, Primitive, TypeSignature};
+
+/// Tidies structured statements into something closer to what was written:
+/// loop conditions, `?:`, array initializers and fewer temporaries
+pub(crate) fn simplify(stmts: Vec<Stmt>, signature: Option<&TypeSignature>) -> Vec<Stmt> {
+    let mut stmts = tidy(stmts);
+    for_each_list(&mut stmts, &mut fold_array_inits);
+
+    let mut counts = HashMap::new();
+    count_locals(&mut stmts, &mut counts);
+    for_each_list(&mut stmts, &mut |list| {
+        fold_copies(list, &mut counts);
+        inline(list, &mut counts);
+    });
+
+    coerce_stmts(&mut stmts, signature.map(|signature| &signature.ret));
+    if let Some(Stmt::Return(None)) = stmts.last() {
+        stmts.pop();
+    }
+    stmts
+}
+
+/// Whether a local was made up by the decompiler to hold a stack value
+fn is_synthetic(local: &Local) -> bool {
+    local.typ.is_none() && (local.name.starts_with("stack") || local.name.starts_with("tmp"))
+}
+
+fn synthetic_target(stmt: &Stmt) -> Option<&Local> {
+    match stmt {
+        Stmt::Assign {
+            target: Expr::Local(local),
+            ..
+        } if is_synthetic(local) => Some(local),
+        _ => None,
+    }
+}
+
+/// Calls `f` on every statement list, innermost first
+fn for_each_list<F: FnMut(&mut Vec<Stmt>)>(stmts: &mut Vec<Stmt>, f: &mut F) {
+    for stmt in stmts.iter_mut() {
+        for body in stmt.bodies_mut() {
+            for_each_list(body, f);
+        }
+    }
+    f(stmts);
+}
+
+fn tidy(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts.into_iter().flat_map(tidy_stmt).collect()
+}
+
+fn tidy_stmt(stmt: Stmt) -> Vec<Stmt> {
+    match stmt {
+        Stmt::If { cond, then, els } => tidy_if(cond, tidy(then), tidy(els)),
+        Stmt::While { label, cond, body } => vec![tidy_loop(label, cond, tidy(body))],
+        Stmt::DoWhile { label, cond, body } => vec![Stmt::DoWhile {
+            label,
+            cond,
+            body: tidy(body),
+        }],
+        Stmt::Switch {
+            label,
+            expr,
+            mut cases,
+        } => {
+            for case in &mut cases {
+                case.body = tidy(std::mem::take(&mut case.body));
+            }
+            vec![Stmt::Switch { label, expr, cases }]
+        }
+        Stmt::Try { body, catches } => vec![Stmt::Try {
+            body: tidy(body),
+            catches: catches.into_iter().map(tidy_catch).collect(),
+        }],
+        stmt => vec![stmt],
+    }
+}
+
+fn tidy_if(cond: Expr, then: Vec<Stmt>, els: Vec<Stmt>) -> Vec<Stmt> {
+    // A stack value chosen by a condition is a `?:`
+    if let ([a], [b]) = (then.as_slice(), els.as_slice()) {
+        if let (Some(local), Stmt::Assign { value: a, .. }, Stmt::Assign { target, value: b }) =
+            (synthetic_target(a), a, b)
+        {
+            if *target == Expr::Local(local.clone()) {
+                return vec![Stmt::Assign {
+                    target: target.clone(),
+                    value: Expr::Ternary {
+                        cond: Box::new(cond),
+                        then: Box::new(a.clone()),
+                        els: Box::new(b.clone()),
+                    },
+                }];
+            }
+        }
+    }
+
+    if then.is_empty() && els.is_empty() {
+        return if cond.is_pure() {
+            vec![]
+        } else {
+            vec![Stmt::Expr(cond)]
+        };
+    }
+    if then.is_empty() {
+        return vec![Stmt::If {
+            cond: cond.negate(),
+            then: els,
+            els: vec![],
+        }];
+    }
+    // When both branches jump the shorter one goes first, so that the
+    // longer one can follow the if without nesting
+    let then_jumps = ends_in_jump(&then);
+    let els_jumps = ends_in_jump(&els) && !matches!(els.as_slice(), [Stmt::If { .. }]);
+    if !els.is_empty() && els_jumps && (!then_jumps || els.len() < then.len()) {
+        let mut out = vec![Stmt::If {
+            cond: cond.negate(),
+            then: els,
+            els: vec![],
+        }];
+        out.extend(then);
+        return out;
+    }
+    if !els.is_empty() && then_jumps {
+        let mut out = vec![Stmt::If {
+            cond,
+            then,
+            els: vec![],
+        }];
+        out.extend(els);
+        return out;
+    }
+
+    vec![Stmt::If { cond, then, els }]
+}
+
+fn tidy_loop(label: Option<String>, cond: Expr, mut body: Vec<Stmt>) -> Stmt {
+    strip_continue(&mut body, &label);
+
+    // while (true) { if (c) break; ... } is while (!c) { ... }
+    if let Some(Stmt::If { cond, then, els }) = body.first() {
+        if els.is_empty() && is_break(then, &label) {
+            let cond = cond.clone().negate();
+            body.remove(0);
+            return Stmt::While { label, cond, body };
+        }
+    }
+
+    // while (true) { ... if (c) break; } is do { ... } while (!c)
+    if let Some(Stmt::If { cond, then, els }) = body.last() {
+        if els.is_empty() && is_break(then, &label) && !has_continue(&body, &label, true) {
+            let cond = cond.clone().negate();
+            body.pop();
+            return Stmt::DoWhile { label, cond, body };
+        }
+    }
+
+    Stmt::While { label, cond, body }
+}
+
+fn is_break(stmts: &[Stmt], label: &Option<String>) -> bool {
+    match stmts {
+        [Stmt::Break(target)] => target.is_none() || target == label,
+        _ => false,
+    }
+}
+
+/// Removes `continue` statements that are the last thing a loop body does
+fn strip_continue(body: &mut Vec<Stmt>, label: &Option<String>) {
+    match body.last_mut() {
+        Some(Stmt::Continue(target)) if target.is_none() || target == label => {
+            body.pop();
+            strip_continue(body, label);
+        }
+        Some(Stmt::If { then, els, .. }) => {
+            strip_continue(then, label);
+            strip_continue(els, label);
+        }
+        Some(Stmt::Try { body, catches }) => {
+            strip_continue(body, label);
+            for catch in catches {
+                strip_continue(&mut catch.body, label);
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(Stmt::If { cond, then, els }) = body.last() {
+        if then.is_empty() {
+            let stmts = tidy_if(cond.clone(), vec![], els.clone());
+            body.pop();
+            body.extend(stmts);
+        }
+    }
+}
+
+/// Whether a loop body continues the loop. `inner` is set while still inside
+/// the loop itself rather than a nested one.
+fn has_continue(stmts: &[Stmt], label: &Option<String>, inner: bool) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::Continue(None) => inner,
+        Stmt::Continue(target) => target == label,
+        Stmt::If { then, els, .. } => {
+            has_continue(then, label, inner) || has_continue(els, label, inner)
+        }
+        Stmt::While { body, .. } | Stmt::DoWhile { body, .. } => has_continue(body, label, false),
+        Stmt::Switch { cases, .. } => cases
+            .iter()
+            .any(|case| has_continue(&case.body, label, inner)),
+        Stmt::Try { body, catches } => {
+            has_continue(body, label, inner)
+                || catches
+                    .iter()
+                    .any(|catch| has_continue(&catch.body, label, inner))
+        }
+        _ => false,
+    })
+}
+
+/// Names the caught exception after the local it is stored in
+fn tidy_catch(catch: Catch) -> Catch {
+    let mut body = tidy(catch.body);
+    let name = match body.first() {
+        Some(Stmt::Assign {
+            target: Expr::Local(local),
+            value: Expr::Caught(_),
+        }) => local.name.clone(),
+        _ => "e".to_string(),
+    };
+    if let Some(Stmt::Assign {
+        value: Expr::Caught(_),
+        ..
+    }) = body.first()
+    {
+        body.remove(0);
+    }
+
+    let local = Expr::Local(Local {
+        name: name.clone(),
+        typ: Some(catch.typ.clone()),
+    });
+    walk_stmts_mut(&mut body, &mut |expr| {
+        if let Expr::Caught(_) = expr {
+            *expr = local.clone();
+        }
+    });
+
+    Catch {
+        typ: catch.typ,
+        name,
+        body,
+    }
+}
+
+/// Turns a new array filled in element by element into an initializer
+fn fold_array_inits(stmts: &mut Vec<Stmt>) {
+    let mut i = 0;
+    while i < stmts.len() {
+        let (local, element, length) = match &stmts[i] {
+            Stmt::Assign {
+                target: Expr::Local(local),
+                value:
+                    Expr::NewArray {
+                        element,
+                        dims,
+                        extra,
+                    },
+            } if is_synthetic(local) => match dims.as_slice() {
+                [Expr::Int(length)] if *length > 0 => {
+                    let element = format!("{}{}", element, "[]".repeat(*extra));
+                    (local.clone(), element, *length as usize)
+                }
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            },
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let array = Expr::Local(local.clone());
+        let mut values = vec![];
+        for stmt in &stmts[i + 1..] {
+            match stmt {
+                Stmt::Assign {
+                    target: Expr::Index { array: a, index },
+                    value,
+                } if **a == array
+                    && **index == Expr::Int(values.len() as i32)
+                    && !value.uses_local(&local.name) =>
+                {
+                    values.push(value.clone())
+                }
+                _ => break,
+            }
+            if values.len() == length {
+                break;
+            }
+        }
+
+        if values.len() == length {
+            stmts.drain(i + 1..i + 1 + length);
+            stmts[i] = Stmt::Assign {
+                target: array,
+                value: Expr::ArrayInit { element, values },
+            };
+        }
+        i += 1;
+    }
+}
+
+/// Counts the assignments and reads of each synthetic local
+fn count_locals(stmts: &mut [Stmt], counts: &mut HashMap<String, (usize, usize)>) {
+    for stmt in stmts.iter_mut() {
+        let assigned = synthetic_target(stmt).map(|local| local.name.clone());
+        let is_assign = matches!(stmt, Stmt::Assign { .. });
+        if let Some(name) = assigned {
+            counts.entry(name).or_default().0 += 1;
+        }
+        for (i, expr) in stmt.exprs_mut().into_iter().enumerate() {
+            if is_assign && i == 0 && matches!(expr, Expr::Local(_)) {
+                continue;
+            }
+            expr.walk_mut(&mut |expr| {
+                if let Expr::Local(local) = expr {
+                    if is_synthetic(local) {
+                        counts.entry(local.name.clone()).or_default().1 += 1;
+                    }
+                }
+            });
+        }
+        for body in stmt.bodies_mut() {
+            count_locals(body, counts);
+        }
+    }
+}
+
+/// Turns `tmp = v; x = tmp;` into `x = v;` when the later reads of the
+/// temporary are in the same block and `x` keeps its value until then
+fn fold_copies(stmts: &mut Vec<Stmt>, counts: &mut HashMap<String, (usize, usize)>) {
+    let mut i = 0;
+    while i + 1 < stmts.len() {
+        if let Some((tmp, copy, last)) = copy_at(stmts, i, counts) {
+            stmts.remove(i + 1);
+            if let Stmt::Assign { target, .. } = &mut stmts[i] {
+                *target = Expr::Local(copy.clone());
+            }
+            for stmt in &mut stmts[i + 1..last] {
+                walk_stmts_mut(std::slice::from_mut(stmt), &mut |expr| {
+                    if *expr == Expr::Local(tmp.clone()) {
+                        *expr = Expr::Local(copy.clone());
+                    }
+                });
+            }
+            counts.insert(tmp.name, (0, 0));
+        }
+        i += 1;
+    }
+}
+
+/// The temporary and local of a copy at `i`, and the end of the statements
+/// reading the temporary after it
+fn copy_at(
+    stmts: &[Stmt],
+    i: usize,
+    counts: &HashMap<String, (usize, usize)>,
+) -> Option<(Local, Local, usize)> {
+    let tmp = synthetic_target(&stmts[i])?;
+    let copy = match &stmts[i + 1] {
+        Stmt::Assign {
+            target: Expr::Local(local),
+            value: Expr::Local(value),
+        } if !is_synthetic(local) && value.name == tmp.name => local,
+        _ => return None,
+    };
+    let (assigns, uses) = counts.get(&tmp.name).copied().unwrap_or_default();
+    if assigns != 1 {
+        return None;
+    }
+
+    let mut rest = uses - 1;
+    let mut end = i + 2;
+    while rest > 0 {
+        let stmt = stmts.get(end)?;
+        let mut found = 0;
+        stmt_locals_read(stmt, &tmp.name, &mut found);
+        rest = rest.checked_sub(found)?;
+        // The copy must still hold the value wherever the temporary is read
+        if assigns_local(stmt, &copy.name)
+            && (rest > 0 || !is_last_read_safe(stmt, &tmp.name, &copy.name))
+        {
+            return None;
+        }
+        end += 1;
+    }
+
+    Some((tmp.clone(), copy.clone(), end - 1))
+}
+
+fn stmt_locals_read(stmt: &Stmt, name: &str, found: &mut usize) {
+    for expr in stmt.exprs() {
+        *found += count_uses(expr, name);
+    }
+    for body in stmt.bodies() {
+        for stmt in body {
+            stmt_locals_read(stmt, name, found);
+        }
+    }
+}
+
+/// Whether a statement, or one nested in it, changes a local
+fn assigns_local(stmt: &Stmt, name: &str) -> bool {
+    let assigned = match stmt {
+        Stmt::Assign {
+            target: Expr::Local(local),
+            ..
+        } => local.name == name,
+        _ => false,
+    };
+    assigned
+        || stmt.exprs().iter().any(|expr| increments(expr, name))
+        || stmt
+            .bodies()
+            .iter()
+            .any(|body| body.iter().any(|stmt| assigns_local(stmt, name)))
+}
+
+fn increments(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::PostIncrement { local, .. } if local.name == name => true,
+        _ => expr.children().iter().any(|child| increments(child, name)),
+    }
+}
+
+/// Whether the last statement reading the temporary reads it before it can
+/// change the local, as in `x = tmp + 1` or `if (tmp == 0) { x = 1; }`
+fn is_last_read_safe(stmt: &Stmt, tmp: &str, name: &str) -> bool {
+    let evaluated_once = matches!(
+        stmt,
+        Stmt::Assign { .. } | Stmt::If { .. } | Stmt::Switch { .. }
+    );
+    let mut nested = 0;
+    for body in stmt.bodies() {
+        for stmt in body {
+            stmt_locals_read(stmt, tmp, &mut nested);
+        }
+    }
+    evaluated_once && nested == 0 && !stmt.exprs().iter().any(|expr| increments(expr, name))
+}
+
+/// Moves synthetic locals that are read once into the statement after them
+fn inline(stmts: &mut Vec<Stmt>, counts: &mut HashMap<String, (usize, usize)>) {
+    let mut i = stmts.len();
+    while i > 0 {
+        i -= 1;
+        let local = match synthetic_target(&stmts[i]) {
+            Some(local) => local.clone(),
+            None => continue,
+        };
+        let (assigns, uses) = counts.get(&local.name).copied().unwrap_or_default();
+
+        if uses == 0 {
+            if let Stmt::Assign { value, .. } = stmts.remove(i) {
+                if !value.is_pure() {
+                    stmts.insert(i, Stmt::Expr(value));
+                }
+            }
+            continue;
+        }
+        if assigns != uses || i + 1 >= stmts.len() {
+            continue;
+        }
+
+        let value = match &stmts[i] {
+            Stmt::Assign { value, .. } => value.clone(),
+            _ => continue,
+        };
+        if !can_inline(&mut stmts[i + 1], &local.name, value.is_pure()) {
+            continue;
+        }
+
+        let mut value = Some(value);
+        for expr in heads(&mut stmts[i + 1]) {
+            expr.walk_mut(&mut |expr| {
+                if *expr == Expr::Local(local.clone()) {
+                    if let Some(value) = value.take() {
+                        *expr = value;
+                    }
+                }
+            });
+        }
+        stmts.remove(i);
+        counts.insert(local.name, (assigns - 1, uses - 1));
+    }
+}
+
+/// The expressions a statement evaluates before anything else it does, in
+/// order. Loop conditions are left out as they are evaluated repeatedly.
+fn heads(stmt: &mut Stmt) -> Vec<&mut Expr> {
+    match stmt {
+        Stmt::While { .. } | Stmt::DoWhile { .. } => vec![],
+        Stmt::Assign { target, value } => {
+            let mut exprs = target.children_mut();
+            exprs.push(value);
+            exprs
+        }
+        stmt => stmt.exprs_mut(),
+    }
+}
+
+fn can_inline(stmt: &mut Stmt, name: &str, pure: bool) -> bool {
+    let exprs = heads(stmt);
+    let uses: usize = exprs.iter().map(|expr| count_uses(expr, name)).sum();
+    if uses != 1 {
+        return false;
+    }
+    if pure {
+        return true;
+    }
+
+    for expr in exprs {
+        if let Some(first) = reached_first(expr, name) {
+            return first;
+        }
+    }
+    false
+}
+
+fn count_uses(expr: &Expr, name: &str) -> usize {
+    let own = match expr {
+        Expr::Local(local) if local.name == name => 1,
+        _ => 0,
+    };
+    own + expr
+        .children()
+        .iter()
+        .map(|child| count_uses(child, name))
+        .sum::<usize>()
+}
+
+/// Whether evaluation reaches a read of the local before anything with a
+/// side effect, or None when the expression has neither
+fn reached_first(expr: &Expr, name: &str) -> Option<bool> {
+    let conditional = match expr {
+        Expr::Binary { op, left, right } if *op == "&&" || *op == "||" => {
+            Some((left.as_ref(), vec![right.as_ref()]))
+        }
+        Expr::Ternary { cond, then, els } => {
+            Some((cond.as_ref(), vec![then.as_ref(), els.as_ref()]))
+        }
+        _ => None,
+    };
+    if let Some((first, rest)) = conditional {
+        if let Some(reached) = reached_first(first, name) {
+            return Some(reached);
+        }
+        // Code that may not run at all cannot take the value
+        return if rest
+            .iter()
+            .all(|expr| expr.is_pure() && !expr.uses_local(name))
+        {
+            None
+        } else {
+            Some(false)
+        };
+    }
+
+    for child in expr.children() {
+        if let Some(reached) = reached_first(child, name) {
+            return Some(reached);
+        }
+    }
+    match expr {
+        Expr::Local(local) if local.name == name => Some(true),
+        Expr::Call { .. }
+        | Expr::New { .. }
+        | Expr::Dynamic { .. }
+        | Expr::NewArray { .. }
+        | Expr::ArrayInit { .. }
+        | Expr::Field { .. }
+        | Expr::Index { .. }
+        | Expr::Length(_)
+        | Expr::PostIncrement { .. } => Some(false),
+        _ => None,
+    }
+}
+
+/// Int constants that stand for a boolean or char
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Boolean,
+    Char,
+}
+
+fn type_kind(typ: &JavaType) -> Option<Kind> {
+    match typ {
+        JavaType::Primitive(Primitive::Boolean) => Some(Kind::Boolean),
+        JavaType::Primitive(Primitive::Char) => Some(Kind::Char),
+        _ => None,
+    }
+}
+
+/// The kind of a Java type name or field descriptor
+fn name_kind(typ: &str) -> Option<Kind> {
+    match typ {
+        "boolean" | "Z" => Some(Kind::Boolean),
+        "char" | "C" => Some(Kind::Char),
+        _ => None,
+    }
+}
+
+fn target_kind(target: &Expr) -> Option<Kind> {
+    match target {
+        Expr::Local(local) => name_kind(local.typ.as_deref()?),
+        Expr::Field { descriptor, .. } => name_kind(descriptor),
+        Expr::Index { array, .. } => match array.as_ref() {
+            Expr::Local(local) => name_kind(local.typ.as_deref()?.strip_suffix("[]")?),
+            Expr::Field { descriptor, .. } => name_kind(descriptor.strip_prefix('[')?),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn coerce_stmts(stmts: &mut [Stmt], ret: Option<&JavaType>) {
+    for stmt in stmts.iter_mut() {
+        for expr in stmt.exprs_mut() {
+            expr.walk_mut(&mut coerce_operands);
+        }
+        match stmt {
+            Stmt::Assign { target, value } => {
+                if let Some(kind) = target_kind(target) {
+                    coerce(value, kind);
+                }
+            }
+            Stmt::Return(Some(value)) => {
+                if let Some(kind) = ret.and_then(type_kind) {
+                    coerce(value, kind);
+                }
+            }
+            _ => {}
+        }
+        for body in stmt.bodies_mut() {
+            coerce_stmts(body, ret);
+        }
+    }
+}
+
+/// Coerces the parts of an expression whose type it decides
+fn coerce_operands(expr: &mut Expr) {
+    match expr {
+        Expr::Call {
+            descriptor, args, ..
+        } => {
+            if let Ok(signature) = TypeSignature::from_str(descriptor.as_str()) {
+                for (arg, typ) in args.iter_mut().zip(signature.args.iter()) {
+                    if let Some(kind) = type_kind(typ) {
+                        coerce(arg, kind);
+                    }
+                }
+            }
+        }
+        Expr::Binary { op, left, right } => {
+            // b != 0 is b when b is a boolean
+            if (*op == "==" || *op == "!=") && left.is_boolean() {
+                if let Expr::Int(val @ 0..=1) = **right {
+                    let left = left.as_ref().clone();
+                    *expr = if (*op == "==") == (val == 1) {
+                        left
+                    } else {
+                        left.negate()
+                    };
+                    return;
+                }
+            }
+            if left.is_char() {
+                coerce(right, Kind::Char);
+            } else if right.is_char() {
+                coerce(left, Kind::Char);
+            }
+        }
+        Expr::ArrayInit { element, values } => {
+            if let Some(kind) = name_kind(element) {
+                for value in values {
+                    coerce(value, kind);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn coerce(expr: &mut Expr, kind: Kind) {
+    match expr {
+        Expr::Int(val) => {
+            let literal = match kind {
+                Kind::Boolean => Some((if *val == 0 { "false" } else { "true" }).to_string()),
+                Kind::Char => char_literal(*val),
+            };
+            if let Some(literal) = literal {
+                *expr = Expr::Literal(literal);
+            }
+        }
+        Expr::Ternary { cond, then, els } => {
+            coerce(then, kind);
+            coerce(els, kind);
+            if kind == Kind::Boolean {
+                let is = |expr: &Expr, text: &str| *expr == Expr::Literal(text.to_string());
+                if is(then, "true") && is(els, "false") {
+                    *expr = cond.as_ref().clone();
+                } else if is(then, "false") && is(els, "true") {
+                    *expr = cond.as_ref().clone().negate();
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::ast::{ends_in_jump, Case, Catch, Expr, Stmt};
+use super::class_name;
+use super::exec::{Exit, Node};
+use crate::attribute::Code;
+use crate::cfg::{BasicBlock, ControlFlowGraph, Edge, EdgeKind, Loop};
+use crate::const_pool::ConstPool;
+
+/// The handlers sharing one protected range, which become a single try
+/// statement
+struct TryBlock {
+    start: u32,
+    end: u32,
+    /// Caught type and handler block of each catch clause
+    handlers: Vec<(String, usize)>,
+}
+
+/// A loop or switch statement that `break` can leave
+struct Breakable {
+    /// Index into the loops, or None for a switch
+    looping: Option<usize>,
+    exit: Option<usize>,
+    label: String,
+}
+
+/// The statements enclosing the code being structured
+#[derive(Default)]
+struct Scope {
+    breakables: Vec<Breakable>,
+    tries: Vec<usize>,
+}
+
+struct Structurer {
+    nodes: Vec<Node>,
+    /// Blocks whose condition was folded into their predecessor's
+    merged: Vec<bool>,
+    /// Immediate post-dominator of each block
+    ipdom: Vec<Option<usize>>,
+    loops: Vec<Loop>,
+    tries: Vec<TryBlock>,
+    handlers: BTreeSet<usize>,
+    emitted: Vec<bool>,
+    labels: usize,
+    used_labels: BTreeSet<String>,
+}
+
+/// Arranges the blocks of a method into nested statements. Jumps that
+/// cannot be expressed as structured control flow are left as gotos.
+pub(crate) fn structure(
+    nodes: Vec<Node>,
+    cfg: &ControlFlowGraph,
+    code: &Code,
+    pool: &ConstPool,
+) -> Vec<Stmt> {
+    let starts: BTreeMap<u32, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.offset, i))
+        .collect();
+
+    let mut tries: Vec<TryBlock> = vec![];
+    let mut handlers = BTreeSet::new();
+    for handler in &code.exception_table {
+        let node = match starts.get(&(handler.handler_pc as u32)) {
+            Some(node) => *node,
+            None => continue,
+        };
+        handlers.insert(node);
+        // Ranges that cover their own handler, as javac emits for
+        // synchronized blocks, cannot be written as a try statement
+        if handler.handler_pc >= handler.start_pc && handler.handler_pc < handler.end_pc {
+            continue;
+        }
+
+        let typ = match pool.class_name(handler.catch_type) {
+            Some(name) => class_name(name),
+            None => "Throwable".to_string(),
+        };
+        let (start, end) = (handler.start_pc as u32, handler.end_pc as u32);
+        match tries.iter_mut().find(|t| t.start == start && t.end == end) {
+            Some(existing) => match existing.handlers.iter_mut().find(|(_, h)| *h == node) {
+                // A multi-catch lists one handler once for each type
+                Some((types, _)) => *types = format!("{} | {}", types, typ),
+                None => existing.handlers.push((typ, node)),
+            },
+            None => tries.push(TryBlock {
+                start,
+                end,
+                handlers: vec![(typ, node)],
+            }),
+        }
+    }
+
+    let count = nodes.len();
+    let mut structurer = Structurer {
+        nodes,
+        merged: vec![false; count],
+        ipdom: vec![],
+        loops: cfg.loops(),
+        tries,
+        handlers,
+        emitted: vec![false; count],
+        labels: 0,
+        used_labels: BTreeSet::new(),
+    };
+    structurer.merge_conditions();
+    structurer.ipdom = structurer.post_dominators();
+
+    let mut scope = Scope::default();
+    let mut stmts = structurer.region(0, None, &mut scope);
+    // Subroutines and other code that was never reached
+    while let Some(node) = (0..count).find(|i| !structurer.emitted[*i] && !structurer.merged[*i]) {
+        let offset = structurer.nodes[node].offset;
+        stmts.push(Stmt::Comment(format!(
+            "unstructured code at offset {}",
+            offset
+        )));
+        stmts.extend(structurer.region(node, None, &mut scope));
+    }
+
+    stmts
+}
+
+impl Structurer {
+    /// Folds chains of conditional jumps into single conditions joined by
+    /// `&&` and `||`
+    fn merge_conditions(self: &mut Structurer) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            let mut predecessors = vec![0; self.nodes.len()];
+            predecessors[0] += 1;
+            for handler in &self.handlers {
+                predecessors[*handler] += 1;
+            }
+            for node in &self.nodes {
+                for successor in node.exit.successors() {
+                    predecessors[successor] += 1;
+                }
+            }
+
+            for a in 0..self.nodes.len() {
+                let (c1, t1, f1) = match &self.nodes[a].exit {
+                    Exit::If { cond, taken, fall } => (cond.clone(), *taken, *fall),
+                    _ => continue,
+                };
+
+                for (b, is_fall) in [(f1, true), (t1, false)] {
+                    if b == a || predecessors[b] != 1 || !self.nodes[b].stmts.is_empty() {
+                        continue;
+                    }
+                    let (c2, t2, f2) = match &self.nodes[b].exit {
+                        Exit::If { cond, taken, fall } => (cond.clone(), *taken, *fall),
+                        _ => continue,
+                    };
+
+                    let merged = if is_fall && t2 == t1 {
+                        Some((Expr::binary("||", c1.clone(), c2), t1, f2))
+                    } else if is_fall && f2 == t1 {
+                        Some((Expr::binary("||", c1.clone(), c2.negate()), t1, t2))
+                    } else if !is_fall && f2 == f1 {
+                        Some((Expr::binary("&&", c1.clone(), c2), t2, f1))
+                    } else if !is_fall && t2 == f1 {
+                        Some((Expr::binary("&&", c1.clone(), c2.negate()), f2, f1))
+                    } else {
+                        None
+                    };
+
+                    if let Some((cond, taken, fall)) = merged {
+                        self.nodes[a].exit = Exit::If { cond, taken, fall };
+                        self.nodes[b].exit = Exit::End;
+                        self.merged[b] = true;
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Immediate post-dominators, found as the dominators of the reversed
+    /// graph with a single exit added after every return. Paths ending in a
+    /// throw are left out, so that `if (c) throw e;` still has a join.
+    fn post_dominators(self: &Structurer) -> Vec<Option<usize>> {
+        let mut edges = vec![];
+        for (i, node) in self.nodes.iter().enumerate() {
+            if self.merged[i] {
+                continue;
+            }
+            let successors = node.exit.successors();
+            if successors.is_empty() && !matches!(node.stmts.last(), Some(Stmt::Throw(_))) {
+                edges.push(Edge {
+                    from: 0,
+                    to: i + 1,
+                    kind: EdgeKind::FallThrough,
+                });
+            }
+            for successor in successors {
+                edges.push(Edge {
+                    from: successor + 1,
+                    to: i + 1,
+                    kind: EdgeKind::FallThrough,
+                });
+            }
+        }
+
+        let empty = BasicBlock {
+            start: 0,
+            end: 0,
+            instructions: vec![],
+        };
+        let reversed = ControlFlowGraph {
+            blocks: vec![empty; self.nodes.len() + 1],
+            edges,
+        };
+        reversed
+            .dominators()
+            .into_iter()
+            .skip(1)
+            .map(|idom| match idom {
+                Some(idom) if idom > 0 => Some(idom - 1),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Statements for the code starting at a block and running until `stop`
+    /// or until control leaves through a jump
+    fn region(
+        self: &mut Structurer,
+        start: usize,
+        stop: Option<usize>,
+        scope: &mut Scope,
+    ) -> Vec<Stmt> {
+        let mut out = vec![];
+        let mut next = Some(start);
+
+        while let Some(current) = next {
+            if self.emitted[current] {
+                next = self.revisit(current, stop, scope, &mut out);
+                continue;
+            }
+
+            let looping = self.loop_at(current, scope);
+            let tried = self.try_at(current, scope);
+            next = match (looping, tried) {
+                (Some(l), Some(t)) if !self.try_encloses(t, l) => {
+                    self.emit_loop(l, stop, scope, &mut out)
+                }
+                (_, Some(t)) => self.emit_try(t, current, stop, scope, &mut out),
+                (Some(l), None) => self.emit_loop(l, stop, scope, &mut out),
+                (None, None) => self.emit_block(current, stop, scope, &mut out),
+            };
+        }
+
+        out
+    }
+
+    /// Continues to a block, or ends the region with a `break`, `continue`
+    /// or goto when the block is somewhere else
+    fn jump(
+        self: &mut Structurer,
+        target: usize,
+        stop: Option<usize>,
+        scope: &Scope,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        if Some(target) == stop {
+            return None;
+        }
+
+        let mut innermost = true;
+        let mut innermost_loop = true;
+        for breakable in scope.breakables.iter().rev() {
+            let header = breakable.looping.map(|l| self.loops[l].header);
+            if header == Some(target) {
+                let label = self.label_for(breakable, innermost_loop);
+                out.push(Stmt::Continue(label));
+                return None;
+            }
+            if breakable.exit == Some(target) {
+                let label = self.label_for(breakable, innermost);
+                out.push(Stmt::Break(label));
+                return None;
+            }
+            innermost = false;
+            if header.is_some() {
+                innermost_loop = false;
+            }
+        }
+
+        if self.emitted[target] {
+            return self.revisit(target, stop, scope, out);
+        }
+        Some(target)
+    }
+
+    /// Ends a region with a jump to a block that was already written out.
+    /// Short blocks that return or jump forward, such as a shared
+    /// `return null;`, are repeated instead.
+    fn revisit(
+        self: &mut Structurer,
+        target: usize,
+        stop: Option<usize>,
+        scope: &Scope,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let node = &self.nodes[target];
+        if node.stmts.len() <= 3 {
+            match node.exit {
+                Exit::End if ends_in_jump(&node.stmts) => {
+                    out.extend(node.stmts.iter().cloned());
+                    return None;
+                }
+                Exit::Goto(next) if self.nodes[next].offset > node.offset => {
+                    out.extend(node.stmts.iter().cloned());
+                    return self.jump(next, stop, scope, out);
+                }
+                _ => {}
+            }
+        }
+
+        out.push(Stmt::Goto(node.offset));
+        None
+    }
+
+    fn label_for(self: &mut Structurer, breakable: &Breakable, innermost: bool) -> Option<String> {
+        if innermost {
+            return None;
+        }
+        self.used_labels.insert(breakable.label.clone());
+        Some(breakable.label.clone())
+    }
+
+    fn new_label(self: &mut Structurer) -> String {
+        self.labels += 1;
+        format!("label{}", self.labels)
+    }
+
+    fn used_label(self: &Structurer, label: String) -> Option<String> {
+        if self.used_labels.contains(&label) {
+            Some(label)
+        } else {
+            None
+        }
+    }
+
+    /// Where control rejoins after a conditional or switch block. There is
+    /// none when the paths only meet again by leaving an enclosing loop or
+    /// switch.
+    fn join(self: &Structurer, node: usize, scope: &Scope) -> Option<usize> {
+        let innermost_loop = scope.breakables.iter().rev().find_map(|b| b.looping);
+        let allowed = |join: usize| {
+            let leaves = scope.breakables.iter().any(|breakable| {
+                let header = breakable.looping.map(|l| self.loops[l].header);
+                header == Some(join) || breakable.exit == Some(join)
+            });
+            let outside = match innermost_loop {
+                Some(l) => !self.loops[l].blocks.contains(&join),
+                None => false,
+            };
+            !leaves && !outside
+        };
+
+        match self.ipdom[node] {
+            Some(join) if allowed(join) => Some(join),
+            _ => self.forward_join(node, allowed),
+        }
+    }
+
+    /// The earliest block that every successor of a block reaches by jumping
+    /// forward. This stands in for the post-dominator when some paths
+    /// return or break early.
+    fn forward_join<F: Fn(usize) -> bool>(
+        self: &Structurer,
+        node: usize,
+        allowed: F,
+    ) -> Option<usize> {
+        let mut common: Option<BTreeSet<usize>> = None;
+        for successor in self.nodes[node].exit.successors() {
+            let mut reached = BTreeSet::new();
+            let mut worklist = vec![successor];
+            while let Some(block) = worklist.pop() {
+                if !reached.insert(block) {
+                    continue;
+                }
+                let offset = self.nodes[block].offset;
+                for next in self.nodes[block].exit.successors() {
+                    if self.nodes[next].offset > offset {
+                        worklist.push(next);
+                    }
+                }
+            }
+            common = Some(match common {
+                Some(common) => common.intersection(&reached).copied().collect(),
+                None => reached,
+            });
+        }
+
+        common?
+            .into_iter()
+            .filter(|block| self.nodes[*block].offset > self.nodes[node].offset && allowed(*block))
+            .min_by_key(|block| self.nodes[*block].offset)
+    }
+
+    fn emit_block(
+        self: &mut Structurer,
+        node: usize,
+        stop: Option<usize>,
+        scope: &mut Scope,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        self.emitted[node] = true;
+        out.extend(self.nodes[node].stmts.clone());
+
+        match self.nodes[node].exit.clone() {
+            Exit::End => None,
+            Exit::Goto(target) => self.jump(target, stop, scope, out),
+            Exit::If { cond, taken, fall } => {
+                let join = self.join(node, scope);
+                let inner = join.or(stop);
+                let then = self.branch(fall, inner, scope);
+                let els = self.branch(taken, inner, scope);
+                out.push(Stmt::If {
+                    cond: cond.negate(),
+                    then,
+                    els,
+                });
+                join.and_then(|join| self.jump(join, stop, scope, out))
+            }
+            Exit::Switch {
+                expr,
+                cases,
+                default,
+            } => {
+                let join = self.join(node, scope);
+                let label = self.new_label();
+                scope.breakables.push(Breakable {
+                    looping: None,
+                    exit: join,
+                    label: label.clone(),
+                });
+
+                let mut targets: Vec<usize> = cases.iter().map(|(_, target)| *target).collect();
+                targets.push(default);
+                targets.retain(|target| Some(*target) != join);
+                targets.sort_by_key(|target| self.nodes[*target].offset);
+                targets.dedup();
+
+                let mut switch_cases = vec![];
+                for (i, target) in targets.iter().enumerate() {
+                    // A case that runs on into the next one stops there
+                    let case_stop = targets.get(i + 1).copied().or(join).or(stop);
+                    let body = self.branch(*target, case_stop, scope);
+                    switch_cases.push(Case {
+                        keys: cases
+                            .iter()
+                            .filter(|(_, t)| t == target)
+                            .map(|(key, _)| *key)
+                            .collect(),
+                        default: *target == default,
+                        body,
+                    });
+                }
+
+                scope.breakables.pop();
+                out.push(Stmt::Switch {
+                    label: self.used_label(label),
+                    expr,
+                    cases: switch_cases,
+                });
+                join.and_then(|join| self.jump(join, stop, scope, out))
+            }
+        }
+    }
+
+    fn branch(
+        self: &mut Structurer,
+        target: usize,
+        stop: Option<usize>,
+        scope: &mut Scope,
+    ) -> Vec<Stmt> {
+        let mut out = vec![];
+        if let Some(next) = self.jump(target, stop, scope, &mut out) {
+            out.extend(self.region(next, stop, scope));
+        }
+        out
+    }
+
+    /// The loop headed by a block, unless the block is being structured as
+    /// the start of that loop already
+    fn loop_at(self: &Structurer, node: usize, scope: &Scope) -> Option<usize> {
+        let l = self.loops.iter().position(|l| l.header == node)?;
+        if scope.breakables.iter().any(|b| b.looping == Some(l)) {
+            return None;
+        }
+        Some(l)
+    }
+
+    /// The block a loop leaves to. This is the exit tested by its header
+    /// for a `while` loop, or else the furthest exit.
+    fn loop_follow(self: &Structurer, l: usize) -> Option<usize> {
+        let blocks = &self.loops[l].blocks;
+        if let Exit::If { taken, fall, .. } = &self.nodes[self.loops[l].header].exit {
+            for target in &[*fall, *taken] {
+                if !blocks.contains(target) {
+                    return Some(*target);
+                }
+            }
+        }
+
+        blocks
+            .iter()
+            .flat_map(|block| self.nodes[*block].exit.successors())
+            .filter(|successor| !blocks.contains(successor))
+            .max_by_key(|successor| self.nodes[*successor].offset)
+    }
+
+    fn emit_loop(
+        self: &mut Structurer,
+        l: usize,
+        stop: Option<usize>,
+        scope: &mut Scope,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let header = self.loops[l].header;
+        let follow = self.loop_follow(l);
+        let label = self.new_label();
+        scope.breakables.push(Breakable {
+            looping: Some(l),
+            exit: follow,
+            label: label.clone(),
+        });
+        let body = self.region(header, None, scope);
+        scope.breakables.pop();
+
+        out.push(Stmt::While {
+            label: self.used_label(label),
+            cond: Expr::Literal("true".to_string()),
+            body,
+        });
+        follow.and_then(|follow| self.jump(follow, stop, scope, out))
+    }
+
+    /// The outermost try block starting at a block that is not already open.
+    /// Ranges whose handlers all belong to an open try block are the part
+    /// of a `finally` that covers the catch clauses, and are left out.
+    fn try_at(self: &Structurer, node: usize, scope: &Scope) -> Option<usize> {
+        let offset = self.nodes[node].offset;
+        let claimed = |handler: usize| {
+            scope.tries.iter().any(|open| {
+                self.tries[*open]
+                    .handlers
+                    .iter()
+                    .any(|(_, h)| *h == handler)
+            })
+        };
+
+        self.tries
+            .iter()
+            .enumerate()
+            .filter(|(i, t)| {
+                t.start == offset
+                    && !scope.tries.contains(i)
+                    && !t.handlers.iter().all(|(_, handler)| claimed(*handler))
+            })
+            .max_by_key(|(_, t)| t.end)
+            .map(|(i, _)| i)
+    }
+
+    fn try_encloses(self: &Structurer, t: usize, l: usize) -> bool {
+        let (start, end) = (self.tries[t].start, self.tries[t].end);
+        self.loops[l].blocks.iter().all(|block| {
+            self.merged[*block]
+                || (self.nodes[*block].offset >= start && self.nodes[*block].offset < end)
+        })
+    }
+
+    /// Where control goes after a try statement: the first block outside
+    /// the protected range that the body and every catch clause that
+    /// completes normally all pass through
+    fn try_follow(self: &Structurer, t: usize, node: usize) -> Option<usize> {
+        let (start, end) = (self.tries[t].start, self.tries[t].end);
+        let outside = |block: &usize| {
+            let offset = self.nodes[*block].offset;
+            offset < start || offset >= end
+        };
+
+        let mut chains: Vec<Vec<usize>> = vec![];
+        for begin in std::iter::once(node).chain(self.tries[t].handlers.iter().map(|(_, h)| *h)) {
+            let mut chain = vec![];
+            let mut current = self.ipdom[begin];
+            while let Some(block) = current {
+                if chain.contains(&block) {
+                    break;
+                }
+                chain.push(block);
+                current = self.ipdom[block];
+            }
+            chain.retain(|block| outside(block));
+            if !chain.is_empty() {
+                chains.push(chain);
+            }
+        }
+
+        let (first, rest) = chains.split_first()?;
+        first
+            .iter()
+            .find(|block| rest.iter().all(|chain| chain.contains(block)))
+            .copied()
+    }
+
+    fn emit_try(
+        self: &mut Structurer,
+        t: usize,
+        node: usize,
+        stop: Option<usize>,
+        scope: &mut Scope,
+        out: &mut Vec<Stmt>,
+    ) -> Option<usize> {
+        let follow = self.try_follow(t, node);
+        let inner = follow.or(stop);
+
+        scope.tries.push(t);
+        let body = self.region(node, inner, scope);
+        let mut catches = vec![];
+        for (typ, handler) in self.tries[t].handlers.clone() {
+            let body = if self.emitted[handler] {
+                vec![Stmt::Goto(self.nodes[handler].offset)]
+            } else {
+                self.region(handler, inner, scope)
+            };
+            catches.push(Catch {
+                typ,
+                name: String::new(),
+                body,
+            });
+        }
+        scope.tries.pop();
+
+        out.push(Stmt::Try { body, catches });
+        follow.and_then(|follow| self.jump(follow, stop, scope, out))
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+use crate::class::{Attribute, Class};
+use crate::const_pool::ConstPool;
+use crate::jvm_const::JvmConst;
+use crate::signature::JavaType;
+
+/// Attributes holding annotations, whose types and values name classes that
+/// never appear as Class constants
+const ANNOTATION_ATTRIBUTES: [&str; 4] = [
+    "RuntimeVisibleAnnotations",
+    "RuntimeInvisibleAnnotations",
+    "RuntimeVisibleParameterAnnotations",
+    "RuntimeInvisibleParameterAnnotations",
+];
+
+/// Every class a class refers to, other than itself. These are found in
+/// Class constants, the descriptors of its members and of member references,
+/// generic signatures and annotations. Array types give their element class.
+pub fn dependencies(class: &Class) -> BTreeSet<String> {
+    let pool = &class.const_pool;
+    let mut deps = BTreeSet::new();
+
+    for (i, c) in pool.consts.iter().enumerate() {
+        match c {
+            JvmConst::Class(_) => {
+                if let Some(name) = pool.class_name(i as u16 + 1) {
+                    if name.starts_with('[') {
+                        add_descriptor(name, &mut deps);
+                    } else {
+                        deps.insert(name.to_string());
+                    }
+                }
+            }
+            JvmConst::NameAndType(_, descriptor) | JvmConst::MethodType(descriptor) => {
+                if let Some(descriptor) = pool.utf8(*descriptor) {
+                    add_descriptor(descriptor, &mut deps);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let members = class.fields.iter().chain(&class.methods);
+    for member in members {
+        add_descriptor(&member.descriptor, &mut deps);
+        add_attributes(&member.attributes, pool, &mut deps);
+    }
+    add_attributes(&class.attributes, pool, &mut deps);
+
+    deps.remove(&class.name);
+    deps
+}
+
+/// Package of a class, such as `java/lang` for `java/lang/String`, or an
+/// empty string for the unnamed package
+pub fn package_of(name: &str) -> &str {
+    match name.rfind('/') {
+        Some(i) => &name[..i],
+        None => "",
+    }
+}
+
+/// Groups class names by their package
+pub fn by_package(names: &BTreeSet<String>) -> BTreeMap<String, BTreeSet<String>> {
+    let mut packages: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for name in names {
+        packages
+            .entry(package_of(name).to_string())
+            .or_default()
+            .insert(name.clone());
+    }
+
+    packages
+}
+
+fn add_attributes(attributes: &[Attribute], pool: &ConstPool, deps: &mut BTreeSet<String>) {
+    for attribute in attributes {
+        if attribute.name == "Signature" && attribute.data.len() == 2 {
+            let index = u16::from_be_bytes([attribute.data[0], attribute.data[1]]);
+            if let Some(signature) = pool.utf8(index) {
+                add_signature(signature, deps);
+            }
+        } else if ANNOTATION_ATTRIBUTES.contains(&attribute.name.as_str()) {
+            let mut reader = AnnotationReader {
+                data: &attribute.data,
+                pos: 0,
+                pool,
+                deps,
+            };
+            // Parameter annotations start with a one byte parameter count
+            let parameters = if attribute.name.contains("Parameter") {
+                reader.u1().map(|count| count as usize)
+            } else {
+                Some(1)
+            };
+            if let Some(parameters) = parameters {
+                for _ in 0..parameters {
+                    if reader.annotations().is_none() {
+                        break;
+                    }
+                }
+            }
+        } else if attribute.name == "AnnotationDefault" {
+            let mut reader = AnnotationReader {
+                data: &attribute.data,
+                pos: 0,
+                pool,
+                deps,
+            };
+            reader.element_value();
+        }
+    }
+}
+
+fn add_descriptor(descriptor: &str, deps: &mut BTreeSet<String>) {
+    if let Ok(typ) = JavaType::from_str(descriptor) {
+        add_java_type(&typ, deps);
+    }
+}
+
+fn add_java_type(typ: &JavaType, deps: &mut BTreeSet<String>) {
+    match typ {
+        JavaType::Primitive(_) => {}
+        JavaType::Object(name) => {
+            deps.insert(name.clone());
+        }
+        JavaType::Array(component) => add_java_type(component, deps),
+        JavaType::Method(signature) => {
+            for arg in &signature.args {
+                add_java_type(arg, deps);
+            }
+            add_java_type(&signature.ret, deps);
+        }
+    }
+}
+
+/// Adds the classes named in a generic signature, such as
+/// `<T:Ljava/lang/Object;>(Ljava/util/List<TT;>;)V`
+fn add_signature(signature: &str, deps: &mut BTreeSet<String>) {
+    let chars: Vec<char> = signature.chars().collect();
+    let mut i = 0;
+
+    // Formal type parameters are an identifier followed by their bounds
+    if chars.first() == Some(&'<') {
+        i = 1;
+        while i < chars.len() && chars[i] != '>' {
+            while i < chars.len() && chars[i] != ':' {
+                i += 1;
+            }
+            while i < chars.len() && chars[i] == ':' {
+                i += 1;
+                if i < chars.len() && chars[i] != ':' {
+                    i = reference_type(&chars, i, deps);
+                }
+            }
+        }
+        i += 1;
+    }
+
+    while i < chars.len() {
+        i = match chars[i] {
+            'L' | 'T' | '[' => reference_type(&chars, i, deps),
+            _ => i + 1,
+        };
+    }
+}
+
+/// Reads a class, type variable or array type signature starting at `i`,
+/// returning where it ends
+fn reference_type(chars: &[char], mut i: usize, deps: &mut BTreeSet<String>) -> usize {
+    while i < chars.len() && chars[i] == '[' {
+        i += 1;
+    }
+
+    match chars.get(i) {
+        Some('L') => {
+            let start = i + 1;
+            while i < chars.len() && !matches!(chars[i], ';' | '<' | '.') {
+                i += 1;
+            }
+            deps.insert(chars[start..i].iter().collect());
+
+            // Type arguments and inner class suffixes, as in `Lp/A<TT;>.B;`
+            while i < chars.len() && chars[i] != ';' {
+                match chars[i] {
+                    '<' => {
+                        i += 1;
+                        while i < chars.len() && chars[i] != '>' {
+                            i = match chars[i] {
+                                '*' | '+' | '-' => i + 1,
+                                _ => reference_type(chars, i, deps),
+                            };
+                        }
+                        i += 1;
+                    }
+                    _ => i += 1,
+                }
+            }
+            i + 1
+        }
+        Some('T') => {
+            while i < chars.len() && chars[i] != ';' {
+                i += 1;
+            }
+            i + 1
+        }
+        _ => i + 1,
+    }
+}
+
+/// Walks the annotation structures of an attribute, adding the annotation
+/// types, enum types and class literals they use. Reading stops at the first
+/// malformed entry.
+struct AnnotationReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    pool: &'a ConstPool,
+    deps: &'a mut BTreeSet<String>,
+}
+
+impl<'a> AnnotationReader<'a> {
+    fn u1(self: &mut AnnotationReader<'a>) -> Option<u8> {
+        let val = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(val)
+    }
+
+    fn u2(self: &mut AnnotationReader<'a>) -> Option<u16> {
+        Some(u16::from_be_bytes([self.u1()?, self.u1()?]))
+    }
+
+    fn descriptor(self: &mut AnnotationReader<'a>) -> Option<()> {
+        let index = self.u2()?;
+        add_descriptor(self.pool.utf8(index)?, self.deps);
+        Some(())
+    }
+
+    fn annotations(self: &mut AnnotationReader<'a>) -> Option<()> {
+        for _ in 0..self.u2()? {
+            self.annotation()?;
+        }
+        Some(())
+    }
+
+    fn annotation(self: &mut AnnotationReader<'a>) -> Option<()> {
+        self.descriptor()?;
+        for _ in 0..self.u2()? {
+            self.u2()?;
+            self.element_value()?;
+        }
+        Some(())
+    }
+
+    fn element_value(self: &mut AnnotationReader<'a>) -> Option<()> {
+        match self.u1()? {
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' => {
+                self.u2()?;
+            }
+            b'e' => {
+                self.descriptor()?;
+                self.u2()?;
+            }
+            b'c' => self.descriptor()?,
+            b'@' => self.annotation()?,
+            b'[' => {
+                for _ in 0..self.u2()? {
+                    self.element_value()?;
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let mut deps = BTreeSet::new();
+        add_signature(
+            "<K:Ljava/lang/Object;LIST::Ljava/util/List<TK;>;>(Ljava/util/Map<TK;+Lp/A<[I>.B;>;)[Lp/C;^Lp/E;",
+            &mut deps,
+        );
+
+        let names: Vec<&str> = deps.iter().map(|s| s.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "java/lang/Object",
+                "java/util/List",
+                "java/util/Map",
+                "p/A",
+                "p/C",
+                "p/E"
+            ]
+        );
+    }
+}
@@ -0,0 +1,465 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::class::{Class, Field};
+use crate::flags::{ClassFlag, MethodFlag};
+
+/// Access level of a class or member, from narrowest to widest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    Private,
+    Package,
+    Protected,
+    Public,
+}
+
+impl Access {
+    pub fn from_flags(flags: u16) -> Access {
+        if MethodFlag::Public.is_set(flags) {
+            Access::Public
+        } else if MethodFlag::Protected.is_set(flags) {
+            Access::Protected
+        } else if MethodFlag::Private.is_set(flags) {
+            Access::Private
+        } else {
+            Access::Package
+        }
+    }
+
+    /// Whether code outside the class's package can see it
+    fn is_exported(self: Access) -> bool {
+        self >= Access::Protected
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Access::Private => write!(f, "private"),
+            Access::Package => write!(f, "package-private"),
+            Access::Protected => write!(f, "protected"),
+            Access::Public => write!(f, "public"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    AccessChanged { old: Access, new: Access },
+    BecameFinal,
+    NoLongerFinal,
+    BecameAbstract,
+    NoLongerAbstract,
+    BecameStatic,
+    NoLongerStatic,
+    BecameInterface,
+    BecameClass,
+    SuperclassChanged { old: String, new: String },
+    InterfaceAdded(String),
+    InterfaceRemoved(String),
+    DescriptorChanged { old: String, new: String },
+}
+
+/// A difference between two versions of a class or one of its members
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub class: String,
+    /// Name and descriptor of the field or method, or None for the class
+    pub member: Option<String>,
+    pub kind: ChangeKind,
+}
+
+impl Change {
+    /// Whether existing binaries linked against the old version still link
+    /// against the new one, following chapter 13 of the JLS
+    pub fn is_compatible(self: &Change) -> bool {
+        match &self.kind {
+            ChangeKind::Added
+            | ChangeKind::NoLongerFinal
+            | ChangeKind::NoLongerAbstract
+            | ChangeKind::InterfaceAdded(_) => true,
+            ChangeKind::AccessChanged { old, new } => new >= old,
+            ChangeKind::Removed
+            | ChangeKind::BecameFinal
+            | ChangeKind::BecameAbstract
+            | ChangeKind::BecameStatic
+            | ChangeKind::NoLongerStatic
+            | ChangeKind::BecameInterface
+            | ChangeKind::BecameClass
+            | ChangeKind::SuperclassChanged { .. }
+            | ChangeKind::InterfaceRemoved(_)
+            | ChangeKind::DescriptorChanged { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.member {
+            Some(member) => write!(f, "{}.{}: ", self.class, member)?,
+            None => write!(f, "{}: ", self.class)?,
+        }
+
+        match &self.kind {
+            ChangeKind::Added => write!(f, "added"),
+            ChangeKind::Removed => write!(f, "removed"),
+            ChangeKind::AccessChanged { old, new } => write!(f, "{} became {}", old, new),
+            ChangeKind::BecameFinal => write!(f, "became final"),
+            ChangeKind::NoLongerFinal => write!(f, "is no longer final"),
+            ChangeKind::BecameAbstract => write!(f, "became abstract"),
+            ChangeKind::NoLongerAbstract => write!(f, "is no longer abstract"),
+            ChangeKind::BecameStatic => write!(f, "became static"),
+            ChangeKind::NoLongerStatic => write!(f, "is no longer static"),
+            ChangeKind::BecameInterface => write!(f, "became an interface"),
+            ChangeKind::BecameClass => write!(f, "is no longer an interface"),
+            ChangeKind::SuperclassChanged { old, new } => {
+                write!(f, "superclass changed from {} to {}", old, new)
+            }
+            ChangeKind::InterfaceAdded(name) => write!(f, "implements {}", name),
+            ChangeKind::InterfaceRemoved(name) => write!(f, "no longer implements {}", name),
+            ChangeKind::DescriptorChanged { old, new } => {
+                write!(f, "descriptor changed from {} to {}", old, new)
+            }
+        }
+    }
+}
+
+/// Compares two sets of classes, such as two versions of a jar. Only classes
+/// and members visible outside their package in either version are compared.
+pub fn diff_classes(old: &HashMap<String, Class>, new: &HashMap<String, Class>) -> Vec<Change> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    let mut changes = vec![];
+    for name in names {
+        let change = |kind| Change {
+            class: name.clone(),
+            member: None,
+            kind,
+        };
+        match (old.get(name), new.get(name)) {
+            (Some(old), Some(new)) => changes.extend(diff_class(old, new)),
+            (Some(old), None) if class_access(old).is_exported() => {
+                changes.push(change(ChangeKind::Removed))
+            }
+            (None, Some(new)) if class_access(new).is_exported() => {
+                changes.push(change(ChangeKind::Added))
+            }
+            _ => {}
+        }
+    }
+
+    changes
+}
+
+/// Compares two versions of the same class
+pub fn diff_class(old: &Class, new: &Class) -> Vec<Change> {
+    let mut changes = vec![];
+    if !class_access(old).is_exported() && !class_access(new).is_exported() {
+        return changes;
+    }
+
+    let mut change = |kind| {
+        changes.push(Change {
+            class: new.name.clone(),
+            member: None,
+            kind,
+        })
+    };
+
+    let (old_access, new_access) = (class_access(old), class_access(new));
+    if old_access != new_access {
+        change(ChangeKind::AccessChanged {
+            old: old_access,
+            new: new_access,
+        });
+    }
+    match (
+        ClassFlag::Interface.is_set(old.flags),
+        ClassFlag::Interface.is_set(new.flags),
+    ) {
+        (false, true) => change(ChangeKind::BecameInterface),
+        (true, false) => change(ChangeKind::BecameClass),
+        _ => {}
+    }
+    if let Some(kind) = flag_change(
+        ClassFlag::Final.is_set(old.flags),
+        ClassFlag::Final.is_set(new.flags),
+        ChangeKind::BecameFinal,
+        ChangeKind::NoLongerFinal,
+    ) {
+        change(kind);
+    }
+    // Interfaces are always abstract
+    if !ClassFlag::Interface.is_set(old.flags) && !ClassFlag::Interface.is_set(new.flags) {
+        if let Some(kind) = flag_change(
+            ClassFlag::Abstract.is_set(old.flags),
+            ClassFlag::Abstract.is_set(new.flags),
+            ChangeKind::BecameAbstract,
+            ChangeKind::NoLongerAbstract,
+        ) {
+            change(kind);
+        }
+    }
+    if old.superclass != new.superclass {
+        change(ChangeKind::SuperclassChanged {
+            old: old.superclass.clone(),
+            new: new.superclass.clone(),
+        });
+    }
+    for interface in &old.interfaces {
+        if !new.interfaces.contains(interface) {
+            change(ChangeKind::InterfaceRemoved(interface.clone()));
+        }
+    }
+    for interface in &new.interfaces {
+        if !old.interfaces.contains(interface) {
+            change(ChangeKind::InterfaceAdded(interface.clone()));
+        }
+    }
+
+    diff_members(&new.name, &old.fields, &new.fields, false, &mut changes);
+    diff_members(&new.name, &old.methods, &new.methods, true, &mut changes);
+
+    changes
+}
+
+fn diff_members(
+    class: &str,
+    old: &[Field],
+    new: &[Field],
+    methods: bool,
+    changes: &mut Vec<Change>,
+) {
+    let by_key = |members: &[Field]| -> BTreeMap<String, Field> {
+        members
+            .iter()
+            .map(|m| {
+                (
+                    format!("{}{}", m.name, member_descriptor(m, methods)),
+                    m.clone(),
+                )
+            })
+            .collect()
+    };
+    let (old, new) = (by_key(old), by_key(new));
+    let visible = |m: &Field| Access::from_flags(m.flags).is_exported();
+    let change = |member: &str, kind| Change {
+        class: class.to_string(),
+        member: Some(member.to_string()),
+        kind,
+    };
+
+    let removed: Vec<&Field> = old
+        .iter()
+        .filter(|(key, m)| visible(m) && !new.contains_key(*key))
+        .map(|(_, m)| m)
+        .collect();
+    let added: Vec<&Field> = new
+        .iter()
+        .filter(|(key, m)| visible(m) && !old.contains_key(*key))
+        .map(|(_, m)| m)
+        .collect();
+
+    // A member whose only removed and added versions share a name had its
+    // descriptor changed
+    let count = |list: &[&Field], name: &str| list.iter().filter(|m| m.name == name).count();
+    let changed = |name: &str| count(&removed, name) == 1 && count(&added, name) == 1;
+    for m in &removed {
+        if changed(&m.name) {
+            let replacement = added.iter().find(|a| a.name == m.name).unwrap();
+            changes.push(change(
+                &m.name,
+                ChangeKind::DescriptorChanged {
+                    old: m.descriptor.clone(),
+                    new: replacement.descriptor.clone(),
+                },
+            ));
+        } else {
+            changes.push(change(&display_member(m, methods), ChangeKind::Removed));
+        }
+    }
+    for m in &added {
+        if !changed(&m.name) {
+            changes.push(change(&display_member(m, methods), ChangeKind::Added));
+        }
+    }
+
+    for (key, old_member) in &old {
+        let new_member = match new.get(key) {
+            Some(m) => m,
+            None => continue,
+        };
+        if !visible(old_member) && !visible(new_member) {
+            continue;
+        }
+        let name = display_member(new_member, methods);
+
+        if old_member.descriptor != new_member.descriptor {
+            changes.push(change(
+                &name,
+                ChangeKind::DescriptorChanged {
+                    old: old_member.descriptor.clone(),
+                    new: new_member.descriptor.clone(),
+                },
+            ));
+        }
+
+        let (old_access, new_access) = (
+            Access::from_flags(old_member.flags),
+            Access::from_flags(new_member.flags),
+        );
+        if old_access != new_access {
+            changes.push(change(
+                &name,
+                ChangeKind::AccessChanged {
+                    old: old_access,
+                    new: new_access,
+                },
+            ));
+        }
+
+        let flags = [
+            (
+                MethodFlag::Static,
+                ChangeKind::BecameStatic,
+                ChangeKind::NoLongerStatic,
+            ),
+            (
+                MethodFlag::Final,
+                ChangeKind::BecameFinal,
+                ChangeKind::NoLongerFinal,
+            ),
+            (
+                MethodFlag::Abstract,
+                ChangeKind::BecameAbstract,
+                ChangeKind::NoLongerAbstract,
+            ),
+        ];
+        for (flag, set, cleared) in flags.iter() {
+            if let Some(kind) = flag_change(
+                flag.is_set(old_member.flags),
+                flag.is_set(new_member.flags),
+                set.clone(),
+                cleared.clone(),
+            ) {
+                changes.push(change(&name, kind));
+            }
+        }
+    }
+}
+
+/// Methods are matched by their full descriptor and fields by name alone, as
+/// a class cannot have two fields with the same name
+fn member_descriptor(member: &Field, methods: bool) -> &str {
+    if methods {
+        &member.descriptor
+    } else {
+        ""
+    }
+}
+
+fn display_member(member: &Field, methods: bool) -> String {
+    if methods {
+        format!("{}{}", member.name, member.descriptor)
+    } else {
+        member.name.clone()
+    }
+}
+
+fn flag_change(old: bool, new: bool, set: ChangeKind, cleared: ChangeKind) -> Option<ChangeKind> {
+    match (old, new) {
+        (false, true) => Some(set),
+        (true, false) => Some(cleared),
+        _ => None,
+    }
+}
+
+fn class_access(class: &Class) -> Access {
+    if ClassFlag::Public.is_set(class.flags) {
+        Access::Public
+    } else {
+        Access::Package
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::const_pool::ConstPool;
+
+    fn class(flags: u16, fields: &[(&str, &str, u16)], methods: &[(&str, &str, u16)]) -> Class {
+        let members = |list: &[(&str, &str, u16)]| {
+            list.iter()
+                .map(|(name, descriptor, flags)| Field {
+                    flags: *flags,
+                    name: name.to_string(),
+                    descriptor: descriptor.to_string(),
+                    attributes: vec![],
+                })
+                .collect()
+        };
+
+        Class {
+            major: 52,
+            minor: 0,
+            const_pool: ConstPool { consts: vec![] },
+            name: "Lib".to_string(),
+            superclass: "java/lang/Object".to_string(),
+            flags,
+            interfaces: vec![],
+            fields: members(fields),
+            methods: members(methods),
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_diff_class() {
+        let old = class(
+            0x0021,
+            &[("count", "I", 0x0001), ("hidden", "I", 0x0002)],
+            &[
+                ("run", "()V", 0x0001),
+                ("stop", "()V", 0x0001),
+                ("size", "()I", 0x0001),
+            ],
+        );
+        let new = class(
+            0x0031,
+            &[("count", "J", 0x0001)],
+            &[
+                ("run", "()V", 0x0002),
+                ("size", "()J", 0x0001),
+                ("reset", "()V", 0x0001),
+            ],
+        );
+
+        let changes: Vec<(String, bool)> = diff_class(&old, &new)
+            .iter()
+            .map(|c| (c.to_string(), c.is_compatible()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("Lib: became final".to_string(), false),
+                (
+                    "Lib.count: descriptor changed from I to J".to_string(),
+                    false
+                ),
+                (
+                    "Lib.size: descriptor changed from ()I to ()J".to_string(),
+                    false
+                ),
+                ("Lib.stop()V: removed".to_string(), false),
+                ("Lib.reset()V: added".to_string(), true),
+                ("Lib.run()V: public became private".to_string(), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::attribute::{Code, ExceptionHandler};
+use crate::class::{Class, Field};
+use crate::instruction::{self, Instruction};
+use crate::opcode::Opcode;
+use crate::stack_map::{self, ClassHierarchy, FrameState, VerificationType};
+use crate::verifier::{Interpreter, STACK_MAP_VERSION};
+
+/// Types inferred for a method's code by following every path through it
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    /// State before each reachable instruction, keyed by offset
+    pub states: BTreeMap<u32, FrameState>,
+    /// Reachable offsets that need a stack map frame: branch targets,
+    /// exception handlers and instructions that cannot be fallen into
+    pub frames: BTreeSet<u32>,
+}
+
+/// Infers the types of the locals and stack before every instruction of a
+/// method. Where paths meet, references merge to their common superclass.
+pub fn analyze(
+    class: &Class,
+    method: &Field,
+    code: &Code,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Analysis, String> {
+    let instructions = instruction::decode(&code.code)?;
+    let positions: BTreeMap<u32, usize> = instructions
+        .iter()
+        .enumerate()
+        .map(|(i, ins)| (ins.offset, i))
+        .collect();
+
+    let interpreter = Interpreter {
+        const_pool: &class.const_pool,
+        class_name: &class.name,
+        code: &code.code,
+        hierarchy,
+    };
+
+    let initial = FrameState {
+        offset: 0,
+        locals: stack_map::expand_locals(&stack_map::initial_locals(&class.name, method)?),
+        stack: vec![],
+    };
+    let mut max_stack = 0;
+    let mut max_locals = initial.locals.len();
+    let mut states = BTreeMap::new();
+    let mut frames = BTreeSet::new();
+    let mut worklist = vec![0];
+    states.insert(0, initial);
+
+    while let Some(offset) = worklist.pop() {
+        let ins = match positions.get(&offset) {
+            Some(i) => &instructions[*i],
+            None => return Err(format!("no instruction at offset {}", offset)),
+        };
+        let error = |e: String| format!("at offset {}: {}", offset, e);
+
+        let mut state = states[&offset].clone();
+        let mut successors = handler_states(class, code, &state).map_err(error)?;
+        interpreter.step(&mut state, ins).map_err(error)?;
+        max_stack = max_stack.max(state.stack_size());
+        max_locals = max_locals.max(state.locals.len());
+
+        // Stores change the locals the handlers see
+        if ins.local().is_some() {
+            successors.extend(handler_states(class, code, &state).map_err(error)?);
+        }
+        frames.extend(successors.iter().map(|s| s.offset));
+
+        for target in ins.branch_targets() {
+            frames.insert(target);
+            successors.push(FrameState {
+                offset: target,
+                ..state.clone()
+            });
+        }
+        if ins.falls_through() {
+            let next = offset + ins.length;
+            if next as usize >= code.code.len() {
+                return Err(error("falls off the end of the code".to_string()));
+            }
+            successors.push(FrameState {
+                offset: next,
+                ..state
+            });
+        }
+
+        for successor in successors {
+            // A handler starts with the exception on the stack, which the
+            // instruction's own state does not count
+            max_stack = max_stack.max(successor.stack_size());
+            let merged = match states.get(&successor.offset) {
+                Some(existing) => merge(existing, &successor, hierarchy)
+                    .map_err(|e| format!("at offset {}: {}", successor.offset, e))?,
+                None => Some(successor),
+            };
+            if let Some(merged) = merged {
+                worklist.push(merged.offset);
+                states.insert(merged.offset, merged);
+            }
+        }
+    }
+
+    // Code after a jump, return or throw can only be reached by a branch, so
+    // it needs a frame even if nothing else marked it
+    for pair in instructions.windows(2) {
+        if states.contains_key(&pair[1].offset)
+            && (!pair[0].falls_through() || !states.contains_key(&pair[0].offset))
+        {
+            frames.insert(pair[1].offset);
+        }
+    }
+
+    Ok(Analysis {
+        max_stack: max_stack as u16,
+        max_locals: max_locals as u16,
+        states,
+        frames,
+    })
+}
+
+/// Recomputes max_stack, max_locals and the StackMapTable of every method in
+/// a class, as needed after generating or rewriting its code. Unreachable
+/// code is replaced by nops ending in athrow, as it has no types to describe.
+pub fn compute_frames(class: &mut Class, hierarchy: &dyn ClassHierarchy) -> Result<(), String> {
+    for i in 0..class.methods.len() {
+        let method = class.methods[i].clone();
+        let mut code = match Code::from_method(&method, &class.const_pool)? {
+            Some(code) => code,
+            None => continue,
+        };
+
+        let analysis = analyze(class, &method, &code, hierarchy)
+            .map_err(|e| format!("{}.{}{} {}", class.name, method.name, method.descriptor, e))?;
+        let mut states: Vec<FrameState> = analysis
+            .frames
+            .iter()
+            .map(|offset| analysis.states[offset].clone())
+            .collect();
+
+        let dead = remove_dead_code(&mut code, &analysis)?;
+        for offset in &dead {
+            states.push(FrameState {
+                offset: *offset,
+                locals: vec![],
+                stack: vec![VerificationType::from_class_name("java/lang/Throwable")],
+            });
+        }
+        states.sort_by_key(|state| state.offset);
+
+        code.max_stack = analysis.max_stack;
+        if !dead.is_empty() {
+            code.max_stack = code.max_stack.max(1);
+        }
+        code.max_locals = analysis.max_locals;
+        code.attributes.retain(|attr| attr.name != "StackMapTable");
+        if !states.is_empty() && class.major >= STACK_MAP_VERSION {
+            let initial = stack_map::initial_locals(&class.name, &method)?;
+            let frames = stack_map::compress(&states, &initial);
+            code.attributes
+                .push(stack_map::write(&frames, &mut class.const_pool));
+        }
+
+        let attribute = code.to_attribute(&mut class.const_pool);
+        for attr in class.methods[i].attributes.iter_mut() {
+            if attr.name == "Code" {
+                *attr = attribute.clone();
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// States that flow into the handlers covering the instruction a state is at
+fn handler_states(
+    class: &Class,
+    code: &Code,
+    state: &FrameState,
+) -> Result<Vec<FrameState>, String> {
+    let mut states = vec![];
+    for handler in &code.exception_table {
+        if state.offset < handler.start_pc as u32 || state.offset >= handler.end_pc as u32 {
+            continue;
+        }
+
+        let catch_type = match handler.catch_type {
+            0 => "java/lang/Throwable",
+            index => match class.const_pool.class_name(index) {
+                Some(name) => name,
+                None => return Err(format!("invalid catch type {}", index)),
+            },
+        };
+        states.push(FrameState {
+            offset: handler.handler_pc as u32,
+            locals: state.locals.clone(),
+            stack: vec![VerificationType::from_class_name(catch_type)],
+        });
+    }
+
+    Ok(states)
+}
+
+/// Merges a state into the one already recorded for an offset, returning the
+/// result if anything changed
+fn merge(
+    existing: &FrameState,
+    incoming: &FrameState,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<Option<FrameState>, String> {
+    if existing.stack.len() != incoming.stack.len() {
+        return Err(format!(
+            "stack has {} items on one path and {} on another",
+            existing.stack.len(),
+            incoming.stack.len()
+        ));
+    }
+
+    let mut stack = vec![];
+    for (a, b) in existing.stack.iter().zip(&incoming.stack) {
+        let typ = merge_type(a, b, hierarchy);
+        if typ == VerificationType::Top {
+            return Err(format!(
+                "stack holds {} on one path and {} on another",
+                a, b
+            ));
+        }
+        stack.push(typ);
+    }
+
+    // Locals only one path has set are unusable after the merge
+    let locals: Vec<VerificationType> = existing
+        .locals
+        .iter()
+        .zip(&incoming.locals)
+        .map(|(a, b)| merge_type(a, b, hierarchy))
+        .collect();
+
+    if locals == existing.locals && stack == existing.stack {
+        return Ok(None);
+    }
+
+    Ok(Some(FrameState {
+        offset: existing.offset,
+        locals,
+        stack,
+    }))
+}
+
+fn merge_type(
+    a: &VerificationType,
+    b: &VerificationType,
+    hierarchy: &dyn ClassHierarchy,
+) -> VerificationType {
+    match (a, b) {
+        _ if a == b => a.clone(),
+        (VerificationType::Null, VerificationType::Object(_)) => b.clone(),
+        (VerificationType::Object(_), VerificationType::Null) => a.clone(),
+        (VerificationType::Object(a), VerificationType::Object(b)) => {
+            VerificationType::Object(hierarchy.common_superclass(a, b))
+        }
+        _ => VerificationType::Top,
+    }
+}
+
+/// Fills each run of unreachable instructions with nops and a final athrow,
+/// and cuts the runs out of the exception table. Returns where each run
+/// starts.
+fn remove_dead_code(code: &mut Code, analysis: &Analysis) -> Result<Vec<u32>, String> {
+    let instructions: Vec<Instruction> = instruction::decode(&code.code)?;
+    let mut runs: Vec<(u32, u32)> = vec![];
+    for ins in &instructions {
+        if analysis.states.contains_key(&ins.offset) {
+            continue;
+        }
+        let end = ins.offset + ins.length;
+        match runs.last_mut() {
+            Some(run) if run.1 == ins.offset => run.1 = end,
+            _ => runs.push((ins.offset, end)),
+        }
+    }
+
+    for (start, end) in &runs {
+        for byte in &mut code.code[*start as usize..*end as usize - 1] {
+            *byte = Opcode::Nop as u8;
+        }
+        code.code[*end as usize - 1] = Opcode::AThrow as u8;
+    }
+
+    let mut exception_table = vec![];
+    for handler in &code.exception_table {
+        let mut start = handler.start_pc as u32;
+        let end = handler.end_pc as u32;
+        for (run_start, run_end) in &runs {
+            if *run_end <= start || *run_start >= end {
+                continue;
+            }
+            if *run_start > start {
+                exception_table.push(ExceptionHandler {
+                    start_pc: start as u16,
+                    end_pc: *run_start as u16,
+                    ..handler.clone()
+                });
+            }
+            start = *run_end;
+        }
+        if start < end {
+            exception_table.push(ExceptionHandler {
+                start_pc: start as u16,
+                ..handler.clone()
+            });
+        }
+    }
+    code.exception_table = exception_table;
+
+    Ok(runs.into_iter().map(|(start, _)| start).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_util::class_with_method;
+    use crate::verifier;
+
+    #[test]
+    fn test_branches() {
+        // if (a == 0) { long b = 1; return 1; } return 0;
+        let mut class = class_with_method(
+            "(I)I",
+            0,
+            0,
+            &[0x1a, 0x99, 0x00, 0x07, 0x0a, 0x40, 0x04, 0xac, 0x03, 0xac],
+        );
+        compute_frames(&mut class, &HashMap::new()).unwrap();
+
+        let code = Code::from_method(&class.methods[0], &class.const_pool)
+            .unwrap()
+            .unwrap();
+        assert_eq!(code.max_stack, 2);
+        assert_eq!(code.max_locals, 3);
+        assert!(code.find_attribute("StackMapTable").is_some());
+        assert_eq!(verifier::verify_class(&class, &HashMap::new()), Ok(()),);
+    }
+
+    #[test]
+    fn test_dead_code() {
+        // return; iconst_0; pop; return
+        let mut class = class_with_method("()V", 0, 0, &[0xb1, 0x03, 0x57, 0xb1]);
+        compute_frames(&mut class, &HashMap::new()).unwrap();
+
+        let code = Code::from_method(&class.methods[0], &class.const_pool)
+            .unwrap()
+            .unwrap();
+        assert_eq!(code.code, vec![0xb1, 0x00, 0x00, 0xbf]);
+        assert_eq!(verifier::verify_class(&class, &HashMap::new()), Ok(()),);
+    }
+
+    #[test]
+    fn test_handlers() {
+        // nop; return; pop; return with a handler at 2 covering the nop
+        let mut class = class_with_method("()V", 0, 0, &[0x00, 0xb1, 0x57, 0xb1]);
+        let mut code = Code::from_method(&class.methods[0], &class.const_pool)
+            .unwrap()
+            .unwrap();
+        code.exception_table.push(ExceptionHandler {
+            start_pc: 0,
+            end_pc: 1,
+            handler_pc: 2,
+            catch_type: 0,
+        });
+        class.methods[0].attributes = vec![code.to_attribute(&mut class.const_pool)];
+        compute_frames(&mut class, &HashMap::new()).unwrap();
+
+        let code = Code::from_method(&class.methods[0], &class.const_pool)
+            .unwrap()
+            .unwrap();
+        assert_eq!(code.max_stack, 1);
+        assert_eq!(verifier::verify_class(&class, &HashMap::new()), Ok(()),);
+    }
+
+    #[test]
+    fn test_stack_mismatch() {
+        // iload_0; ifeq +4; iconst_1; iconst_0; ireturn
+        let class = class_with_method("(I)I", 0, 0, &[0x1a, 0x99, 0x00, 0x04, 0x04, 0x03, 0xac]);
+        let code = Code::from_method(&class.methods[0], &class.const_pool)
+            .unwrap()
+            .unwrap();
+
+        assert!(analyze(&class, &class.methods[0], &code, &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,592 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+use crate::attribute::Code;
+use crate::class::{Class, Field};
+use crate::flags::{ClassFlag, MethodFlag};
+use crate::instruction::{self, Operand};
+use crate::opcode::Opcode;
+
+/// A field or method, named by the class declaring it
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemberRef {
+    pub class: String,
+    pub name: String,
+    pub descriptor: String,
+}
+
+impl MemberRef {
+    pub fn new(class: &str, name: &str, descriptor: &str) -> MemberRef {
+        MemberRef {
+            class: class.to_string(),
+            name: name.to_string(),
+            descriptor: descriptor.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for MemberRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.descriptor.starts_with('(') {
+            write!(f, "{}.{}{}", self.class, self.name, self.descriptor)
+        } else {
+            write!(f, "{}.{}:{}", self.class, self.name, self.descriptor)
+        }
+    }
+}
+
+/// A reference from code or a class declaration to something missing from
+/// the loaded classes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Unresolved {
+    Class(String),
+    Method(MemberRef),
+    Field(MemberRef),
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unresolved::Class(name) => write!(f, "class {}", name),
+            Unresolved::Method(method) => write!(f, "method {}", method),
+            Unresolved::Field(field) => write!(f, "field {}", field),
+        }
+    }
+}
+
+/// Hierarchy links each loaded class to its direct subclasses and
+/// implementors so it can be walked in either direction
+pub struct Hierarchy<'a> {
+    pub classes: &'a HashMap<String, Class>,
+    subtypes: HashMap<String, BTreeSet<String>>,
+}
+
+impl<'a> Hierarchy<'a> {
+    pub fn new(classes: &'a HashMap<String, Class>) -> Hierarchy<'a> {
+        let mut subtypes: HashMap<String, BTreeSet<String>> = HashMap::new();
+        for class in classes.values() {
+            let supertypes = class.interfaces.iter().chain(Some(&class.superclass));
+            for supertype in supertypes.filter(|s| !s.is_empty()) {
+                subtypes
+                    .entry(supertype.clone())
+                    .or_default()
+                    .insert(class.name.clone());
+            }
+        }
+
+        Hierarchy { classes, subtypes }
+    }
+
+    /// Classes that directly extend or implement a class or interface
+    pub fn direct_subtypes(self: &Hierarchy<'a>, name: &str) -> BTreeSet<String> {
+        self.subtypes.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Every loaded class that extends or implements a class or interface,
+    /// directly or not
+    pub fn subtypes(self: &Hierarchy<'a>, name: &str) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        let mut worklist = vec![name.to_string()];
+        while let Some(current) = worklist.pop() {
+            for subtype in self.direct_subtypes(&current) {
+                if found.insert(subtype.clone()) {
+                    worklist.push(subtype);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Every loaded class and interface a class extends or implements,
+    /// directly or not. Missing classes are included but not followed.
+    pub fn supertypes(self: &Hierarchy<'a>, name: &str) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        let mut worklist = vec![name.to_string()];
+        while let Some(current) = worklist.pop() {
+            if let Some(class) = self.classes.get(&current) {
+                let supertypes = class.interfaces.iter().chain(Some(&class.superclass));
+                for supertype in supertypes.filter(|s| !s.is_empty()) {
+                    if found.insert(supertype.clone()) {
+                        worklist.push(supertype.clone());
+                    }
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Superclasses and interfaces named by loaded classes that are not
+    /// loaded themselves
+    pub fn missing_supertypes(self: &Hierarchy<'a>) -> BTreeSet<String> {
+        self.subtypes
+            .keys()
+            .filter(|name| !self.classes.contains_key(*name))
+            .cloned()
+            .collect()
+    }
+
+    /// Finds the method a reference resolves to, looking through the
+    /// superclasses and then the superinterfaces as the JVM does
+    pub fn resolve_method(
+        self: &Hierarchy<'a>,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<MemberRef> {
+        let mut current = class.to_string();
+        while let Some(c) = self.classes.get(&current) {
+            if c.methods
+                .iter()
+                .any(|m| matches_method(c, m, name, descriptor))
+            {
+                return Some(MemberRef::new(&current, name, descriptor));
+            }
+            current = c.superclass.clone();
+        }
+
+        let mut interfaces: Vec<String> = self
+            .supertypes(class)
+            .into_iter()
+            .filter(
+                |s| matches!(self.classes.get(s), Some(c) if ClassFlag::Interface.is_set(c.flags)),
+            )
+            .collect();
+        // Prefer an interface's default method over an abstract declaration
+        interfaces.sort_by_key(|s| {
+            let c = &self.classes[s];
+            !c.methods.iter().any(|m| {
+                m.name == name
+                    && m.descriptor == descriptor
+                    && !MethodFlag::Abstract.is_set(m.flags)
+            })
+        });
+        interfaces.into_iter().find_map(|interface| {
+            let c = &self.classes[&interface];
+            c.methods
+                .iter()
+                .find(|m| {
+                    m.name == name
+                        && m.descriptor == descriptor
+                        && !MethodFlag::Static.is_set(m.flags)
+                })
+                .map(|_| MemberRef::new(&interface, name, descriptor))
+        })
+    }
+
+    /// Finds the field a reference resolves to, looking through the class,
+    /// its interfaces and then its superclass
+    pub fn resolve_field(
+        self: &Hierarchy<'a>,
+        class: &str,
+        name: &str,
+        descriptor: &str,
+    ) -> Option<MemberRef> {
+        let c = self.classes.get(class)?;
+        if c.fields
+            .iter()
+            .any(|f| f.name == name && f.descriptor == descriptor)
+        {
+            return Some(MemberRef::new(class, name, descriptor));
+        }
+
+        c.interfaces
+            .iter()
+            .chain(Some(&c.superclass))
+            .find_map(|supertype| self.resolve_field(supertype, name, descriptor))
+    }
+
+    /// Methods of a class that override or implement a method declared by
+    /// one of its supertypes, paired with what they override
+    pub fn overrides(self: &Hierarchy<'a>, name: &str) -> Vec<(MemberRef, MemberRef)> {
+        let class = match self.classes.get(name) {
+            Some(class) => class,
+            None => return vec![],
+        };
+
+        let mut found = vec![];
+        for method in &class.methods {
+            if !is_overridable(method) {
+                continue;
+            }
+            for supertype in self.supertypes(name) {
+                let declares = matches!(self.classes.get(&supertype), Some(c) if c.methods.iter().any(|m| {
+                    m.name == method.name && m.descriptor == method.descriptor && is_overridable(m)
+                }));
+                if declares {
+                    found.push((
+                        MemberRef::new(name, &method.name, &method.descriptor),
+                        MemberRef::new(&supertype, &method.name, &method.descriptor),
+                    ));
+                }
+            }
+        }
+
+        found
+    }
+
+    /// Implementations an invokevirtual or invokeinterface of a method may
+    /// reach: the method each concrete subtype of the class resolves it to
+    pub fn dispatch_targets(self: &Hierarchy<'a>, method: &MemberRef) -> BTreeSet<MemberRef> {
+        let mut types = self.subtypes(&method.class);
+        types.insert(method.class.clone());
+
+        types
+            .iter()
+            .filter(|name| {
+                matches!(self.classes.get(*name), Some(c)
+                    if !ClassFlag::Interface.is_set(c.flags) && !ClassFlag::Abstract.is_set(c.flags))
+            })
+            .filter_map(|name| self.resolve_method(name, &method.name, &method.descriptor))
+            .collect()
+    }
+
+    pub(crate) fn find_method(self: &Hierarchy<'a>, method: &MemberRef) -> Option<&'a Field> {
+        self.classes.get(&method.class).and_then(|c| {
+            c.methods
+                .iter()
+                .find(|m| m.name == method.name && m.descriptor == method.descriptor)
+        })
+    }
+}
+
+/// Method call graph found by class hierarchy analysis, where a virtual call
+/// may reach the implementation in any subtype of the declared class
+#[derive(Debug, Clone, Default)]
+pub struct CallGraph {
+    pub calls: BTreeMap<MemberRef, BTreeSet<MemberRef>>,
+    pub reachable: BTreeSet<MemberRef>,
+    pub unresolved: BTreeSet<Unresolved>,
+}
+
+impl CallGraph {
+    /// Follows calls from the entry points, such as `main` methods. Class
+    /// initialisers are reached when code first uses their class.
+    /// invokedynamic call sites are not followed.
+    pub fn build(hierarchy: &Hierarchy, entries: &[MemberRef]) -> CallGraph {
+        let mut graph = CallGraph::default();
+        let mut worklist: Vec<MemberRef> = entries.to_vec();
+
+        while let Some(method) = worklist.pop() {
+            if !graph.reachable.insert(method.clone()) {
+                continue;
+            }
+
+            let callees = graph.callees(hierarchy, &method);
+            for callee in &callees {
+                if !graph.reachable.contains(callee) {
+                    worklist.push(callee.clone());
+                }
+            }
+            graph.calls.insert(method, callees);
+        }
+
+        graph
+    }
+
+    fn callees(
+        self: &mut CallGraph,
+        hierarchy: &Hierarchy,
+        method: &MemberRef,
+    ) -> BTreeSet<MemberRef> {
+        let mut callees = BTreeSet::new();
+        let class = &hierarchy.classes[&method.class];
+        let code = match hierarchy.find_method(method) {
+            Some(field) => match Code::from_method(field, &class.const_pool) {
+                Ok(Some(code)) => code,
+                _ => return callees,
+            },
+            None => return callees,
+        };
+        let instructions = match instruction::decode(&code.code) {
+            Ok(instructions) => instructions,
+            Err(_) => return callees,
+        };
+
+        for ins in instructions {
+            let index = match ins.operand {
+                Operand::Constant(index) | Operand::InvokeInterface(index, _) => index,
+                _ => continue,
+            };
+
+            match ins.opcode {
+                Opcode::New => {
+                    if let Some(name) = class.const_pool.class_name(index) {
+                        self.initialise(hierarchy, name, &mut callees);
+                    }
+                }
+                Opcode::GetStatic | Opcode::PutStatic | Opcode::GetField | Opcode::PutField => {
+                    let (owner, name, descriptor) = match class.const_pool.member_ref(index) {
+                        Some(member) => member,
+                        None => continue,
+                    };
+                    if !self.check_class(hierarchy, owner) {
+                        continue;
+                    }
+                    match hierarchy.resolve_field(owner, name, descriptor) {
+                        Some(field) => {
+                            if matches!(ins.opcode, Opcode::GetStatic | Opcode::PutStatic) {
+                                self.initialise(hierarchy, &field.class, &mut callees);
+                            }
+                        }
+                        None => {
+                            self.unresolved
+                                .insert(Unresolved::Field(MemberRef::new(owner, name, descriptor)));
+                        }
+                    }
+                }
+                Opcode::InvokeVirtual
+                | Opcode::InvokeInterface
+                | Opcode::InvokeSpecial
+                | Opcode::InvokeStatic => {
+                    let (owner, name, descriptor) = match class.const_pool.member_ref(index) {
+                        Some(member) => member,
+                        None => continue,
+                    };
+                    // Array methods are those of java/lang/Object
+                    let owner = if owner.starts_with('[') {
+                        "java/lang/Object"
+                    } else {
+                        owner
+                    };
+                    if !self.check_class(hierarchy, owner) {
+                        continue;
+                    }
+
+                    let resolved = match hierarchy.resolve_method(owner, name, descriptor) {
+                        Some(resolved) => resolved,
+                        None if is_signature_polymorphic(owner) => continue,
+                        None => {
+                            self.unresolved.insert(Unresolved::Method(MemberRef::new(
+                                owner, name, descriptor,
+                            )));
+                            continue;
+                        }
+                    };
+
+                    match ins.opcode {
+                        Opcode::InvokeVirtual | Opcode::InvokeInterface => {
+                            let is_final = matches!(hierarchy.find_method(&resolved), Some(m) if !is_overridable(m));
+                            if is_final {
+                                callees.insert(resolved);
+                            } else {
+                                callees
+                                    .extend(hierarchy.dispatch_targets(&MemberRef::new(
+                                        owner, name, descriptor,
+                                    )));
+                            }
+                        }
+                        Opcode::InvokeStatic => {
+                            self.initialise(hierarchy, &resolved.class, &mut callees);
+                            callees.insert(resolved);
+                        }
+                        _ => {
+                            callees.insert(resolved);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        callees
+    }
+
+    /// Records a class as unresolved if it is not loaded, returning whether it
+    /// is
+    fn check_class(self: &mut CallGraph, hierarchy: &Hierarchy, name: &str) -> bool {
+        if hierarchy.classes.contains_key(name) {
+            true
+        } else {
+            self.unresolved.insert(Unresolved::Class(name.to_string()));
+            false
+        }
+    }
+
+    /// Adds the static initialisers that run when a class is first used
+    fn initialise(
+        self: &mut CallGraph,
+        hierarchy: &Hierarchy,
+        name: &str,
+        callees: &mut BTreeSet<MemberRef>,
+    ) {
+        if name.starts_with('[') || !self.check_class(hierarchy, name) {
+            return;
+        }
+
+        let mut current = name.to_string();
+        while let Some(class) = hierarchy.classes.get(&current) {
+            let clinit = MemberRef::new(&current, "<clinit>", "()V");
+            if hierarchy.find_method(&clinit).is_some() {
+                callees.insert(clinit);
+            }
+            current = class.superclass.clone();
+        }
+    }
+}
+
+/// Every `public static void main(String[])` method in the given classes
+pub fn main_methods(classes: &[&Class]) -> Vec<MemberRef> {
+    classes
+        .iter()
+        .filter(|c| {
+            c.methods.iter().any(|m| {
+                m.name == "main"
+                    && m.descriptor == "([Ljava/lang/String;)V"
+                    && MethodFlag::Static.is_set(m.flags)
+            })
+        })
+        .map(|c| MemberRef::new(&c.name, "main", "([Ljava/lang/String;)V"))
+        .collect()
+}
+
+fn matches_method(class: &Class, method: &Field, name: &str, descriptor: &str) -> bool {
+    method.name == name
+        && (method.descriptor == descriptor
+            || (is_signature_polymorphic(&class.name)
+                && MethodFlag::Native.is_set(method.flags)
+                && MethodFlag::VarArgs.is_set(method.flags)))
+}
+
+/// MethodHandle and VarHandle have native methods such as `invoke` that can be
+/// called with any descriptor
+pub(crate) fn is_signature_polymorphic(class: &str) -> bool {
+    class == "java/lang/invoke/MethodHandle" || class == "java/lang/invoke/VarHandle"
+}
+
+pub(crate) fn is_overridable(method: &Field) -> bool {
+    !method.name.starts_with('<')
+        && !MethodFlag::Static.is_set(method.flags)
+        && !MethodFlag::Private.is_set(method.flags)
+        && !MethodFlag::Final.is_set(method.flags)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::class::Attribute;
+    use crate::const_pool::ConstPool;
+
+    fn class(
+        name: &str,
+        superclass: &str,
+        flags: u16,
+        methods: &[(&str, u16, Vec<u8>)],
+        const_pool: ConstPool,
+    ) -> Class {
+        Class {
+            major: 52,
+            minor: 0,
+            const_pool,
+            name: name.to_string(),
+            superclass: superclass.to_string(),
+            flags,
+            interfaces: vec![],
+            fields: vec![],
+            methods: methods
+                .iter()
+                .map(|(method, flags, code)| {
+                    let mut data = vec![0, 2, 0, 1];
+                    data.extend_from_slice(&(code.len() as u32).to_be_bytes());
+                    data.extend_from_slice(code);
+                    data.extend_from_slice(&[0, 0, 0, 0]);
+                    Field {
+                        flags: *flags,
+                        name: method.to_string(),
+                        descriptor: "()V".to_string(),
+                        attributes: vec![Attribute {
+                            name: "Code".to_string(),
+                            data,
+                        }],
+                    }
+                })
+                .collect(),
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_call_graph() {
+        use crate::jvm_const::JvmConst;
+
+        // Main.main calls Shape.draw and Missing.run
+        let const_pool = ConstPool {
+            consts: vec![
+                JvmConst::UTF8("Shape".to_string()),
+                JvmConst::Class(1),
+                JvmConst::UTF8("draw".to_string()),
+                JvmConst::UTF8("()V".to_string()),
+                JvmConst::NameAndType(3, 4),
+                JvmConst::MethodRef(2, 5),
+                JvmConst::UTF8("Missing".to_string()),
+                JvmConst::Class(7),
+                JvmConst::UTF8("run".to_string()),
+                JvmConst::NameAndType(9, 4),
+                JvmConst::MethodRef(8, 10),
+            ],
+        };
+        let empty = || ConstPool { consts: vec![] };
+        let classes: HashMap<String, Class> = vec![
+            class("java/lang/Object", "", 0x0021, &[], empty()),
+            class(
+                "Shape",
+                "java/lang/Object",
+                0x0421,
+                &[("draw", 0x0401, vec![])],
+                empty(),
+            ),
+            class(
+                "Square",
+                "Shape",
+                0x0021,
+                &[("draw", 0x0001, vec![0xb1])],
+                empty(),
+            ),
+            class(
+                "Circle",
+                "Shape",
+                0x0021,
+                &[("draw", 0x0001, vec![0xb1])],
+                empty(),
+            ),
+            class(
+                "Main",
+                "java/lang/Object",
+                0x0021,
+                &[(
+                    "main",
+                    0x0009,
+                    vec![0x01, 0xb6, 0x00, 0x06, 0xb8, 0x00, 0x0b, 0xb1],
+                )],
+                const_pool,
+            ),
+        ]
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect();
+
+        let hierarchy = Hierarchy::new(&classes);
+        assert_eq!(hierarchy.subtypes("Shape").len(), 2);
+        assert_eq!(
+            hierarchy.overrides("Square"),
+            vec![(
+                MemberRef::new("Square", "draw", "()V"),
+                MemberRef::new("Shape", "draw", "()V")
+            )]
+        );
+
+        let graph = CallGraph::build(&hierarchy, &[MemberRef::new("Main", "main", "()V")]);
+        let calls: Vec<String> = graph.calls[&MemberRef::new("Main", "main", "()V")]
+            .iter()
+            .map(|m| m.to_string())
+            .collect();
+        assert_eq!(calls, vec!["Circle.draw()V", "Square.draw()V"]);
+        assert_eq!(graph.reachable.len(), 3);
+        assert!(graph
+            .unresolved
+            .contains(&Unresolved::Class("Missing".to_string())));
+    }
+}
@@ -0,0 +1,309 @@
+use std::convert::TryInto;
+
+use num_traits::FromPrimitive;
+
+use crate::opcode::Opcode;
+
+/// Operand of a decoded instruction. Branch targets are absolute offsets into
+/// the code rather than the relative offsets stored in the class file.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operand {
+    None,
+    Byte(i8),
+    Short(i16),
+    Local(u16),
+    Constant(u16),
+    Branch(u32),
+    Increment(u16, i16),
+    TableSwitch {
+        default: u32,
+        low: i32,
+        high: i32,
+        targets: Vec<u32>,
+    },
+    LookupSwitch {
+        default: u32,
+        pairs: Vec<(i32, u32)>,
+    },
+    InvokeInterface(u16, u8),
+    ArrayType(u8),
+    MultiArray(u16, u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instruction {
+    pub offset: u32,
+    pub length: u32,
+    pub opcode: Opcode,
+    pub operand: Operand,
+    /// Set when the instruction was prefixed with `wide`
+    pub wide: bool,
+}
+
+impl Instruction {
+    /// Local variable slot used by a load, store, iinc or ret, including the
+    /// `_0` to `_3` forms
+    pub fn local(self: &Instruction) -> Option<u16> {
+        let op = self.opcode as u8;
+        match self.operand {
+            Operand::Local(index) | Operand::Increment(index, _) => Some(index),
+            _ => match op {
+                0x1a..=0x2d => Some(((op - 0x1a) % 4) as u16),
+                0x3b..=0x4e => Some(((op - 0x3b) % 4) as u16),
+                _ => None,
+            },
+        }
+    }
+
+    /// Every offset control can move to after this instruction other than the
+    /// next instruction
+    pub fn branch_targets(self: &Instruction) -> Vec<u32> {
+        match &self.operand {
+            Operand::Branch(target) => vec![*target],
+            Operand::TableSwitch {
+                default, targets, ..
+            } => {
+                let mut all = vec![*default];
+                all.extend(targets);
+                all
+            }
+            Operand::LookupSwitch { default, pairs } => {
+                let mut all = vec![*default];
+                all.extend(pairs.iter().map(|(_, target)| *target));
+                all
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Whether control can continue on to the next instruction
+    pub fn falls_through(self: &Instruction) -> bool {
+        !matches!(
+            self.opcode,
+            Opcode::Goto
+                | Opcode::GotoW
+                | Opcode::TableSwitch
+                | Opcode::LookupSwitch
+                | Opcode::IReturn
+                | Opcode::LReturn
+                | Opcode::FReturn
+                | Opcode::DReturn
+                | Opcode::AReturn
+                | Opcode::Return
+                | Opcode::AThrow
+                | Opcode::Ret
+        )
+    }
+}
+
+/// Decodes a method's bytecode into instructions
+pub fn decode(code: &[u8]) -> Result<Vec<Instruction>, String> {
+    let mut instructions = vec![];
+    let mut pos = 0usize;
+
+    while pos < code.len() {
+        let instruction = decode_at(code, pos)?;
+        pos += instruction.length as usize;
+        instructions.push(instruction);
+    }
+
+    Ok(instructions)
+}
+
+fn decode_at(code: &[u8], offset: usize) -> Result<Instruction, String> {
+    let mut reader = CodeReader { code, pos: offset };
+    let byte = reader.u1()?;
+    let mut opcode = match Opcode::from_u8(byte) {
+        Some(op) => op,
+        None => return Err(format!("invalid opcode {:#04x} at {}", byte, offset)),
+    };
+    let mut wide = false;
+
+    let operand = match opcode {
+        Opcode::BiPush => Operand::Byte(reader.u1()? as i8),
+        Opcode::SiPush => Operand::Short(reader.u2()? as i16),
+        Opcode::Ldc | Opcode::NewArray => {
+            let val = reader.u1()?;
+            if opcode == Opcode::Ldc {
+                Operand::Constant(val as u16)
+            } else {
+                Operand::ArrayType(val)
+            }
+        }
+        Opcode::ILoad
+        | Opcode::LLoad
+        | Opcode::FLoad
+        | Opcode::DLoad
+        | Opcode::ALoad
+        | Opcode::IStore
+        | Opcode::LStore
+        | Opcode::FStore
+        | Opcode::DStore
+        | Opcode::AStore
+        | Opcode::Ret => Operand::Local(reader.u1()? as u16),
+        Opcode::IInc => {
+            let index = reader.u1()? as u16;
+            Operand::Increment(index, reader.u1()? as i8 as i16)
+        }
+        Opcode::LdcW
+        | Opcode::Ldc2W
+        | Opcode::GetStatic
+        | Opcode::PutStatic
+        | Opcode::GetField
+        | Opcode::PutField
+        | Opcode::InvokeVirtual
+        | Opcode::InvokeSpecial
+        | Opcode::InvokeStatic
+        | Opcode::New
+        | Opcode::ANewArray
+        | Opcode::CheckCast
+        | Opcode::InstanceOf => Operand::Constant(reader.u2()?),
+        Opcode::InvokeDynamic => {
+            let index = reader.u2()?;
+            reader.u2()?;
+            Operand::Constant(index)
+        }
+        Opcode::InvokeInterface => {
+            let index = reader.u2()?;
+            let count = reader.u1()?;
+            reader.u1()?;
+            Operand::InvokeInterface(index, count)
+        }
+        Opcode::MultiANewArray => {
+            let index = reader.u2()?;
+            Operand::MultiArray(index, reader.u1()?)
+        }
+        Opcode::IfEq
+        | Opcode::IfNe
+        | Opcode::IfLt
+        | Opcode::IfGe
+        | Opcode::IfGt
+        | Opcode::IfLe
+        | Opcode::IfIcmpEq
+        | Opcode::IfIcmpNe
+        | Opcode::IfIcmpLt
+        | Opcode::IfIcmpGe
+        | Opcode::IfIcmpGt
+        | Opcode::IfIcmpLe
+        | Opcode::IfAcmpEq
+        | Opcode::IfAcmpNe
+        | Opcode::Goto
+        | Opcode::Jsr
+        | Opcode::IfNull
+        | Opcode::IfNonNull => Operand::Branch(target(offset, reader.u2()? as i16 as i32)?),
+        Opcode::GotoW | Opcode::JsrW => Operand::Branch(target(offset, reader.u4()? as i32)?),
+        Opcode::TableSwitch => {
+            reader.align();
+            let default = target(offset, reader.u4()? as i32)?;
+            let low = reader.u4()? as i32;
+            let high = reader.u4()? as i32;
+            if low > high {
+                return Err(format!("tableswitch at {} has low above high", offset));
+            }
+            let mut targets = vec![];
+            for _ in low..=high {
+                targets.push(target(offset, reader.u4()? as i32)?);
+            }
+            Operand::TableSwitch {
+                default,
+                low,
+                high,
+                targets,
+            }
+        }
+        Opcode::LookupSwitch => {
+            reader.align();
+            let default = target(offset, reader.u4()? as i32)?;
+            let npairs = reader.u4()? as i32;
+            if npairs < 0 {
+                return Err(format!("lookupswitch at {} has negative npairs", offset));
+            }
+            let mut pairs = vec![];
+            for _ in 0..npairs {
+                let key = reader.u4()? as i32;
+                pairs.push((key, target(offset, reader.u4()? as i32)?));
+            }
+            Operand::LookupSwitch { default, pairs }
+        }
+        Opcode::Wide => {
+            wide = true;
+            let byte = reader.u1()?;
+            opcode = match Opcode::from_u8(byte) {
+                Some(op) => op,
+                None => return Err(format!("invalid opcode {:#04x} at {}", byte, offset)),
+            };
+            match opcode {
+                Opcode::ILoad
+                | Opcode::LLoad
+                | Opcode::FLoad
+                | Opcode::DLoad
+                | Opcode::ALoad
+                | Opcode::IStore
+                | Opcode::LStore
+                | Opcode::FStore
+                | Opcode::DStore
+                | Opcode::AStore
+                | Opcode::Ret => Operand::Local(reader.u2()?),
+                Opcode::IInc => {
+                    let index = reader.u2()?;
+                    Operand::Increment(index, reader.u2()? as i16)
+                }
+                _ => return Err(format!("{:?} cannot be widened at {}", opcode, offset)),
+            }
+        }
+        _ => Operand::None,
+    };
+
+    Ok(Instruction {
+        offset: offset as u32,
+        length: (reader.pos - offset) as u32,
+        opcode,
+        operand,
+        wide,
+    })
+}
+
+fn target(offset: usize, relative: i32) -> Result<u32, String> {
+    let target = offset as i64 + relative as i64;
+    if target < 0 || target > u32::MAX as i64 {
+        return Err(format!("branch at {} jumps outside of the code", offset));
+    }
+
+    Ok(target as u32)
+}
+
+struct CodeReader<'a> {
+    code: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CodeReader<'a> {
+    fn bytes(self: &mut CodeReader<'a>, count: usize) -> Result<&'a [u8], String> {
+        match self.code.get(self.pos..self.pos + count) {
+            Some(bytes) => {
+                self.pos += count;
+                Ok(bytes)
+            }
+            None => Err(format!("truncated instruction at {}", self.pos)),
+        }
+    }
+
+    fn u1(self: &mut CodeReader<'a>) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u2(self: &mut CodeReader<'a>) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u4(self: &mut CodeReader<'a>) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Skips the padding after a switch opcode up to a multiple of four
+    fn align(self: &mut CodeReader<'a>) {
+        self.pos += (4 - self.pos % 4) % 4;
+    }
+}
@@ -0,0 +1,609 @@
+use std::{convert::TryInto, fs, io::Cursor, path::Path};
+
+use crate::class::Class;
+use crate::class_loader::ClassLoader;
+
+/// Magic number at the start of a jimage, in the byte order of the image
+const IMAGE_MAGIC: u32 = 0xCAFE_DADA;
+const MAJOR_VERSION: u16 = 1;
+const MINOR_VERSION: u16 = 0;
+
+/// Magic, version, flags, resource count, table length, locations size and
+/// strings size
+const HEADER_SIZE: usize = 7 * 4;
+
+/// Multiplier and default seed of the hash used for the redirect table
+const HASH_MULTIPLIER: u32 = 0x0100_0193;
+
+const ATTRIBUTE_END: u8 = 0;
+const ATTRIBUTE_MODULE: u8 = 1;
+const ATTRIBUTE_PARENT: u8 = 2;
+const ATTRIBUTE_BASE: u8 = 3;
+const ATTRIBUTE_EXTENSION: u8 = 4;
+const ATTRIBUTE_OFFSET: u8 = 5;
+const ATTRIBUTE_COMPRESSED: u8 = 6;
+const ATTRIBUTE_UNCOMPRESSED: u8 = 7;
+const ATTRIBUTE_COUNT: u8 = 8;
+
+#[derive(Debug, Clone)]
+pub struct ImageHeader {
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub flags: u32,
+    pub resource_count: u32,
+    pub table_length: u32,
+    pub locations_size: u32,
+    pub strings_size: u32,
+}
+
+/// ImageLocation describes a single resource in the image, such as
+/// `/java.base/java/lang/Object.class`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageLocation {
+    pub module: String,
+    pub parent: String,
+    pub base: String,
+    pub extension: String,
+    pub content_offset: u64,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+}
+
+impl ImageLocation {
+    pub fn full_name(self: &ImageLocation) -> String {
+        let mut name = String::new();
+        if !self.module.is_empty() {
+            name.push('/');
+            name.push_str(&self.module);
+            name.push('/');
+        }
+        if !self.parent.is_empty() {
+            name.push_str(&self.parent);
+            name.push('/');
+        }
+        name.push_str(&self.base);
+        if !self.extension.is_empty() {
+            name.push('.');
+            name.push_str(&self.extension);
+        }
+
+        name
+    }
+}
+
+/// ImageReader reads resources out of a JDK runtime image (`lib/modules`)
+pub struct ImageReader {
+    pub header: ImageHeader,
+    bytes: Vec<u8>,
+    big_endian: bool,
+}
+
+impl ImageReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<ImageReader, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        ImageReader::from_bytes(bytes)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<ImageReader, String> {
+        if bytes.len() < HEADER_SIZE {
+            return Err("image is too small to hold a header".to_string());
+        }
+
+        let magic: [u8; 4] = bytes[0..4].try_into().unwrap();
+        let big_endian = if u32::from_le_bytes(magic) == IMAGE_MAGIC {
+            false
+        } else if u32::from_be_bytes(magic) == IMAGE_MAGIC {
+            true
+        } else {
+            return Err(format!(
+                "invalid image magic {:#010x}",
+                u32::from_le_bytes(magic)
+            ));
+        };
+
+        let mut reader = ImageReader {
+            header: ImageHeader {
+                major_version: 0,
+                minor_version: 0,
+                flags: 0,
+                resource_count: 0,
+                table_length: 0,
+                locations_size: 0,
+                strings_size: 0,
+            },
+            bytes,
+            big_endian,
+        };
+
+        let version = reader.u4(4)?;
+        reader.header = ImageHeader {
+            major_version: (version >> 16) as u16,
+            minor_version: (version & 0xffff) as u16,
+            flags: reader.u4(8)?,
+            resource_count: reader.u4(12)?,
+            table_length: reader.u4(16)?,
+            locations_size: reader.u4(20)?,
+            strings_size: reader.u4(24)?,
+        };
+
+        if reader.header.major_version != MAJOR_VERSION
+            || reader.header.minor_version != MINOR_VERSION
+        {
+            return Err(format!(
+                "unsupported image version {}.{}",
+                reader.header.major_version, reader.header.minor_version
+            ));
+        }
+
+        if reader.index_size() > reader.bytes.len() {
+            return Err("image index is larger than the image".to_string());
+        }
+
+        Ok(reader)
+    }
+
+    /// Size of the header, tables, locations and strings. Resource content
+    /// offsets are relative to the end of the index.
+    pub fn index_size(self: &ImageReader) -> usize {
+        HEADER_SIZE
+            + self.header.table_length as usize * 8
+            + self.header.locations_size as usize
+            + self.header.strings_size as usize
+    }
+
+    fn redirect_start(self: &ImageReader) -> usize {
+        HEADER_SIZE
+    }
+
+    fn offsets_start(self: &ImageReader) -> usize {
+        self.redirect_start() + self.header.table_length as usize * 4
+    }
+
+    fn locations_start(self: &ImageReader) -> usize {
+        self.offsets_start() + self.header.table_length as usize * 4
+    }
+
+    fn strings_start(self: &ImageReader) -> usize {
+        self.locations_start() + self.header.locations_size as usize
+    }
+
+    fn u4(self: &ImageReader, pos: usize) -> Result<u32, String> {
+        let bytes: [u8; 4] = match self.bytes.get(pos..pos + 4) {
+            Some(b) => b.try_into().unwrap(),
+            None => return Err(format!("read past end of image at {:#x}", pos)),
+        };
+
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    /// Reads a NUL terminated string from the strings table
+    pub fn string_at(self: &ImageReader, offset: u32) -> Result<String, String> {
+        if offset >= self.header.strings_size {
+            return Err(format!("string offset {} out of range", offset));
+        }
+
+        let start = self.strings_start() + offset as usize;
+        let end = self.strings_start() + self.header.strings_size as usize;
+        let len = match self.bytes[start..end].iter().position(|b| *b == 0) {
+            Some(len) => len,
+            None => return Err(format!("unterminated string at {}", offset)),
+        };
+
+        cesu8::from_java_cesu8(&self.bytes[start..start + len])
+            .map(|s| s.to_string())
+            .map_err(|_| format!("invalid string at {}", offset))
+    }
+
+    /// Decodes the location attribute stream at the given offset into the
+    /// locations table
+    pub fn location_at(self: &ImageReader, offset: u32) -> Result<ImageLocation, String> {
+        if offset >= self.header.locations_size {
+            return Err(format!("location offset {} out of range", offset));
+        }
+
+        let mut attributes = [0u64; ATTRIBUTE_COUNT as usize];
+        let mut pos = self.locations_start() + offset as usize;
+        let end = self.strings_start();
+        loop {
+            if pos >= end {
+                return Err(format!("unterminated location at {}", offset));
+            }
+
+            let data = self.bytes[pos];
+            pos += 1;
+            let kind = data >> 3;
+            if kind == ATTRIBUTE_END {
+                break;
+            }
+            if kind >= ATTRIBUTE_COUNT {
+                return Err(format!("invalid location attribute {}", kind));
+            }
+
+            // Values are stored big endian in one to eight bytes
+            let length = (data & 0x7) as usize + 1;
+            if pos + length > end {
+                return Err(format!("unterminated location at {}", offset));
+            }
+            let mut value = 0u64;
+            for b in &self.bytes[pos..pos + length] {
+                value = (value << 8) | *b as u64;
+            }
+            pos += length;
+
+            attributes[kind as usize] = value;
+        }
+
+        Ok(ImageLocation {
+            module: self.string_at(attributes[ATTRIBUTE_MODULE as usize] as u32)?,
+            parent: self.string_at(attributes[ATTRIBUTE_PARENT as usize] as u32)?,
+            base: self.string_at(attributes[ATTRIBUTE_BASE as usize] as u32)?,
+            extension: self.string_at(attributes[ATTRIBUTE_EXTENSION as usize] as u32)?,
+            content_offset: attributes[ATTRIBUTE_OFFSET as usize],
+            compressed_size: attributes[ATTRIBUTE_COMPRESSED as usize],
+            uncompressed_size: attributes[ATTRIBUTE_UNCOMPRESSED as usize],
+        })
+    }
+
+    /// Looks a resource up by its full name, such as
+    /// `/java.base/java/lang/Object.class`
+    pub fn find_location(self: &ImageReader, name: &str) -> Option<ImageLocation> {
+        let count = self.header.table_length;
+        if count == 0 {
+            return None;
+        }
+
+        let redirect = self
+            .u4(self.redirect_start() + (hash_code(name, HASH_MULTIPLIER) % count) as usize * 4)
+            .ok()? as i32;
+        let index = match redirect {
+            0 => return None,
+            r if r < 0 => (-r - 1) as u32,
+            r => hash_code(name, r as u32) % count,
+        };
+        if index >= count {
+            return None;
+        }
+
+        let offset = self.u4(self.offsets_start() + index as usize * 4).ok()?;
+        let location = self.location_at(offset).ok()?;
+
+        // The redirect table is a perfect hash over the names in the image,
+        // so a missing name still lands on some location
+        if location.full_name() == name {
+            Some(location)
+        } else {
+            None
+        }
+    }
+
+    /// Every location in the image, in table order
+    pub fn locations(self: &ImageReader) -> Result<Vec<ImageLocation>, String> {
+        let mut locations = vec![];
+        for i in 0..self.header.table_length as usize {
+            let offset = self.u4(self.offsets_start() + i * 4)?;
+            locations.push(self.location_at(offset)?);
+        }
+
+        Ok(locations)
+    }
+
+    pub fn read_resource(self: &ImageReader, location: &ImageLocation) -> Result<Vec<u8>, String> {
+        if location.compressed_size != 0 {
+            return Err(format!(
+                "{} is compressed, which is not supported",
+                location.full_name()
+            ));
+        }
+
+        let start = (self.index_size() as u64).checked_add(location.content_offset);
+        let end = start.and_then(|s| s.checked_add(location.uncompressed_size));
+        match (start, end) {
+            (Some(start), Some(end)) if end <= self.bytes.len() as u64 => {
+                Ok(self.bytes[start as usize..end as usize].to_vec())
+            }
+            _ => Err(format!(
+                "{} lies outside of the image",
+                location.full_name()
+            )),
+        }
+    }
+
+    /// Reads a resource from a module, such as `java.base` and
+    /// `java/lang/Object.class`
+    pub fn find_resource(self: &ImageReader, module: &str, path: &str) -> Result<Vec<u8>, String> {
+        let name = format!("/{}/{}", module, path);
+        match self.find_location(&name) {
+            Some(location) => self.read_resource(&location),
+            None => Err(format!("{} not found in image", name)),
+        }
+    }
+
+    pub fn read_class_bytes(
+        self: &ImageReader,
+        module: &str,
+        name: &str,
+    ) -> Result<Vec<u8>, String> {
+        self.find_resource(module, &format!("{}.class", name))
+    }
+
+    pub fn find_class(self: &ImageReader, module: &str, name: &str) -> Result<Class, String> {
+        let bytes = self.read_class_bytes(module, name)?;
+
+        ClassLoader::new(Cursor::new(bytes))
+            .load()
+            .map_err(|e| format!("{}: {}", name, e))
+    }
+
+    /// Finds the module that contains a package, such as `java/lang`, using
+    /// the `/packages` entries of the image
+    pub fn module_for_package(self: &ImageReader, package: &str) -> Option<String> {
+        let location = self.find_location(&format!("/packages/{}", package.replace('/', ".")))?;
+        let content = self.read_resource(&location).ok()?;
+
+        // Each entry is an "is empty" flag followed by the module name offset
+        let mut fallback = None;
+        for entry in content.chunks_exact(8) {
+            let (is_empty, module) = if self.big_endian {
+                (
+                    u32::from_be_bytes(entry[0..4].try_into().unwrap()),
+                    u32::from_be_bytes(entry[4..8].try_into().unwrap()),
+                )
+            } else {
+                (
+                    u32::from_le_bytes(entry[0..4].try_into().unwrap()),
+                    u32::from_le_bytes(entry[4..8].try_into().unwrap()),
+                )
+            };
+            let module = self.string_at(module).ok()?;
+            if is_empty == 0 {
+                return Some(module);
+            }
+            fallback.get_or_insert(module);
+        }
+
+        fallback
+    }
+
+    /// Loads a class by name alone, such as `java/lang/Object`
+    pub fn find_class_by_name(self: &ImageReader, name: &str) -> Result<Class, String> {
+        let package = match name.rfind('/') {
+            Some(i) => &name[..i],
+            None => "",
+        };
+
+        match self.module_for_package(package) {
+            Some(module) => self.find_class(&module, name),
+            None => Err(format!("no module contains package {}", package)),
+        }
+    }
+}
+
+/// Hash of a name as used by the redirect table, seeded either with the
+/// default multiplier or with a redirect value
+fn hash_code(name: &str, seed: u32) -> u32 {
+    let mut hash = seed;
+    for b in cesu8::to_java_cesu8(name).iter() {
+        hash = hash.wrapping_mul(HASH_MULTIPLIER) ^ *b as u32;
+    }
+
+    hash & 0x7fff_ffff
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Resource {
+        module: &'static str,
+        parent: &'static str,
+        base: &'static str,
+        extension: &'static str,
+        content: Vec<u8>,
+    }
+
+    /// Builds a little endian image holding the given resources
+    fn build_image(resources: &[Resource]) -> Vec<u8> {
+        let mut strings = vec![0u8];
+        let mut add_string = |s: &str| -> u64 {
+            if s.is_empty() {
+                return 0;
+            }
+            let offset = strings.len() as u64;
+            strings.extend_from_slice(s.as_bytes());
+            strings.push(0);
+            offset
+        };
+
+        let mut locations = vec![];
+        let mut location_offsets = vec![];
+        let mut content = vec![];
+        for r in resources {
+            location_offsets.push(locations.len() as u32);
+            let attributes = [
+                (ATTRIBUTE_MODULE, add_string(r.module)),
+                (ATTRIBUTE_PARENT, add_string(r.parent)),
+                (ATTRIBUTE_BASE, add_string(r.base)),
+                (ATTRIBUTE_EXTENSION, add_string(r.extension)),
+                (ATTRIBUTE_OFFSET, content.len() as u64),
+                (ATTRIBUTE_UNCOMPRESSED, r.content.len() as u64),
+            ];
+            for (kind, value) in attributes.iter() {
+                let bytes = value.to_be_bytes();
+                let skip = bytes.iter().take(7).take_while(|b| **b == 0).count();
+                locations.push((kind << 3) | (7 - skip) as u8);
+                locations.extend_from_slice(&bytes[skip..]);
+            }
+            locations.push(ATTRIBUTE_END);
+            content.extend_from_slice(&r.content);
+        }
+
+        // Place every name in its own slot, looking for a seed for any
+        // bucket that holds more than one name
+        let count = resources.len() as u32;
+        let names: Vec<String> = resources
+            .iter()
+            .map(|r| {
+                ImageLocation {
+                    module: r.module.to_string(),
+                    parent: r.parent.to_string(),
+                    base: r.base.to_string(),
+                    extension: r.extension.to_string(),
+                    content_offset: 0,
+                    compressed_size: 0,
+                    uncompressed_size: 0,
+                }
+                .full_name()
+            })
+            .collect();
+        let mut buckets = vec![vec![]; count as usize];
+        for (i, name) in names.iter().enumerate() {
+            buckets[(hash_code(name, HASH_MULTIPLIER) % count) as usize].push(i);
+        }
+        let mut order: Vec<usize> = (0..buckets.len()).collect();
+        order.sort_by_key(|b| std::cmp::Reverse(buckets[*b].len()));
+
+        let mut redirect = vec![0i32; count as usize];
+        let mut slots = vec![None; count as usize];
+        for b in order.iter().filter(|b| buckets[**b].len() > 1) {
+            let mut seed = 1;
+            loop {
+                let wanted: Vec<usize> = buckets[*b]
+                    .iter()
+                    .map(|i| (hash_code(&names[*i], seed) % count) as usize)
+                    .collect();
+                let mut unique = wanted.clone();
+                unique.sort_unstable();
+                unique.dedup();
+                if unique.len() == wanted.len() && wanted.iter().all(|s| slots[*s].is_none()) {
+                    for (i, s) in buckets[*b].iter().zip(wanted) {
+                        slots[s] = Some(*i);
+                    }
+                    redirect[*b] = seed as i32;
+                    break;
+                }
+                seed += 1;
+            }
+        }
+        for b in order.iter().filter(|b| buckets[**b].len() == 1) {
+            let free = slots.iter().position(|s| s.is_none()).unwrap();
+            slots[free] = Some(buckets[*b][0]);
+            redirect[*b] = -(free as i32) - 1;
+        }
+
+        let mut image = vec![];
+        for value in [
+            IMAGE_MAGIC,
+            (MAJOR_VERSION as u32) << 16 | MINOR_VERSION as u32,
+            0,
+            count,
+            count,
+            locations.len() as u32,
+            strings.len() as u32,
+        ]
+        .iter()
+        {
+            image.extend_from_slice(&value.to_le_bytes());
+        }
+        for r in redirect {
+            image.extend_from_slice(&r.to_le_bytes());
+        }
+        for s in slots {
+            image.extend_from_slice(&location_offsets[s.unwrap()].to_le_bytes());
+        }
+        image.extend_from_slice(&locations);
+        image.extend_from_slice(&strings);
+        image.extend_from_slice(&content);
+
+        image
+    }
+
+    fn resources() -> Vec<Resource> {
+        let mut packages = vec![];
+        packages.extend_from_slice(&0u32.to_le_bytes());
+        // Filled in below once the module name has a string offset
+        packages.extend_from_slice(&0u32.to_le_bytes());
+
+        vec![
+            Resource {
+                module: "java.base",
+                parent: "java/lang",
+                base: "Object",
+                extension: "class",
+                content: vec![0xca, 0xfe, 0xba, 0xbe],
+            },
+            Resource {
+                module: "java.base",
+                parent: "java/lang",
+                base: "String",
+                extension: "class",
+                content: vec![1, 2, 3],
+            },
+            Resource {
+                module: "java.logging",
+                parent: "java/util/logging",
+                base: "Logger",
+                extension: "class",
+                content: vec![4, 5],
+            },
+            Resource {
+                module: "packages",
+                parent: "",
+                base: "java.lang",
+                extension: "",
+                content: packages,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_find_resource() {
+        let image = ImageReader::from_bytes(build_image(&resources())).unwrap();
+
+        assert_eq!(image.header.resource_count, 4);
+        assert_eq!(
+            image.find_resource("java.base", "java/lang/Object.class"),
+            Ok(vec![0xca, 0xfe, 0xba, 0xbe])
+        );
+        assert_eq!(
+            image.read_class_bytes("java.logging", "java/util/logging/Logger"),
+            Ok(vec![4, 5])
+        );
+        assert!(image
+            .find_location("/java.base/java/lang/Missing.class")
+            .is_none());
+        assert!(image
+            .find_location("/java.logging/java/lang/String.class")
+            .is_none());
+        assert_eq!(image.locations().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_module_for_package() {
+        let mut resources = resources();
+        // "java.base" is the first string added to the strings table
+        resources[3].content[4..8].copy_from_slice(&1u32.to_le_bytes());
+        let image = ImageReader::from_bytes(build_image(&resources)).unwrap();
+
+        assert_eq!(
+            image.module_for_package("java/lang"),
+            Some("java.base".to_string())
+        );
+        assert_eq!(image.module_for_package("java/util"), None);
+    }
+
+    #[test]
+    fn test_invalid_image() {
+        assert!(ImageReader::from_bytes(vec![0; 4]).is_err());
+        assert!(ImageReader::from_bytes(vec![0; HEADER_SIZE]).is_err());
+
+        let mut image = build_image(&resources());
+        image.truncate(HEADER_SIZE + 8);
+        assert!(ImageReader::from_bytes(image).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use zip::ZipArchive;
+
+use crate::class::Class;
+use crate::class_loader::ClassLoader;
+
+/// Every jmod starts with "JM" followed by a major and minor version
+const JMOD_MAGIC: [u8; 4] = [0x4a, 0x4d, 0x01, 0x00];
+
+/// JmodFile reads classes out of a JDK `.jmod` file, which is a ZIP archive
+/// with a four byte header in front of it
+pub struct JmodFile {
+    pub module: String,
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+}
+
+impl JmodFile {
+    /// Opens a jmod, taking the module name from the file name
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JmodFile, String> {
+        let path = path.as_ref();
+        let module = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => return Err(format!("invalid jmod path {}", path.display())),
+        };
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        JmodFile::from_bytes(module, bytes)
+    }
+
+    pub fn from_bytes(module: String, bytes: Vec<u8>) -> Result<JmodFile, String> {
+        if bytes.len() < JMOD_MAGIC.len() || bytes[..JMOD_MAGIC.len()] != JMOD_MAGIC {
+            return Err(format!("{} is not a jmod file", module));
+        }
+
+        let archive = ZipArchive::new(Cursor::new(bytes[JMOD_MAGIC.len()..].to_vec()))
+            .map_err(|e| format!("{}: {}", module, e))?;
+
+        Ok(JmodFile { module, archive })
+    }
+
+    /// Names of all classes in the jmod, such as `java/lang/Object`
+    pub fn class_names(self: &JmodFile) -> Vec<String> {
+        self.archive
+            .file_names()
+            .filter_map(|name| name.strip_prefix("classes/"))
+            .filter_map(|name| name.strip_suffix(".class"))
+            .filter(|name| *name != "module-info")
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    /// Reads an entry from the jmod, such as `classes/java/lang/Object.class`
+    pub fn read_entry(self: &mut JmodFile, path: &str) -> Result<Vec<u8>, String> {
+        let module = &self.module;
+        let mut file = self
+            .archive
+            .by_name(path)
+            .map_err(|_| format!("{} not found in {}", path, module))?;
+
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)
+            .map_err(|e| format!("{}: {}", path, e))?;
+
+        Ok(bytes)
+    }
+
+    pub fn read_class_bytes(self: &mut JmodFile, name: &str) -> Result<Vec<u8>, String> {
+        self.read_entry(&format!("classes/{}.class", name))
+    }
+
+    pub fn load_class(self: &mut JmodFile, name: &str) -> Result<Class, String> {
+        let bytes = self.read_class_bytes(name)?;
+
+        ClassLoader::new(Cursor::new(bytes))
+            .load()
+            .map_err(|e| format!("{}: {}", name, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    /// public class Empty {} with no methods
+    const EMPTY_CLASS: &[u8] = &[
+        0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52, 0, 5, 1, 0, 5, b'E', b'm', b'p', b't', b'y', 7, 0, 1,
+        1, 0, 16, b'j', b'a', b'v', b'a', b'/', b'l', b'a', b'n', b'g', b'/', b'O', b'b', b'j',
+        b'e', b'c', b't', 7, 0, 3, 0, 0x21, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+
+    /// A jmod holding these entries
+    fn build_jmod(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+
+        let mut bytes = JMOD_MAGIC.to_vec();
+        bytes.extend(zip.finish().unwrap().into_inner());
+        bytes
+    }
+
+    #[test]
+    fn test_jmod() {
+        let bytes = build_jmod(&[
+            ("classes/module-info.class", b"module"),
+            ("classes/Empty.class", EMPTY_CLASS),
+            ("classes/com/example/Other.class", b"other"),
+            ("conf/example.properties", b"x=1"),
+        ]);
+        let mut jmod = JmodFile::from_bytes("example".to_string(), bytes).unwrap();
+
+        let mut names = jmod.class_names();
+        names.sort();
+        assert_eq!(names, vec!["Empty", "com/example/Other"]);
+        assert_eq!(
+            jmod.read_class_bytes("com/example/Other").unwrap(),
+            b"other"
+        );
+        assert_eq!(jmod.read_entry("conf/example.properties").unwrap(), b"x=1");
+        assert!(jmod.read_class_bytes("Missing").is_err());
+        assert_eq!(jmod.load_class("Empty").unwrap().name, "Empty");
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut bytes = build_jmod(&[("classes/Empty.class", EMPTY_CLASS)]);
+        bytes[1] = b'X';
+        assert!(JmodFile::from_bytes("bad".to_string(), bytes).is_err());
+        // A plain zip, as in a jar, has no header
+        let zip = build_jmod(&[])[JMOD_MAGIC.len()..].to_vec();
+        assert!(JmodFile::from_bytes("jar".to_string(), zip).is_err());
+        assert!(JmodFile::from_bytes("short".to_string(), vec![0x4a]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::class_loader::ClassLoader;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum JvmConst {
     UTF8(String),
     Integer(u32),
@@ -19,35 +20,38 @@ pub enum JvmConst {
     InvokeDynamic(u16, u16),
     Module(u16),
     Package(u16),
+    /// The slot after a Long or Double, which takes up two entries
+    Unusable,
 }
 
 impl JvmConst {
     pub fn from_tag(tag: u8, loader: &mut ClassLoader) -> Result<JvmConst, String> {
         match tag {
             0x01 => {
-                let string_length = loader.u2();
-                Ok(JvmConst::UTF8(
-                    cesu8::from_java_cesu8(&loader.read_bytes(string_length as i32))
-                        .unwrap()
-                        .to_string(),
-                ))
+                let string_length = loader.u2()?;
+                let bytes = loader.read_bytes(string_length as usize)?;
+                // Lone surrogates are valid in class files but not in a String
+                Ok(JvmConst::UTF8(match cesu8::from_java_cesu8(&bytes) {
+                    Ok(val) => val.to_string(),
+                    Err(_) => String::from_utf8_lossy(&bytes).to_string(),
+                }))
             }
-            0x03 => Ok(JvmConst::Integer(loader.u4())),
-            0x04 => Ok(JvmConst::Float(loader.u4())),
-            0x05 => Ok(JvmConst::Long(loader.u4(), loader.u4())),
-            0x06 => Ok(JvmConst::Double(loader.u4(), loader.u4())),
-            0x07 => Ok(JvmConst::Class(loader.u2())),
-            0x08 => Ok(JvmConst::String(loader.u2())),
-            0x09 => Ok(JvmConst::FieldRef(loader.u2(), loader.u2())),
-            0x0a => Ok(JvmConst::MethodRef(loader.u2(), loader.u2())),
-            0x0b => Ok(JvmConst::InterfaceMethodRef(loader.u2(), loader.u2())),
-            0x0c => Ok(JvmConst::NameAndType(loader.u2(), loader.u2())),
-            0x0f => Ok(JvmConst::MethodHandle(loader.u1(), loader.u2())),
-            0x10 => Ok(JvmConst::MethodType(loader.u2())),
-            0x11 => Ok(JvmConst::Dynamic(loader.u2(), loader.u2())),
-            0x12 => Ok(JvmConst::InvokeDynamic(loader.u2(), loader.u2())),
-            0x13 => Ok(JvmConst::Module(loader.u2())),
-            0x14 => Ok(JvmConst::Package(loader.u2())),
+            0x03 => Ok(JvmConst::Integer(loader.u4()?)),
+            0x04 => Ok(JvmConst::Float(loader.u4()?)),
+            0x05 => Ok(JvmConst::Long(loader.u4()?, loader.u4()?)),
+            0x06 => Ok(JvmConst::Double(loader.u4()?, loader.u4()?)),
+            0x07 => Ok(JvmConst::Class(loader.u2()?)),
+            0x08 => Ok(JvmConst::String(loader.u2()?)),
+            0x09 => Ok(JvmConst::FieldRef(loader.u2()?, loader.u2()?)),
+            0x0a => Ok(JvmConst::MethodRef(loader.u2()?, loader.u2()?)),
+            0x0b => Ok(JvmConst::InterfaceMethodRef(loader.u2()?, loader.u2()?)),
+            0x0c => Ok(JvmConst::NameAndType(loader.u2()?, loader.u2()?)),
+            0x0f => Ok(JvmConst::MethodHandle(loader.u1()?, loader.u2()?)),
+            0x10 => Ok(JvmConst::MethodType(loader.u2()?)),
+            0x11 => Ok(JvmConst::Dynamic(loader.u2()?, loader.u2()?)),
+            0x12 => Ok(JvmConst::InvokeDynamic(loader.u2()?, loader.u2()?)),
+            0x13 => Ok(JvmConst::Module(loader.u2()?)),
+            0x14 => Ok(JvmConst::Package(loader.u2()?)),
             _ => Err(format!("invalid tag {:#04x}", tag)),
         }
     }
@@ -1,10 +1,29 @@
 #[macro_use]
 extern crate num_derive;
 
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod attribute;
+pub mod cfg;
 pub mod class;
 pub mod class_loader;
+pub mod class_writer;
+pub mod classpath;
 pub mod const_pool;
+pub mod decompiler;
+pub mod deps;
+pub mod diff;
 pub mod flags;
+pub mod frames;
+pub mod hierarchy;
+pub mod instruction;
+pub mod jimage;
+pub mod jmod;
 pub mod jvm_const;
 pub mod opcode;
+pub mod shrink;
 pub mod signature;
+pub mod stack_map;
+#[cfg(test)]
+mod test_util;
+pub mod verifier;
@@ -7,7 +7,7 @@ use jjvm_loader::flags::ClassFlag;
 fn main() {
     let mut loader = ClassLoader::new(Cursor::new(fs::read("SemanticdbVisitor.class").unwrap()));
 
-    let class = loader.load();
+    let class = loader.load().unwrap();
 
     println!("Class: {}", class.name);
     println!("Superclass: {}", class.superclass);
@@ -0,0 +1,530 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::{Seek, Write};
+
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::attribute::{BootstrapMethod, Code};
+use crate::class::Class;
+use crate::class_writer::ClassWriter;
+use crate::const_pool::ConstPool;
+use crate::flags::{ClassFlag, MethodFlag};
+use crate::hierarchy::{Hierarchy, MemberRef};
+use crate::instruction::{self, Operand};
+use crate::jvm_const::JvmConst;
+use crate::opcode::Opcode;
+
+/// Methods of java/lang/Object that the JDK calls on any class
+const OBJECT_METHODS: [(&str, &str); 5] = [
+    ("toString", "()Ljava/lang/String;"),
+    ("equals", "(Ljava/lang/Object;)Z"),
+    ("hashCode", "()I"),
+    ("clone", "()Ljava/lang/Object;"),
+    ("finalize", "()V"),
+];
+
+/// Patterns for classes to keep whole, with every method and field. A
+/// pattern is a class name, a package followed by `*` for the classes in it,
+/// or a package followed by `**` to take in its subpackages too.
+#[derive(Debug, Clone, Default)]
+pub struct KeepRules {
+    pub patterns: Vec<String>,
+}
+
+impl KeepRules {
+    /// Reads one pattern per line, skipping blank lines and `#` comments.
+    /// Names may use dots or slashes.
+    pub fn parse(text: &str) -> KeepRules {
+        let patterns = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(|line| line.replace('.', "/"))
+            .collect();
+
+        KeepRules { patterns }
+    }
+
+    pub fn matches(self: &KeepRules, class: &str) -> bool {
+        self.patterns.iter().any(|pattern| {
+            if let Some(package) = pattern.strip_suffix("**") {
+                class.starts_with(package)
+            } else if let Some(package) = pattern.strip_suffix('*') {
+                class.starts_with(package) && !class[package.len()..].contains('/')
+            } else {
+                class == pattern
+            }
+        })
+    }
+}
+
+/// What is reachable from the entry points of a program
+#[derive(Debug, Clone, Default)]
+pub struct Reachable {
+    pub classes: BTreeSet<String>,
+    pub methods: BTreeSet<MemberRef>,
+    pub fields: BTreeSet<MemberRef>,
+}
+
+enum Item {
+    Class(String),
+    Method(MemberRef),
+    Field(MemberRef),
+}
+
+impl Reachable {
+    /// Follows the code of each reachable method through the constant pool,
+    /// taking in the classes, fields and methods it refers to. Virtual calls
+    /// may reach the implementation in any subtype, and a class keeps its
+    /// supertypes, its static initialiser and the methods the JDK may call
+    /// back through a supertype that is not loaded.
+    pub fn find(hierarchy: &Hierarchy, entries: &[MemberRef], keep: &KeepRules) -> Reachable {
+        let mut reachable = Reachable::default();
+        let mut worklist: Vec<Item> = entries.iter().cloned().map(Item::Method).collect();
+        worklist.extend(
+            hierarchy
+                .classes
+                .keys()
+                .filter(|name| keep.matches(name))
+                .map(|name| Item::Class(name.clone())),
+        );
+
+        while let Some(item) = worklist.pop() {
+            match item {
+                Item::Class(name) => reachable.visit_class(hierarchy, keep, &name, &mut worklist),
+                Item::Method(method) => reachable.visit_method(hierarchy, method, &mut worklist),
+                Item::Field(field) => {
+                    if hierarchy.classes.contains_key(&field.class) {
+                        worklist.push(Item::Class(field.class.clone()));
+                        reachable.fields.insert(field);
+                    }
+                }
+            }
+        }
+
+        reachable
+    }
+
+    fn visit_class(
+        self: &mut Reachable,
+        hierarchy: &Hierarchy,
+        keep: &KeepRules,
+        name: &str,
+        worklist: &mut Vec<Item>,
+    ) {
+        let class = match hierarchy.classes.get(name) {
+            Some(class) => class,
+            None => return,
+        };
+        if !self.classes.insert(name.to_string()) {
+            return;
+        }
+
+        let supertypes = class.interfaces.iter().chain(Some(&class.superclass));
+        worklist.extend(
+            supertypes
+                .filter(|s| !s.is_empty())
+                .map(|s| Item::Class(s.clone())),
+        );
+
+        let method_ref = |name: &str, descriptor: &str| {
+            Item::Method(MemberRef::new(&class.name, name, descriptor))
+        };
+        if keep.matches(name) {
+            worklist.extend(
+                class
+                    .methods
+                    .iter()
+                    .map(|m| method_ref(&m.name, &m.descriptor)),
+            );
+            worklist.extend(
+                class
+                    .fields
+                    .iter()
+                    .map(|f| Item::Field(MemberRef::new(name, &f.name, &f.descriptor))),
+            );
+            return;
+        }
+
+        worklist.push(method_ref("<clinit>", "()V"));
+        // The JDK looks up the constants of an enum reflectively
+        if ClassFlag::Enum.is_set(class.flags) {
+            worklist.push(method_ref("values", &format!("()[L{};", name)));
+        }
+
+        // Methods the JDK may call through a supertype it declares
+        let missing: Vec<String> = hierarchy
+            .supertypes(name)
+            .into_iter()
+            .filter(|s| !hierarchy.classes.contains_key(s))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        let only_object = missing.iter().all(|s| s == "java/lang/Object");
+        for method in &class.methods {
+            let is_visible = MethodFlag::Public.is_set(method.flags)
+                || MethodFlag::Protected.is_set(method.flags);
+            let is_instance = !method.name.starts_with('<')
+                && !MethodFlag::Static.is_set(method.flags)
+                && is_visible;
+            let from_object = OBJECT_METHODS
+                .iter()
+                .any(|(n, d)| method.name == *n && method.descriptor == *d);
+            if is_instance && (!only_object || from_object) {
+                worklist.push(method_ref(&method.name, &method.descriptor));
+            }
+        }
+    }
+
+    fn visit_method(
+        self: &mut Reachable,
+        hierarchy: &Hierarchy,
+        method: MemberRef,
+        worklist: &mut Vec<Item>,
+    ) {
+        let field = match hierarchy.find_method(&method) {
+            Some(field) => field,
+            None => return,
+        };
+        if !self.methods.insert(method.clone()) {
+            return;
+        }
+        worklist.push(Item::Class(method.class.clone()));
+
+        let class = &hierarchy.classes[&method.class];
+        let pool = &class.const_pool;
+        let code = match Code::from_method(field, pool) {
+            Ok(Some(code)) => code,
+            _ => return,
+        };
+        for handler in &code.exception_table {
+            if let Some(name) = pool.class_name(handler.catch_type) {
+                worklist.push(Item::Class(name.to_string()));
+            }
+        }
+        let instructions = match instruction::decode(&code.code) {
+            Ok(instructions) => instructions,
+            Err(_) => return,
+        };
+
+        let mut bootstrap_methods = None;
+        for ins in instructions {
+            let index = match ins.operand {
+                Operand::Constant(index)
+                | Operand::InvokeInterface(index, _)
+                | Operand::MultiArray(index, _) => index,
+                _ => continue,
+            };
+
+            match ins.opcode {
+                Opcode::InvokeDynamic => {
+                    let bootstrap = match pool.get(index) {
+                        Some(JvmConst::InvokeDynamic(bootstrap, _)) => *bootstrap as usize,
+                        _ => continue,
+                    };
+                    let methods =
+                        bootstrap_methods.get_or_insert_with(|| BootstrapMethod::from_class(class));
+                    if let Some(method) = methods.get(bootstrap) {
+                        for arg in Some(method.handle).iter().chain(&method.args) {
+                            constant(hierarchy, pool, *arg, worklist);
+                        }
+                    }
+                }
+                Opcode::InvokeVirtual
+                | Opcode::InvokeInterface
+                | Opcode::InvokeSpecial
+                | Opcode::InvokeStatic => {
+                    let is_virtual =
+                        matches!(ins.opcode, Opcode::InvokeVirtual | Opcode::InvokeInterface);
+                    if let Some((owner, name, descriptor)) = pool.member_ref(index) {
+                        invoke(hierarchy, owner, name, descriptor, is_virtual, worklist);
+                    }
+                }
+                Opcode::GetStatic | Opcode::PutStatic | Opcode::GetField | Opcode::PutField => {
+                    if let Some((owner, name, descriptor)) = pool.member_ref(index) {
+                        access(hierarchy, owner, name, descriptor, worklist);
+                    }
+                }
+                _ => constant(hierarchy, pool, index, worklist),
+            }
+        }
+    }
+}
+
+/// Takes in what a loaded constant, such as a class literal or a method
+/// handle, refers to
+fn constant(hierarchy: &Hierarchy, pool: &ConstPool, index: u16, worklist: &mut Vec<Item>) {
+    match pool.get(index) {
+        Some(JvmConst::Class(_)) => {
+            if let Some(name) = pool.class_name(index) {
+                // Array classes are named by descriptor
+                let name = name.trim_start_matches('[');
+                let name = name
+                    .strip_prefix('L')
+                    .and_then(|name| name.strip_suffix(';'))
+                    .unwrap_or(name);
+                worklist.push(Item::Class(name.to_string()));
+            }
+        }
+        Some(JvmConst::MethodHandle(kind, reference)) => {
+            if let Some((owner, name, descriptor)) = pool.member_ref(*reference) {
+                match kind {
+                    1..=4 => access(hierarchy, owner, name, descriptor, worklist),
+                    // invokevirtual and invokeinterface handles dispatch
+                    5 | 9 => invoke(hierarchy, owner, name, descriptor, true, worklist),
+                    _ => invoke(hierarchy, owner, name, descriptor, false, worklist),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn invoke(
+    hierarchy: &Hierarchy,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+    is_virtual: bool,
+    worklist: &mut Vec<Item>,
+) {
+    // Array methods are those of java/lang/Object
+    if owner.starts_with('[') {
+        return;
+    }
+    worklist.push(Item::Class(owner.to_string()));
+    if let Some(resolved) = hierarchy.resolve_method(owner, name, descriptor) {
+        worklist.push(Item::Method(resolved));
+    }
+    if is_virtual {
+        let targets = hierarchy.dispatch_targets(&MemberRef::new(owner, name, descriptor));
+        worklist.extend(targets.into_iter().map(Item::Method));
+    }
+}
+
+fn access(
+    hierarchy: &Hierarchy,
+    owner: &str,
+    name: &str,
+    descriptor: &str,
+    worklist: &mut Vec<Item>,
+) {
+    worklist.push(Item::Class(owner.to_string()));
+    if let Some(field) = hierarchy.resolve_field(owner, name, descriptor) {
+        worklist.push(Item::Field(field));
+    }
+}
+
+/// Copies of the reachable classes holding only their reachable methods and
+/// fields, sorted by name
+pub fn shrink(classes: &HashMap<String, Class>, reachable: &Reachable) -> Vec<Class> {
+    reachable
+        .classes
+        .iter()
+        .filter_map(|name| classes.get(name))
+        .map(|class| {
+            let mut class = class.clone();
+            let name = class.name.clone();
+            class.methods.retain(|m| {
+                reachable
+                    .methods
+                    .contains(&MemberRef::new(&name, &m.name, &m.descriptor))
+            });
+            class.fields.retain(|f| {
+                reachable
+                    .fields
+                    .contains(&MemberRef::new(&name, &f.name, &f.descriptor))
+            });
+            class
+        })
+        .collect()
+}
+
+/// Writes classes into a jar after the given resources, with the manifest
+/// first as the jar format expects. Signature files are left out, since the
+/// rewritten classes no longer match the digests they sign.
+pub fn write_jar<W: Write + Seek>(
+    out: W,
+    classes: &[Class],
+    resources: &[(String, Vec<u8>)],
+) -> Result<W, String> {
+    let mut zip = ZipWriter::new(out);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let error = |e: zip::result::ZipError| e.to_string();
+
+    let mut resources: Vec<&(String, Vec<u8>)> = resources
+        .iter()
+        .filter(|(name, _)| !is_signature(name))
+        .collect();
+    resources.sort_by_key(|(name, _)| name != "META-INF/MANIFEST.MF");
+    for (name, data) in resources {
+        zip.start_file(name, options).map_err(error)?;
+        zip.write_all(data).map_err(|e| e.to_string())?;
+    }
+    for class in classes {
+        zip.start_file(format!("{}.class", class.name), options)
+            .map_err(error)?;
+        zip.write_all(&ClassWriter::new().write(class))
+            .map_err(|e| e.to_string())?;
+    }
+
+    zip.finish().map_err(error)
+}
+
+/// Whether a jar entry is part of the jar's signature, such as
+/// `META-INF/CERT.SF` or `META-INF/CERT.RSA`
+fn is_signature(name: &str) -> bool {
+    match name.strip_prefix("META-INF/") {
+        Some(file) if !file.contains('/') => {
+            let file = file.to_uppercase();
+            file.starts_with("SIG-")
+                || [".SF", ".RSA", ".DSA", ".EC"]
+                    .iter()
+                    .any(|extension| file.ends_with(extension))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::class::{Attribute, Field};
+
+    fn method(name: &str, flags: u16, code: &[u8]) -> Field {
+        let mut data = vec![0, 2, 0, 1];
+        data.extend_from_slice(&(code.len() as u32).to_be_bytes());
+        data.extend_from_slice(code);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        Field {
+            flags,
+            name: name.to_string(),
+            descriptor: "()V".to_string(),
+            attributes: vec![Attribute {
+                name: "Code".to_string(),
+                data,
+            }],
+        }
+    }
+
+    fn class(name: &str, methods: Vec<Field>, consts: Vec<JvmConst>) -> Class {
+        Class {
+            major: 52,
+            minor: 0,
+            const_pool: ConstPool { consts },
+            name: name.to_string(),
+            superclass: "java/lang/Object".to_string(),
+            flags: 0x0021,
+            interfaces: vec![],
+            fields: vec![],
+            methods,
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_keep_rules() {
+        let keep =
+            KeepRules::parse("com.example.Api # the public API\n\ncom/example/model/*\norg/**\n");
+        assert!(keep.matches("com/example/Api"));
+        assert!(!keep.matches("com/example/Impl"));
+        assert!(keep.matches("com/example/model/User"));
+        assert!(!keep.matches("com/example/model/internal/Cache"));
+        assert!(keep.matches("org/lib/deep/Thing"));
+    }
+
+    #[test]
+    fn test_shrink() {
+        // Main.main calls Used.run
+        let consts = vec![
+            JvmConst::UTF8("Used".to_string()),
+            JvmConst::Class(1),
+            JvmConst::UTF8("run".to_string()),
+            JvmConst::UTF8("()V".to_string()),
+            JvmConst::NameAndType(3, 4),
+            JvmConst::MethodRef(2, 5),
+        ];
+        let classes: HashMap<String, Class> = vec![
+            class(
+                "Main",
+                vec![
+                    method("main", 0x0009, &[0xb8, 0x00, 0x06, 0xb1]),
+                    method("helper", 0x0008, &[0xb1]),
+                ],
+                consts,
+            ),
+            class(
+                "Used",
+                vec![
+                    method("run", 0x0009, &[0xb1]),
+                    method("other", 0x0009, &[0xb1]),
+                ],
+                vec![],
+            ),
+            class("Dead", vec![method("run", 0x0009, &[0xb1])], vec![]),
+            class("Kept", vec![method("run", 0x0009, &[0xb1])], vec![]),
+        ]
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect();
+
+        let hierarchy = Hierarchy::new(&classes);
+        let keep = KeepRules::parse("Kept");
+        let reachable =
+            Reachable::find(&hierarchy, &[MemberRef::new("Main", "main", "()V")], &keep);
+        let shrunk = shrink(&classes, &reachable);
+
+        let names: Vec<(&str, Vec<&str>)> = shrunk
+            .iter()
+            .map(|c| {
+                (
+                    c.name.as_str(),
+                    c.methods.iter().map(|m| m.name.as_str()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Kept", vec!["run"]),
+                ("Main", vec!["main"]),
+                ("Used", vec!["run"])
+            ]
+        );
+
+        let jar = write_jar(std::io::Cursor::new(vec![]), &shrunk, &[]).unwrap();
+        let mut archive = zip::ZipArchive::new(jar).unwrap();
+        assert_eq!(archive.len(), 3);
+        assert_eq!(archive.by_index(1).unwrap().name(), "Main.class");
+    }
+
+    #[test]
+    fn test_signatures_dropped() {
+        let resources: Vec<(String, Vec<u8>)> = [
+            "META-INF/MANIFEST.MF",
+            "META-INF/CERT.SF",
+            "META-INF/CERT.RSA",
+            "META-INF/other.dsa",
+            "META-INF/KEY.EC",
+            "META-INF/SIG-KEY",
+            "META-INF/services/example.SF",
+            "config.properties",
+        ]
+        .iter()
+        .map(|name| (name.to_string(), vec![]))
+        .collect();
+
+        let jar = write_jar(std::io::Cursor::new(vec![]), &[], &resources).unwrap();
+        let archive = zip::ZipArchive::new(jar).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "META-INF/MANIFEST.MF",
+                "META-INF/services/example.SF",
+                "config.properties"
+            ]
+        );
+    }
+}
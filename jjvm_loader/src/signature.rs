@@ -11,6 +11,7 @@ use combine::{
 /// an object.
 #[allow(missing_docs)]
 #[derive(Eq, PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Primitive {
     Boolean, // Z
     Byte,    // B
@@ -42,6 +43,7 @@ impl fmt::Display for Primitive {
 /// Enum representing any java type in addition to method signatures.
 #[allow(missing_docs)]
 #[derive(Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum JavaType {
     Primitive(Primitive),
     Object(String),
@@ -81,6 +83,7 @@ impl fmt::Display for JavaType {
 /// functions on jnienv to ensure safety.
 #[allow(missing_docs)]
 #[derive(Eq, PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeSignature {
     pub args: Vec<JavaType>,
     pub ret: JavaType,
@@ -206,11 +209,11 @@ mod test {
         ];
 
         for each in inputs.iter() {
-            let res = JavaType::from_str(*each).unwrap();
+            let res = JavaType::from_str(each).unwrap();
             println!("{:#?}", res);
             let s = format!("{}", res);
             assert_eq!(s, *each);
-            let res2 = JavaType::from_str(*each).unwrap();
+            let res2 = JavaType::from_str(each).unwrap();
             println!("{:#?}", res2);
             assert_eq!(res2, res);
         }
@@ -0,0 +1,642 @@
+use std::{collections::HashMap, fmt, io::Cursor};
+
+use crate::class::{Attribute, Class, Field};
+use crate::class_loader::ClassLoader;
+use crate::const_pool::ConstPool;
+use crate::flags::{ClassFlag, MethodFlag};
+use crate::signature::{JavaType, Primitive, TypeSignature};
+
+/// Deepest superclass chain followed before giving up, which guards against
+/// cycles in broken class files
+const MAX_HIERARCHY_DEPTH: usize = 256;
+
+/// Type of a local variable or operand stack slot as used by the verifier.
+/// Array classes are named by their descriptor, such as `[I`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object(String),
+    /// Object created by the `new` at the given offset that has not had its
+    /// constructor called yet
+    Uninitialized(u16),
+}
+
+impl VerificationType {
+    pub fn is_category2(self: &VerificationType) -> bool {
+        matches!(self, VerificationType::Long | VerificationType::Double)
+    }
+
+    /// Number of slots the type takes up on the stack or in the locals
+    pub fn size(self: &VerificationType) -> usize {
+        if self.is_category2() {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn is_reference(self: &VerificationType) -> bool {
+        matches!(
+            self,
+            VerificationType::Null
+                | VerificationType::UninitializedThis
+                | VerificationType::Object(_)
+                | VerificationType::Uninitialized(_)
+        )
+    }
+
+    /// Type of a field or argument descriptor, or None for void
+    pub fn from_java_type(typ: &JavaType) -> Option<VerificationType> {
+        match typ {
+            JavaType::Primitive(p) => match p {
+                Primitive::Boolean
+                | Primitive::Byte
+                | Primitive::Char
+                | Primitive::Short
+                | Primitive::Int => Some(VerificationType::Integer),
+                Primitive::Float => Some(VerificationType::Float),
+                Primitive::Long => Some(VerificationType::Long),
+                Primitive::Double => Some(VerificationType::Double),
+                Primitive::Void => None,
+            },
+            JavaType::Object(name) => Some(VerificationType::Object(name.clone())),
+            JavaType::Array(_) => Some(VerificationType::Object(typ.to_string())),
+            JavaType::Method(_) => None,
+        }
+    }
+
+    /// Type of a class named by a constant pool Class entry, which uses the
+    /// descriptor form for arrays
+    pub fn from_class_name(name: &str) -> VerificationType {
+        VerificationType::Object(name.to_string())
+    }
+}
+
+impl fmt::Display for VerificationType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerificationType::Top => write!(f, "top"),
+            VerificationType::Integer => write!(f, "int"),
+            VerificationType::Float => write!(f, "float"),
+            VerificationType::Double => write!(f, "double"),
+            VerificationType::Long => write!(f, "long"),
+            VerificationType::Null => write!(f, "null"),
+            VerificationType::UninitializedThis => write!(f, "uninitializedThis"),
+            VerificationType::Object(name) => write!(f, "{}", name),
+            VerificationType::Uninitialized(offset) => write!(f, "uninitialized({})", offset),
+        }
+    }
+}
+
+/// A single entry of a StackMapTable attribute. Locals are given in their
+/// compact form, where a long or double is a single entry.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackMapFrame {
+    Same {
+        offset_delta: u16,
+    },
+    SameLocals1StackItem {
+        offset_delta: u16,
+        stack: VerificationType,
+    },
+    Chop {
+        offset_delta: u16,
+        count: u8,
+    },
+    Append {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+    },
+    Full {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>,
+    },
+}
+
+impl StackMapFrame {
+    pub fn offset_delta(self: &StackMapFrame) -> u16 {
+        match self {
+            StackMapFrame::Same { offset_delta }
+            | StackMapFrame::SameLocals1StackItem { offset_delta, .. }
+            | StackMapFrame::Chop { offset_delta, .. }
+            | StackMapFrame::Append { offset_delta, .. }
+            | StackMapFrame::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
+/// The full types of the locals and stack at an offset. Locals are expanded
+/// so that a long or double is followed by a Top for its second slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameState {
+    pub offset: u32,
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+
+impl FrameState {
+    pub fn stack_size(self: &FrameState) -> usize {
+        self.stack.iter().map(|t| t.size()).sum()
+    }
+}
+
+/// Adds the Top that follows every long and double in the locals
+pub fn expand_locals(locals: &[VerificationType]) -> Vec<VerificationType> {
+    let mut expanded = vec![];
+    for local in locals {
+        expanded.push(local.clone());
+        if local.is_category2() {
+            expanded.push(VerificationType::Top);
+        }
+    }
+
+    expanded
+}
+
+/// Removes the Top that follows every long and double along with any
+/// trailing Tops
+pub fn compact_locals(locals: &[VerificationType]) -> Vec<VerificationType> {
+    let mut compact = vec![];
+    let mut i = 0;
+    while i < locals.len() {
+        compact.push(locals[i].clone());
+        i += locals[i].size();
+    }
+    while compact.last() == Some(&VerificationType::Top) {
+        compact.pop();
+    }
+
+    compact
+}
+
+/// Types of the locals on entry to a method, in compact form
+pub fn initial_locals(class_name: &str, method: &Field) -> Result<Vec<VerificationType>, String> {
+    let signature = TypeSignature::from_str(&method.descriptor)
+        .map_err(|_| format!("invalid method descriptor {}", method.descriptor))?;
+
+    let mut locals = vec![];
+    if !MethodFlag::Static.is_set(method.flags) {
+        if method.name == "<init>" && class_name != "java/lang/Object" {
+            locals.push(VerificationType::UninitializedThis);
+        } else {
+            locals.push(VerificationType::Object(class_name.to_string()));
+        }
+    }
+    for arg in &signature.args {
+        match VerificationType::from_java_type(arg) {
+            Some(typ) => locals.push(typ),
+            None => return Err(format!("invalid method descriptor {}", method.descriptor)),
+        }
+    }
+
+    Ok(locals)
+}
+
+/// Parses the entries of a StackMapTable attribute
+pub fn parse(attribute: &Attribute, const_pool: &ConstPool) -> Result<Vec<StackMapFrame>, String> {
+    let mut loader = ClassLoader::new(Cursor::new(attribute.data.clone()));
+    let count = loader.u2()?;
+    let mut frames = vec![];
+
+    for _ in 0..count {
+        let tag = loader.u1()?;
+        frames.push(match tag {
+            0..=63 => StackMapFrame::Same {
+                offset_delta: tag as u16,
+            },
+            64..=127 => StackMapFrame::SameLocals1StackItem {
+                offset_delta: tag as u16 - 64,
+                stack: parse_type(&mut loader, const_pool)?,
+            },
+            247 => StackMapFrame::SameLocals1StackItem {
+                offset_delta: loader.u2()?,
+                stack: parse_type(&mut loader, const_pool)?,
+            },
+            248..=250 => StackMapFrame::Chop {
+                offset_delta: loader.u2()?,
+                count: 251 - tag,
+            },
+            251 => StackMapFrame::Same {
+                offset_delta: loader.u2()?,
+            },
+            252..=254 => {
+                let offset_delta = loader.u2()?;
+                let mut locals = vec![];
+                for _ in 0..tag - 251 {
+                    locals.push(parse_type(&mut loader, const_pool)?);
+                }
+                StackMapFrame::Append {
+                    offset_delta,
+                    locals,
+                }
+            }
+            255 => {
+                let offset_delta = loader.u2()?;
+                let mut locals = vec![];
+                for _ in 0..loader.u2()? {
+                    locals.push(parse_type(&mut loader, const_pool)?);
+                }
+                let mut stack = vec![];
+                for _ in 0..loader.u2()? {
+                    stack.push(parse_type(&mut loader, const_pool)?);
+                }
+                StackMapFrame::Full {
+                    offset_delta,
+                    locals,
+                    stack,
+                }
+            }
+            _ => return Err(format!("invalid stack map frame type {}", tag)),
+        });
+    }
+
+    Ok(frames)
+}
+
+fn parse_type(
+    loader: &mut ClassLoader,
+    const_pool: &ConstPool,
+) -> Result<VerificationType, String> {
+    let tag = loader.u1()?;
+    Ok(match tag {
+        0 => VerificationType::Top,
+        1 => VerificationType::Integer,
+        2 => VerificationType::Float,
+        3 => VerificationType::Double,
+        4 => VerificationType::Long,
+        5 => VerificationType::Null,
+        6 => VerificationType::UninitializedThis,
+        7 => {
+            let index = loader.u2()?;
+            match const_pool.class_name(index) {
+                Some(name) => VerificationType::from_class_name(name),
+                None => return Err(format!("invalid class index {} in stack map", index)),
+            }
+        }
+        8 => VerificationType::Uninitialized(loader.u2()?),
+        _ => return Err(format!("invalid verification type {}", tag)),
+    })
+}
+
+/// Turns the delta encoded frames of a StackMapTable into the full state at
+/// each offset, starting from the method's initial locals
+pub fn expand(
+    frames: &[StackMapFrame],
+    initial_locals: &[VerificationType],
+) -> Result<Vec<FrameState>, String> {
+    let mut states = vec![];
+    let mut locals = initial_locals.to_vec();
+    let mut offset: Option<u32> = None;
+
+    for frame in frames {
+        let next = match offset {
+            None => frame.offset_delta() as u32,
+            Some(o) => o + frame.offset_delta() as u32 + 1,
+        };
+        offset = Some(next);
+
+        let stack = match frame {
+            StackMapFrame::Same { .. } => vec![],
+            StackMapFrame::SameLocals1StackItem { stack, .. } => vec![stack.clone()],
+            StackMapFrame::Chop { count, .. } => {
+                if *count as usize > locals.len() {
+                    return Err(format!("chop frame at {} removes too many locals", next));
+                }
+                locals.truncate(locals.len() - *count as usize);
+                vec![]
+            }
+            StackMapFrame::Append {
+                locals: appended, ..
+            } => {
+                locals.extend(appended.iter().cloned());
+                vec![]
+            }
+            StackMapFrame::Full {
+                locals: full,
+                stack,
+                ..
+            } => {
+                locals = full.clone();
+                stack.clone()
+            }
+        };
+
+        states.push(FrameState {
+            offset: next,
+            locals: expand_locals(&locals),
+            stack,
+        });
+    }
+
+    Ok(states)
+}
+
+/// Turns the states at each frame offset back into the shortest frames that
+/// describe them. The reverse of `expand`.
+pub fn compress(states: &[FrameState], initial_locals: &[VerificationType]) -> Vec<StackMapFrame> {
+    let mut frames = vec![];
+    let mut previous_locals = initial_locals.to_vec();
+    let mut previous_offset: Option<u32> = None;
+
+    for state in states {
+        let offset_delta = match previous_offset {
+            None => state.offset,
+            Some(o) => state.offset - o - 1,
+        } as u16;
+        previous_offset = Some(state.offset);
+
+        let locals = compact_locals(&state.locals);
+        let same_locals = locals == previous_locals;
+
+        frames.push(match state.stack.len() {
+            0 if same_locals => StackMapFrame::Same { offset_delta },
+            1 if same_locals => StackMapFrame::SameLocals1StackItem {
+                offset_delta,
+                stack: state.stack[0].clone(),
+            },
+            0 if locals.len() < previous_locals.len()
+                && previous_locals.len() - locals.len() <= 3
+                && previous_locals.starts_with(&locals) =>
+            {
+                StackMapFrame::Chop {
+                    offset_delta,
+                    count: (previous_locals.len() - locals.len()) as u8,
+                }
+            }
+            0 if locals.len() > previous_locals.len()
+                && locals.len() - previous_locals.len() <= 3
+                && locals.starts_with(&previous_locals) =>
+            {
+                StackMapFrame::Append {
+                    offset_delta,
+                    locals: locals[previous_locals.len()..].to_vec(),
+                }
+            }
+            _ => StackMapFrame::Full {
+                offset_delta,
+                locals: locals.clone(),
+                stack: state.stack.clone(),
+            },
+        });
+
+        previous_locals = locals;
+    }
+
+    frames
+}
+
+/// Encodes frames as a StackMapTable attribute, adding any class names they
+/// use to the constant pool
+pub fn write(frames: &[StackMapFrame], const_pool: &mut ConstPool) -> Attribute {
+    let mut data = vec![];
+    data.extend_from_slice(&(frames.len() as u16).to_be_bytes());
+
+    for frame in frames {
+        match frame {
+            StackMapFrame::Same { offset_delta } => {
+                if *offset_delta < 64 {
+                    data.push(*offset_delta as u8);
+                } else {
+                    data.push(251);
+                    data.extend_from_slice(&offset_delta.to_be_bytes());
+                }
+            }
+            StackMapFrame::SameLocals1StackItem {
+                offset_delta,
+                stack,
+            } => {
+                if *offset_delta < 64 {
+                    data.push(*offset_delta as u8 + 64);
+                } else {
+                    data.push(247);
+                    data.extend_from_slice(&offset_delta.to_be_bytes());
+                }
+                write_type(stack, &mut data, const_pool);
+            }
+            StackMapFrame::Chop {
+                offset_delta,
+                count,
+            } => {
+                data.push(251 - count);
+                data.extend_from_slice(&offset_delta.to_be_bytes());
+            }
+            StackMapFrame::Append {
+                offset_delta,
+                locals,
+            } => {
+                data.push(251 + locals.len() as u8);
+                data.extend_from_slice(&offset_delta.to_be_bytes());
+                for local in locals {
+                    write_type(local, &mut data, const_pool);
+                }
+            }
+            StackMapFrame::Full {
+                offset_delta,
+                locals,
+                stack,
+            } => {
+                data.push(255);
+                data.extend_from_slice(&offset_delta.to_be_bytes());
+                data.extend_from_slice(&(locals.len() as u16).to_be_bytes());
+                for local in locals {
+                    write_type(local, &mut data, const_pool);
+                }
+                data.extend_from_slice(&(stack.len() as u16).to_be_bytes());
+                for item in stack {
+                    write_type(item, &mut data, const_pool);
+                }
+            }
+        }
+    }
+
+    Attribute {
+        name: "StackMapTable".to_string(),
+        data,
+    }
+}
+
+fn write_type(typ: &VerificationType, data: &mut Vec<u8>, const_pool: &mut ConstPool) {
+    match typ {
+        VerificationType::Top => data.push(0),
+        VerificationType::Integer => data.push(1),
+        VerificationType::Float => data.push(2),
+        VerificationType::Double => data.push(3),
+        VerificationType::Long => data.push(4),
+        VerificationType::Null => data.push(5),
+        VerificationType::UninitializedThis => data.push(6),
+        VerificationType::Object(name) => {
+            data.push(7);
+            data.extend_from_slice(&const_pool.add_class(name).to_be_bytes());
+        }
+        VerificationType::Uninitialized(offset) => {
+            data.push(8);
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+    }
+}
+
+/// ClassHierarchy gives the verifier and frame computation access to classes
+/// other than the one being checked
+pub trait ClassHierarchy {
+    /// The superclass of a class and whether it is an interface, or None if
+    /// the class is not known. java/lang/Object has an empty superclass.
+    fn lookup(&self, name: &str) -> Option<(String, bool)>;
+
+    /// Closest class both classes extend, used when two control flow paths
+    /// merge. Interfaces and classes that cannot be found merge to
+    /// java/lang/Object.
+    fn common_superclass(&self, a: &str, b: &str) -> String {
+        if a == b {
+            return a.to_string();
+        }
+
+        if let (Some(a), Some(b)) = (a.strip_prefix('['), b.strip_prefix('[')) {
+            return match (component_class(a), component_class(b)) {
+                (Some(a), Some(b)) => {
+                    let common = self.common_superclass(a, b);
+                    if common.starts_with('[') {
+                        format!("[{}", common)
+                    } else {
+                        format!("[L{};", common)
+                    }
+                }
+                _ => "java/lang/Object".to_string(),
+            };
+        }
+
+        match (superclasses(a, self), superclasses(b, self)) {
+            (Some(a), Some(b)) => a
+                .into_iter()
+                .find(|class| b.contains(class))
+                .unwrap_or_else(|| "java/lang/Object".to_string()),
+            _ => "java/lang/Object".to_string(),
+        }
+    }
+}
+
+/// A class followed by each of its superclasses, or None if the class is an
+/// interface or an array, or part of the chain cannot be found
+fn superclasses<H: ClassHierarchy + ?Sized>(name: &str, hierarchy: &H) -> Option<Vec<String>> {
+    if name.starts_with('[') {
+        return None;
+    }
+
+    let mut chain = vec![name.to_string()];
+    for _ in 0..MAX_HIERARCHY_DEPTH {
+        let current = chain.last().unwrap();
+        if current == "java/lang/Object" {
+            return Some(chain);
+        }
+        match hierarchy.lookup(current) {
+            Some((_, true)) | None => return None,
+            Some((superclass, false)) => {
+                if superclass.is_empty() {
+                    return Some(chain);
+                }
+                chain.push(superclass);
+            }
+        }
+    }
+
+    None
+}
+
+impl ClassHierarchy for HashMap<String, Class> {
+    fn lookup(&self, name: &str) -> Option<(String, bool)> {
+        self.get(name).map(|class| {
+            (
+                class.superclass.clone(),
+                ClassFlag::Interface.is_set(class.flags),
+            )
+        })
+    }
+}
+
+/// Whether a value of one type may be used where another is expected
+pub fn is_assignable(
+    from: &VerificationType,
+    to: &VerificationType,
+    hierarchy: &dyn ClassHierarchy,
+) -> bool {
+    if from == to {
+        return true;
+    }
+
+    match (from, to) {
+        (_, VerificationType::Top) => true,
+        (VerificationType::Null, VerificationType::Object(_)) => true,
+        (VerificationType::Object(from), VerificationType::Object(to)) => {
+            is_class_assignable(from, to, hierarchy)
+        }
+        _ => false,
+    }
+}
+
+fn is_class_assignable(from: &str, to: &str, hierarchy: &dyn ClassHierarchy) -> bool {
+    if from == to || to == "java/lang/Object" {
+        return true;
+    }
+
+    if let Some(to_component) = to.strip_prefix('[') {
+        return match from.strip_prefix('[') {
+            Some(from_component) => match (
+                component_class(from_component),
+                component_class(to_component),
+            ) {
+                (Some(from), Some(to)) => is_class_assignable(from, to, hierarchy),
+                _ => from_component == to_component,
+            },
+            None => false,
+        };
+    }
+
+    if from.starts_with('[') {
+        return to == "java/lang/Cloneable" || to == "java/io/Serializable";
+    }
+
+    // Interfaces are treated like java/lang/Object, and classes that cannot be
+    // found are left for resolution to fail on later
+    match hierarchy.lookup(to) {
+        None | Some((_, true)) => return true,
+        _ => {}
+    }
+
+    let mut current = from.to_string();
+    for _ in 0..MAX_HIERARCHY_DEPTH {
+        match hierarchy.lookup(&current) {
+            None => return true,
+            Some((superclass, _)) => {
+                if superclass.is_empty() {
+                    return false;
+                }
+                if superclass == to {
+                    return true;
+                }
+                current = superclass;
+            }
+        }
+    }
+
+    false
+}
+
+/// Class name of a reference component descriptor, such as `Ljava/lang/String;`
+/// or `[I`, or None for primitive components
+fn component_class(descriptor: &str) -> Option<&str> {
+    if descriptor.starts_with('[') {
+        Some(descriptor)
+    } else {
+        descriptor
+            .strip_prefix('L')
+            .and_then(|name| name.strip_suffix(';'))
+    }
+}
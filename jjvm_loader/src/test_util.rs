@@ -0,0 +1,34 @@
+use crate::class::{Attribute, Class, Field};
+use crate::const_pool::ConstPool;
+
+/// A class `Test` with one static method `test` running `code`, with no
+/// exception handlers or attributes of its own
+pub fn class_with_method(descriptor: &str, max_stack: u16, max_locals: u16, code: &[u8]) -> Class {
+    let mut data = vec![];
+    data.extend_from_slice(&max_stack.to_be_bytes());
+    data.extend_from_slice(&max_locals.to_be_bytes());
+    data.extend_from_slice(&(code.len() as u32).to_be_bytes());
+    data.extend_from_slice(code);
+    data.extend_from_slice(&[0, 0, 0, 0]);
+
+    Class {
+        major: 52,
+        minor: 0,
+        const_pool: ConstPool { consts: vec![] },
+        name: "Test".to_string(),
+        superclass: "java/lang/Object".to_string(),
+        flags: 0x0021,
+        interfaces: vec![],
+        fields: vec![],
+        methods: vec![Field {
+            flags: 0x0009,
+            name: "test".to_string(),
+            descriptor: descriptor.to_string(),
+            attributes: vec![Attribute {
+                name: "Code".to_string(),
+                data,
+            }],
+        }],
+        attributes: vec![],
+    }
+}
@@ -0,0 +1,1243 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::attribute::Code;
+use crate::class::{Class, Field};
+use crate::const_pool::ConstPool;
+use crate::instruction::{self, Instruction, Operand};
+use crate::jvm_const::JvmConst;
+use crate::opcode::Opcode;
+use crate::signature::{JavaType, TypeSignature};
+use crate::stack_map::{self, ClassHierarchy, FrameState, VerificationType};
+
+use VerificationType::{Double, Float, Integer, Long, Null, Top, UninitializedThis};
+
+/// First class file version that is type-checked against a StackMapTable.
+/// Older classes only get the structural checks.
+pub(crate) const STACK_MAP_VERSION: u16 = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyError {
+    pub class: String,
+    pub method: String,
+    pub offset: u32,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "VerifyError: {}.{} at offset {}: {}",
+            self.class, self.method, self.offset, self.message
+        )
+    }
+}
+
+/// Verifies every method of a class that has code
+pub fn verify_class(class: &Class, hierarchy: &dyn ClassHierarchy) -> Result<(), VerifyError> {
+    for method in &class.methods {
+        verify_method(class, method, hierarchy)?;
+    }
+
+    Ok(())
+}
+
+pub fn verify_method(
+    class: &Class,
+    method: &Field,
+    hierarchy: &dyn ClassHierarchy,
+) -> Result<(), VerifyError> {
+    let error = |offset: u32, message: String| VerifyError {
+        class: class.name.clone(),
+        method: format!("{}{}", method.name, method.descriptor),
+        offset,
+        message,
+    };
+
+    let code = match Code::from_method(method, &class.const_pool) {
+        Ok(Some(code)) => code,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(error(0, e)),
+    };
+
+    let instructions = instruction::decode(&code.code).map_err(|e| error(0, e))?;
+    if instructions.is_empty() {
+        return Err(error(0, "method has no code".to_string()));
+    }
+
+    check_structure(&code, &instructions).map_err(|(offset, e)| error(offset, e))?;
+
+    if class.major < STACK_MAP_VERSION {
+        return Ok(());
+    }
+
+    let initial = stack_map::initial_locals(&class.name, method).map_err(|e| error(0, e))?;
+    let frames = match code.find_attribute("StackMapTable") {
+        Some(attribute) => {
+            let frames = stack_map::parse(attribute, &class.const_pool).map_err(|e| error(0, e))?;
+            stack_map::expand(&frames, &initial).map_err(|e| error(0, e))?
+        }
+        None => vec![],
+    };
+
+    let checker = Checker {
+        interpreter: Interpreter {
+            const_pool: &class.const_pool,
+            class_name: &class.name,
+            code: &code.code,
+            hierarchy,
+        },
+        method,
+        code: &code,
+        frames: frames.into_iter().map(|f| (f.offset, f)).collect(),
+    };
+
+    let initial = FrameState {
+        offset: 0,
+        locals: stack_map::expand_locals(&initial),
+        stack: vec![],
+    };
+
+    checker
+        .check(initial, &instructions)
+        .map_err(|(offset, e)| error(offset, e))
+}
+
+/// Checks that do not need types: instruction boundaries, branch targets and
+/// the exception table
+fn check_structure(code: &Code, instructions: &[Instruction]) -> Result<(), (u32, String)> {
+    let starts: Vec<u32> = instructions.iter().map(|i| i.offset).collect();
+    let is_start = |offset: u32| starts.binary_search(&offset).is_ok();
+    let code_length = code.code.len() as u32;
+
+    for ins in instructions {
+        for target in ins.branch_targets() {
+            if !is_start(target) {
+                return Err((ins.offset, format!("invalid branch target {}", target)));
+            }
+        }
+
+        if let Some(index) = ins.local() {
+            let size = match ins.opcode {
+                Opcode::LLoad
+                | Opcode::LStore
+                | Opcode::DLoad
+                | Opcode::DStore
+                | Opcode::LLoad0
+                | Opcode::LLoad1
+                | Opcode::LLoad2
+                | Opcode::LLoad3
+                | Opcode::DLoad0
+                | Opcode::DLoad1
+                | Opcode::DLoad2
+                | Opcode::DLoad3
+                | Opcode::LStore0
+                | Opcode::LStore1
+                | Opcode::LStore2
+                | Opcode::LStore3
+                | Opcode::DStore0
+                | Opcode::DStore1
+                | Opcode::DStore2
+                | Opcode::DStore3 => 2,
+                _ => 1,
+            };
+            if index as u32 + size > code.max_locals as u32 {
+                return Err((
+                    ins.offset,
+                    format!("local {} exceeds max_locals {}", index, code.max_locals),
+                ));
+            }
+        }
+    }
+
+    if instructions.last().unwrap().falls_through() {
+        let last = instructions.last().unwrap();
+        return Err((last.offset, "falls off the end of the code".to_string()));
+    }
+
+    for handler in &code.exception_table {
+        let start = handler.start_pc as u32;
+        let end = handler.end_pc as u32;
+        if start >= end || !is_start(start) || (end != code_length && !is_start(end)) {
+            return Err((start, format!("invalid exception range {}..{}", start, end)));
+        }
+        if !is_start(handler.handler_pc as u32) {
+            return Err((
+                start,
+                format!("invalid exception handler {}", handler.handler_pc),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+struct Checker<'a> {
+    interpreter: Interpreter<'a>,
+    method: &'a Field,
+    code: &'a Code,
+    frames: BTreeMap<u32, FrameState>,
+}
+
+impl<'a> Checker<'a> {
+    fn check(
+        self: &Checker<'a>,
+        initial: FrameState,
+        instructions: &[Instruction],
+    ) -> Result<(), (u32, String)> {
+        for (offset, frame) in &self.frames {
+            if instructions
+                .binary_search_by_key(offset, |i| i.offset)
+                .is_err()
+            {
+                return Err((
+                    *offset,
+                    "stack map frame is not on an instruction".to_string(),
+                ));
+            }
+            if frame.locals.len() > self.code.max_locals as usize {
+                return Err((*offset, "stack map frame exceeds max_locals".to_string()));
+            }
+            if frame.stack_size() > self.code.max_stack as usize {
+                return Err((*offset, "stack map frame exceeds max_stack".to_string()));
+            }
+        }
+
+        if initial.locals.len() > self.code.max_locals as usize {
+            return Err((0, "arguments exceed max_locals".to_string()));
+        }
+
+        let return_type = self.return_type().map_err(|e| (0, e))?;
+        let mut state = initial;
+        let mut falls_through = true;
+
+        for ins in instructions {
+            let offset = ins.offset;
+            if let Some(frame) = self.frames.get(&offset) {
+                if falls_through {
+                    self.check_frame(&state, frame).map_err(|e| (offset, e))?;
+                }
+                state = frame.clone();
+            } else if !falls_through {
+                return Err((offset, "expected a stack map frame".to_string()));
+            }
+            state.offset = offset;
+
+            self.check_handlers(&state, offset)
+                .map_err(|e| (offset, e))?;
+
+            self.check_return(&state, ins, &return_type)
+                .map_err(|e| (offset, e))?;
+
+            self.interpreter
+                .step(&mut state, ins)
+                .map_err(|e| (offset, e))?;
+
+            if state.stack_size() > self.code.max_stack as usize {
+                return Err((
+                    offset,
+                    format!("stack exceeds max_stack {}", self.code.max_stack),
+                ));
+            }
+            if state.locals.len() > self.code.max_locals as usize {
+                return Err((
+                    offset,
+                    format!("locals exceed max_locals {}", self.code.max_locals),
+                ));
+            }
+
+            // Stores change the locals the handlers see
+            if ins.local().is_some() {
+                self.check_handlers(&state, offset)
+                    .map_err(|e| (offset, e))?;
+            }
+
+            for target in ins.branch_targets() {
+                match self.frames.get(&target) {
+                    Some(frame) => self
+                        .check_frame(&state, frame)
+                        .map_err(|e| (offset, format!("branch to {}: {}", target, e)))?,
+                    None => {
+                        return Err((offset, format!("no stack map frame at target {}", target)))
+                    }
+                }
+            }
+
+            falls_through = ins.falls_through();
+        }
+
+        Ok(())
+    }
+
+    fn return_type(self: &Checker<'a>) -> Result<Option<VerificationType>, String> {
+        let signature = TypeSignature::from_str(&self.method.descriptor)
+            .map_err(|_| format!("invalid method descriptor {}", self.method.descriptor))?;
+
+        Ok(VerificationType::from_java_type(&signature.ret))
+    }
+
+    fn check_return(
+        self: &Checker<'a>,
+        state: &FrameState,
+        ins: &Instruction,
+        return_type: &Option<VerificationType>,
+    ) -> Result<(), String> {
+        let expected = match ins.opcode {
+            Opcode::IReturn => Some(Integer),
+            Opcode::LReturn => Some(Long),
+            Opcode::FReturn => Some(Float),
+            Opcode::DReturn => Some(Double),
+            Opcode::AReturn => return_type.clone().filter(|t| t.is_reference()),
+            Opcode::Return => {
+                if return_type.is_some() {
+                    return Err("return from a method that returns a value".to_string());
+                }
+                if self.method.name == "<init>" && state.locals.first() == Some(&UninitializedThis)
+                {
+                    return Err("constructor returns before calling super".to_string());
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        match (expected, return_type) {
+            (Some(expected), Some(ret)) if expected == *ret || ins.opcode == Opcode::AReturn => {
+                let top = state.stack.last().cloned().unwrap_or(Top);
+                if stack_map::is_assignable(&top, ret, self.interpreter.hierarchy) {
+                    Ok(())
+                } else {
+                    Err(format!("returns {} from a method returning {}", top, ret))
+                }
+            }
+            _ => Err(format!(
+                "{:?} does not match return type {}",
+                ins.opcode,
+                return_type
+                    .as_ref()
+                    .map(|t| t.to_string())
+                    .unwrap_or_else(|| "void".to_string())
+            )),
+        }
+    }
+
+    /// Checks that the current state may flow into a stack map frame
+    fn check_frame(
+        self: &Checker<'a>,
+        state: &FrameState,
+        frame: &FrameState,
+    ) -> Result<(), String> {
+        let hierarchy = self.interpreter.hierarchy;
+        for i in 0..state.locals.len().max(frame.locals.len()) {
+            let from = state.locals.get(i).unwrap_or(&Top);
+            let to = frame.locals.get(i).unwrap_or(&Top);
+            if !stack_map::is_assignable(from, to, hierarchy) {
+                return Err(format!("local {} is {} but frame expects {}", i, from, to));
+            }
+        }
+
+        if state.stack.len() != frame.stack.len() {
+            return Err(format!(
+                "stack has {} items but frame expects {}",
+                state.stack.len(),
+                frame.stack.len()
+            ));
+        }
+        for (from, to) in state.stack.iter().zip(&frame.stack) {
+            if !stack_map::is_assignable(from, to, hierarchy) {
+                return Err(format!("stack holds {} but frame expects {}", from, to));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_handlers(self: &Checker<'a>, state: &FrameState, offset: u32) -> Result<(), String> {
+        for handler in &self.code.exception_table {
+            if offset < handler.start_pc as u32 || offset >= handler.end_pc as u32 {
+                continue;
+            }
+
+            let catch_type = match handler.catch_type {
+                0 => "java/lang/Throwable",
+                index => match self.interpreter.const_pool.class_name(index) {
+                    Some(name) => name,
+                    None => return Err(format!("invalid catch type {}", index)),
+                },
+            };
+            let thrown = VerificationType::from_class_name(catch_type);
+            if !stack_map::is_assignable(
+                &thrown,
+                &VerificationType::from_class_name("java/lang/Throwable"),
+                self.interpreter.hierarchy,
+            ) {
+                return Err(format!("{} is not throwable", catch_type));
+            }
+
+            let handler_state = FrameState {
+                offset: handler.handler_pc as u32,
+                locals: state.locals.clone(),
+                stack: vec![thrown],
+            };
+            match self.frames.get(&(handler.handler_pc as u32)) {
+                Some(frame) => self
+                    .check_frame(&handler_state, frame)
+                    .map_err(|e| format!("handler at {}: {}", handler.handler_pc, e))?,
+                None => {
+                    return Err(format!(
+                        "no stack map frame at handler {}",
+                        handler.handler_pc
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Interpreter applies the effect of an instruction to the types of the
+/// locals and stack, failing when an operand has the wrong type
+pub(crate) struct Interpreter<'a> {
+    pub const_pool: &'a ConstPool,
+    pub class_name: &'a str,
+    pub code: &'a [u8],
+    pub hierarchy: &'a dyn ClassHierarchy,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn step(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        ins: &Instruction,
+    ) -> Result<(), String> {
+        match ins.opcode {
+            Opcode::Nop => {}
+            Opcode::AConstNull => push(state, Null),
+            Opcode::IConstM1
+            | Opcode::IConst0
+            | Opcode::IConst1
+            | Opcode::IConst2
+            | Opcode::IConst3
+            | Opcode::IConst4
+            | Opcode::IConst5
+            | Opcode::BiPush
+            | Opcode::SiPush => push(state, Integer),
+            Opcode::LConst0 | Opcode::LConst1 => push(state, Long),
+            Opcode::FConst0 | Opcode::FConst1 | Opcode::FConst2 => push(state, Float),
+            Opcode::DConst0 | Opcode::DConst1 => push(state, Double),
+            Opcode::Ldc | Opcode::LdcW | Opcode::Ldc2W => {
+                let typ = self.constant_type(constant(ins)?)?;
+                if typ.is_category2() != (ins.opcode == Opcode::Ldc2W) {
+                    return Err(format!("{:?} cannot load a {}", ins.opcode, typ));
+                }
+                push(state, typ);
+            }
+            Opcode::ILoad | Opcode::ILoad0 | Opcode::ILoad1 | Opcode::ILoad2 | Opcode::ILoad3 => {
+                self.load(state, ins, Integer)?
+            }
+            Opcode::LLoad | Opcode::LLoad0 | Opcode::LLoad1 | Opcode::LLoad2 | Opcode::LLoad3 => {
+                self.load(state, ins, Long)?
+            }
+            Opcode::FLoad | Opcode::FLoad0 | Opcode::FLoad1 | Opcode::FLoad2 | Opcode::FLoad3 => {
+                self.load(state, ins, Float)?
+            }
+            Opcode::DLoad | Opcode::DLoad0 | Opcode::DLoad1 | Opcode::DLoad2 | Opcode::DLoad3 => {
+                self.load(state, ins, Double)?
+            }
+            Opcode::ALoad | Opcode::ALoad0 | Opcode::ALoad1 | Opcode::ALoad2 | Opcode::ALoad3 => {
+                let index = ins.local().unwrap() as usize;
+                let typ = state.locals.get(index).cloned().unwrap_or(Top);
+                if !typ.is_reference() {
+                    return Err(format!("aload of local {} which is {}", index, typ));
+                }
+                push(state, typ);
+            }
+            Opcode::IaLoad => self.array_load(state, &["[I"], Integer)?,
+            Opcode::LaLoad => self.array_load(state, &["[J"], Long)?,
+            Opcode::FaLoad => self.array_load(state, &["[F"], Float)?,
+            Opcode::DaLoad => self.array_load(state, &["[D"], Double)?,
+            Opcode::BaLoad => self.array_load(state, &["[B", "[Z"], Integer)?,
+            Opcode::CaLoad => self.array_load(state, &["[C"], Integer)?,
+            Opcode::SaLoad => self.array_load(state, &["[S"], Integer)?,
+            Opcode::AaLoad => {
+                self.pop(state, &Integer)?;
+                let array = pop_reference(state)?;
+                let component = match &array {
+                    Null => Null,
+                    VerificationType::Object(name) => match array_component(name) {
+                        Some(component) if component.is_reference() => component,
+                        _ => return Err(format!("aaload from {}", array)),
+                    },
+                    _ => return Err(format!("aaload from {}", array)),
+                };
+                push(state, component);
+            }
+            Opcode::IStore
+            | Opcode::IStore0
+            | Opcode::IStore1
+            | Opcode::IStore2
+            | Opcode::IStore3 => self.store(state, ins, Integer)?,
+            Opcode::LStore
+            | Opcode::LStore0
+            | Opcode::LStore1
+            | Opcode::LStore2
+            | Opcode::LStore3 => self.store(state, ins, Long)?,
+            Opcode::FStore
+            | Opcode::FStore0
+            | Opcode::FStore1
+            | Opcode::FStore2
+            | Opcode::FStore3 => self.store(state, ins, Float)?,
+            Opcode::DStore
+            | Opcode::DStore0
+            | Opcode::DStore1
+            | Opcode::DStore2
+            | Opcode::DStore3 => self.store(state, ins, Double)?,
+            Opcode::AStore
+            | Opcode::AStore0
+            | Opcode::AStore1
+            | Opcode::AStore2
+            | Opcode::AStore3 => {
+                let typ = pop_reference(state)?;
+                set_local(state, ins.local().unwrap() as usize, typ);
+            }
+            Opcode::IaStore => self.array_store(state, &["[I"], Integer)?,
+            Opcode::LaStore => self.array_store(state, &["[J"], Long)?,
+            Opcode::FaStore => self.array_store(state, &["[F"], Float)?,
+            Opcode::DaStore => self.array_store(state, &["[D"], Double)?,
+            Opcode::BaStore => self.array_store(state, &["[B", "[Z"], Integer)?,
+            Opcode::CaStore => self.array_store(state, &["[C"], Integer)?,
+            Opcode::SaStore => self.array_store(state, &["[S"], Integer)?,
+            Opcode::AaStore => {
+                pop_reference(state)?;
+                self.pop(state, &Integer)?;
+                let array = pop_reference(state)?;
+                match &array {
+                    Null => {}
+                    VerificationType::Object(name) if matches!(array_component(name), Some(c) if c.is_reference()) =>
+                        {}
+                    _ => return Err(format!("aastore into {}", array)),
+                }
+            }
+            Opcode::Pop => {
+                pop_category1(state)?;
+            }
+            Opcode::Pop2 => {
+                if !pop_any(state)?.is_category2() {
+                    pop_category1(state)?;
+                }
+            }
+            Opcode::Dup => {
+                let v1 = pop_category1(state)?;
+                push_all(state, &[v1.clone(), v1]);
+            }
+            Opcode::DupX1 => {
+                let v1 = pop_category1(state)?;
+                let v2 = pop_category1(state)?;
+                push_all(state, &[v1.clone(), v2, v1]);
+            }
+            Opcode::DupX2 => {
+                let v1 = pop_category1(state)?;
+                let v2 = pop_any(state)?;
+                if v2.is_category2() {
+                    push_all(state, &[v1.clone(), v2, v1]);
+                } else {
+                    let v3 = pop_category1(state)?;
+                    push_all(state, &[v1.clone(), v3, v2, v1]);
+                }
+            }
+            Opcode::Dup2 => {
+                let v1 = pop_any(state)?;
+                if v1.is_category2() {
+                    push_all(state, &[v1.clone(), v1]);
+                } else {
+                    let v2 = pop_category1(state)?;
+                    push_all(state, &[v2.clone(), v1.clone(), v2, v1]);
+                }
+            }
+            Opcode::Dup2X1 => {
+                let v1 = pop_any(state)?;
+                if v1.is_category2() {
+                    let v2 = pop_category1(state)?;
+                    push_all(state, &[v1.clone(), v2, v1]);
+                } else {
+                    let v2 = pop_category1(state)?;
+                    let v3 = pop_category1(state)?;
+                    push_all(state, &[v2.clone(), v1.clone(), v3, v2, v1]);
+                }
+            }
+            Opcode::Dup2X2 => {
+                let v1 = pop_any(state)?;
+                if v1.is_category2() {
+                    let v2 = pop_any(state)?;
+                    if v2.is_category2() {
+                        push_all(state, &[v1.clone(), v2, v1]);
+                    } else {
+                        let v3 = pop_category1(state)?;
+                        push_all(state, &[v1.clone(), v3, v2, v1]);
+                    }
+                } else {
+                    let v2 = pop_category1(state)?;
+                    let v3 = pop_any(state)?;
+                    if v3.is_category2() {
+                        push_all(state, &[v2.clone(), v1.clone(), v3, v2, v1]);
+                    } else {
+                        let v4 = pop_category1(state)?;
+                        push_all(state, &[v2.clone(), v1.clone(), v4, v3, v2, v1]);
+                    }
+                }
+            }
+            Opcode::Swap => {
+                let v1 = pop_category1(state)?;
+                let v2 = pop_category1(state)?;
+                push_all(state, &[v1, v2]);
+            }
+            Opcode::IAdd
+            | Opcode::ISub
+            | Opcode::IMul
+            | Opcode::IDiv
+            | Opcode::IRem
+            | Opcode::IShl
+            | Opcode::IShr
+            | Opcode::IuShr
+            | Opcode::IAnd
+            | Opcode::IOr
+            | Opcode::IXOr => self.binary(state, &Integer, &Integer, Integer)?,
+            Opcode::LAdd
+            | Opcode::LSub
+            | Opcode::LMul
+            | Opcode::LDiv
+            | Opcode::LRem
+            | Opcode::LAnd
+            | Opcode::LOr
+            | Opcode::LXOr => self.binary(state, &Long, &Long, Long)?,
+            Opcode::LShl | Opcode::LShr | Opcode::LuShr => {
+                self.binary(state, &Long, &Integer, Long)?
+            }
+            Opcode::FAdd | Opcode::FSub | Opcode::FMul | Opcode::FDiv | Opcode::FRem => {
+                self.binary(state, &Float, &Float, Float)?
+            }
+            Opcode::DAdd | Opcode::DSub | Opcode::DMul | Opcode::DDiv | Opcode::DRem => {
+                self.binary(state, &Double, &Double, Double)?
+            }
+            Opcode::INeg | Opcode::I2B | Opcode::I2C | Opcode::I2S => {
+                self.unary(state, &Integer, Integer)?
+            }
+            Opcode::LNeg => self.unary(state, &Long, Long)?,
+            Opcode::FNeg => self.unary(state, &Float, Float)?,
+            Opcode::DNeg => self.unary(state, &Double, Double)?,
+            Opcode::IInc => {
+                let index = ins.local().unwrap() as usize;
+                if state.locals.get(index) != Some(&Integer) {
+                    return Err(format!("iinc of local {} which is not an int", index));
+                }
+            }
+            Opcode::I2L => self.unary(state, &Integer, Long)?,
+            Opcode::I2F => self.unary(state, &Integer, Float)?,
+            Opcode::I2D => self.unary(state, &Integer, Double)?,
+            Opcode::L2I => self.unary(state, &Long, Integer)?,
+            Opcode::L2F => self.unary(state, &Long, Float)?,
+            Opcode::L2D => self.unary(state, &Long, Double)?,
+            Opcode::F2I => self.unary(state, &Float, Integer)?,
+            Opcode::F2L => self.unary(state, &Float, Long)?,
+            Opcode::F2D => self.unary(state, &Float, Double)?,
+            Opcode::D2I => self.unary(state, &Double, Integer)?,
+            Opcode::D2L => self.unary(state, &Double, Long)?,
+            Opcode::D2F => self.unary(state, &Double, Float)?,
+            Opcode::LCmp => self.binary(state, &Long, &Long, Integer)?,
+            Opcode::FCmpl | Opcode::FCmpg => self.binary(state, &Float, &Float, Integer)?,
+            Opcode::DCmpl | Opcode::DCmpg => self.binary(state, &Double, &Double, Integer)?,
+            Opcode::IfEq
+            | Opcode::IfNe
+            | Opcode::IfLt
+            | Opcode::IfGe
+            | Opcode::IfGt
+            | Opcode::IfLe
+            | Opcode::TableSwitch
+            | Opcode::LookupSwitch => {
+                self.pop(state, &Integer)?;
+            }
+            Opcode::IfIcmpEq
+            | Opcode::IfIcmpNe
+            | Opcode::IfIcmpLt
+            | Opcode::IfIcmpGe
+            | Opcode::IfIcmpGt
+            | Opcode::IfIcmpLe => {
+                self.pop(state, &Integer)?;
+                self.pop(state, &Integer)?;
+            }
+            Opcode::IfAcmpEq | Opcode::IfAcmpNe => {
+                pop_reference(state)?;
+                pop_reference(state)?;
+            }
+            Opcode::IfNull | Opcode::IfNonNull | Opcode::MonitorEnter | Opcode::MonitorExit => {
+                pop_reference(state)?;
+            }
+            Opcode::Goto | Opcode::GotoW => {}
+            Opcode::Jsr | Opcode::JsrW | Opcode::Ret => {
+                return Err(format!("{:?} is not allowed with stack maps", ins.opcode))
+            }
+            Opcode::IReturn => {
+                self.pop(state, &Integer)?;
+            }
+            Opcode::LReturn => {
+                self.pop(state, &Long)?;
+            }
+            Opcode::FReturn => {
+                self.pop(state, &Float)?;
+            }
+            Opcode::DReturn => {
+                self.pop(state, &Double)?;
+            }
+            Opcode::AReturn => {
+                pop_reference(state)?;
+            }
+            Opcode::Return => {}
+            Opcode::GetStatic | Opcode::PutStatic | Opcode::GetField | Opcode::PutField => {
+                self.field(state, ins)?
+            }
+            Opcode::InvokeVirtual
+            | Opcode::InvokeSpecial
+            | Opcode::InvokeStatic
+            | Opcode::InvokeInterface
+            | Opcode::InvokeDynamic => self.invoke(state, ins)?,
+            Opcode::New => {
+                let index = constant(ins)?;
+                match self.const_pool.class_name(index) {
+                    Some(name) if !name.starts_with('[') => {}
+                    _ => return Err(format!("new of invalid class {}", index)),
+                }
+                push(state, VerificationType::Uninitialized(ins.offset as u16));
+            }
+            Opcode::NewArray => {
+                let descriptor = match ins.operand {
+                    Operand::ArrayType(4) => "[Z",
+                    Operand::ArrayType(5) => "[C",
+                    Operand::ArrayType(6) => "[F",
+                    Operand::ArrayType(7) => "[D",
+                    Operand::ArrayType(8) => "[B",
+                    Operand::ArrayType(9) => "[S",
+                    Operand::ArrayType(10) => "[I",
+                    Operand::ArrayType(11) => "[J",
+                    _ => return Err(format!("invalid newarray type {:?}", ins.operand)),
+                };
+                self.pop(state, &Integer)?;
+                push(state, VerificationType::from_class_name(descriptor));
+            }
+            Opcode::ANewArray => {
+                let name = self.class_name(constant(ins)?)?;
+                self.pop(state, &Integer)?;
+                push(state, VerificationType::Object(array_of(name)));
+            }
+            Opcode::ArrayLength => {
+                let array = pop_reference(state)?;
+                match &array {
+                    Null => {}
+                    VerificationType::Object(name) if name.starts_with('[') => {}
+                    _ => return Err(format!("arraylength of {}", array)),
+                }
+                push(state, Integer);
+            }
+            Opcode::AThrow => {
+                self.pop(
+                    state,
+                    &VerificationType::from_class_name("java/lang/Throwable"),
+                )?;
+            }
+            Opcode::CheckCast => {
+                let name = self.class_name(constant(ins)?)?;
+                pop_reference(state)?;
+                push(state, VerificationType::from_class_name(name));
+            }
+            Opcode::InstanceOf => {
+                self.class_name(constant(ins)?)?;
+                pop_reference(state)?;
+                push(state, Integer);
+            }
+            Opcode::MultiANewArray => {
+                let (index, dimensions) = match ins.operand {
+                    Operand::MultiArray(index, dimensions) => (index, dimensions),
+                    _ => unreachable!(),
+                };
+                let name = self.class_name(index)?;
+                if dimensions == 0
+                    || name.chars().take_while(|c| *c == '[').count() < dimensions as usize
+                {
+                    return Err(format!(
+                        "multianewarray of {} with {} dimensions",
+                        name, dimensions
+                    ));
+                }
+                for _ in 0..dimensions {
+                    self.pop(state, &Integer)?;
+                }
+                push(state, VerificationType::from_class_name(name));
+            }
+            Opcode::Wide | Opcode::BreakPoint => {
+                return Err(format!("unexpected {:?}", ins.opcode));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pop(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        expected: &VerificationType,
+    ) -> Result<VerificationType, String> {
+        let actual = pop_any(state)?;
+        if !stack_map::is_assignable(&actual, expected, self.hierarchy) {
+            return Err(format!(
+                "expected {} on the stack but found {}",
+                expected, actual
+            ));
+        }
+
+        Ok(actual)
+    }
+
+    fn unary(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        operand: &VerificationType,
+        result: VerificationType,
+    ) -> Result<(), String> {
+        self.pop(state, operand)?;
+        push(state, result);
+        Ok(())
+    }
+
+    fn binary(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        left: &VerificationType,
+        right: &VerificationType,
+        result: VerificationType,
+    ) -> Result<(), String> {
+        self.pop(state, right)?;
+        self.pop(state, left)?;
+        push(state, result);
+        Ok(())
+    }
+
+    fn load(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        ins: &Instruction,
+        expected: VerificationType,
+    ) -> Result<(), String> {
+        let index = ins.local().unwrap() as usize;
+        if state.locals.get(index) != Some(&expected) {
+            return Err(format!(
+                "load of local {} as {} but it is {}",
+                index,
+                expected,
+                state.locals.get(index).unwrap_or(&Top)
+            ));
+        }
+
+        push(state, expected);
+        Ok(())
+    }
+
+    fn store(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        ins: &Instruction,
+        typ: VerificationType,
+    ) -> Result<(), String> {
+        self.pop(state, &typ)?;
+        set_local(state, ins.local().unwrap() as usize, typ);
+        Ok(())
+    }
+
+    fn array_load(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        arrays: &[&str],
+        result: VerificationType,
+    ) -> Result<(), String> {
+        self.pop(state, &Integer)?;
+        check_array(pop_reference(state)?, arrays)?;
+        push(state, result);
+        Ok(())
+    }
+
+    fn array_store(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        arrays: &[&str],
+        value: VerificationType,
+    ) -> Result<(), String> {
+        self.pop(state, &value)?;
+        self.pop(state, &Integer)?;
+        check_array(pop_reference(state)?, arrays)
+    }
+
+    fn class_name(self: &Interpreter<'a>, index: u16) -> Result<&'a str, String> {
+        self.const_pool
+            .class_name(index)
+            .ok_or_else(|| format!("constant {} is not a class", index))
+    }
+
+    fn constant_type(self: &Interpreter<'a>, index: u16) -> Result<VerificationType, String> {
+        Ok(match self.const_pool.get(index) {
+            Some(JvmConst::Integer(_)) => Integer,
+            Some(JvmConst::Float(_)) => Float,
+            Some(JvmConst::Long(_, _)) => Long,
+            Some(JvmConst::Double(_, _)) => Double,
+            Some(JvmConst::String(_)) => VerificationType::from_class_name("java/lang/String"),
+            Some(JvmConst::Class(_)) => VerificationType::from_class_name("java/lang/Class"),
+            Some(JvmConst::MethodType(_)) => {
+                VerificationType::from_class_name("java/lang/invoke/MethodType")
+            }
+            Some(JvmConst::MethodHandle(_, _)) => {
+                VerificationType::from_class_name("java/lang/invoke/MethodHandle")
+            }
+            Some(JvmConst::Dynamic(_, nat)) => match self.const_pool.name_and_type(*nat) {
+                Some((_, descriptor)) => field_type(descriptor)?,
+                None => return Err(format!("invalid dynamic constant {}", index)),
+            },
+            _ => return Err(format!("constant {} cannot be loaded", index)),
+        })
+    }
+
+    fn field(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        ins: &Instruction,
+    ) -> Result<(), String> {
+        let index = constant(ins)?;
+        let (class, _, descriptor) = match self.const_pool.get(index) {
+            Some(JvmConst::FieldRef(_, _)) => self
+                .const_pool
+                .member_ref(index)
+                .ok_or_else(|| format!("invalid member reference {}", index))?,
+            _ => return Err(format!("constant {} is not a field", index)),
+        };
+        let typ = field_type(descriptor)?;
+
+        match ins.opcode {
+            Opcode::GetStatic => push(state, typ),
+            Opcode::PutStatic => {
+                self.pop(state, &typ)?;
+            }
+            Opcode::GetField => {
+                self.pop(state, &VerificationType::from_class_name(class))?;
+                push(state, typ);
+            }
+            _ => {
+                self.pop(state, &typ)?;
+                let object = pop_reference(state)?;
+                // Constructors may set their own fields before calling super
+                let this_field = object == UninitializedThis && class == self.class_name;
+                if !this_field
+                    && !stack_map::is_assignable(
+                        &object,
+                        &VerificationType::from_class_name(class),
+                        self.hierarchy,
+                    )
+                {
+                    return Err(format!("putfield on {} for a field of {}", object, class));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn invoke(
+        self: &Interpreter<'a>,
+        state: &mut FrameState,
+        ins: &Instruction,
+    ) -> Result<(), String> {
+        let index = match ins.operand {
+            Operand::Constant(index) | Operand::InvokeInterface(index, _) => index,
+            _ => unreachable!(),
+        };
+
+        let (class, name, descriptor) = match (ins.opcode, self.const_pool.get(index)) {
+            (Opcode::InvokeDynamic, Some(JvmConst::InvokeDynamic(_, nat))) => {
+                match self.const_pool.name_and_type(*nat) {
+                    Some((name, descriptor)) => ("", name, descriptor),
+                    None => return Err(format!("invalid invokedynamic constant {}", index)),
+                }
+            }
+            (Opcode::InvokeDynamic, _) => {
+                return Err(format!("constant {} is not an invokedynamic", index))
+            }
+            (_, Some(JvmConst::MethodRef(_, _)))
+            | (_, Some(JvmConst::InterfaceMethodRef(_, _))) => self
+                .const_pool
+                .member_ref(index)
+                .ok_or_else(|| format!("invalid member reference {}", index))?,
+            _ => return Err(format!("constant {} is not a method", index)),
+        };
+
+        let signature = TypeSignature::from_str(descriptor)
+            .map_err(|_| format!("invalid method descriptor {}", descriptor))?;
+        for arg in signature.args.iter().rev() {
+            match VerificationType::from_java_type(arg) {
+                Some(typ) => self.pop(state, &typ)?,
+                None => return Err(format!("invalid method descriptor {}", descriptor)),
+            };
+        }
+
+        if name == "<init>" {
+            if ins.opcode != Opcode::InvokeSpecial {
+                return Err(format!("{:?} of a constructor", ins.opcode));
+            }
+            let object = pop_reference(state)?;
+            let initialized = match &object {
+                UninitializedThis => VerificationType::from_class_name(self.class_name),
+                VerificationType::Uninitialized(offset) => {
+                    let created = self.created_class(*offset)?;
+                    if created != class {
+                        return Err(format!("{}.<init> called on a new {}", class, created));
+                    }
+                    VerificationType::from_class_name(class)
+                }
+                _ => return Err(format!("<init> called on {}", object)),
+            };
+
+            for typ in state.locals.iter_mut().chain(state.stack.iter_mut()) {
+                if *typ == object {
+                    *typ = initialized.clone();
+                }
+            }
+        } else {
+            match ins.opcode {
+                Opcode::InvokeStatic | Opcode::InvokeDynamic => {}
+                Opcode::InvokeInterface => {
+                    let object = pop_reference(state)?;
+                    if !matches!(object, Null | VerificationType::Object(_)) {
+                        return Err(format!("invokeinterface on {}", object));
+                    }
+                }
+                Opcode::InvokeSpecial => {
+                    self.pop(state, &VerificationType::from_class_name(self.class_name))?;
+                }
+                _ => {
+                    self.pop(state, &VerificationType::from_class_name(class))?;
+                }
+            }
+        }
+
+        if let Some(ret) = VerificationType::from_java_type(&signature.ret) {
+            push(state, ret);
+        }
+
+        Ok(())
+    }
+
+    /// Class created by the `new` instruction at an offset
+    fn created_class(self: &Interpreter<'a>, offset: u16) -> Result<&'a str, String> {
+        let offset = offset as usize;
+        match self.code.get(offset..offset + 3) {
+            Some([0xbb, high, low]) => self.class_name(u16::from_be_bytes([*high, *low])),
+            _ => Err(format!(
+                "uninitialized({}) does not refer to a new instruction",
+                offset
+            )),
+        }
+    }
+}
+
+fn constant(ins: &Instruction) -> Result<u16, String> {
+    match ins.operand {
+        Operand::Constant(index) => Ok(index),
+        _ => Err(format!("{:?} has no constant operand", ins.opcode)),
+    }
+}
+
+fn field_type(descriptor: &str) -> Result<VerificationType, String> {
+    descriptor
+        .parse::<JavaType>()
+        .ok()
+        .and_then(|typ| VerificationType::from_java_type(&typ))
+        .ok_or_else(|| format!("invalid field descriptor {}", descriptor))
+}
+
+/// Component type of an array class such as `[I` or `[Ljava/lang/String;`
+fn array_component(name: &str) -> Option<VerificationType> {
+    let component = name.strip_prefix('[')?;
+    if component.starts_with('[') {
+        return Some(VerificationType::from_class_name(component));
+    }
+
+    field_type(component).ok()
+}
+
+/// Array class with the given component class
+fn array_of(name: &str) -> String {
+    if name.starts_with('[') {
+        format!("[{}", name)
+    } else {
+        format!("[L{};", name)
+    }
+}
+
+fn check_array(array: VerificationType, expected: &[&str]) -> Result<(), String> {
+    match &array {
+        Null => Ok(()),
+        VerificationType::Object(name) if expected.contains(&name.as_str()) => Ok(()),
+        _ => Err(format!(
+            "expected {} but found {}",
+            expected.join(" or "),
+            array
+        )),
+    }
+}
+
+fn push(state: &mut FrameState, typ: VerificationType) {
+    state.stack.push(typ);
+}
+
+fn push_all(state: &mut FrameState, types: &[VerificationType]) {
+    state.stack.extend(types.iter().cloned());
+}
+
+fn pop_any(state: &mut FrameState) -> Result<VerificationType, String> {
+    state
+        .stack
+        .pop()
+        .ok_or_else(|| "pop from an empty stack".to_string())
+}
+
+fn pop_category1(state: &mut FrameState) -> Result<VerificationType, String> {
+    let typ = pop_any(state)?;
+    if typ.is_category2() {
+        return Err(format!("expected a category 1 value but found {}", typ));
+    }
+
+    Ok(typ)
+}
+
+fn pop_reference(state: &mut FrameState) -> Result<VerificationType, String> {
+    let typ = pop_any(state)?;
+    if !typ.is_reference() {
+        return Err(format!("expected a reference but found {}", typ));
+    }
+
+    Ok(typ)
+}
+
+/// Sets a local, clearing any long or double whose second slot it overwrites
+fn set_local(state: &mut FrameState, index: usize, typ: VerificationType) {
+    let size = typ.size();
+    if state.locals.len() < index + size {
+        state.locals.resize(index + size, Top);
+    }
+    if index > 0 && state.locals[index - 1].is_category2() {
+        state.locals[index - 1] = Top;
+    }
+    if state.locals[index].is_category2() && size == 1 && index + 1 < state.locals.len() {
+        state.locals[index + 1] = Top;
+    }
+
+    state.locals[index] = typ;
+    if size == 2 {
+        state.locals[index + 1] = Top;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_util::class_with_method;
+
+    fn verify(class: &Class) -> Result<(), VerifyError> {
+        verify_class(class, &HashMap::new())
+    }
+
+    #[test]
+    fn test_valid_method() {
+        // iload_0, iconst_1, iadd, ireturn
+        let class = class_with_method("(I)I", 2, 1, &[0x1a, 0x04, 0x60, 0xac]);
+        assert_eq!(verify(&class), Ok(()));
+    }
+
+    #[test]
+    fn test_stack_underflow() {
+        // iconst_1, iadd, ireturn
+        let class = class_with_method("()I", 2, 0, &[0x04, 0x60, 0xac]);
+        let err = verify(&class).unwrap_err();
+        assert_eq!(err.method, "test()I");
+        assert_eq!(err.offset, 1);
+    }
+
+    #[test]
+    fn test_wrong_types() {
+        // aconst_null, ireturn
+        let class = class_with_method("()I", 1, 0, &[0x01, 0xac]);
+        assert_eq!(verify(&class).unwrap_err().offset, 1);
+
+        // iload_0 on a long argument
+        let class = class_with_method("(J)I", 1, 2, &[0x1a, 0xac]);
+        assert_eq!(verify(&class).unwrap_err().offset, 0);
+    }
+
+    #[test]
+    fn test_limits() {
+        // iconst_1, iconst_1, iadd, ireturn with room for one value
+        let class = class_with_method("()I", 1, 0, &[0x04, 0x04, 0x60, 0xac]);
+        assert_eq!(verify(&class).unwrap_err().offset, 1);
+
+        // istore_1 with a single local
+        let class = class_with_method("(I)V", 1, 1, &[0x04, 0x3c, 0xb1]);
+        assert_eq!(verify(&class).unwrap_err().offset, 1);
+    }
+
+    #[test]
+    fn test_branches() {
+        // iload_0, ifeq 4, iconst_1, ireturn with no stack map frame at 4
+        let class = class_with_method("(I)I", 1, 1, &[0x1a, 0x99, 0x00, 0x03, 0x04, 0xac]);
+        assert!(verify(&class)
+            .unwrap_err()
+            .message
+            .contains("no stack map frame"));
+
+        // goto into the middle of an instruction
+        let class = class_with_method("()V", 1, 0, &[0xa7, 0x00, 0x01, 0xb1]);
+        assert!(verify(&class)
+            .unwrap_err()
+            .message
+            .contains("invalid branch target"));
+
+        // iconst_1 falls off the end of the code
+        let class = class_with_method("()V", 1, 0, &[0x04]);
+        assert!(verify(&class).is_err());
+    }
+
+    #[test]
+    fn test_malformed_member_refs() {
+        // A Fieldref and a Methodref whose class and name_and_type entries
+        // are both UTF8 constants
+        let const_pool = ConstPool {
+            consts: vec![
+                JvmConst::UTF8("x".to_string()),
+                JvmConst::FieldRef(1, 1),
+                JvmConst::MethodRef(1, 1),
+            ],
+        };
+
+        // getstatic #2, return
+        let mut class = class_with_method("()V", 1, 0, &[0xb2, 0x00, 0x02, 0xb1]);
+        class.const_pool = const_pool.clone();
+        assert!(verify(&class)
+            .unwrap_err()
+            .message
+            .contains("invalid member reference 2"));
+
+        // invokestatic #3, return
+        let mut class = class_with_method("()V", 1, 0, &[0xb8, 0x00, 0x03, 0xb1]);
+        class.const_pool = const_pool;
+        assert!(verify(&class)
+            .unwrap_err()
+            .message
+            .contains("invalid member reference 3"));
+    }
+}
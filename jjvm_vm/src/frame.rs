@@ -1,6 +1,9 @@
 use core::panic;
 
-use jjvm_loader::class::Class;
+use jjvm_loader::{
+    attribute::{Code, ExceptionHandler},
+    class::Class,
+};
 
 use crate::jvm_val::JvmVal;
 use logging_timer::time;
@@ -10,8 +13,12 @@ pub struct Frame {
     pub id: i32,
     pub ip: u32,
     pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionHandler>,
     pub locals: Vec<JvmVal>,
     pub stack: Vec<JvmVal>,
+    /// Set by a `wide` prefix so that the next instruction reads 16-bit
+    /// operands
+    pub wide: bool,
 }
 
 static mut FRAME_ID: i32 = 0;
@@ -31,27 +38,33 @@ impl Frame {
             .find(|attr| attr.name == "Code" && attr.data.len() > 8)
             .unwrap();
 
+        let code = Code::from_attribute(code_attribute, &class.const_pool)?;
+        Ok(Frame::with_code(code, args))
+    }
+
+    /// A frame running code that is already decoded, with the arguments in
+    /// its first locals
+    pub fn with_code(code: Code, args: Vec<JvmVal>) -> Frame {
         let mut frame = Frame {
             id: unsafe { FRAME_ID },
-            code: code_attribute.data[8..].to_vec(),
-            locals: vec![
-                JvmVal::Null,
-                JvmVal::Null,
-                JvmVal::Null,
-                JvmVal::Null,
-                JvmVal::Null,
-            ],
+            code: code.code,
+            exception_table: code.exception_table,
+            locals: vec![JvmVal::Null; (code.max_locals as usize).max(5)],
             ip: 0,
             stack: vec![],
+            wide: false,
         };
 
         unsafe { FRAME_ID += 1 };
 
-        for (i, arg) in args.iter().enumerate() {
-            frame.locals[i] = arg.clone();
+        let mut index = 0;
+        for arg in args {
+            let width = if arg.is_category_2() { 2 } else { 1 };
+            frame.store(index, arg);
+            index += width;
         }
 
-        Ok(frame)
+        frame
     }
 
     pub fn pop_int(self: &mut Frame) -> i32 {
@@ -81,10 +94,81 @@ impl Frame {
         panic!("popped value was not double, got {:?}", val);
     }
 
+    pub fn pop_long(self: &mut Frame) -> i64 {
+        let val = self.stack.pop().unwrap();
+        if let JvmVal::Long(x) = val {
+            return x;
+        }
+
+        panic!("popped value was not long, got {:?}", val);
+    }
+
+    /// Stores a local, growing the locals to fit. A long or double also
+    /// takes the slot after it, which is left empty.
+    pub fn store(self: &mut Frame, index: usize, val: JvmVal) {
+        let width = if val.is_category_2() { 2 } else { 1 };
+        if self.locals.len() < index + width {
+            self.locals.resize(index + width, JvmVal::Null);
+        }
+        if width == 2 {
+            self.locals[index + 1] = JvmVal::Null;
+        }
+        self.locals[index] = val;
+    }
+
     pub fn push(self: &mut Frame, val: JvmVal) {
         self.stack.push(val);
     }
 
+    /// Copies the values making up the top `words` words of the stack and
+    /// inserts them beneath the `below` words under them, as the `dup`
+    /// family of instructions does
+    pub fn dup_words(self: &mut Frame, words: usize, below: usize) {
+        let count = self.entries(0, words);
+        let skipped = self.entries(count, below);
+        let top = self.stack.len() - count;
+        let copy = self.stack[top..].to_vec();
+        let at = top - skipped;
+        self.stack.splice(at..at, copy);
+    }
+
+    /// Pops the values making up the top `words` words of the stack
+    pub fn pop_words(self: &mut Frame, words: usize) {
+        let count = self.entries(0, words);
+        self.stack.truncate(self.stack.len() - count);
+    }
+
+    /// The number of stack entries holding `words` words, starting `skip`
+    /// entries down from the top. Longs and doubles are one entry but count
+    /// as two words.
+    fn entries(self: &Frame, skip: usize, words: usize) -> usize {
+        let mut count = 0;
+        let mut taken = 0;
+        while taken < words {
+            let val = &self.stack[self.stack.len() - 1 - skip - count];
+            taken += if val.is_category_2() { 2 } else { 1 };
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Reads the signed 16-bit offset of a branch at `ip` and jumps by it
+    /// when the branch is taken
+    pub fn branch(self: &mut Frame, taken: bool) {
+        let origin_ip = self.ip;
+        let offset = self.read_two_byte_index() as i16;
+        if taken {
+            self.jump(origin_ip, offset as i32);
+        }
+    }
+
+    /// Jumps by an offset from the instruction at `origin_ip`. This stops one
+    /// short, since `VM::exec` steps past every instruction it runs.
+    pub fn jump(self: &mut Frame, origin_ip: u32, offset: i32) {
+        self.ip = (origin_ip as i64 + offset as i64 - 1) as u32;
+    }
+
     pub fn read_two_byte_index(self: &mut Frame) -> u16 {
         self.ip += 2;
         ((self.code[(self.ip - 1) as usize] as u16) << 8) | (self.code[(self.ip) as usize] as u16)
@@ -99,6 +183,16 @@ impl Frame {
         )
     }
 
+    /// Reads the local variable index of a load, store, `iinc` or `ret`,
+    /// which is two bytes after a `wide` prefix
+    pub fn read_local_index(self: &mut Frame) -> usize {
+        if std::mem::take(&mut self.wide) {
+            self.read_two_byte_index() as usize
+        } else {
+            self.read_one_byte_index() as usize
+        }
+    }
+
     pub fn read_one_byte_index(self: &mut Frame) -> u8 {
         self.ip += 1;
         self.code[self.ip as usize]
@@ -45,26 +45,20 @@ impl Heap {
 
     #[time]
     pub fn gc(self: &mut Heap, references: HashMap<i32, Vec<u32>>) -> i32 {
-        let references: Vec<_> = references.values().flatten().collect();
-        let mut all_refs = vec![];
-        for refer in references {
-            all_refs.push(*refer);
-            if let JvmVal::Class(_, vals) = self.heap[*refer as usize].clone().0 {
-                for val in vals.values() {
-                    if let JvmVal::Reference(_) = val {
-                        all_refs.append(&mut self.check_class(val.clone()));
-                    }
-                }
+        let mut reachable = vec![false; self.heap.len()];
+        let mut pending: Vec<u32> = references.values().flatten().copied().collect();
+        while let Some(refer) = pending.pop() {
+            if reachable[refer as usize] {
+                continue;
             }
+            reachable[refer as usize] = true;
+            pending.extend(self.references_in(refer));
         }
 
         let mut claimed = 0;
-        for i in 0..self.heap.len() {
-            if !self.heap[i].1 {
-                continue;
-            }
-            if !all_refs.contains(&(i as u32)) {
-                self.heap[i].1 = false;
+        for (block, reachable) in self.heap.iter_mut().zip(reachable) {
+            if block.1 && !reachable {
+                block.1 = false;
                 claimed += 1;
             }
         }
@@ -72,13 +66,20 @@ impl Heap {
         claimed
     }
 
-    fn check_class(self: &Heap, val: JvmVal) -> Vec<u32> {
-        let mut refs = vec![];
-        if let JvmVal::Reference(refer) = val {
-            refs.push(refer);
-            refs.append(&mut self.check_class(self.heap[refer as usize].clone().0));
-        }
+    /// The references held in the fields of an object or the elements of an
+    /// array
+    fn references_in(self: &Heap, refer: u32) -> Vec<u32> {
+        let vals: Vec<&JvmVal> = match &self.heap[refer as usize].0 {
+            JvmVal::Class(_, vals) | JvmVal::BuiltinClass(_, vals) => vals.values().collect(),
+            JvmVal::Array(_, vals) => vals.iter().collect(),
+            _ => vec![],
+        };
 
-        refs
+        vals.into_iter()
+            .filter_map(|val| match val {
+                JvmVal::Reference(refer) => Some(*refer),
+                _ => None,
+            })
+            .collect()
     }
 }
@@ -14,5 +14,146 @@ pub enum JvmVal {
     Double(f64),
     Null,
     Boolean(bool),
-    Array(Vec<JvmVal>),
+    /// A reference array, with the type of its components as a class name
+    /// or, for arrays of arrays, an array descriptor such as `[I`
+    Array(String, Vec<JvmVal>),
+    PrimitiveArray(PrimitiveArray),
+    /// The address of the instruction after a `jsr`, which `ret` returns to
+    ReturnAddress(u32),
+}
+
+impl JvmVal {
+    /// The value a field of this descriptor holds before it is assigned
+    pub fn default_for(descriptor: &str) -> JvmVal {
+        match descriptor.chars().next() {
+            Some('J') => JvmVal::Long(0),
+            Some('F') => JvmVal::Float(0.0),
+            Some('D') => JvmVal::Double(0.0),
+            Some('L') | Some('[') => JvmVal::Null,
+            _ => JvmVal::Int(0),
+        }
+    }
+
+    /// Longs and doubles take up two local variable slots and count as two
+    /// words to the stack manipulation instructions
+    pub fn is_category_2(self: &JvmVal) -> bool {
+        matches!(self, JvmVal::Long(_) | JvmVal::Double(_))
+    }
+}
+
+/// An array of one of the primitive types, holding its elements unboxed
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrimitiveArray {
+    Boolean(Vec<bool>),
+    Byte(Vec<i8>),
+    Char(Vec<u16>),
+    Short(Vec<i16>),
+    Int(Vec<i32>),
+    Long(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl PrimitiveArray {
+    /// A zeroed array of the type `newarray` gives by its `atype` operand,
+    /// or `None` if that names no primitive type
+    pub fn new(atype: u8, length: usize) -> Option<PrimitiveArray> {
+        match atype {
+            4 => Some(PrimitiveArray::Boolean(vec![false; length])),
+            5 => Some(PrimitiveArray::Char(vec![0; length])),
+            6 => Some(PrimitiveArray::Float(vec![0.0; length])),
+            7 => Some(PrimitiveArray::Double(vec![0.0; length])),
+            8 => Some(PrimitiveArray::Byte(vec![0; length])),
+            9 => Some(PrimitiveArray::Short(vec![0; length])),
+            10 => Some(PrimitiveArray::Int(vec![0; length])),
+            11 => Some(PrimitiveArray::Long(vec![0; length])),
+            _ => None,
+        }
+    }
+
+    /// A zeroed array of the primitive type with this field descriptor, such
+    /// as `I` for int
+    pub fn with_descriptor(descriptor: &str, length: usize) -> Option<PrimitiveArray> {
+        let atype = match descriptor {
+            "Z" => 4,
+            "C" => 5,
+            "F" => 6,
+            "D" => 7,
+            "B" => 8,
+            "S" => 9,
+            "I" => 10,
+            "J" => 11,
+            _ => return None,
+        };
+        PrimitiveArray::new(atype, length)
+    }
+
+    /// The field descriptor of the element type
+    pub fn descriptor(self: &PrimitiveArray) -> &'static str {
+        match self {
+            PrimitiveArray::Boolean(_) => "Z",
+            PrimitiveArray::Byte(_) => "B",
+            PrimitiveArray::Char(_) => "C",
+            PrimitiveArray::Short(_) => "S",
+            PrimitiveArray::Int(_) => "I",
+            PrimitiveArray::Long(_) => "J",
+            PrimitiveArray::Float(_) => "F",
+            PrimitiveArray::Double(_) => "D",
+        }
+    }
+
+    pub fn len(self: &PrimitiveArray) -> usize {
+        match self {
+            PrimitiveArray::Boolean(vals) => vals.len(),
+            PrimitiveArray::Byte(vals) => vals.len(),
+            PrimitiveArray::Char(vals) => vals.len(),
+            PrimitiveArray::Short(vals) => vals.len(),
+            PrimitiveArray::Int(vals) => vals.len(),
+            PrimitiveArray::Long(vals) => vals.len(),
+            PrimitiveArray::Float(vals) => vals.len(),
+            PrimitiveArray::Double(vals) => vals.len(),
+        }
+    }
+
+    pub fn is_empty(self: &PrimitiveArray) -> bool {
+        self.len() == 0
+    }
+
+    /// An element as it is pushed on the stack, where the types narrower than
+    /// int are widened to it
+    pub fn get(self: &PrimitiveArray, index: usize) -> JvmVal {
+        match self {
+            PrimitiveArray::Boolean(vals) => JvmVal::Int(vals[index] as i32),
+            PrimitiveArray::Byte(vals) => JvmVal::Int(vals[index] as i32),
+            PrimitiveArray::Char(vals) => JvmVal::Int(vals[index] as i32),
+            PrimitiveArray::Short(vals) => JvmVal::Int(vals[index] as i32),
+            PrimitiveArray::Int(vals) => JvmVal::Int(vals[index]),
+            PrimitiveArray::Long(vals) => JvmVal::Long(vals[index]),
+            PrimitiveArray::Float(vals) => JvmVal::Float(vals[index]),
+            PrimitiveArray::Double(vals) => JvmVal::Double(vals[index]),
+        }
+    }
+
+    /// Stores a value from the stack, truncating ints to the element type
+    pub fn set(self: &mut PrimitiveArray, index: usize, val: JvmVal) -> Result<(), String> {
+        match (self, val) {
+            (PrimitiveArray::Boolean(vals), JvmVal::Int(v)) => vals[index] = v & 1 != 0,
+            (PrimitiveArray::Byte(vals), JvmVal::Int(v)) => vals[index] = v as i8,
+            (PrimitiveArray::Char(vals), JvmVal::Int(v)) => vals[index] = v as u16,
+            (PrimitiveArray::Short(vals), JvmVal::Int(v)) => vals[index] = v as i16,
+            (PrimitiveArray::Int(vals), JvmVal::Int(v)) => vals[index] = v,
+            (PrimitiveArray::Long(vals), JvmVal::Long(v)) => vals[index] = v,
+            (PrimitiveArray::Float(vals), JvmVal::Float(v)) => vals[index] = v,
+            (PrimitiveArray::Double(vals), JvmVal::Double(v)) => vals[index] = v,
+            (array, val) => {
+                return Err(format!(
+                    "cannot store {:?} in an array of {}",
+                    val,
+                    array.descriptor()
+                ))
+            }
+        }
+
+        Ok(())
+    }
 }
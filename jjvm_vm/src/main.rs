@@ -13,11 +13,11 @@ fn main() {
 
     let mut loader = ClassLoader::new(Cursor::new(fs::read("../Test.class").unwrap()));
 
-    let class = loader.load();
+    let class = loader.load().unwrap();
 
     let mut add_loader = ClassLoader::new(Cursor::new(fs::read("../OtherTest.class").unwrap()));
 
-    let add_class = add_loader.load();
+    let add_class = add_loader.load().unwrap();
 
     let mut vm = VM {
         heap: Heap { heap: vec![] },
@@ -26,6 +26,9 @@ fn main() {
         heap_last_gc_size: 4,
         should_gc: false,
         debug: true,
+        exception: None,
+        code: HashMap::new(),
+        constants: HashMap::new(),
     };
 
     for entry in WalkDir::new("../std")
@@ -40,7 +43,7 @@ fn main() {
         println!("{}", f_name);
         let mut loader = ClassLoader::new(Cursor::new(fs::read(f_name).unwrap()));
 
-        let class = loader.load();
+        let class = loader.load().unwrap();
         let name = class.name.clone();
         vm.classes.insert(name, class.clone());
     }
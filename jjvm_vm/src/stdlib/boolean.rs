@@ -22,14 +22,21 @@ impl BuiltinClass for BooleanClass {
         }]
     }
 
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
-        match method.as_str() {
-            "<init>" => BooleanClass::init,
-            "valueOf" => BooleanClass::value_of,
-            "booleanValue" => BooleanClass::boolean_value,
-            _ => panic!("method not found {}", method),
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "<init>" => Some(BooleanClass::init),
+            "valueOf" => Some(BooleanClass::value_of),
+            "booleanValue" => Some(BooleanClass::boolean_value),
+            _ => None,
         }
     }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!(
+            (method, descriptor),
+            ("valueOf", "(Z)Ljava/lang/Boolean;") | ("booleanValue", "()Z")
+        )
+    }
 }
 
 impl BooleanClass {
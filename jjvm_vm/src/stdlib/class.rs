@@ -5,5 +5,15 @@ use crate::{jvm_val::JvmVal, vm::VM};
 pub trait BuiltinClass {
     fn get_class_name(self) -> String;
     fn get_fields(&self) -> Vec<Field>;
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal;
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal>;
+    /// Whether the method found by name is implemented for this descriptor,
+    /// as it may not handle every overload sharing the name
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool;
+
+    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
+        match self.find_method(&method) {
+            Some(method) => method,
+            None => panic!("method not found {}", method),
+        }
+    }
 }
@@ -22,12 +22,16 @@ impl BuiltinClass for FileClass {
         }]
     }
 
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
-        match method.as_str() {
-            "<init>" => FileClass::init,
-            _ => panic!("method not found"),
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "<init>" => Some(FileClass::init),
+            _ => None,
         }
     }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!((method, descriptor), ("<init>", "(Ljava/lang/String;)V"))
+    }
 }
 
 impl FileClass {
@@ -22,15 +22,25 @@ impl BuiltinClass for IntegerClass {
         }]
     }
 
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
-        match method.as_str() {
-            "<init>" => IntegerClass::init,
-            "parseInt" => IntegerClass::parse_int,
-            "valueOf" => IntegerClass::value_of,
-            "intValue" => IntegerClass::int_value,
-            _ => panic!("method not found {}", method),
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "<init>" => Some(IntegerClass::init),
+            "parseInt" => Some(IntegerClass::parse_int),
+            "valueOf" => Some(IntegerClass::value_of),
+            "intValue" => Some(IntegerClass::int_value),
+            _ => None,
         }
     }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!(
+            (method, descriptor),
+            ("<init>", "(I)V")
+                | ("parseInt", "(Ljava/lang/String;)I")
+                | ("valueOf", "(Ljava/lang/String;)Ljava/lang/Integer;")
+                | ("intValue", "()I")
+        )
+    }
 }
 
 impl IntegerClass {
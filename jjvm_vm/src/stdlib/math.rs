@@ -15,15 +15,31 @@ impl BuiltinClass for MathClass {
         vec![]
     }
 
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
-        match method.as_str() {
-            "abs" => MathClass::abs,
-            "ceil" => MathClass::ceil,
-            "floor" => MathClass::floor,
-            "min" => MathClass::min,
-            _ => panic!("method not found {}", method),
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "abs" => Some(MathClass::abs),
+            "ceil" => Some(MathClass::ceil),
+            "floor" => Some(MathClass::floor),
+            "min" => Some(MathClass::min),
+            _ => None,
         }
     }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!(
+            (method, descriptor),
+            ("abs", "(I)I")
+                | ("abs", "(J)J")
+                | ("abs", "(F)F")
+                | ("abs", "(D)D")
+                | ("ceil", "(D)D")
+                | ("floor", "(D)D")
+                | ("min", "(II)I")
+                | ("min", "(JJ)J")
+                | ("min", "(FF)F")
+                | ("min", "(DD)D")
+        )
+    }
 }
 
 impl MathClass {
@@ -31,8 +47,8 @@ impl MathClass {
         let arg = args[0].clone();
 
         match arg {
-            JvmVal::Int(i) => JvmVal::Int(i.abs()),
-            JvmVal::Long(l) => JvmVal::Long(l.abs()),
+            JvmVal::Int(i) => JvmVal::Int(i.wrapping_abs()),
+            JvmVal::Long(l) => JvmVal::Long(l.wrapping_abs()),
             JvmVal::Float(f) => JvmVal::Float(f.abs()),
             JvmVal::Double(d) => JvmVal::Double(d.abs()),
             _ => panic!("unsupported type"),
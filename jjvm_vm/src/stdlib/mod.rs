@@ -8,15 +8,54 @@ pub mod integer;
 pub mod math;
 pub mod scanner;
 pub mod string;
+pub mod throwable;
 
 pub fn get_builtins(name: String) -> Box<dyn BuiltinClass> {
-    match name.as_str() {
-        "java/io/File" => Box::new(FileClass {}),
-        "java/util/Scanner" => Box::new(scanner::ScannerClass {}),
-        "java/lang/Boolean" => Box::new(boolean::BooleanClass {}),
-        "java/lang/Integer" => Box::new(integer::IntegerClass {}),
-        "java/lang/String" => Box::new(string::StringClass {}),
-        "java/lang/Math" => Box::new(math::MathClass {}),
-        _ => panic!("Builtin class not found: {}", name),
+    match find_builtin(&name) {
+        Some(builtin) => builtin,
+        None => panic!("Builtin class not found: {}", name),
+    }
+}
+
+pub fn find_builtin(name: &str) -> Option<Box<dyn BuiltinClass>> {
+    match name {
+        "java/io/File" => Some(Box::new(FileClass {})),
+        "java/util/Scanner" => Some(Box::new(scanner::ScannerClass {})),
+        "java/lang/Boolean" => Some(Box::new(boolean::BooleanClass {})),
+        "java/lang/Integer" => Some(Box::new(integer::IntegerClass {})),
+        "java/lang/String" => Some(Box::new(string::StringClass {})),
+        "java/lang/Math" => Some(Box::new(math::MathClass {})),
+        _ if throwable::superclass(name).is_some() => Some(Box::new(throwable::ThrowableClass {
+            name: name.to_string(),
+        })),
+        _ => None,
+    }
+}
+
+/// Whether the VM can provide a class without loading it, either as one of
+/// the builtins above or by handling it in the interpreter
+pub fn is_builtin(name: &str) -> bool {
+    find_builtin(name).is_some()
+        || matches!(
+            name,
+            "java/lang/Object" | "java/lang/System" | "java/io/PrintStream"
+        )
+}
+
+/// Whether the VM can call a method of a class it provides itself, with
+/// this descriptor. `PrintStream.println` prints its argument as a string,
+/// int, long, float or double, and the `Object` constructor does nothing.
+pub fn has_method(class: &str, method: &str, descriptor: &str) -> bool {
+    match class {
+        "java/io/PrintStream" => {
+            method == "println"
+                && matches!(
+                    descriptor,
+                    "(Ljava/lang/String;)V" | "(I)V" | "(J)V" | "(F)V" | "(D)V"
+                )
+        }
+        "java/lang/Object" => method == "<init>" && descriptor == "()V",
+        _ => matches!(find_builtin(class), Some(builtin)
+            if builtin.find_method(method).is_some() && builtin.has_overload(method, descriptor)),
     }
 }
@@ -22,15 +22,25 @@ impl BuiltinClass for ScannerClass {
         }]
     }
 
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
-        match method.as_str() {
-            "<init>" => ScannerClass::init,
-            "hasNextLine" => ScannerClass::has_next_line,
-            "nextLine" => ScannerClass::next_line,
-            "close" => ScannerClass::close,
-            _ => panic!("method not found {}", method),
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "<init>" => Some(ScannerClass::init),
+            "hasNextLine" => Some(ScannerClass::has_next_line),
+            "nextLine" => Some(ScannerClass::next_line),
+            "close" => Some(ScannerClass::close),
+            _ => None,
         }
     }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!(
+            (method, descriptor),
+            ("<init>", "(Ljava/io/File;)V")
+                | ("hasNextLine", "()Z")
+                | ("nextLine", "()Ljava/lang/String;")
+                | ("close", "()V")
+        )
+    }
 }
 
 impl ScannerClass {
@@ -114,7 +124,7 @@ impl ScannerClass {
             _ => panic!("invalid argument"),
         };
 
-        let content = match cloned.get(&"content".to_string()) {
+        let content = match cloned.get("content") {
             Some(JvmVal::String(s)) => s,
             _ => panic!("invalid argument"),
         };
@@ -22,15 +22,24 @@ impl BuiltinClass for StringClass {
         }]
     }
 
-    fn get_method(&self, method: String) -> fn(&mut VM, Vec<JvmVal>) -> JvmVal {
-        match method.as_str() {
-            "<init>" => StringClass::init,
-            "split" => StringClass::split,
-            "hashCode" => StringClass::hashcode,
-            "equals" => StringClass::equals,
-            _ => panic!("method not found {}", method),
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "<init>" => Some(StringClass::init),
+            "split" => Some(StringClass::split),
+            "hashCode" => Some(StringClass::hashcode),
+            "equals" => Some(StringClass::equals),
+            _ => None,
         }
     }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!(
+            (method, descriptor),
+            ("split", "(Ljava/lang/String;)[Ljava/lang/String;")
+                | ("hashCode", "()I")
+                | ("equals", "(Ljava/lang/Object;)Z")
+        )
+    }
 }
 
 impl StringClass {
@@ -56,13 +65,9 @@ impl StringClass {
             .map(JvmVal::String)
             .collect::<Vec<JvmVal>>();
 
-        let mut ptrs = vec![];
-        for str in result {
-            let ptr = vm.heap.alloc(str);
-            ptrs.push(JvmVal::Reference(ptr));
-        }
-
-        let ptr = vm.heap.alloc(JvmVal::Array(ptrs));
+        let ptr = vm
+            .heap
+            .alloc(JvmVal::Array("java/lang/String".to_string(), result));
 
         JvmVal::Reference(ptr)
     }
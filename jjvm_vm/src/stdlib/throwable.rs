@@ -0,0 +1,122 @@
+use jjvm_loader::class::Field;
+
+use crate::{jvm_val::JvmVal, vm::VM};
+
+use super::class::BuiltinClass;
+
+/// Throwable and the exceptions the VM throws itself, which all share the
+/// same fields and methods
+pub struct ThrowableClass {
+    pub name: String,
+}
+
+impl BuiltinClass for ThrowableClass {
+    fn get_class_name(self) -> String {
+        self.name
+    }
+
+    fn get_fields(&self) -> Vec<Field> {
+        vec![Field {
+            flags: 0x0002,
+            name: "message".to_string(),
+            descriptor: "Ljava/lang/String;".to_string(),
+            attributes: vec![],
+        }]
+    }
+
+    fn find_method(&self, method: &str) -> Option<fn(&mut VM, Vec<JvmVal>) -> JvmVal> {
+        match method {
+            "<init>" => Some(ThrowableClass::init),
+            "getMessage" => Some(ThrowableClass::get_message),
+            "toString" => Some(ThrowableClass::to_string),
+            _ => None,
+        }
+    }
+
+    fn has_overload(&self, method: &str, descriptor: &str) -> bool {
+        matches!(
+            (method, descriptor),
+            ("<init>", "()V")
+                | ("<init>", "(Ljava/lang/String;)V")
+                | ("getMessage", "()Ljava/lang/String;")
+                | ("toString", "()Ljava/lang/String;")
+        )
+    }
+}
+
+impl ThrowableClass {
+    fn init(vm: &mut VM, vals: Vec<JvmVal>) -> JvmVal {
+        let message = vals.get(1).cloned().unwrap_or(JvmVal::Null);
+        if let JvmVal::Reference(ptr) = vals[0] {
+            if let JvmVal::Class(_, fields) = vm.heap.fetch_mut(ptr) {
+                fields.insert("message".to_string(), message);
+            }
+        }
+
+        JvmVal::Null
+    }
+
+    fn get_message(vm: &mut VM, vals: Vec<JvmVal>) -> JvmVal {
+        match vals[0] {
+            JvmVal::Reference(ptr) => message(vm, ptr),
+            _ => panic!("getMessage expects a reference"),
+        }
+    }
+
+    fn to_string(vm: &mut VM, vals: Vec<JvmVal>) -> JvmVal {
+        match vals[0] {
+            JvmVal::Reference(ptr) => JvmVal::String(describe(vm, ptr)),
+            _ => panic!("toString expects a reference"),
+        }
+    }
+}
+
+/// The superclass of each exception class the VM provides
+pub fn superclass(name: &str) -> Option<&'static str> {
+    match name {
+        "java/lang/Throwable" => Some("java/lang/Object"),
+        "java/lang/Exception" | "java/lang/Error" => Some("java/lang/Throwable"),
+        "java/lang/RuntimeException" => Some("java/lang/Exception"),
+        "java/lang/LinkageError" => Some("java/lang/Error"),
+        "java/lang/VerifyError" => Some("java/lang/LinkageError"),
+        "java/lang/ArithmeticException"
+        | "java/lang/ArrayStoreException"
+        | "java/lang/ClassCastException"
+        | "java/lang/IllegalArgumentException"
+        | "java/lang/IllegalStateException"
+        | "java/lang/IndexOutOfBoundsException"
+        | "java/lang/NegativeArraySizeException"
+        | "java/lang/NullPointerException"
+        | "java/lang/UnsupportedOperationException" => Some("java/lang/RuntimeException"),
+        "java/lang/ArrayIndexOutOfBoundsException"
+        | "java/lang/StringIndexOutOfBoundsException" => {
+            Some("java/lang/IndexOutOfBoundsException")
+        }
+        "java/lang/NumberFormatException" => Some("java/lang/IllegalArgumentException"),
+        _ => None,
+    }
+}
+
+fn message(vm: &mut VM, ptr: u32) -> JvmVal {
+    match vm.heap.fetch(ptr) {
+        JvmVal::Class(_, fields) => match fields.get("message") {
+            Some(JvmVal::String(message)) => JvmVal::String(message.clone()),
+            _ => JvmVal::Null,
+        },
+        val => panic!("not a throwable, got {:?}", val),
+    }
+}
+
+/// An exception as Throwable.toString shows it, such as
+/// `java.lang.ArithmeticException: / by zero`
+pub fn describe(vm: &mut VM, ptr: u32) -> String {
+    let name = match vm.heap.fetch(ptr) {
+        JvmVal::Class(name, _) => name.replace('/', "."),
+        val => panic!("not a throwable, got {:?}", val),
+    };
+
+    match message(vm, ptr) {
+        JvmVal::String(message) => format!("{}: {}", name, message),
+        _ => name,
+    }
+}
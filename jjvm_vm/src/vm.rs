@@ -1,8 +1,21 @@
 use std::{collections::HashMap, time::Instant};
 
-use jjvm_loader::{class::Class, const_pool::Const, flags::MethodFlag, opcode::Opcode, signature};
-
-use crate::{frame::Frame, heap::Heap, jvm_val::JvmVal, stdlib};
+use jjvm_loader::{
+    attribute::Code,
+    class::Class,
+    const_pool::Const,
+    flags::MethodFlag,
+    opcode::Opcode,
+    signature,
+    verifier::{self, VerifyError},
+};
+
+use crate::{
+    frame::Frame,
+    heap::Heap,
+    jvm_val::{JvmVal, PrimitiveArray},
+    stdlib,
+};
 use logging_timer::{time, timer};
 
 pub struct VM {
@@ -14,12 +27,23 @@ pub struct VM {
     pub should_gc: bool,
 
     pub debug: bool,
+
+    /// The throwable being thrown, until a handler catches it
+    pub exception: Option<u32>,
+
+    /// Code attributes decoded ahead of time, such as by an archive, by class
+    /// and then in the order of its methods
+    pub code: HashMap<String, Vec<Option<Code>>>,
+    /// Constants resolved ahead of time, by class and then indexed like its
+    /// constant pool from 1
+    pub constants: HashMap<String, Vec<Option<Const>>>,
 }
 
 impl VM {
     #[time]
     pub fn exec(self: &mut VM, class: &Class, frame: &mut Frame) -> JvmVal {
         while frame.ip < frame.code.len() as u32 {
+            let pc = frame.ip;
             let op = frame.code[frame.ip as usize];
             self.debug(
                 frame.id,
@@ -65,23 +89,45 @@ impl VM {
                 Opcode::IConst4 => frame.stack.push(JvmVal::Int(4)),
                 Opcode::IConst5 => frame.stack.push(JvmVal::Int(5)),
                 Opcode::FConst0 => frame.stack.push(JvmVal::Float(0.0)),
+                Opcode::FConst1 => frame.stack.push(JvmVal::Float(1.0)),
+                Opcode::FConst2 => frame.stack.push(JvmVal::Float(2.0)),
+                Opcode::LConst0 => frame.stack.push(JvmVal::Long(0)),
+                Opcode::LConst1 => frame.stack.push(JvmVal::Long(1)),
+                Opcode::DConst0 => frame.stack.push(JvmVal::Double(0.0)),
+                Opcode::DConst1 => frame.stack.push(JvmVal::Double(1.0)),
                 Opcode::AConstNull => frame.stack.push(JvmVal::Null),
                 Opcode::ILoad => {
-                    let index = frame.read_one_byte_index();
-                    frame.stack.push(frame.locals[index as usize].clone());
+                    let index = frame.read_local_index();
+                    frame.stack.push(frame.locals[index].clone());
                 }
                 Opcode::ILoad0 => frame.stack.push(frame.locals[0].clone()),
                 Opcode::ILoad1 => frame.stack.push(frame.locals[1].clone()),
                 Opcode::ILoad2 => frame.stack.push(frame.locals[2].clone()),
                 Opcode::ILoad3 => frame.stack.push(frame.locals[3].clone()),
                 Opcode::FLoad => {
-                    let index = frame.read_one_byte_index();
-                    frame.stack.push(frame.locals[index as usize].clone());
+                    let index = frame.read_local_index();
+                    frame.stack.push(frame.locals[index].clone());
                 }
                 Opcode::FLoad0 => frame.stack.push(frame.locals[0].clone()),
                 Opcode::FLoad1 => frame.stack.push(frame.locals[1].clone()),
                 Opcode::FLoad2 => frame.stack.push(frame.locals[2].clone()),
                 Opcode::FLoad3 => frame.stack.push(frame.locals[3].clone()),
+                Opcode::LLoad => {
+                    let index = frame.read_local_index();
+                    frame.stack.push(frame.locals[index].clone());
+                }
+                Opcode::LLoad0 => frame.stack.push(frame.locals[0].clone()),
+                Opcode::LLoad1 => frame.stack.push(frame.locals[1].clone()),
+                Opcode::LLoad2 => frame.stack.push(frame.locals[2].clone()),
+                Opcode::LLoad3 => frame.stack.push(frame.locals[3].clone()),
+                Opcode::DLoad => {
+                    let index = frame.read_local_index();
+                    frame.stack.push(frame.locals[index].clone());
+                }
+                Opcode::DLoad0 => frame.stack.push(frame.locals[0].clone()),
+                Opcode::DLoad1 => frame.stack.push(frame.locals[1].clone()),
+                Opcode::DLoad2 => frame.stack.push(frame.locals[2].clone()),
+                Opcode::DLoad3 => frame.stack.push(frame.locals[3].clone()),
                 Opcode::ALoad0 => {
                     let refer = frame.locals[0].clone();
                     frame.stack.push(refer)
@@ -99,29 +145,89 @@ impl VM {
                     frame.stack.push(refer)
                 }
                 Opcode::ALoad => {
-                    let index = frame.read_one_byte_index();
-                    frame.stack.push(frame.locals[index as usize].clone());
+                    let index = frame.read_local_index();
+                    frame.stack.push(frame.locals[index].clone());
                 }
                 Opcode::AaLoad => {
                     let index = frame.pop_int();
-                    let arrayref = match frame.stack.pop().unwrap() {
-                        JvmVal::Reference(x) => x,
-                        _ => panic!("ALoad: Expected reference"),
-                    };
+                    let array = frame.stack.pop().unwrap();
+                    if let Some((ptr, index)) = self.array_element(array, index) {
+                        match self.heap.fetch(ptr) {
+                            JvmVal::Array(_, vals) => frame.push(vals[index].clone()),
+                            _ => self.throw_wrong_array(Opcode::AaLoad),
+                        }
+                    }
+                }
+                Opcode::AaStore => {
+                    let value = frame.stack.pop().unwrap();
+                    let index = frame.pop_int();
+                    let array = frame.stack.pop().unwrap();
+                    if let Some((ptr, index)) = self.array_element(array, index) {
+                        let component = match self.heap.fetch(ptr) {
+                            JvmVal::Array(component, _) => Some(component.clone()),
+                            _ => None,
+                        };
+                        let stored = match (&component, self.type_of(&value)) {
+                            (Some(component), Some(class)) => self.is_assignable(&class, component),
+                            _ => true,
+                        };
 
-                    let array = match self.heap.fetch(arrayref) {
-                        JvmVal::Array(vals) => &vals[index as usize],
-                        _ => panic!("ALoad: Expected array"),
+                        if component.is_none() {
+                            self.throw_wrong_array(Opcode::AaStore);
+                        } else if !stored {
+                            let message = self.type_of(&value).unwrap().replace('/', ".");
+                            self.throw_new("java/lang/ArrayStoreException", Some(&message));
+                        } else if let JvmVal::Array(_, vals) = self.heap.fetch_mut(ptr) {
+                            vals[index] = value;
+                        }
+                    }
+                }
+                Opcode::ANewArray => {
+                    let index = frame.read_two_byte_index();
+                    let component = match class.const_pool.resolve(index).unwrap() {
+                        Const::String(name) => name,
+                        cons => panic!("anewarray expects a class, got {:?}", cons),
                     };
-
-                    let refer = match array {
-                        JvmVal::Reference(x) => *x,
-                        _ => panic!("ALoad: Expected reference"),
+                    let count = frame.pop_int();
+                    if count < 0 {
+                        self.throw_new(
+                            "java/lang/NegativeArraySizeException",
+                            Some(&count.to_string()),
+                        );
+                    } else {
+                        let array = JvmVal::Array(component, vec![JvmVal::Null; count as usize]);
+                        let ptr = self.heap.alloc(array);
+                        frame.push(JvmVal::Reference(ptr));
+                    }
+                }
+                Opcode::MultiANewArray => {
+                    let index = frame.read_two_byte_index();
+                    let array_type = match class.const_pool.resolve(index).unwrap() {
+                        Const::String(name) => name,
+                        cons => panic!("multianewarray expects a class, got {:?}", cons),
                     };
-
-                    let loaded = self.heap.fetch(refer);
-
-                    frame.stack.push(loaded.clone());
+                    let dimensions = frame.read_one_byte_index() as usize;
+                    let at = frame.stack.len() - dimensions;
+                    let counts: Vec<i32> = frame
+                        .stack
+                        .split_off(at)
+                        .into_iter()
+                        .map(|count| match count {
+                            JvmVal::Int(count) => count,
+                            val => panic!("multianewarray expects int counts, got {:?}", val),
+                        })
+                        .collect();
+
+                    match counts.iter().find(|count| **count < 0) {
+                        Some(count) => self.throw_new(
+                            "java/lang/NegativeArraySizeException",
+                            Some(&count.to_string()),
+                        ),
+                        None => {
+                            let array = self.new_multi_array(&array_type, &counts);
+                            frame.push(array);
+                        }
+                    }
                 }
                 Opcode::ArrayLength => {
                     let arrayref = match frame.stack.pop().unwrap() {
@@ -130,26 +236,85 @@ impl VM {
                     };
 
                     let length = match self.heap.fetch(arrayref) {
-                        JvmVal::Array(vals) => vals.len(),
+                        JvmVal::Array(_, vals) => vals.len(),
+                        JvmVal::PrimitiveArray(array) => array.len(),
                         _ => panic!("ALoad: Expected array"),
                     };
 
                     frame.stack.push(JvmVal::Int(length as i32));
                 }
+                Opcode::NewArray => {
+                    let atype = frame.read_one_byte_index();
+                    let count = frame.pop_int();
+                    if count < 0 {
+                        self.throw_new(
+                            "java/lang/NegativeArraySizeException",
+                            Some(&count.to_string()),
+                        );
+                    } else {
+                        match PrimitiveArray::new(atype, count as usize) {
+                            Some(array) => {
+                                let ptr = self.heap.alloc(JvmVal::PrimitiveArray(array));
+                                frame.push(JvmVal::Reference(ptr));
+                            }
+                            None => {
+                                let message = format!("invalid newarray type {}", atype);
+                                self.throw_new("java/lang/VerifyError", Some(&message));
+                            }
+                        }
+                    }
+                }
+                // Unverified code can use an instruction that does not match
+                // the array, which throws a VerifyError
+                Opcode::IaLoad
+                | Opcode::LaLoad
+                | Opcode::FaLoad
+                | Opcode::DaLoad
+                | Opcode::BaLoad
+                | Opcode::CaLoad
+                | Opcode::SaLoad => {
+                    let index = frame.pop_int();
+                    let array = frame.stack.pop().unwrap();
+                    if let Some((ptr, index)) = self.array_element(array, index) {
+                        match self.heap.fetch(ptr) {
+                            JvmVal::PrimitiveArray(array) if accesses(Opcode::from(op), array) => {
+                                frame.push(array.get(index))
+                            }
+                            _ => self.throw_wrong_array(Opcode::from(op)),
+                        }
+                    }
+                }
+                Opcode::IaStore
+                | Opcode::LaStore
+                | Opcode::FaStore
+                | Opcode::DaStore
+                | Opcode::BaStore
+                | Opcode::CaStore
+                | Opcode::SaStore => {
+                    let value = frame.stack.pop().unwrap();
+                    let index = frame.pop_int();
+                    let array = frame.stack.pop().unwrap();
+                    if let Some((ptr, index)) = self.array_element(array, index) {
+                        let stored = match self.heap.fetch_mut(ptr) {
+                            JvmVal::PrimitiveArray(array) if accesses(Opcode::from(op), array) => {
+                                array.set(index, value)
+                            }
+                            _ => Err(format!("{:?} of another type of array", Opcode::from(op))),
+                        };
+                        if let Err(message) = stored {
+                            self.throw_new("java/lang/VerifyError", Some(&message));
+                        }
+                    }
+                }
                 Opcode::IStore0 => frame.locals[0] = JvmVal::Int(frame.pop_int()),
                 Opcode::IStore1 => frame.locals[1] = JvmVal::Int(frame.pop_int()),
                 Opcode::IStore2 => frame.locals[2] = JvmVal::Int(frame.pop_int()),
                 Opcode::IStore3 => frame.locals[3] = JvmVal::Int(frame.pop_int()),
                 Opcode::IStore => {
                     let _istore = timer!("ISTORE");
-                    let index = frame.read_one_byte_index();
-                    if frame.locals.len() as u8 <= index {
-                        frame
-                            .locals
-                            .extend(vec![JvmVal::Null; index as usize - frame.locals.len() + 1]);
-                    }
-
-                    frame.locals[index as usize] = frame.stack.pop().unwrap();
+                    let index = frame.read_local_index();
+                    let val = frame.stack.pop().unwrap();
+                    frame.store(index, val);
                 }
                 Opcode::FStore0 => frame.locals[0] = JvmVal::Float(frame.pop_float()),
                 Opcode::FStore1 => frame.locals[1] = JvmVal::Float(frame.pop_float()),
@@ -157,25 +322,57 @@ impl VM {
                 Opcode::FStore3 => frame.locals[3] = JvmVal::Float(frame.pop_float()),
                 Opcode::FStore => {
                     let _istore = timer!("ISTORE");
-                    let index = frame.read_one_byte_index();
-                    if frame.locals.len() as u8 <= index {
-                        frame
-                            .locals
-                            .extend(vec![JvmVal::Null; index as usize - frame.locals.len() + 1]);
-                    }
-
-                    frame.locals[index as usize] = frame.stack.pop().unwrap();
+                    let index = frame.read_local_index();
+                    let val = frame.stack.pop().unwrap();
+                    frame.store(index, val);
+                }
+                Opcode::LStore0 => {
+                    let val = JvmVal::Long(frame.pop_long());
+                    frame.store(0, val);
+                }
+                Opcode::LStore1 => {
+                    let val = JvmVal::Long(frame.pop_long());
+                    frame.store(1, val);
+                }
+                Opcode::LStore2 => {
+                    let val = JvmVal::Long(frame.pop_long());
+                    frame.store(2, val);
+                }
+                Opcode::LStore3 => {
+                    let val = JvmVal::Long(frame.pop_long());
+                    frame.store(3, val);
+                }
+                Opcode::LStore => {
+                    let index = frame.read_local_index();
+                    let val = JvmVal::Long(frame.pop_long());
+                    frame.store(index, val);
+                }
+                Opcode::DStore0 => {
+                    let val = JvmVal::Double(frame.pop_double());
+                    frame.store(0, val);
+                }
+                Opcode::DStore1 => {
+                    let val = JvmVal::Double(frame.pop_double());
+                    frame.store(1, val);
+                }
+                Opcode::DStore2 => {
+                    let val = JvmVal::Double(frame.pop_double());
+                    frame.store(2, val);
+                }
+                Opcode::DStore3 => {
+                    let val = JvmVal::Double(frame.pop_double());
+                    frame.store(3, val);
+                }
+                Opcode::DStore => {
+                    let index = frame.read_local_index();
+                    let val = JvmVal::Double(frame.pop_double());
+                    frame.store(index, val);
                 }
                 Opcode::AStore => {
                     let _istore = timer!("ASTORE");
-                    let index = frame.read_one_byte_index();
-                    if frame.locals.len() as u8 <= index {
-                        frame
-                            .locals
-                            .extend(vec![JvmVal::Null; index as usize - frame.locals.len() + 1]);
-                    }
-
-                    frame.locals[index as usize] = frame.stack.pop().unwrap();
+                    let index = frame.read_local_index();
+                    let val = frame.stack.pop().unwrap();
+                    frame.store(index, val);
                 }
                 Opcode::AStore0 => frame.locals[0] = frame.stack.pop().unwrap(),
                 Opcode::AStore1 => frame.locals[1] = frame.stack.pop().unwrap(),
@@ -184,27 +381,84 @@ impl VM {
                 Opcode::IAdd => {
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    frame.push(JvmVal::Int(a + b));
+                    frame.push(JvmVal::Int(a.wrapping_add(b)));
                 }
                 Opcode::ISub => {
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    frame.push(JvmVal::Int(a - b));
+                    frame.push(JvmVal::Int(a.wrapping_sub(b)));
                 }
                 Opcode::IMul => {
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    frame.push(JvmVal::Int(a * b));
+                    frame.push(JvmVal::Int(a.wrapping_mul(b)));
                 }
+                // Integer.MIN_VALUE / -1 overflows back to MIN_VALUE
                 Opcode::IDiv => {
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    frame.push(JvmVal::Int(a / b));
+                    if b == 0 {
+                        self.divide_by_zero();
+                    } else {
+                        frame.push(JvmVal::Int(a.wrapping_div(b)));
+                    }
                 }
                 Opcode::IRem => {
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    frame.push(JvmVal::Int(a - (a / b) * b));
+                    if b == 0 {
+                        self.divide_by_zero();
+                    } else {
+                        frame.push(JvmVal::Int(a.wrapping_rem(b)));
+                    }
+                }
+                Opcode::INeg => {
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a.wrapping_neg()));
+                }
+                // Only the low five bits of the distance are used
+                Opcode::IShl => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a << (b & 0x1f)));
+                }
+                Opcode::IShr => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a >> (b & 0x1f)));
+                }
+                Opcode::IuShr => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(((a as u32) >> (b & 0x1f)) as i32));
+                }
+                Opcode::IAnd => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a & b));
+                }
+                Opcode::IOr => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a | b));
+                }
+                Opcode::IXOr => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a ^ b));
+                }
+                // Narrowed values stay ints on the stack, sign or zero extended back
+                Opcode::I2B => {
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a as i8 as i32));
+                }
+                Opcode::I2C => {
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a as u16 as i32));
+                }
+                Opcode::I2S => {
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Int(a as i16 as i32));
                 }
                 Opcode::I2F => {
                     let b = frame.pop_int();
@@ -230,97 +484,314 @@ impl VM {
                     let a = frame.pop_float();
                     frame.push(JvmVal::Float(a / b));
                 }
+                // Truncating like fmod, so the result takes the sign of `a`
                 Opcode::FRem => {
                     let b = frame.pop_float();
                     let a = frame.pop_float();
-                    frame.push(JvmVal::Float(a - (a / b) * b));
+                    frame.push(JvmVal::Float(a % b));
+                }
+                Opcode::FNeg => {
+                    let a = frame.pop_float();
+                    frame.push(JvmVal::Float(-a));
+                }
+                // The two only differ in what they push when either value is NaN
+                Opcode::FCmpl | Opcode::FCmpg => {
+                    let b = frame.pop_float();
+                    let a = frame.pop_float();
+                    let nan = if Opcode::from(op) == Opcode::FCmpg {
+                        1
+                    } else {
+                        -1
+                    };
+                    frame.push(JvmVal::Int(a.partial_cmp(&b).map_or(nan, |o| o as i32)));
                 }
                 Opcode::F2D => {
                     let b = frame.pop_float();
                     frame.push(JvmVal::Double(b as f64));
                 }
+                // Rust's casts saturate and turn NaN into 0, as Java's do
+                Opcode::F2I => {
+                    let b = frame.pop_float();
+                    frame.push(JvmVal::Int(b as i32));
+                }
+                Opcode::F2L => {
+                    let b = frame.pop_float();
+                    frame.push(JvmVal::Long(b as i64));
+                }
                 Opcode::D2I => {
                     let b = frame.pop_double();
                     frame.push(JvmVal::Int(b as i32));
                 }
+                Opcode::D2L => {
+                    let b = frame.pop_double();
+                    frame.push(JvmVal::Long(b as i64));
+                }
+                Opcode::D2F => {
+                    let b = frame.pop_double();
+                    frame.push(JvmVal::Float(b as f32));
+                }
+                Opcode::I2D => {
+                    let b = frame.pop_int();
+                    frame.push(JvmVal::Double(b as f64));
+                }
+                Opcode::DAdd => {
+                    let b = frame.pop_double();
+                    let a = frame.pop_double();
+                    frame.push(JvmVal::Double(a + b));
+                }
+                Opcode::DSub => {
+                    let b = frame.pop_double();
+                    let a = frame.pop_double();
+                    frame.push(JvmVal::Double(a - b));
+                }
+                Opcode::DMul => {
+                    let b = frame.pop_double();
+                    let a = frame.pop_double();
+                    frame.push(JvmVal::Double(a * b));
+                }
+                Opcode::DDiv => {
+                    let b = frame.pop_double();
+                    let a = frame.pop_double();
+                    frame.push(JvmVal::Double(a / b));
+                }
+                // Rust's % on floats truncates like C's fmod, which is what Java does
+                Opcode::DRem => {
+                    let b = frame.pop_double();
+                    let a = frame.pop_double();
+                    frame.push(JvmVal::Double(a % b));
+                }
+                Opcode::DNeg => {
+                    let a = frame.pop_double();
+                    frame.push(JvmVal::Double(-a));
+                }
+                // The two only differ in what they push when either value is NaN
+                Opcode::DCmpl | Opcode::DCmpg => {
+                    let b = frame.pop_double();
+                    let a = frame.pop_double();
+                    let nan = if Opcode::from(op) == Opcode::DCmpg {
+                        1
+                    } else {
+                        -1
+                    };
+                    frame.push(JvmVal::Int(a.partial_cmp(&b).map_or(nan, |o| o as i32)));
+                }
+                Opcode::LAdd => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a.wrapping_add(b)));
+                }
+                Opcode::LSub => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a.wrapping_sub(b)));
+                }
+                Opcode::LMul => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a.wrapping_mul(b)));
+                }
+                Opcode::LDiv => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    if b == 0 {
+                        self.divide_by_zero();
+                    } else {
+                        frame.push(JvmVal::Long(a.wrapping_div(b)));
+                    }
+                }
+                Opcode::LRem => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    if b == 0 {
+                        self.divide_by_zero();
+                    } else {
+                        frame.push(JvmVal::Long(a.wrapping_rem(b)));
+                    }
+                }
+                Opcode::LNeg => {
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a.wrapping_neg()));
+                }
+                // Only the low six bits of the distance are used
+                Opcode::LShl => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a << (b & 0x3f)));
+                }
+                Opcode::LShr => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a >> (b & 0x3f)));
+                }
+                Opcode::LuShr => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(((a as u64) >> (b & 0x3f)) as i64));
+                }
+                Opcode::LAnd => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a & b));
+                }
+                Opcode::LOr => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a | b));
+                }
+                Opcode::LXOr => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Long(a ^ b));
+                }
+                Opcode::LCmp => {
+                    let b = frame.pop_long();
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Int(a.cmp(&b) as i32));
+                }
+                Opcode::I2L => {
+                    let a = frame.pop_int();
+                    frame.push(JvmVal::Long(a as i64));
+                }
+                Opcode::L2I => {
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Int(a as i32));
+                }
+                Opcode::L2F => {
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Float(a as f32));
+                }
+                Opcode::L2D => {
+                    let a = frame.pop_long();
+                    frame.push(JvmVal::Double(a as f64));
+                }
                 Opcode::IInc => {
-                    let index = frame.read_one_byte_index();
-                    let cons = frame.read_one_byte_index();
+                    let wide = frame.wide;
+                    let index = frame.read_local_index();
+                    let cons = if wide {
+                        frame.read_two_byte_index() as i16 as i32
+                    } else {
+                        frame.read_one_byte_index() as i8 as i32
+                    };
 
-                    match frame.locals[index as usize] {
-                        JvmVal::Int(v) => {
-                            frame.locals[index as usize] = JvmVal::Int(v + cons as i32)
-                        }
+                    match frame.locals[index] {
+                        JvmVal::Int(v) => frame.locals[index] = JvmVal::Int(v.wrapping_add(cons)),
                         _ => panic!("not an int"),
                     };
                 }
-                Opcode::Goto => {
-                    let offset = frame.read_two_byte_index() as i16;
-                    frame.ip = (frame.ip as i16 + offset - 3) as u32;
-                    // self.should_gc = true;
+                // Only marks the frame, as the instruction it modifies reads
+                // its own operands
+                Opcode::Wide => frame.wide = true,
+                Opcode::Jsr => {
+                    let origin_ip = frame.ip;
+                    let offset = frame.read_two_byte_index() as i16 as i32;
+                    frame.push(JvmVal::ReturnAddress(frame.ip + 1));
+                    frame.jump(origin_ip, offset);
+                }
+                Opcode::JsrW => {
+                    let origin_ip = frame.ip;
+                    let offset = frame.read_four_byte_index() as i32;
+                    frame.push(JvmVal::ReturnAddress(frame.ip + 1));
+                    frame.jump(origin_ip, offset);
+                }
+                Opcode::Ret => {
+                    let index = frame.read_local_index();
+                    match frame.locals[index] {
+                        JvmVal::ReturnAddress(address) => frame.jump(address, 0),
+                        ref val => panic!("ret expects a return address, got {:?}", val),
+                    }
+                }
+                Opcode::Goto => frame.branch(true),
+                Opcode::GotoW => {
+                    let origin_ip = frame.ip;
+                    let offset = frame.read_four_byte_index() as i32;
+                    frame.jump(origin_ip, offset);
+                }
+                Opcode::IfEq => {
+                    let a = frame.pop_int();
+                    frame.branch(a == 0);
+                }
+                Opcode::IfNe => {
+                    let a = frame.pop_int();
+                    frame.branch(a != 0);
+                }
+                Opcode::IfLt => {
+                    let a = frame.pop_int();
+                    frame.branch(a < 0);
+                }
+                Opcode::IfGe => {
+                    let a = frame.pop_int();
+                    frame.branch(a >= 0);
+                }
+                Opcode::IfGt => {
+                    let a = frame.pop_int();
+                    frame.branch(a > 0);
+                }
+                Opcode::IfLe => {
+                    let a = frame.pop_int();
+                    frame.branch(a <= 0);
+                }
+                Opcode::IfIcmpEq => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.branch(a == b);
                 }
                 Opcode::IfIcmpNe => {
-                    let offset = frame.read_two_byte_index();
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    if a != b {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32;
-                    }
+                    frame.branch(a != b);
+                }
+                Opcode::IfIcmpLt => {
+                    let b = frame.pop_int();
+                    let a = frame.pop_int();
+                    frame.branch(a < b);
                 }
                 Opcode::IfIcmpGe => {
-                    let offset = frame.read_two_byte_index();
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    if a >= b {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32;
-                    }
+                    frame.branch(a >= b);
                 }
                 Opcode::IfIcmpGt => {
-                    let offset = frame.read_two_byte_index();
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    if a > b {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32
-                    }
+                    frame.branch(a > b);
                 }
                 Opcode::IfIcmpLe => {
-                    let offset = frame.read_two_byte_index();
                     let b = frame.pop_int();
                     let a = frame.pop_int();
-                    if a <= b {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32;
-                    }
+                    frame.branch(a <= b);
                 }
-                Opcode::IfNe => {
-                    let offset = frame.read_two_byte_index();
-                    let b = frame.pop_int();
-                    if b != 0 {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32
-                    }
+                // References are equal when they point at the same object.
+                // Strings are not on the heap, so they compare by value.
+                Opcode::IfAcmpEq => {
+                    let b = frame.stack.pop().unwrap();
+                    let a = frame.stack.pop().unwrap();
+                    frame.branch(a == b);
                 }
-                Opcode::IfEq => {
-                    let offset = frame.read_two_byte_index();
-                    let b = frame.pop_int();
-                    if b == 0 {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32
-                    }
+                Opcode::IfAcmpNe => {
+                    let b = frame.stack.pop().unwrap();
+                    let a = frame.stack.pop().unwrap();
+                    frame.branch(a != b);
+                }
+                Opcode::IfNull => {
+                    let a = frame.stack.pop().unwrap();
+                    frame.branch(a == JvmVal::Null);
                 }
                 Opcode::IfNonNull => {
-                    let offset = frame.read_two_byte_index();
-                    let popped = frame.stack.pop().unwrap();
-                    // let ptr = match popped {
-                    //     JvmVal::Int(i) => i,
-                    //     _ => panic!("not an int, got {:?}", popped),
-                    // };
-                    // let b = self.heap.fetch(ptr as u32);
-                    if popped != JvmVal::Null {
-                        frame.ip = (frame.ip as i32 + offset as i32 - 3) as u32
-                    }
+                    let a = frame.stack.pop().unwrap();
+                    frame.branch(a != JvmVal::Null);
                 }
                 Opcode::IReturn => {
                     self.references.remove(&frame.id);
                     return JvmVal::Int(frame.pop_int());
                 }
+                Opcode::LReturn => {
+                    self.references.remove(&frame.id);
+                    return JvmVal::Long(frame.pop_long());
+                }
+                Opcode::DReturn => {
+                    self.references.remove(&frame.id);
+                    return JvmVal::Double(frame.pop_double());
+                }
                 Opcode::FReturn => {
                     self.references.remove(&frame.id);
                     return JvmVal::Float(frame.pop_float());
@@ -336,7 +807,7 @@ impl VM {
                 Opcode::Ldc => {
                     let _l = timer!("LDC");
                     let index = frame.read_one_byte_index();
-                    let val = class.const_pool.resolve(index as u16).unwrap();
+                    let val = self.constant(class, index as u16);
 
                     frame.push(match val {
                         Const::String(v) => JvmVal::String(v),
@@ -345,6 +816,14 @@ impl VM {
                         _ => panic!("non-string constant"),
                     })
                 }
+                Opcode::Ldc2W => {
+                    let index = frame.read_two_byte_index();
+                    frame.push(match self.constant(class, index) {
+                        Const::Long(v) => JvmVal::Long(v),
+                        Const::Double(v) => JvmVal::Double(v),
+                        val => panic!("ldc2_w of non-wide constant {:?}", val),
+                    })
+                }
                 Opcode::InvokeVirtual => {
                     let index = frame.read_two_byte_index();
                     let args = vec![];
@@ -387,11 +866,11 @@ impl VM {
                     }
                 }
                 Opcode::BiPush => {
-                    let val = frame.read_one_byte_index() as i32;
+                    let val = frame.read_one_byte_index() as i8 as i32;
                     frame.push(JvmVal::Int(val));
                 }
                 Opcode::SiPush => {
-                    let val = frame.read_two_byte_index() as i32;
+                    let val = frame.read_two_byte_index() as i16 as i32;
                     frame.push(JvmVal::Int(val));
                 }
                 Opcode::New => {
@@ -407,7 +886,7 @@ impl VM {
                         let cls = clss.unwrap();
                         let mut vals = HashMap::new();
                         for f in &cls.fields {
-                            vals.insert(f.name.clone(), JvmVal::Int(0));
+                            vals.insert(f.name.clone(), JvmVal::default_for(&f.descriptor));
                         }
 
                         let ptr = self.heap.alloc(JvmVal::Class(cls.name.clone(), vals));
@@ -425,7 +904,7 @@ impl VM {
 
                         let mut vals = HashMap::new();
                         for f in builtin_cls.get_fields() {
-                            vals.insert(f.name.clone(), JvmVal::Int(0));
+                            vals.insert(f.name.clone(), JvmVal::default_for(&f.descriptor));
                         }
 
                         let ptr = self
@@ -435,9 +914,15 @@ impl VM {
                         frame.push(JvmVal::Reference(ptr));
                     }
                 }
-                Opcode::Dup => {
-                    let val = frame.stack.last().unwrap().clone();
-                    frame.push(val);
+                Opcode::Dup => frame.dup_words(1, 0),
+                Opcode::DupX1 => frame.dup_words(1, 1),
+                Opcode::DupX2 => frame.dup_words(1, 2),
+                Opcode::Dup2 => frame.dup_words(2, 0),
+                Opcode::Dup2X1 => frame.dup_words(2, 1),
+                Opcode::Dup2X2 => frame.dup_words(2, 2),
+                Opcode::Swap => {
+                    let len = frame.stack.len();
+                    frame.stack.swap(len - 1, len - 2);
                 }
                 Opcode::PutField => {
                     let _l = timer!("PUT_FIELD");
@@ -484,6 +969,7 @@ impl VM {
                 Opcode::Pop => {
                     frame.stack.pop().unwrap();
                 }
+                Opcode::Pop2 => frame.pop_words(2),
                 Opcode::TableSwitch => {
                     let origin_ip = frame.ip;
 
@@ -502,11 +988,10 @@ impl VM {
                     let index = frame.pop_int();
 
                     if index < low || index > high {
-                        frame.ip = (origin_ip as i32 + default) as u32 - 1;
+                        frame.jump(origin_ip, default);
                     } else {
                         let position = index - low;
-                        let offset = offsets[position as usize];
-                        frame.ip = ((origin_ip as i32) + offset) as u32 - 1;
+                        frame.jump(origin_ip, offsets[position as usize]);
                     }
                 }
                 Opcode::LookupSwitch => {
@@ -527,11 +1012,9 @@ impl VM {
 
                     let key = frame.pop_int();
 
-                    if !pairs.contains_key(&key) {
-                        frame.ip = (origin_ip as i32 + default) as u32 - 1;
-                    } else {
-                        let offset = pairs.get(&key).unwrap();
-                        frame.ip = ((origin_ip as i32) + offset) as u32 - 1;
+                    match pairs.get(&key) {
+                        Some(offset) => frame.jump(origin_ip, *offset),
+                        None => frame.jump(origin_ip, default),
                     }
                 }
                 Opcode::InstanceOf => {
@@ -542,10 +1025,32 @@ impl VM {
                 Opcode::CheckCast => {
                     let _ = frame.read_two_byte_index();
                 }
+                Opcode::AThrow => match frame.stack.pop().unwrap() {
+                    JvmVal::Reference(ptr) => self.exception = Some(ptr),
+                    _ => self.throw_new("java/lang/NullPointerException", None),
+                },
                 _ => panic!("unhandled opcode {:?}, {:#04x}", Opcode::from(op), op),
             }
 
-            frame.ip += 1;
+            // Thrown here or by a method this one called
+            if let Some(exception) = self.exception {
+                match self.find_handler(class, frame, pc, exception) {
+                    Some(handler_pc) => {
+                        self.exception = None;
+                        frame.stack.clear();
+                        frame.push(JvmVal::Reference(exception));
+                        frame.ip = handler_pc as u32;
+                        continue;
+                    }
+                    None => {
+                        self.references.remove(&frame.id);
+                        return JvmVal::Null;
+                    }
+                }
+            }
+
+            // A jump to the first instruction leaves ip one before it
+            frame.ip = frame.ip.wrapping_add(1);
         }
 
         self.references.remove(&frame.id);
@@ -567,12 +1072,16 @@ impl VM {
                 Const::String(val) => match val.as_str() {
                     // Cheat-y way of getting System.out.println working without a standard library to access
                     "java/io/PrintStream" => {
-                        let args = vec![frame.stack.pop().unwrap()];
+                        let args = [frame.stack.pop().unwrap()];
                         match &args[0] {
                             JvmVal::String(val) => println!("{}", val),
                             JvmVal::Int(val) => println!("{}", val),
-                            JvmVal::Float(val) => println!("{}", val),
-                            JvmVal::Array(vals) => println!("{:?}", vals),
+                            JvmVal::Long(val) => println!("{}", val),
+                            JvmVal::Float(val) => {
+                                println!("{}", java_number(*val as f64, val, val))
+                            }
+                            JvmVal::Double(val) => println!("{}", java_number(*val, val, val)),
+                            JvmVal::Array(_, vals) => println!("{:?}", vals),
                             JvmVal::Reference(val) => {
                                 let val = self.heap.fetch(*val);
                                 match val {
@@ -594,7 +1103,7 @@ impl VM {
                                             }
                                         }
                                     }
-                                    JvmVal::Array(vals) => println!("{:?}", vals),
+                                    JvmVal::Array(_, vals) => println!("{:?}", vals),
                                     _ => panic!("not a class, got {:?}", val),
                                 }
                             }
@@ -616,7 +1125,9 @@ impl VM {
                         for _ in 0..parse_descriptors(typ) {
                             args.push(frame.stack.pop().unwrap());
                         }
-                        let clss = self.classes.get(&val);
+                        args.reverse();
+                        let owner = self.declaring_class(&val, &name);
+                        let clss = self.classes.get(&owner);
                         if clss.is_some() {
                             let cls = clss.unwrap().clone();
                             if !MethodFlag::Static
@@ -625,12 +1136,12 @@ impl VM {
                                 let refer = frame.stack.pop().unwrap();
                                 args.insert(0, refer);
                             }
-                            let mut f = Frame::from_method(&cls, name, args).unwrap();
+                            let mut f = self.frame(&cls, name, args).unwrap();
 
                             let result = self.exec(&cls, &mut f);
                             frame.stack.push(result);
                         } else {
-                            let builtin = stdlib::get_builtins(val.clone());
+                            let builtin = stdlib::get_builtins(owner);
 
                             let refer = frame.stack.pop().unwrap();
                             args.insert(0, refer);
@@ -661,11 +1172,15 @@ impl VM {
                 Const::String(val) => match val.as_str() {
                     // Cheat-y way of getting System.out.println working without a standard library to access
                     "java/io/PrintStream" => {
-                        let args = vec![frame.stack.pop().unwrap()];
+                        let args = [frame.stack.pop().unwrap()];
                         match &args[0] {
                             JvmVal::String(val) => println!("{}", val),
                             JvmVal::Int(val) => println!("{}", val),
-                            JvmVal::Float(val) => println!("{}", val),
+                            JvmVal::Long(val) => println!("{}", val),
+                            JvmVal::Float(val) => {
+                                println!("{}", java_number(*val as f64, val, val))
+                            }
+                            JvmVal::Double(val) => println!("{}", java_number(*val, val, val)),
                             JvmVal::Reference(val) => {
                                 let val = self.heap.fetch(*val);
                                 match val {
@@ -708,6 +1223,7 @@ impl VM {
                         for _ in 0..parse_descriptors(typ) {
                             args.push(frame.stack.pop().unwrap());
                         }
+                        args.reverse();
                         let clss = self.classes.get(&val);
                         if clss.is_some() {
                             let cls = clss.unwrap().clone();
@@ -717,7 +1233,7 @@ impl VM {
                                 let refer = frame.stack.pop().unwrap();
                                 args.insert(0, refer);
                             }
-                            let mut f = Frame::from_method(&cls, name, args).unwrap();
+                            let mut f = self.frame(&cls, name, args).unwrap();
 
                             let result = self.exec(&cls, &mut f);
                             frame.stack.push(result);
@@ -779,16 +1295,17 @@ impl VM {
         for _ in 0..parse_descriptors(typ) {
             args.push(frame.stack.pop().unwrap());
         }
+        args.reverse();
 
         let clss = self.classes.get(&class_name);
 
         if clss.is_some() {
-            let cls = clss.unwrap();
+            let cls = clss.unwrap().clone();
 
-            let mut f = Frame::from_method(cls, name, args).unwrap();
+            let mut f = self.frame(&cls, name, args).unwrap();
 
             let _a = timer!("Static Exec");
-            return self.exec(class, &mut f);
+            return self.exec(&cls, &mut f);
         }
 
         let builtin = stdlib::get_builtins(class_name.clone());
@@ -806,8 +1323,8 @@ impl VM {
     ) -> JvmVal {
         let l = class.const_pool.resolve(index).unwrap();
         self.debug(frame.id, format!("invoke_special: {:?}", l));
-        match l {
-            Const::MethodRef(i, l) => match *i {
+        if let Const::MethodRef(i, l) = l {
+            match *i {
                 Const::String(val) => {
                     let (name, typ) = match *l {
                         Const::NameAndType(name, typ) => match *name {
@@ -834,7 +1351,7 @@ impl VM {
                         let clss = self.classes.get(&val);
                         if clss.is_some() {
                             let cls = clss.unwrap().clone();
-                            let mut f = Frame::from_method(&cls, name, args).unwrap();
+                            let mut f = self.frame(&cls, name, args).unwrap();
                             let _a = timer!("Special Exec");
                             let result = self.exec(&cls, &mut f);
                             return result;
@@ -846,12 +1363,249 @@ impl VM {
                     }
                 }
                 _ => panic!(),
-            },
-            _ => {}
+            }
         }
         JvmVal::Null
     }
 
+    /// A frame for a method, running its code as decoded ahead of time when
+    /// there is any and otherwise decoding its Code attribute
+    pub fn frame(
+        self: &VM,
+        class: &Class,
+        method: String,
+        args: Vec<JvmVal>,
+    ) -> Result<Frame, String> {
+        let decoded = class
+            .methods
+            .iter()
+            .position(|m| m.name == method)
+            .and_then(|index| self.code.get(&class.name)?.get(index)?.as_ref());
+        match decoded {
+            Some(code) => Ok(Frame::with_code(code.clone(), args)),
+            None => Frame::from_method(class, method, args),
+        }
+    }
+
+    /// A constant that `ldc` or `ldc2_w` loads, resolving it unless that was
+    /// done ahead of time
+    fn constant(self: &VM, class: &Class, index: u16) -> Const {
+        let resolved = self
+            .constants
+            .get(&class.name)
+            .and_then(|constants| constants.get(index as usize - 1)?.clone());
+        resolved.unwrap_or_else(|| class.const_pool.resolve(index).unwrap())
+    }
+
+    /// Throws a new instance of one of the exception classes the VM provides
+    pub fn throw_new(self: &mut VM, class_name: &str, message: Option<&str>) {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "message".to_string(),
+            message.map_or(JvmVal::Null, |m| JvmVal::String(m.to_string())),
+        );
+        let ptr = self
+            .heap
+            .alloc(JvmVal::Class(class_name.to_string(), fields));
+        self.exception = Some(ptr);
+    }
+
+    /// Throws a VerifyError for an array load or store run on another type
+    /// of array, which only unverified code can do
+    fn throw_wrong_array(self: &mut VM, opcode: Opcode) {
+        let message = format!("{:?} of another type of array", opcode);
+        self.throw_new("java/lang/VerifyError", Some(&message));
+    }
+
+    /// The address of an array and an index into it, or `None` once the
+    /// matching exception is thrown when the array is null or the index is
+    /// out of bounds
+    fn array_element(self: &mut VM, array: JvmVal, index: i32) -> Option<(u32, usize)> {
+        let ptr = match array {
+            JvmVal::Reference(ptr) => ptr,
+            JvmVal::Null => {
+                self.throw_new("java/lang/NullPointerException", None);
+                return None;
+            }
+            val => {
+                let message = format!("not an array reference, got {:?}", val);
+                self.throw_new("java/lang/VerifyError", Some(&message));
+                return None;
+            }
+        };
+        let length = match self.heap.fetch(ptr) {
+            JvmVal::Array(_, vals) => vals.len(),
+            JvmVal::PrimitiveArray(array) => array.len(),
+            val => {
+                let message = format!("not an array, got {:?}", val);
+                self.throw_new("java/lang/VerifyError", Some(&message));
+                return None;
+            }
+        };
+
+        if index < 0 || index as usize >= length {
+            let message = format!("Index {} out of bounds for length {}", index, length);
+            self.throw_new("java/lang/ArrayIndexOutOfBoundsException", Some(&message));
+            return None;
+        }
+        Some((ptr, index as usize))
+    }
+
+    /// Allocates an array of `array_type`, such as `[[I`, with a dimension
+    /// for each count and any further dimensions left null
+    fn new_multi_array(self: &mut VM, array_type: &str, counts: &[i32]) -> JvmVal {
+        let component = component_type(array_type);
+        let length = counts[0] as usize;
+        let array = match PrimitiveArray::with_descriptor(component, length) {
+            Some(array) => JvmVal::PrimitiveArray(array),
+            None if counts.len() > 1 => {
+                let vals = (0..length)
+                    .map(|_| self.new_multi_array(component, &counts[1..]))
+                    .collect();
+                JvmVal::Array(component.to_string(), vals)
+            }
+            None => JvmVal::Array(component.to_string(), vec![JvmVal::Null; length]),
+        };
+
+        JvmVal::Reference(self.heap.alloc(array))
+    }
+
+    fn divide_by_zero(self: &mut VM) {
+        self.throw_new("java/lang/ArithmeticException", Some("/ by zero"));
+    }
+
+    /// Where the handler for an exception thrown at `pc` starts, if the
+    /// method has one
+    fn find_handler(
+        self: &mut VM,
+        class: &Class,
+        frame: &Frame,
+        pc: u32,
+        exception: u32,
+    ) -> Option<u16> {
+        let thrown = match self.heap.fetch(exception) {
+            JvmVal::Class(name, _) => name.clone(),
+            val => panic!("thrown value is not an object, got {:?}", val),
+        };
+
+        frame
+            .exception_table
+            .iter()
+            .filter(|handler| (handler.start_pc as u32..handler.end_pc as u32).contains(&pc))
+            .find(|handler| {
+                handler.catch_type == 0
+                    || matches!(class.const_pool.class_name(handler.catch_type),
+                        Some(caught) if self.is_subclass(&thrown, caught))
+            })
+            .map(|handler| handler.handler_pc)
+    }
+
+    /// The class a method is inherited from, which is the first of `class` and
+    /// its loaded superclasses to declare it, or else the builtin they extend
+    fn declaring_class(self: &VM, class: &str, method: &str) -> String {
+        let mut current = class.to_string();
+        while let Some(loaded) = self.classes.get(&current) {
+            if loaded.methods.iter().any(|m| m.name == method) {
+                break;
+            }
+            current = loaded.superclass.clone();
+        }
+
+        current
+    }
+
+    /// Whether a class is `target` or extends it, looking through both the
+    /// loaded classes and the exceptions the VM provides
+    pub fn is_subclass(self: &VM, class: &str, target: &str) -> bool {
+        let mut current = class.to_string();
+        loop {
+            if current == target {
+                return true;
+            }
+            current = match self.classes.get(&current) {
+                Some(loaded) => loaded.superclass.clone(),
+                None => match stdlib::throwable::superclass(&current) {
+                    Some(superclass) => superclass.to_string(),
+                    None => return false,
+                },
+            };
+        }
+    }
+
+    /// The class of a value stored in a reference, as an array descriptor for
+    /// arrays, or `None` for null
+    fn type_of(self: &mut VM, val: &JvmVal) -> Option<String> {
+        let val = match val {
+            JvmVal::Reference(ptr) => self.heap.fetch(*ptr),
+            val => val,
+        };
+        match val {
+            JvmVal::Null => None,
+            JvmVal::String(_) => Some("java/lang/String".to_string()),
+            JvmVal::Class(name, _) | JvmVal::BuiltinClass(name, _) => Some(name.clone()),
+            JvmVal::Array(component, _) if component.starts_with('[') => {
+                Some(format!("[{}", component))
+            }
+            JvmVal::Array(component, _) => Some(format!("[L{};", component)),
+            JvmVal::PrimitiveArray(array) => Some(format!("[{}", array.descriptor())),
+            val => panic!("not a reference, got {:?}", val),
+        }
+    }
+
+    /// Whether a value of `class` can be stored where `target` is expected.
+    /// Interfaces the VM does not load are assumed to be implemented, since
+    /// the builtin classes do not list theirs.
+    pub fn is_assignable(self: &VM, class: &str, target: &str) -> bool {
+        if class == target || target == "java/lang/Object" {
+            return true;
+        }
+        if class.starts_with('[') {
+            if !target.starts_with('[') {
+                return target == "java/lang/Cloneable" || target == "java/io/Serializable";
+            }
+            let (component, target) = (component_type(class), component_type(target));
+            let primitive = |name: &str| PrimitiveArray::with_descriptor(name, 0).is_some();
+            if primitive(component) || primitive(target) {
+                return component == target;
+            }
+            return self.is_assignable(component, target);
+        }
+        if target.starts_with('[') {
+            return false;
+        }
+
+        match self.classes.get(target) {
+            Some(loaded) if loaded.flags & 0x0200 != 0 => self.implements(class, target),
+            Some(_) => self.is_subclass(class, target),
+            None if stdlib::find_builtin(target).is_some() => self.is_subclass(class, target),
+            None => true,
+        }
+    }
+
+    /// Whether a loaded class or one of its superclasses lists `interface`,
+    /// directly or through the interfaces it extends
+    fn implements(self: &VM, class: &str, interface: &str) -> bool {
+        let mut current = class;
+        while let Some(loaded) = self.classes.get(current) {
+            if loaded
+                .interfaces
+                .iter()
+                .any(|name| name == interface || self.implements(name, interface))
+            {
+                return true;
+            }
+            current = &loaded.superclass;
+        }
+
+        false
+    }
+
+    /// Verifies a class against the classes loaded into the VM. This should be
+    /// run before `exec` on any class that is not trusted.
+    pub fn link(self: &VM, class: &Class) -> Result<(), VerifyError> {
+        verifier::verify_class(class, &self.classes)
+    }
+
     pub fn debug(self: &mut VM, frame_id: i32, message: String) {
         if !self.debug {
             return;
@@ -860,6 +1614,267 @@ impl VM {
     }
 }
 
+/// Whether `exec` has an implementation for an opcode. This has to be kept
+/// in step with the opcodes matched there.
+pub fn supports_opcode(op: Opcode) -> bool {
+    matches!(
+        op,
+        Opcode::Nop
+            | Opcode::IConstM1
+            | Opcode::IConst0
+            | Opcode::IConst1
+            | Opcode::IConst2
+            | Opcode::IConst3
+            | Opcode::IConst4
+            | Opcode::IConst5
+            | Opcode::FConst0
+            | Opcode::FConst1
+            | Opcode::FConst2
+            | Opcode::LConst0
+            | Opcode::LConst1
+            | Opcode::DConst0
+            | Opcode::DConst1
+            | Opcode::AConstNull
+            | Opcode::ILoad
+            | Opcode::ILoad0
+            | Opcode::ILoad1
+            | Opcode::ILoad2
+            | Opcode::ILoad3
+            | Opcode::FLoad
+            | Opcode::FLoad0
+            | Opcode::FLoad1
+            | Opcode::FLoad2
+            | Opcode::FLoad3
+            | Opcode::LLoad
+            | Opcode::LLoad0
+            | Opcode::LLoad1
+            | Opcode::LLoad2
+            | Opcode::LLoad3
+            | Opcode::DLoad
+            | Opcode::DLoad0
+            | Opcode::DLoad1
+            | Opcode::DLoad2
+            | Opcode::DLoad3
+            | Opcode::ALoad
+            | Opcode::ALoad0
+            | Opcode::ALoad1
+            | Opcode::ALoad2
+            | Opcode::ALoad3
+            | Opcode::AaLoad
+            | Opcode::ArrayLength
+            | Opcode::NewArray
+            | Opcode::ANewArray
+            | Opcode::MultiANewArray
+            | Opcode::AaStore
+            | Opcode::IaLoad
+            | Opcode::LaLoad
+            | Opcode::FaLoad
+            | Opcode::DaLoad
+            | Opcode::BaLoad
+            | Opcode::CaLoad
+            | Opcode::SaLoad
+            | Opcode::IaStore
+            | Opcode::LaStore
+            | Opcode::FaStore
+            | Opcode::DaStore
+            | Opcode::BaStore
+            | Opcode::CaStore
+            | Opcode::SaStore
+            | Opcode::IStore
+            | Opcode::IStore0
+            | Opcode::IStore1
+            | Opcode::IStore2
+            | Opcode::IStore3
+            | Opcode::FStore
+            | Opcode::FStore0
+            | Opcode::FStore1
+            | Opcode::FStore2
+            | Opcode::FStore3
+            | Opcode::LStore
+            | Opcode::LStore0
+            | Opcode::LStore1
+            | Opcode::LStore2
+            | Opcode::LStore3
+            | Opcode::DStore
+            | Opcode::DStore0
+            | Opcode::DStore1
+            | Opcode::DStore2
+            | Opcode::DStore3
+            | Opcode::AStore
+            | Opcode::AStore0
+            | Opcode::AStore1
+            | Opcode::AStore2
+            | Opcode::AStore3
+            | Opcode::IAdd
+            | Opcode::ISub
+            | Opcode::IMul
+            | Opcode::IDiv
+            | Opcode::IRem
+            | Opcode::INeg
+            | Opcode::IShl
+            | Opcode::IShr
+            | Opcode::IuShr
+            | Opcode::IAnd
+            | Opcode::IOr
+            | Opcode::IXOr
+            | Opcode::I2B
+            | Opcode::I2C
+            | Opcode::I2S
+            | Opcode::I2F
+            | Opcode::FAdd
+            | Opcode::FSub
+            | Opcode::FMul
+            | Opcode::FDiv
+            | Opcode::FRem
+            | Opcode::FNeg
+            | Opcode::FCmpl
+            | Opcode::FCmpg
+            | Opcode::F2D
+            | Opcode::F2I
+            | Opcode::F2L
+            | Opcode::D2I
+            | Opcode::D2L
+            | Opcode::D2F
+            | Opcode::I2D
+            | Opcode::DAdd
+            | Opcode::DSub
+            | Opcode::DMul
+            | Opcode::DDiv
+            | Opcode::DRem
+            | Opcode::DNeg
+            | Opcode::DCmpl
+            | Opcode::DCmpg
+            | Opcode::LAdd
+            | Opcode::LSub
+            | Opcode::LMul
+            | Opcode::LDiv
+            | Opcode::LRem
+            | Opcode::LNeg
+            | Opcode::LShl
+            | Opcode::LShr
+            | Opcode::LuShr
+            | Opcode::LAnd
+            | Opcode::LOr
+            | Opcode::LXOr
+            | Opcode::LCmp
+            | Opcode::I2L
+            | Opcode::L2I
+            | Opcode::L2F
+            | Opcode::L2D
+            | Opcode::IInc
+            | Opcode::Goto
+            | Opcode::GotoW
+            | Opcode::Wide
+            | Opcode::Jsr
+            | Opcode::JsrW
+            | Opcode::Ret
+            | Opcode::IfEq
+            | Opcode::IfNe
+            | Opcode::IfLt
+            | Opcode::IfGe
+            | Opcode::IfGt
+            | Opcode::IfLe
+            | Opcode::IfIcmpEq
+            | Opcode::IfIcmpNe
+            | Opcode::IfIcmpLt
+            | Opcode::IfIcmpGe
+            | Opcode::IfIcmpGt
+            | Opcode::IfIcmpLe
+            | Opcode::IfAcmpEq
+            | Opcode::IfAcmpNe
+            | Opcode::IfNull
+            | Opcode::IfNonNull
+            | Opcode::IReturn
+            | Opcode::LReturn
+            | Opcode::DReturn
+            | Opcode::FReturn
+            | Opcode::AReturn
+            | Opcode::Return
+            | Opcode::Ldc
+            | Opcode::Ldc2W
+            | Opcode::InvokeVirtual
+            | Opcode::GetStatic
+            | Opcode::InvokeStatic
+            | Opcode::InvokeSpecial
+            | Opcode::InvokeDynamic
+            | Opcode::BiPush
+            | Opcode::SiPush
+            | Opcode::New
+            | Opcode::Dup
+            | Opcode::DupX1
+            | Opcode::DupX2
+            | Opcode::Dup2
+            | Opcode::Dup2X1
+            | Opcode::Dup2X2
+            | Opcode::Swap
+            | Opcode::PutField
+            | Opcode::GetField
+            | Opcode::Pop
+            | Opcode::Pop2
+            | Opcode::TableSwitch
+            | Opcode::LookupSwitch
+            | Opcode::InstanceOf
+            | Opcode::CheckCast
+            | Opcode::AThrow
+    )
+}
+
+/// The component type of an array descriptor, named the way `anewarray` names
+/// it: `[I` has `I`, `[[I` has `[I` and `[Ljava/lang/String;` has
+/// `java/lang/String`
+fn component_type(array_type: &str) -> &str {
+    let component = &array_type[1..];
+    match component.strip_prefix('L') {
+        Some(class) => class.trim_end_matches(';'),
+        None => component,
+    }
+}
+
+/// Whether a primitive array load or store works on this type of array.
+/// `baload` and `bastore` work on both byte and boolean arrays.
+fn accesses(opcode: Opcode, array: &PrimitiveArray) -> bool {
+    let types = match opcode {
+        Opcode::IaLoad | Opcode::IaStore => "I",
+        Opcode::LaLoad | Opcode::LaStore => "J",
+        Opcode::FaLoad | Opcode::FaStore => "F",
+        Opcode::DaLoad | Opcode::DaStore => "D",
+        Opcode::BaLoad | Opcode::BaStore => "BZ",
+        Opcode::CaLoad | Opcode::CaStore => "C",
+        Opcode::SaLoad | Opcode::SaStore => "S",
+        _ => "",
+    };
+    types.contains(array.descriptor())
+}
+
+/// Formats a float or double as Java's toString does, from the value's
+/// shortest plain and scientific forms
+fn java_number(val: f64, plain: impl std::fmt::Display, sci: impl std::fmt::LowerExp) -> String {
+    if val.is_nan() {
+        return "NaN".to_string();
+    }
+    if val.is_infinite() {
+        return if val > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+
+    let abs = val.abs();
+    if abs == 0.0 || (1e-3..1e7).contains(&abs) {
+        let plain = plain.to_string();
+        return if plain.contains('.') {
+            plain
+        } else {
+            format!("{}.0", plain)
+        };
+    }
+
+    let sci = format!("{:e}", sci);
+    let (mantissa, exponent) = sci.split_once('e').unwrap();
+    if mantissa.contains('.') {
+        format!("{}E{}", mantissa, exponent)
+    } else {
+        format!("{}.0E{}", mantissa, exponent)
+    }
+}
+
 #[time]
 fn parse_descriptors(descriptor: String) -> usize {
     signature::TypeSignature::from_str(descriptor)
@@ -888,3 +1903,732 @@ fn deref_field_ref(cn: Const) -> (String, String, String) {
         _ => panic!("not a fieldref, got {:?}", cn),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use jjvm_loader::{
+        class::{Attribute, Field},
+        const_pool::ConstPool,
+        jvm_const::JvmConst,
+    };
+
+    use super::*;
+
+    fn method(name: &str, descriptor: &str, max_locals: u16, code: &[u8]) -> Field {
+        method_with_handlers(name, descriptor, max_locals, code, &[])
+    }
+
+    /// A method with exception handlers given as start, end, handler and
+    /// catch type
+    fn method_with_handlers(
+        name: &str,
+        descriptor: &str,
+        max_locals: u16,
+        code: &[u8],
+        handlers: &[[u16; 4]],
+    ) -> Field {
+        let mut data = vec![];
+        data.extend_from_slice(&8u16.to_be_bytes());
+        data.extend_from_slice(&max_locals.to_be_bytes());
+        data.extend_from_slice(&(code.len() as u32).to_be_bytes());
+        data.extend_from_slice(code);
+        data.extend_from_slice(&(handlers.len() as u16).to_be_bytes());
+        for handler in handlers {
+            for val in handler {
+                data.extend_from_slice(&val.to_be_bytes());
+            }
+        }
+        data.extend_from_slice(&[0, 0]);
+
+        Field {
+            flags: 0x0009,
+            name: name.to_string(),
+            descriptor: descriptor.to_string(),
+            attributes: vec![Attribute {
+                name: "Code".to_string(),
+                data,
+            }],
+        }
+    }
+
+    fn class_with_methods(consts: Vec<JvmConst>, methods: Vec<Field>) -> Class {
+        Class {
+            major: 52,
+            minor: 0,
+            const_pool: ConstPool { consts },
+            name: "Test".to_string(),
+            superclass: "java/lang/Object".to_string(),
+            flags: 0x0021,
+            interfaces: vec![],
+            fields: vec![],
+            methods,
+            attributes: vec![],
+        }
+    }
+
+    /// Runs the class's `test` method, returning the VM, to look at what it
+    /// threw, and the value returned
+    fn run(class: Class) -> (VM, JvmVal) {
+        let mut vm = VM {
+            heap: Heap { heap: vec![] },
+            classes: HashMap::new(),
+            references: HashMap::new(),
+            heap_last_gc_size: 4,
+            should_gc: false,
+            debug: false,
+            exception: None,
+            code: HashMap::new(),
+            constants: HashMap::new(),
+        };
+        vm.classes.insert(class.name.clone(), class.clone());
+
+        let mut frame = vm.frame(&class, "test".to_string(), vec![]).unwrap();
+        let result = vm.exec(&class, &mut frame);
+        (vm, result)
+    }
+
+    /// The class of the exception the `test` method threw and did not catch
+    fn thrown(vm: &mut VM) -> String {
+        match vm.heap.fetch(vm.exception.unwrap()) {
+            JvmVal::Class(name, _) => name.clone(),
+            val => panic!("not a throwable, got {:?}", val),
+        }
+    }
+
+    /// The exception code with these constants throws as the `test` method
+    fn eval_thrown(consts: Vec<JvmConst>, code: &[u8]) -> String {
+        let (mut vm, _) = run(class_with_methods(
+            consts,
+            vec![method("test", "()V", 4, code)],
+        ));
+        thrown(&mut vm)
+    }
+
+    /// Runs code with these constants as the `test` method, returning what it
+    /// returns
+    fn eval(consts: Vec<JvmConst>, code: &[u8]) -> JvmVal {
+        let (vm, result) = run(class_with_methods(
+            consts,
+            vec![method("test", "()V", 4, code)],
+        ));
+        assert_eq!(vm.exception, None);
+        result
+    }
+
+    /// Int constants for `ldc`, the nth at index n + 1
+    fn ints(vals: &[i32]) -> Vec<JvmConst> {
+        vals.iter()
+            .map(|val| JvmConst::Integer(*val as u32))
+            .collect()
+    }
+
+    /// Float constants for `ldc`, the nth at index n + 1
+    fn floats(vals: &[f32]) -> Vec<JvmConst> {
+        vals.iter()
+            .map(|val| JvmConst::Float(val.to_bits()))
+            .collect()
+    }
+
+    /// Long constants for `ldc2_w`, the nth at index 2n + 1
+    fn longs(vals: &[i64]) -> Vec<JvmConst> {
+        vals.iter()
+            .flat_map(|val| {
+                let bits = *val as u64;
+                vec![
+                    JvmConst::Long((bits >> 32) as u32, bits as u32),
+                    JvmConst::Unusable,
+                ]
+            })
+            .collect()
+    }
+
+    /// Double constants for `ldc2_w`, the nth at index 2n + 1
+    fn doubles(vals: &[f64]) -> Vec<JvmConst> {
+        vals.iter()
+            .flat_map(|val| {
+                let bits = val.to_bits();
+                vec![
+                    JvmConst::Double((bits >> 32) as u32, bits as u32),
+                    JvmConst::Unusable,
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_static_call_arguments() {
+        let consts = vec![
+            JvmConst::UTF8("Test".to_string()),
+            JvmConst::Class(1),
+            JvmConst::UTF8("sub".to_string()),
+            JvmConst::UTF8("(JI)J".to_string()),
+            JvmConst::NameAndType(3, 4),
+            JvmConst::MethodRef(2, 5),
+        ];
+        // return sub(10L, 3)
+        let test = method(
+            "test",
+            "()J",
+            0,
+            &[0x10, 0x0a, 0x85, 0x06, 0xb8, 0x00, 0x06, 0xad],
+        );
+        // static long sub(long a, int b) { return a - b; }
+        let sub = method("sub", "(JI)J", 3, &[0x1e, 0x1c, 0x85, 0x65, 0xad]);
+
+        let (_, result) = run(class_with_methods(consts, vec![test, sub]));
+        assert_eq!(result, JvmVal::Long(7));
+    }
+
+    #[test]
+    fn test_longs() {
+        let consts = longs(&[i64::MAX, 1 << 32, -7, 2]);
+        let binary =
+            |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x14, 0, a, 0x14, 0, b, op, 0xad]);
+
+        // ladd, lsub and lmul wrap
+        assert_eq!(binary(0x61, 1, 1), JvmVal::Long(-2));
+        assert_eq!(binary(0x65, 5, 1), JvmVal::Long(i64::MAX - 5));
+        assert_eq!(binary(0x69, 3, 3), JvmVal::Long(0));
+        // ldiv and lrem truncate towards zero
+        assert_eq!(binary(0x6d, 5, 7), JvmVal::Long(-3));
+        assert_eq!(binary(0x71, 5, 7), JvmVal::Long(-1));
+        // land, lor and lxor
+        assert_eq!(binary(0x7f, 5, 7), JvmVal::Long(-7 & 2));
+        assert_eq!(binary(0x81, 5, 7), JvmVal::Long(-7 | 2));
+        assert_eq!(binary(0x83, 5, 7), JvmVal::Long(-7 ^ 2));
+        // lneg
+        assert_eq!(
+            eval(consts.clone(), &[0x14, 0, 3, 0x75, 0xad]),
+            JvmVal::Long(-(1 << 32))
+        );
+
+        // The shift distance is an int masked to 6 bits: -7 << 65, >> 65 and >>> 65
+        let shift = |op: u8| eval(consts.clone(), &[0x14, 0, 5, 0x10, 65, op, 0xad]);
+        assert_eq!(shift(0x79), JvmVal::Long(-14));
+        assert_eq!(shift(0x7b), JvmVal::Long(-4));
+        assert_eq!(shift(0x7d), JvmVal::Long((-7i64 as u64 >> 1) as i64));
+
+        // lcmp
+        let compare = |a: u8, b: u8| eval(consts.clone(), &[0x14, 0, a, 0x14, 0, b, 0x94, 0xac]);
+        assert_eq!(compare(5, 7), JvmVal::Int(-1));
+        assert_eq!(compare(7, 7), JvmVal::Int(0));
+        assert_eq!(compare(1, 7), JvmVal::Int(1));
+
+        // l2i keeps the low 32 bits
+        assert_eq!(
+            eval(consts.clone(), &[0x14, 0, 3, 0x0a, 0x61, 0x88, 0xac]),
+            JvmVal::Int(1)
+        );
+    }
+
+    #[test]
+    fn test_long_locals() {
+        // long a = 1L << 32; int b = 2; return (a + 1) * b; with a in locals
+        // 1 and 2 and b in local 3
+        let code = [
+            0x14, 0, 1, 0x40, 0x05, 0x3e, 0x1f, 0x0a, 0x61, 0x1d, 0x85, 0x69, 0xad,
+        ];
+        assert_eq!(eval(longs(&[1 << 32]), &code), JvmVal::Long(0x2_0000_0002));
+    }
+
+    #[test]
+    fn test_doubles() {
+        let consts = doubles(&[-7.5, 2.0, f64::NAN, 1e300]);
+        let binary =
+            |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x14, 0, a, 0x14, 0, b, op, 0xaf]);
+
+        assert_eq!(binary(0x63, 1, 3), JvmVal::Double(-5.5));
+        assert_eq!(binary(0x6b, 1, 3), JvmVal::Double(-15.0));
+        // drem takes the sign of the dividend, as fmod does
+        assert_eq!(binary(0x73, 1, 3), JvmVal::Double(-1.5));
+        // Dividing by zero gives an infinity or NaN rather than throwing
+        assert_eq!(
+            eval(consts.clone(), &[0x0f, 0x0e, 0x6f, 0xaf]),
+            JvmVal::Double(f64::INFINITY)
+        );
+        assert!(matches!(
+            eval(consts.clone(), &[0x0e, 0x0e, 0x6f, 0xaf]),
+            JvmVal::Double(v) if v.is_nan()
+        ));
+        assert!(matches!(binary(0x67, 1, 5), JvmVal::Double(v) if v.is_nan()));
+
+        // dcmpl pushes -1 and dcmpg 1 when either is NaN
+        let compare =
+            |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x14, 0, a, 0x14, 0, b, op, 0xac]);
+        assert_eq!(compare(0x97, 1, 3), JvmVal::Int(-1));
+        assert_eq!(compare(0x98, 7, 3), JvmVal::Int(1));
+        assert_eq!(compare(0x97, 3, 3), JvmVal::Int(0));
+        assert_eq!(compare(0x97, 5, 3), JvmVal::Int(-1));
+        assert_eq!(compare(0x98, 5, 3), JvmVal::Int(1));
+        assert_eq!(compare(0x97, 3, 5), JvmVal::Int(-1));
+        assert_eq!(compare(0x98, 3, 5), JvmVal::Int(1));
+
+        // d2i and d2l saturate and take NaN to 0
+        let convert = |op: u8, ret: u8, a: u8| eval(consts.clone(), &[0x14, 0, a, op, ret]);
+        assert_eq!(convert(0x8e, 0xac, 1), JvmVal::Int(-7));
+        assert_eq!(convert(0x8e, 0xac, 7), JvmVal::Int(i32::MAX));
+        assert_eq!(convert(0x8e, 0xac, 5), JvmVal::Int(0));
+        assert_eq!(convert(0x8f, 0xad, 7), JvmVal::Long(i64::MAX));
+        assert_eq!(convert(0x90, 0xae, 7), JvmVal::Float(f32::INFINITY));
+
+        // double a = -7.5; return -a; with a in locals 1 and 2
+        assert_eq!(
+            eval(consts.clone(), &[0x14, 0, 1, 0x48, 0x27, 0x77, 0xaf]),
+            JvmVal::Double(7.5)
+        );
+    }
+
+    #[test]
+    fn test_java_number() {
+        let double = |val: f64| java_number(val, val, val);
+        assert_eq!(double(1.0), "1.0");
+        assert_eq!(double(-0.0), "-0.0");
+        assert_eq!(double(0.001), "0.001");
+        assert_eq!(double(123456.789), "123456.789");
+        assert_eq!(double(1e7), "1.0E7");
+        assert_eq!(double(1.5e-4), "1.5E-4");
+        assert_eq!(double(-2.5e300), "-2.5E300");
+        assert_eq!(double(f64::NAN), "NaN");
+        assert_eq!(double(f64::NEG_INFINITY), "-Infinity");
+
+        // A float prints its own shortest digits, not those of the double
+        let float = 0.1f32;
+        assert_eq!(java_number(float as f64, float, float), "0.1");
+    }
+
+    #[test]
+    fn test_int_bitwise_and_shifts() {
+        let consts = ints(&[-8, 33, 0x0f0f, 0x00ff]);
+        let binary = |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x12, a, 0x12, b, op, 0xac]);
+
+        // The shift distance is masked to 5 bits, so these shift by 1
+        assert_eq!(binary(0x78, 1, 2), JvmVal::Int(-16));
+        assert_eq!(binary(0x7a, 1, 2), JvmVal::Int(-4));
+        assert_eq!(binary(0x7c, 1, 2), JvmVal::Int((-8i32 as u32 >> 1) as i32));
+        assert_eq!(binary(0x7e, 3, 4), JvmVal::Int(0x000f));
+        assert_eq!(binary(0x80, 3, 4), JvmVal::Int(0x0fff));
+        assert_eq!(binary(0x82, 3, 4), JvmVal::Int(0x0ff0));
+        // ineg
+        assert_eq!(eval(consts.clone(), &[0x12, 1, 0x74, 0xac]), JvmVal::Int(8));
+    }
+
+    #[test]
+    fn test_int_narrowing() {
+        let consts = ints(&[0x1_ff80, -1]);
+        let narrow = |op: u8, a: u8| eval(consts.clone(), &[0x12, a, op, 0xac]);
+
+        // i2b and i2s sign-extend the low bits, i2c zero-extends them
+        assert_eq!(narrow(0x91, 1), JvmVal::Int(-128));
+        assert_eq!(narrow(0x93, 1), JvmVal::Int(-128));
+        assert_eq!(narrow(0x92, 1), JvmVal::Int(0xff80));
+        assert_eq!(narrow(0x92, 2), JvmVal::Int(0xffff));
+        assert_eq!(narrow(0x93, 2), JvmVal::Int(-1));
+    }
+
+    #[test]
+    fn test_int_arithmetic() {
+        let consts = ints(&[i32::MAX, i32::MIN, -1, -7, 2, 7, -2]);
+        let binary = |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x12, a, 0x12, b, op, 0xac]);
+
+        // iadd, isub and imul wrap
+        assert_eq!(binary(0x60, 1, 1), JvmVal::Int(-2));
+        assert_eq!(binary(0x64, 2, 5), JvmVal::Int(i32::MAX - 1));
+        assert_eq!(binary(0x68, 1, 5), JvmVal::Int(-2));
+        // MIN_VALUE / -1 overflows back to MIN_VALUE, leaving no remainder
+        assert_eq!(binary(0x6c, 2, 3), JvmVal::Int(i32::MIN));
+        assert_eq!(binary(0x70, 2, 3), JvmVal::Int(0));
+        // idiv truncates towards zero and irem takes the sign of the dividend
+        assert_eq!(binary(0x6c, 4, 5), JvmVal::Int(-3));
+        assert_eq!(binary(0x70, 4, 5), JvmVal::Int(-1));
+        assert_eq!(binary(0x70, 6, 7), JvmVal::Int(1));
+
+        // int i = 5; i += -1; return i;
+        assert_eq!(
+            eval(vec![], &[0x08, 0x3b, 0x84, 0x00, 0xff, 0x1a, 0xac]),
+            JvmVal::Int(4)
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        for code in [
+            // 1 / 0 and 1 % 0
+            [0x04, 0x03, 0x6c, 0xac],
+            [0x04, 0x03, 0x70, 0xac],
+            // 1L / 0L and 1L % 0L
+            [0x0a, 0x09, 0x6d, 0xad],
+            [0x0a, 0x09, 0x71, 0xad],
+        ] {
+            let (mut vm, _) = run(class_with_methods(
+                vec![],
+                vec![method("test", "()V", 0, &code)],
+            ));
+            assert_eq!(thrown(&mut vm), "java/lang/ArithmeticException");
+        }
+
+        // try { return 1 / 0; } catch (ArithmeticException e) { return 42; }
+        let consts = vec![
+            JvmConst::UTF8("java/lang/ArithmeticException".to_string()),
+            JvmConst::Class(1),
+        ];
+        let code = [0x04, 0x03, 0x6c, 0xac, 0x57, 0x10, 42, 0xac];
+        let test = method_with_handlers("test", "()I", 0, &code, &[[0, 4, 4, 2]]);
+        let (vm, result) = run(class_with_methods(consts, vec![test]));
+        assert_eq!(vm.exception, None);
+        assert_eq!(result, JvmVal::Int(42));
+    }
+
+    #[test]
+    fn test_floats() {
+        let consts = floats(&[-7.5, 2.0, f32::NAN, 1e20, 5.1, 3.0]);
+        let binary = |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x12, a, 0x12, b, op, 0xae]);
+
+        // frem truncates the quotient, so the result takes the dividend's sign
+        assert_eq!(binary(0x72, 1, 2), JvmVal::Float(-1.5));
+        assert_eq!(binary(0x72, 5, 6), JvmVal::Float(5.1 % 3.0));
+        assert!(matches!(binary(0x72, 2, 3), JvmVal::Float(v) if v.is_nan()));
+        // fneg
+        assert_eq!(
+            eval(consts.clone(), &[0x12, 1, 0x76, 0xae]),
+            JvmVal::Float(7.5)
+        );
+
+        // fcmpl pushes -1 and fcmpg 1 when either is NaN
+        let compare = |op: u8, a: u8, b: u8| eval(consts.clone(), &[0x12, a, 0x12, b, op, 0xac]);
+        assert_eq!(compare(0x95, 1, 2), JvmVal::Int(-1));
+        assert_eq!(compare(0x96, 2, 1), JvmVal::Int(1));
+        assert_eq!(compare(0x95, 2, 2), JvmVal::Int(0));
+        assert_eq!(compare(0x95, 3, 2), JvmVal::Int(-1));
+        assert_eq!(compare(0x96, 3, 2), JvmVal::Int(1));
+        assert_eq!(compare(0x95, 2, 3), JvmVal::Int(-1));
+        assert_eq!(compare(0x96, 2, 3), JvmVal::Int(1));
+
+        // f2i and f2l saturate and take NaN to 0
+        let convert = |op: u8, ret: u8, a: u8| eval(consts.clone(), &[0x12, a, op, ret]);
+        assert_eq!(convert(0x8b, 0xac, 1), JvmVal::Int(-7));
+        assert_eq!(convert(0x8b, 0xac, 4), JvmVal::Int(i32::MAX));
+        assert_eq!(convert(0x8b, 0xac, 3), JvmVal::Int(0));
+        assert_eq!(convert(0x8c, 0xad, 4), JvmVal::Long(i64::MAX));
+        assert_eq!(convert(0x8c, 0xad, 3), JvmVal::Long(0));
+    }
+
+    #[test]
+    fn test_branches() {
+        // Pushes the operands, then returns 1 if the branch jumps 5 ahead and
+        // 0 if it falls through
+        let branch = |operands: &[u8], op: u8| {
+            let mut code = operands.to_vec();
+            code.extend_from_slice(&[op, 0x00, 0x05, 0x03, 0xac, 0x04, 0xac]);
+            eval(vec![], &code)
+        };
+        let taken = JvmVal::Int(1);
+        let not_taken = JvmVal::Int(0);
+        let minus_one = [0x02];
+        let zero = [0x03];
+        let null = [0x01];
+
+        assert_eq!(branch(&zero, 0x99), taken);
+        assert_eq!(branch(&minus_one, 0x99), not_taken);
+        assert_eq!(branch(&minus_one, 0x9a), taken);
+        assert_eq!(branch(&minus_one, 0x9b), taken);
+        assert_eq!(branch(&zero, 0x9b), not_taken);
+        assert_eq!(branch(&zero, 0x9c), taken);
+        assert_eq!(branch(&minus_one, 0x9c), not_taken);
+        assert_eq!(branch(&zero, 0x9d), not_taken);
+        assert_eq!(branch(&zero, 0x9e), taken);
+
+        // -1 against 2
+        let ints = [0x02, 0x05];
+        assert_eq!(branch(&ints, 0x9f), not_taken);
+        assert_eq!(branch(&ints, 0xa0), taken);
+        assert_eq!(branch(&ints, 0xa1), taken);
+        assert_eq!(branch(&ints, 0xa2), not_taken);
+        assert_eq!(branch(&ints, 0xa3), not_taken);
+        assert_eq!(branch(&ints, 0xa4), taken);
+
+        assert_eq!(branch(&[0x01, 0x01], 0xa5), taken);
+        assert_eq!(branch(&[0x01, 0x01], 0xa6), not_taken);
+        assert_eq!(branch(&null, 0xc6), taken);
+        assert_eq!(branch(&null, 0xc7), not_taken);
+    }
+
+    #[test]
+    fn test_backward_branches() {
+        // int sum = 0; for (int i = 5; i > 0; i--) { sum += i; } return sum;
+        // with the loop closed by goto -11
+        let loop_start = [0x03, 0x3b, 0x08, 0x3c, 0x1b];
+        let body = [0x1a, 0x1b, 0x60, 0x3b, 0x84, 0x01, 0xff];
+
+        let mut code = loop_start.to_vec();
+        code.extend_from_slice(&[0x9e, 0x00, 0x0d]);
+        code.extend_from_slice(&body);
+        code.extend_from_slice(&[0xa7, 0xff, 0xf5, 0x1a, 0xac]);
+        assert_eq!(eval(vec![], &code), JvmVal::Int(15));
+
+        // The same loop closed by goto_w -11
+        let mut code = loop_start.to_vec();
+        code.extend_from_slice(&[0x9e, 0x00, 0x0f]);
+        code.extend_from_slice(&body);
+        code.extend_from_slice(&[0xc8, 0xff, 0xff, 0xff, 0xf5, 0x1a, 0xac]);
+        assert_eq!(eval(vec![], &code), JvmVal::Int(15));
+    }
+
+    #[test]
+    fn test_primitive_arrays() {
+        // new byte[2], storing 300 at 0 and loading it back
+        let code = [
+            0x05, 0xbc, 8, 0x59, 0x03, 0x11, 0x01, 0x2c, 0x54, 0x03, 0x33, 0xac,
+        ];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(44));
+        // new char[2], storing -1 at 0 and loading it back
+        let code = [0x05, 0xbc, 5, 0x59, 0x03, 0x02, 0x55, 0x03, 0x34, 0xac];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(0xffff));
+        // new boolean[2], storing 3 at 1 with bastore and loading it back
+        let code = [0x05, 0xbc, 4, 0x59, 0x04, 0x06, 0x54, 0x04, 0x33, 0xac];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(1));
+        // new long[2], storing 1L at 1 and loading it back
+        let code = [0x05, 0xbc, 11, 0x59, 0x04, 0x0a, 0x50, 0x04, 0x2f, 0xad];
+        assert_eq!(eval(vec![], &code), JvmVal::Long(1));
+        // new double[3] starts out zeroed
+        let code = [0x06, 0xbc, 7, 0x05, 0x31, 0xaf];
+        assert_eq!(eval(vec![], &code), JvmVal::Double(0.0));
+        // new float[3].length
+        assert_eq!(eval(vec![], &[0x06, 0xbc, 6, 0xbe, 0xac]), JvmVal::Int(3));
+    }
+
+    #[test]
+    fn test_primitive_array_exceptions() {
+        // (new int[2])[2]
+        assert_eq!(
+            eval_thrown(vec![], &[0x05, 0xbc, 10, 0x05, 0x2e, 0xac]),
+            "java/lang/ArrayIndexOutOfBoundsException"
+        );
+        // new int[-1]
+        assert_eq!(
+            eval_thrown(vec![], &[0x02, 0xbc, 10, 0xb0]),
+            "java/lang/NegativeArraySizeException"
+        );
+        // ((int[]) null)[0]
+        assert_eq!(
+            eval_thrown(vec![], &[0x01, 0x03, 0x2e, 0xac]),
+            "java/lang/NullPointerException"
+        );
+
+        // Unverified code can name no primitive type, or use an array of
+        // another type than the instruction
+        assert_eq!(
+            eval_thrown(vec![], &[0x04, 0xbc, 3, 0xb0]),
+            "java/lang/VerifyError"
+        );
+        // iastore into a long[1]
+        let code = [0x04, 0xbc, 11, 0x03, 0x03, 0x4f, 0xb1];
+        assert_eq!(eval_thrown(vec![], &code), "java/lang/VerifyError");
+        // aaload from an int[1]
+        let code = [0x04, 0xbc, 10, 0x03, 0x32, 0xb0];
+        assert_eq!(eval_thrown(vec![], &code), "java/lang/VerifyError");
+        // iaload from a short[1]
+        let code = [0x04, 0xbc, 9, 0x03, 0x2e, 0xac];
+        assert_eq!(eval_thrown(vec![], &code), "java/lang/VerifyError");
+        // lastore of an int into a long[1]
+        let code = [0x04, 0xbc, 11, 0x03, 0x03, 0x50, 0xb1];
+        assert_eq!(eval_thrown(vec![], &code), "java/lang/VerifyError");
+    }
+
+    #[test]
+    fn test_reference_arrays() {
+        let consts = vec![
+            JvmConst::UTF8("java/lang/Object".to_string()),
+            JvmConst::Class(1),
+            JvmConst::UTF8("x".to_string()),
+            JvmConst::String(3),
+            JvmConst::UTF8("[[I".to_string()),
+            JvmConst::Class(5),
+        ];
+
+        // Object[] a = new Object[2]; a[1] = "x"; return a[1];
+        let code = [
+            0x05, 0xbd, 0, 2, 0x59, 0x04, 0x12, 4, 0x53, 0x04, 0x32, 0xb0,
+        ];
+        assert_eq!(eval(consts.clone(), &code), JvmVal::String("x".to_string()));
+        // Elements start out null: return new Object[2][0];
+        let code = [0x05, 0xbd, 0, 2, 0x03, 0x32, 0xb0];
+        assert_eq!(eval(consts.clone(), &code), JvmVal::Null);
+
+        // int[][] a = new int[2][3]; return a[1].length + a[1][2];
+        let code = [
+            0x05, 0x06, 0xc5, 0, 6, 2, 0x59, 0x04, 0x32, 0xbe, 0x5f, 0x04, 0x32, 0x05, 0x2e, 0x60,
+            0xac,
+        ];
+        assert_eq!(eval(consts.clone(), &code), JvmVal::Int(3));
+        // The rows of new int[2][3] are separate arrays: a[0][0] = 5; return a[1][0];
+        let code = [
+            0x05, 0x06, 0xc5, 0, 6, 2, 0x59, 0x03, 0x32, 0x03, 0x08, 0x4f, 0x04, 0x32, 0x03, 0x2e,
+            0xac,
+        ];
+        assert_eq!(eval(consts.clone(), &code), JvmVal::Int(0));
+        // new int[2][-1]
+        let code = [0x05, 0x02, 0xc5, 0, 6, 2, 0xb0];
+        assert_eq!(
+            eval_thrown(consts.clone(), &code),
+            "java/lang/NegativeArraySizeException"
+        );
+    }
+
+    #[test]
+    fn test_array_store_checks() {
+        let consts = vec![
+            JvmConst::UTF8("java/lang/Integer".to_string()),
+            JvmConst::Class(1),
+            JvmConst::UTF8("x".to_string()),
+            JvmConst::String(3),
+            JvmConst::UTF8("[I".to_string()),
+            JvmConst::Class(5),
+        ];
+        let store = |class: u8, value: &[u8]| {
+            let mut code = vec![0x04, 0xbd, 0, class, 0x59, 0x03];
+            code.extend_from_slice(value);
+            code.extend_from_slice(&[0x53, 0x03, 0x32, 0xb0]);
+            code
+        };
+
+        // An Integer[] takes no String
+        assert_eq!(
+            eval_thrown(consts.clone(), &store(2, &[0x12, 4])),
+            "java/lang/ArrayStoreException"
+        );
+        // An int[][] takes an int[] but no String, and any array takes null
+        assert!(matches!(
+            eval(consts.clone(), &store(6, &[0x04, 0xbc, 10])),
+            JvmVal::Reference(_)
+        ));
+        assert_eq!(
+            eval_thrown(consts.clone(), &store(6, &[0x12, 4])),
+            "java/lang/ArrayStoreException"
+        );
+        assert_eq!(eval(consts.clone(), &store(2, &[0x01])), JvmVal::Null);
+        // iaload from a String[1] and iastore into it
+        let strings = vec![
+            JvmConst::UTF8("java/lang/String".to_string()),
+            JvmConst::Class(1),
+        ];
+        let code = [0x04, 0xbd, 0, 2, 0x03, 0x2e, 0xac];
+        assert_eq!(eval_thrown(strings.clone(), &code), "java/lang/VerifyError");
+        let code = [0x04, 0xbd, 0, 2, 0x03, 0x03, 0x4f, 0xb1];
+        assert_eq!(eval_thrown(strings, &code), "java/lang/VerifyError");
+        // aastore into an int[1], and iaload from an int
+        let code = [0x04, 0xbc, 10, 0x03, 0x01, 0x53, 0xb1];
+        assert_eq!(eval_thrown(vec![], &code), "java/lang/VerifyError");
+        let code = [0x04, 0x03, 0x2e, 0xac];
+        assert_eq!(eval_thrown(vec![], &code), "java/lang/VerifyError");
+        // A long[] is no int[]
+        assert_eq!(
+            eval_thrown(consts.clone(), &store(6, &[0x04, 0xbc, 11])),
+            "java/lang/ArrayStoreException"
+        );
+    }
+
+    #[test]
+    fn test_wide() {
+        // int a = 1000 in local 299; a -= 500; then a subroutine keeping its
+        // return address in local 280 adds 1 before a is returned
+        let code = [
+            0x11, 0x03, 0xe8, 0xc4, 0x36, 0x01, 0x2b, 0xc4, 0x84, 0x01, 0x2b, 0xfe, 0x0c, 0xa8,
+            0x00, 0x08, 0xc4, 0x15, 0x01, 0x2b, 0xac, 0xc4, 0x3a, 0x01, 0x18, 0xc4, 0x84, 0x01,
+            0x2b, 0x00, 0x01, 0xc4, 0xa9, 0x01, 0x18,
+        ];
+        let test = method("test", "()I", 300, &code);
+        let (vm, result) = run(class_with_methods(vec![], vec![test]));
+        assert_eq!(vm.exception, None);
+        assert_eq!(result, JvmVal::Int(501));
+    }
+
+    #[test]
+    fn test_subroutines() {
+        // jsr_w to a subroutine that sets local 0 to 7 and returns through
+        // the address in local 1, after which local 0 is returned
+        let code = [
+            0xc9, 0x00, 0x00, 0x00, 0x08, 0x1a, 0xac, 0x00, 0x4c, 0x10, 0x07, 0x3b, 0xa9, 0x01,
+        ];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(7));
+
+        // The same subroutine run twice through jsr, adding 7 each time
+        let code = [
+            0x03, 0x3b, 0xa8, 0x00, 0x08, 0xa8, 0x00, 0x05, 0x1a, 0xac, 0x4c, 0x84, 0x00, 0x07,
+            0xa9, 0x01,
+        ];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(14));
+    }
+
+    #[test]
+    fn test_stack_ops() {
+        // dup2 copies one long or two ints: 1L + 1L and (1 - 2 * (1 - 2))
+        assert_eq!(eval(vec![], &[0x0a, 0x5c, 0x61, 0xad]), JvmVal::Long(2));
+        let code = [0x04, 0x05, 0x5c, 0x64, 0x68, 0x64, 0xac];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(3));
+        // pop2 pops one long or two ints, leaving the 5 under them
+        assert_eq!(eval(vec![], &[0x08, 0x0a, 0x58, 0xac]), JvmVal::Int(5));
+        assert_eq!(
+            eval(vec![], &[0x08, 0x04, 0x05, 0x58, 0xac]),
+            JvmVal::Int(5)
+        );
+
+        // dup_x1 of 2 over 1 gives 2, 1, 2: 2 - (1 - 2)
+        let code = [0x04, 0x05, 0x5a, 0x64, 0x64, 0xac];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(3));
+        // swap: 2 - 1
+        assert_eq!(
+            eval(vec![], &[0x04, 0x05, 0x5f, 0x64, 0xac]),
+            JvmVal::Int(1)
+        );
+
+        // dup_x2 of 3 over 1.0 goes under the double: 3, 1.0, 3 and then
+        // 3 - (int) (1.0 + 3)
+        let code = [0x0f, 0x06, 0x5b, 0x87, 0x63, 0x8e, 0x64, 0xac];
+        assert_eq!(eval(vec![], &code), JvmVal::Int(-1));
+
+        // dup2_x1 of 1L over 3 gives 1L, 3, 1L, then the top copy is
+        // popped: 1L - 3
+        let code = [0x06, 0x0a, 0x5d, 0x58, 0x85, 0x65, 0xad];
+        assert_eq!(eval(vec![], &code), JvmVal::Long(-2));
+
+        // dup2_x2 of 2L over 7L gives 2L, 7L, 2L: 2L - (7L - 2L)
+        let code = [0x14, 0, 1, 0x14, 0, 3, 0x5e, 0x65, 0x65, 0xad];
+        assert_eq!(eval(longs(&[7, 2]), &code), JvmVal::Long(-3));
+        // dup2_x2 of 1L over the ints 5 and 2 gives 1L, 5, 2, 1L, then the
+        // top copy is popped: 1L + (5 - 2)
+        let code = [0x08, 0x05, 0x0a, 0x5e, 0x58, 0x64, 0x85, 0x61, 0xad];
+        assert_eq!(eval(vec![], &code), JvmVal::Long(4));
+        // dup2_x2 of the ints 1 and 2 over 0.5, above a 4, gives 4, 1, 2,
+        // 0.5, 1, 2: 4 * (1 - (2 + (int) (0.5 + (1 - 2))))
+        let code = [
+            0x07, 0x14, 0, 1, 0x04, 0x05, 0x5e, 0x64, 0x87, 0x63, 0x8e, 0x60, 0x64, 0x68, 0xac,
+        ];
+        assert_eq!(eval(doubles(&[0.5]), &code), JvmVal::Int(-4));
+    }
+
+    #[test]
+    fn test_math_abs() {
+        let method_ref = |descriptor: &str| {
+            vec![
+                JvmConst::UTF8("java/lang/Math".to_string()),
+                JvmConst::Class(1),
+                JvmConst::UTF8("abs".to_string()),
+                JvmConst::UTF8(descriptor.to_string()),
+                JvmConst::NameAndType(3, 4),
+                JvmConst::MethodRef(2, 5),
+            ]
+        };
+
+        // Math.abs(Integer.MIN_VALUE) and Math.abs(Long.MIN_VALUE) overflow
+        // back to MIN_VALUE
+        let mut consts = method_ref("(I)I");
+        consts.extend(ints(&[i32::MIN]));
+        let code = [0x12, 7, 0xb8, 0, 6, 0xac];
+        assert_eq!(eval(consts, &code), JvmVal::Int(i32::MIN));
+
+        let mut consts = method_ref("(J)J");
+        consts.extend(longs(&[i64::MIN]));
+        let code = [0x14, 0, 7, 0xb8, 0, 6, 0xad];
+        assert_eq!(eval(consts, &code), JvmVal::Long(i64::MIN));
+    }
+}